@@ -18,7 +18,8 @@ pub struct ImageCache {
 }
 
 impl ImageCache {
-  const CACHE_VERSION: u8 = 1;
+  // 2: raw developments at full resolution as half float RGB
+  const CACHE_VERSION: u8 = 2;
   const CACHE_SUBDIR: &'static str = "raw_cache";
 
   pub fn new() -> Result<Self, FileLoaderError> {
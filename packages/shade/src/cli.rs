@@ -3,7 +3,15 @@
 //! This module provides a user-friendly CLI for creating and executing
 //! image processing pipelines with various color grading and filter operations.
 
-use crate::shade::{ImagePipeline, NodeParams, NodeType};
+use crate::color::{ColorSettings, ColorSpace};
+use crate::curves::{self, CurvePoints};
+use crate::geometry::{self, Resampling};
+use crate::grading::{self, GradingWheel};
+use crate::hsl::{self, HslAdjustment, HslBand, HslBands};
+use crate::lut::LutInterpolation;
+use crate::raw::{DemosaicAlgorithm, HighlightRecovery, InputProfile, RawDevelopParams};
+use crate::shade::{ImagePipeline, NodeParams, NodeType, ToneMapOperator};
+use crate::white_balance::WhiteBalanceMethod;
 
 use clap::{Arg, ArgMatches, Command, value_parser};
 use std::path::PathBuf;
@@ -18,23 +26,342 @@ pub struct PipelineOperation {
 /// Types of operations that can be performed in the pipeline
 #[derive(Debug, Clone)]
 pub enum OperationType {
+  Exposure(f32),
   Brightness(f32),
   Contrast(f32),
   Saturation(f32),
+  Vibrance(f32),
   Hue(f32),
+  Hsl(HslBands),
   Gamma(f32),
+  Levels {
+    input_black: f32,
+    input_white: f32,
+    output_black: f32,
+    output_white: f32,
+  },
+  Curves {
+    master: CurvePoints,
+    red: CurvePoints,
+    green: CurvePoints,
+    blue: CurvePoints,
+  },
+  ColorBalance {
+    shadows: [f32; 3],
+    midtones: [f32; 3],
+    highlights: [f32; 3],
+  },
+  ColorGrading {
+    shadows: GradingWheel,
+    midtones: GradingWheel,
+    highlights: GradingWheel,
+    balance: f32,
+    blending: f32,
+  },
   WhiteBalance {
     auto_adjust: bool,
     temperature: Option<f32>,
     tint: Option<f32>,
+    method: WhiteBalanceMethod,
+    neutral_point: Option<[f32; 2]>,
+    kelvin: Option<f32>,
+  },
+  Lut3D {
+    path: PathBuf,
+    intensity: f32,
+    interpolation: LutInterpolation,
+  },
+  ToneMap {
+    operator: ToneMapOperator,
+    white_point: f32,
+    contrast: f32,
+  },
+  Denoise {
+    luminance: f32,
+    chroma: f32,
+    radius: f32,
   },
   Blur(f32),
   Sharpen(f32),
+  UnsharpMask {
+    amount: f32,
+    radius: f32,
+    threshold: f32,
+  },
+  Clarity {
+    amount: f32,
+    radius: f32,
+  },
   Noise(f32),
+  Vignette {
+    amount: f32,
+    midpoint: f32,
+    roundness: f32,
+    feather: f32,
+  },
+  Grain {
+    amount: f32,
+    size: f32,
+    seed: u32,
+  },
+  Dehaze(f32),
   Resize {
     width: Option<u32>,
     height: Option<u32>,
   },
+  /// Region in pixels
+  Crop {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+  },
+  /// Clockwise quarter turns
+  Rotate(u32),
+  Flip {
+    horizontal: bool,
+    vertical: bool,
+  },
+  Straighten {
+    angle: f32,
+    crop: bool,
+    resampling: Resampling,
+  },
+  Perspective {
+    corners: [[f32; 2]; 4],
+    resampling: Resampling,
+  },
+  Invert,
+  /// Blends the adjustments since the last blend point over the image at that point,
+  /// from 0.0 keeping it to 1.0 taking the adjusted image
+  Mix(f32),
+  /// Limits the adjustments since the last blend point to a mask generated from the
+  /// image at that point
+  Mask {
+    generator: NodeParams,
+    invert: bool,
+  },
+}
+
+impl OperationType {
+  /// Names of all operations in `process_image` requests, one for every variant
+  pub const NAMES: &[&str] = &[
+    "exposure",
+    "brightness",
+    "contrast",
+    "saturation",
+    "vibrance",
+    "hue",
+    "hsl",
+    "gamma",
+    "levels",
+    "curves",
+    "color_balance",
+    "color_grading",
+    "white_balance",
+    "lut",
+    "tone_map",
+    "denoise",
+    "blur",
+    "sharpen",
+    "unsharp_mask",
+    "clarity",
+    "noise",
+    "vignette",
+    "grain",
+    "dehaze",
+    "resize",
+    "crop",
+    "rotate",
+    "flip",
+    "straighten",
+    "perspective",
+    "invert",
+    "mix",
+    "mask",
+  ];
+
+  /// Name of the operation in `process_image` requests
+  pub fn name(&self) -> &'static str {
+    match self {
+      OperationType::Exposure(_) => "exposure",
+      OperationType::Brightness(_) => "brightness",
+      OperationType::Contrast(_) => "contrast",
+      OperationType::Saturation(_) => "saturation",
+      OperationType::Vibrance(_) => "vibrance",
+      OperationType::Hue(_) => "hue",
+      OperationType::Hsl(_) => "hsl",
+      OperationType::Gamma(_) => "gamma",
+      OperationType::Levels { .. } => "levels",
+      OperationType::Curves { .. } => "curves",
+      OperationType::ColorBalance { .. } => "color_balance",
+      OperationType::ColorGrading { .. } => "color_grading",
+      OperationType::WhiteBalance { .. } => "white_balance",
+      OperationType::Lut3D { .. } => "lut",
+      OperationType::ToneMap { .. } => "tone_map",
+      OperationType::Denoise { .. } => "denoise",
+      OperationType::Blur(_) => "blur",
+      OperationType::Sharpen(_) => "sharpen",
+      OperationType::UnsharpMask { .. } => "unsharp_mask",
+      OperationType::Clarity { .. } => "clarity",
+      OperationType::Noise(_) => "noise",
+      OperationType::Vignette { .. } => "vignette",
+      OperationType::Grain { .. } => "grain",
+      OperationType::Dehaze(_) => "dehaze",
+      OperationType::Resize { .. } => "resize",
+      OperationType::Crop { .. } => "crop",
+      OperationType::Rotate(_) => "rotate",
+      OperationType::Flip { .. } => "flip",
+      OperationType::Straighten { .. } => "straighten",
+      OperationType::Perspective { .. } => "perspective",
+      OperationType::Invert => "invert",
+      OperationType::Mix(_) => "mix",
+      OperationType::Mask { .. } => "mask",
+    }
+  }
+
+  /// Check the parameters beyond what their types enforce
+  pub fn validate(&self) -> Result<(), String> {
+    match self {
+      OperationType::Levels {
+        input_black,
+        input_white,
+        ..
+      } if input_white <= input_black => Err(format!(
+        "Levels input white {} must be above input black {}",
+        input_white, input_black
+      )),
+      OperationType::Crop { width, height, .. } if *width == 0 || *height == 0 => {
+        Err(format!("Crop region {}x{} has no area", width, height))
+      }
+      OperationType::Perspective { corners, .. } => {
+        geometry::perspective_matrix(corners).map(|_| ())
+      }
+      OperationType::Mix(factor) if !(0.0..=1.0).contains(factor) => {
+        Err(format!("Mix factor {} is outside 0.0 to 1.0", factor))
+      }
+      OperationType::Mask { generator, .. } if generator.mask_generator().is_none() => {
+        Err("Mask parameters must be those of a mask generator".to_string())
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Whether the operation moves pixels or changes the image size. Blends never reach
+  /// back past these, so they start a new blend point.
+  fn is_geometric(&self) -> bool {
+    matches!(
+      self,
+      OperationType::Resize { .. }
+        | OperationType::Crop { .. }
+        | OperationType::Rotate(_)
+        | OperationType::Flip { .. }
+        | OperationType::Straighten { .. }
+        | OperationType::Perspective { .. }
+    )
+  }
+}
+
+/// Kinds of generated mask, each set by a `--mask-<kind>` option or `mask_<kind>` INI
+/// key, with the values they take
+pub const MASK_KINDS: [(&str, &str); 4] = [
+  ("luminance", "LOW:HIGH"),
+  ("color", "HUE:RANGE"),
+  ("linear", "X:Y:ANGLE"),
+  ("radial", "X:Y:RADIUS"),
+];
+
+/// Parse `N` numbers separated by colons, like `0.5:1.0`
+pub fn parse_numbers<const N: usize>(value: &str) -> Result<[f32; N], String> {
+  let numbers = value
+    .split(':')
+    .map(|number| {
+      number
+        .trim()
+        .parse::<f32>()
+        .map_err(|e| format!("Invalid number '{}' in '{}': {}", number, value, e))
+    })
+    .collect::<Result<Vec<f32>, String>>()?;
+  numbers.try_into().map_err(|numbers: Vec<f32>| {
+    format!(
+      "Expected {} values separated by ':' in '{}', got {}",
+      N,
+      value,
+      numbers.len()
+    )
+  })
+}
+
+/// Parse levels written as input `black:white`, optionally followed by output
+/// `black:white`
+pub fn parse_levels(value: &str) -> Result<[f32; 4], String> {
+  let levels = match value.split(':').count() {
+    2 => {
+      let [black, white] = parse_numbers(value)?;
+      [black, white, 0.0, 1.0]
+    }
+    _ => parse_numbers(value)?,
+  };
+  if levels[1] <= levels[0] {
+    return Err(format!(
+      "Levels input white {} must be above input black {}",
+      levels[1], levels[0]
+    ));
+  }
+  Ok(levels)
+}
+
+/// Mask generator parameters for a mask of `kind` (see `MASK_KINDS`) from its values.
+/// `feather` overrides the default softness of the mask edge.
+pub fn parse_mask(
+  kind: &str,
+  value: &str,
+  feather: Option<f32>,
+) -> Result<NodeParams, String> {
+  match kind {
+    "luminance" => {
+      let [low, high] = parse_numbers(value)?;
+      Ok(NodeParams::LuminanceMask {
+        low,
+        high,
+        feather: feather.unwrap_or(0.1),
+      })
+    }
+    "color" => {
+      let [hue, hue_range] = parse_numbers(value)?;
+      Ok(NodeParams::ColorRangeMask {
+        hue,
+        hue_range,
+        hue_feather: 20.0,
+        saturation: [0.1, 1.0],
+        luminance: [0.0, 1.0],
+        feather: feather.unwrap_or(0.1),
+      })
+    }
+    "linear" => {
+      let [x, y, angle] = parse_numbers(value)?;
+      Ok(NodeParams::LinearGradient {
+        center: [x, y],
+        angle,
+        feather: feather.unwrap_or(0.5),
+        invert: false,
+      })
+    }
+    "radial" => {
+      let [x, y, radius] = parse_numbers(value)?;
+      Ok(NodeParams::RadialGradient {
+        center: [x, y],
+        radius: [radius, radius],
+        angle: 0.0,
+        feather: feather.unwrap_or(0.5),
+        invert: false,
+      })
+    }
+    _ => Err(format!(
+      "Unknown mask '{}', expected luminance, color, linear or radial",
+      kind
+    )),
+  }
 }
 
 /// CLI configuration structure
@@ -45,8 +372,19 @@ pub struct ProcessingConfig {
   pub pipeline_config: PipelineConfig,
   pub verbose: bool,
   pub config_path: Option<PathBuf>,
+  /// JSON graph document describing the whole pipeline, used instead of operations
+  pub graph_path: Option<PathBuf>,
+  /// Where to write the pipeline as a JSON graph document
+  pub save_graph_path: Option<PathBuf>,
+  /// Directory of `.wgsl` shaders for `Custom` graph nodes
+  pub shader_dir: Option<PathBuf>,
   pub clear_cache: bool,
   pub show_cache_info: bool,
+  /// Run the pipeline on the CPU instead of the GPU
+  pub use_cpu: bool,
+  pub color: ColorSettings,
+  /// Development of camera raw input files
+  pub raw: RawDevelopParams,
 }
 
 /// Pipeline configuration from CLI arguments
@@ -71,8 +409,14 @@ impl Default for ProcessingConfig {
       pipeline_config: PipelineConfig::default(),
       verbose: false,
       config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
       clear_cache: false,
       show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw: RawDevelopParams::default(),
     }
   }
 }
@@ -92,10 +436,24 @@ impl ProcessingConfig {
       .or(matches.get_one::<PathBuf>("input"))
       .cloned();
     let config_path = matches.get_one::<PathBuf>("config").cloned();
+    let graph_path = matches.get_one::<PathBuf>("graph").cloned();
+    let save_graph_path = matches.get_one::<PathBuf>("save-graph").cloned();
+    let shader_dir = matches.get_one::<PathBuf>("shader-dir").cloned();
 
     let mut operations = Vec::new();
 
     // Collect operations with their indices
+    if let Some(value) = matches.get_one::<f32>("exposure") {
+      if let Some(indices) = matches.indices_of("exposure") {
+        for index in indices {
+          operations.push(PipelineOperation {
+            op_type: OperationType::Exposure(*value),
+            index,
+          });
+        }
+      }
+    }
+
     if let Some(value) = matches.get_one::<f32>("brightness") {
       if let Some(indices) = matches.indices_of("brightness") {
         for index in indices {
@@ -129,6 +487,17 @@ impl ProcessingConfig {
       }
     }
 
+    if let Some(value) = matches.get_one::<f32>("vibrance") {
+      if let Some(indices) = matches.indices_of("vibrance") {
+        for index in indices {
+          operations.push(PipelineOperation {
+            op_type: OperationType::Vibrance(*value),
+            index,
+          });
+        }
+      }
+    }
+
     if let Some(value) = matches.get_one::<f32>("hue") {
       if let Some(indices) = matches.indices_of("hue") {
         for index in indices {
@@ -140,6 +509,25 @@ impl ProcessingConfig {
       }
     }
 
+    // Every --hsl band adjustment goes into one HSL operation
+    if let Some(index) = matches
+      .indices_of("hsl")
+      .and_then(|mut indices| indices.next())
+    {
+      let mut bands = HslBands::default();
+      for (band, adjustment) in matches
+        .get_many::<(HslBand, HslAdjustment)>("hsl")
+        .into_iter()
+        .flatten()
+      {
+        *bands.get_mut(*band) = *adjustment;
+      }
+      operations.push(PipelineOperation {
+        op_type: OperationType::Hsl(bands),
+        index,
+      });
+    }
+
     if let Some(value) = matches.get_one::<f32>("gamma") {
       if let Some(indices) = matches.indices_of("gamma") {
         for index in indices {
@@ -151,12 +539,128 @@ impl ProcessingConfig {
       }
     }
 
+    if let Some([input_black, input_white, output_black, output_white]) =
+      matches.get_one::<[f32; 4]>("levels")
+    {
+      if let Some(index) = matches.index_of("levels") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Levels {
+            input_black: *input_black,
+            input_white: *input_white,
+            output_black: *output_black,
+            output_white: *output_white,
+          },
+          index,
+        });
+      }
+    }
+
+    // Handle curves - all curve arguments form a single curves operation
+    let curve_args = ["curve", "curve-red", "curve-green", "curve-blue"];
+    let curve_index = curve_args
+      .iter()
+      .filter_map(|arg| {
+        matches
+          .indices_of(arg)
+          .and_then(|mut indices| indices.next())
+      })
+      .min();
+
+    if let Some(index) = curve_index {
+      let points = |arg: &str| {
+        matches
+          .get_one::<CurvePoints>(arg)
+          .cloned()
+          .unwrap_or_default()
+      };
+      operations.push(PipelineOperation {
+        op_type: OperationType::Curves {
+          master: points("curve"),
+          red: points("curve-red"),
+          green: points("curve-green"),
+          blue: points("curve-blue"),
+        },
+        index,
+      });
+    }
+
+    // Shadows, midtones and highlights form a single colour balance operation
+    let balance_args = [
+      "color-balance-shadows",
+      "color-balance-midtones",
+      "color-balance-highlights",
+    ];
+    let balance_index = balance_args
+      .iter()
+      .filter_map(|arg| matches.index_of(arg))
+      .min();
+
+    if let Some(index) = balance_index {
+      let gains = |arg: &str| {
+        matches
+          .get_one::<[f32; 3]>(arg)
+          .copied()
+          .unwrap_or_default()
+      };
+      operations.push(PipelineOperation {
+        op_type: OperationType::ColorBalance {
+          shadows: gains("color-balance-shadows"),
+          midtones: gains("color-balance-midtones"),
+          highlights: gains("color-balance-highlights"),
+        },
+        index,
+      });
+    }
+
+    // The three grading wheels and their balance and blending form a single operation
+    let grading_args = [
+      "grade-shadows",
+      "grade-midtones",
+      "grade-highlights",
+      "grade-balance",
+      "grade-blending",
+    ];
+    let grading_index = grading_args
+      .iter()
+      .filter_map(|arg| matches.index_of(arg))
+      .min();
+
+    if let Some(index) = grading_index {
+      let wheel = |arg: &str| {
+        matches
+          .get_one::<GradingWheel>(arg)
+          .copied()
+          .unwrap_or_default()
+      };
+      let number =
+        |arg: &str, default: f32| matches.get_one::<f32>(arg).copied().unwrap_or(default);
+      operations.push(PipelineOperation {
+        op_type: OperationType::ColorGrading {
+          shadows: wheel("grade-shadows"),
+          midtones: wheel("grade-midtones"),
+          highlights: wheel("grade-highlights"),
+          balance: number("grade-balance", 0.0),
+          blending: number("grade-blending", 0.5),
+        },
+        index,
+      });
+    }
+
     // Handle white balance - check for any white balance related arguments
     let auto_wb = matches.get_flag("auto-white-balance");
     let wb_temp = matches.get_one::<f32>("wb-temperature").copied();
     let wb_tint = matches.get_one::<f32>("wb-tint").copied();
-
-    if auto_wb || wb_temp.is_some() || wb_tint.is_some() {
+    let wb_method = matches.get_one::<WhiteBalanceMethod>("wb-method").copied();
+    let wb_neutral = matches.get_one::<[f32; 2]>("wb-neutral").copied();
+    let wb_kelvin = matches.get_one::<f32>("wb-kelvin").copied();
+
+    if auto_wb
+      || wb_temp.is_some()
+      || wb_tint.is_some()
+      || wb_method.is_some()
+      || wb_neutral.is_some()
+      || wb_kelvin.is_some()
+    {
       // Find the earliest index among white balance arguments
       let mut wb_index = usize::MAX;
 
@@ -175,19 +679,82 @@ impl ProcessingConfig {
           wb_index = wb_index.min(indices.min().unwrap_or(usize::MAX));
         }
       }
+      for arg in ["wb-method", "wb-neutral", "wb-kelvin"] {
+        if let Some(index) = matches.index_of(arg) {
+          wb_index = wb_index.min(index);
+        }
+      }
 
       if wb_index != usize::MAX {
         operations.push(PipelineOperation {
           op_type: OperationType::WhiteBalance {
-            auto_adjust: auto_wb,
+            // Choosing a method asks for an automatic white balance
+            auto_adjust: auto_wb || wb_method.is_some(),
             temperature: wb_temp,
             tint: wb_tint,
+            method: wb_method.unwrap_or_default(),
+            neutral_point: wb_neutral,
+            kelvin: wb_kelvin,
           },
           index: wb_index,
         });
       }
     }
 
+    if let Some(path) = matches.get_one::<PathBuf>("lut") {
+      if let Some(index) = matches.index_of("lut") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Lut3D {
+            path: path.clone(),
+            intensity: matches
+              .get_one::<f32>("lut-intensity")
+              .copied()
+              .unwrap_or(1.0),
+            interpolation: matches
+              .get_one::<LutInterpolation>("lut-interpolation")
+              .copied()
+              .unwrap_or_default(),
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some(operator) = matches.get_one::<ToneMapOperator>("tone-map") {
+      if let Some(index) = matches.index_of("tone-map") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::ToneMap {
+            operator: *operator,
+            white_point: matches
+              .get_one::<f32>("tone-map-white")
+              .copied()
+              .unwrap_or(16.0),
+            contrast: matches
+              .get_one::<f32>("tone-map-contrast")
+              .copied()
+              .unwrap_or(1.0),
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some([luminance, chroma]) = matches.get_one::<[f32; 2]>("denoise") {
+      if let Some(index) = matches.index_of("denoise") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Denoise {
+            luminance: *luminance,
+            chroma: *chroma,
+            radius: matches
+              .get_one::<f32>("denoise-radius")
+              .copied()
+              .unwrap_or(2.0),
+          },
+          index,
+        });
+      }
+    }
+
     if let Some(value) = matches.get_one::<f32>("blur") {
       if let Some(indices) = matches.indices_of("blur") {
         for index in indices {
@@ -210,6 +777,40 @@ impl ProcessingConfig {
       }
     }
 
+    if let Some(amount) = matches.get_one::<f32>("unsharp") {
+      if let Some(index) = matches.index_of("unsharp") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::UnsharpMask {
+            amount: *amount,
+            radius: matches
+              .get_one::<f32>("unsharp-radius")
+              .copied()
+              .unwrap_or(1.0),
+            threshold: matches
+              .get_one::<f32>("unsharp-threshold")
+              .copied()
+              .unwrap_or(0.0),
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some(amount) = matches.get_one::<f32>("clarity") {
+      if let Some(index) = matches.index_of("clarity") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Clarity {
+            amount: *amount,
+            radius: matches
+              .get_one::<f32>("clarity-radius")
+              .copied()
+              .unwrap_or(40.0),
+          },
+          index,
+        });
+      }
+    }
+
     if let Some(value) = matches.get_one::<f32>("noise") {
       if let Some(indices) = matches.indices_of("noise") {
         for index in indices {
@@ -221,6 +822,111 @@ impl ProcessingConfig {
       }
     }
 
+    if let Some(amount) = matches.get_one::<f32>("vignette") {
+      if let Some(index) = matches.index_of("vignette") {
+        let option = |name: &str, default: f32| {
+          matches.get_one::<f32>(name).copied().unwrap_or(default)
+        };
+        operations.push(PipelineOperation {
+          op_type: OperationType::Vignette {
+            amount: *amount,
+            midpoint: option("vignette-midpoint", 0.5),
+            roundness: option("vignette-roundness", 0.0),
+            feather: option("vignette-feather", 0.5),
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some(amount) = matches.get_one::<f32>("grain") {
+      if let Some(index) = matches.index_of("grain") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Grain {
+            amount: *amount,
+            size: matches.get_one::<f32>("grain-size").copied().unwrap_or(1.0),
+            seed: matches.get_one::<u32>("grain-seed").copied().unwrap_or(42),
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some(amount) = matches.get_one::<f32>("dehaze") {
+      if let Some(index) = matches.index_of("dehaze") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Dehaze(*amount),
+          index,
+        });
+      }
+    }
+
+    // Straighten and perspective share the resampling filter
+    let resampling = matches
+      .get_one::<Resampling>("resampling")
+      .copied()
+      .unwrap_or_default();
+
+    if let Some([x, y, width, height]) = matches.get_one::<[u32; 4]>("crop") {
+      if let Some(index) = matches.index_of("crop") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Crop {
+            x: *x,
+            y: *y,
+            width: *width,
+            height: *height,
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some(quarter_turns) = matches.get_one::<u32>("rotate") {
+      if let Some(index) = matches.index_of("rotate") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Rotate(*quarter_turns),
+          index,
+        });
+      }
+    }
+
+    if let Some((horizontal, vertical)) = matches.get_one::<(bool, bool)>("flip") {
+      if let Some(index) = matches.index_of("flip") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Flip {
+            horizontal: *horizontal,
+            vertical: *vertical,
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some(angle) = matches.get_one::<f32>("straighten") {
+      if let Some(index) = matches.index_of("straighten") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Straighten {
+            angle: *angle,
+            crop: !matches.get_flag("straighten-expand"),
+            resampling,
+          },
+          index,
+        });
+      }
+    }
+
+    if let Some(corners) = matches.get_one::<[[f32; 2]; 4]>("perspective") {
+      if let Some(index) = matches.index_of("perspective") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Perspective {
+            corners: *corners,
+            resampling,
+          },
+          index,
+        });
+      }
+    }
+
     // Handle resize - check for resize width or height arguments
     let resize_width = matches.get_one::<u32>("resize-width").copied();
     let resize_height = matches.get_one::<u32>("resize-height").copied();
@@ -251,6 +957,41 @@ impl ProcessingConfig {
       }
     }
 
+    if matches.get_flag("invert") {
+      if let Some(index) = matches.index_of("invert") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Invert,
+          index,
+        });
+      }
+    }
+
+    if let Some(factor) = matches.get_one::<f32>("mix") {
+      if let Some(index) = matches.index_of("mix") {
+        operations.push(PipelineOperation {
+          op_type: OperationType::Mix(*factor),
+          index,
+        });
+      }
+    }
+
+    // Masks share the feather and inversion options
+    let mask_feather = matches.get_one::<f32>("mask-feather").copied();
+    for (kind, _) in MASK_KINDS {
+      let arg = format!("mask-{}", kind);
+      if let Some(value) = matches.get_one::<String>(&arg) {
+        if let Some(index) = matches.index_of(&arg) {
+          operations.push(PipelineOperation {
+            op_type: OperationType::Mask {
+              generator: parse_mask(kind, value, mask_feather)?,
+              invert: matches.get_flag("mask-invert"),
+            },
+            index,
+          });
+        }
+      }
+    }
+
     // Sort operations by their original index
     operations.sort_by_key(|op| op.index);
 
@@ -259,6 +1000,27 @@ impl ProcessingConfig {
     let verbose = matches.get_flag("verbose");
     let clear_cache = matches.get_flag("clear-cache");
     let show_cache_info = matches.get_flag("cache-info");
+    let use_cpu = matches.get_flag("cpu");
+
+    let mut color = ColorSettings::default();
+    color.input_space = matches.get_one::<ColorSpace>("input-space").copied();
+    if let Some(space) = matches.get_one::<ColorSpace>("working-space") {
+      color.working_space = *space;
+    }
+    color.output_space = matches.get_one::<ColorSpace>("output-space").copied();
+
+    let mut raw = RawDevelopParams::default();
+    if let Some(demosaic) = matches.get_one::<DemosaicAlgorithm>("raw-demosaic") {
+      raw.demosaic = *demosaic;
+    }
+    if let Some(highlights) = matches.get_one::<HighlightRecovery>("raw-highlights") {
+      raw.highlights = *highlights;
+    }
+    raw.black_level = matches.get_one::<u32>("raw-black-level").copied();
+    raw.white_level = matches.get_one::<u32>("raw-white-level").copied();
+    if let Some(matrix) = matches.get_one::<[f32; 9]>("raw-matrix") {
+      raw.input_profile = InputProfile::custom(*matrix);
+    }
 
     Ok(ProcessingConfig {
       input_path,
@@ -266,8 +1028,14 @@ impl ProcessingConfig {
       pipeline_config,
       verbose,
       config_path,
+      graph_path,
+      save_graph_path,
+      shader_dir,
       clear_cache,
       show_cache_info,
+      use_cpu,
+      color,
+      raw,
     })
   }
 
@@ -278,10 +1046,28 @@ impl ProcessingConfig {
     // Add input node
     let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
     let mut last_node_id = input_id;
+    // Image that masks and mixes blend the adjustments after it over
+    let mut base_node_id = input_id;
 
     // Add processing nodes in the order they were specified on command line
     for operation in &self.pipeline_config.operations {
       match &operation.op_type {
+        OperationType::Exposure(stops) => {
+          let node_id = pipeline.add_node("Exposure".to_string(), NodeType::Exposure);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Exposure { stops: *stops });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect exposure node");
+          last_node_id = node_id;
+        }
+
         OperationType::Brightness(value) => {
           let node_id = pipeline.add_node("Brightness".to_string(), NodeType::Brightness);
           if let Some(node) = pipeline.get_node_mut(node_id) {
@@ -346,6 +1132,24 @@ impl ProcessingConfig {
           last_node_id = node_id;
         }
 
+        OperationType::Hsl(bands) => {
+          let node_id = pipeline.add_node("HSL".to_string(), NodeType::Hsl);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Hsl {
+              bands: bands.clone(),
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect HSL node");
+          last_node_id = node_id;
+        }
+
         OperationType::Gamma(value) => {
           let node_id = pipeline.add_node("Gamma".to_string(), NodeType::Gamma);
           if let Some(node) = pipeline.get_node_mut(node_id) {
@@ -362,20 +1166,19 @@ impl ProcessingConfig {
           last_node_id = node_id;
         }
 
-        OperationType::WhiteBalance {
-          auto_adjust,
-          temperature,
-          tint,
+        OperationType::Levels {
+          input_black,
+          input_white,
+          output_black,
+          output_white,
         } => {
-          let node_id =
-            pipeline.add_node("WhiteBalance".to_string(), NodeType::WhiteBalance);
+          let node_id = pipeline.add_node("Levels".to_string(), NodeType::Levels);
           if let Some(node) = pipeline.get_node_mut(node_id) {
-            let temp = temperature.unwrap_or(0.0);
-            let tint_val = tint.unwrap_or(0.0);
-            node.set_params(NodeParams::WhiteBalance {
-              auto_adjust: *auto_adjust,
-              temperature: temp,
-              tint: tint_val,
+            node.set_params(NodeParams::Levels {
+              input_black: *input_black,
+              input_white: *input_white,
+              output_black: *output_black,
+              output_white: *output_white,
             });
           }
           pipeline
@@ -385,14 +1188,24 @@ impl ProcessingConfig {
               node_id,
               "image".to_string(),
             )
-            .expect("Failed to connect white balance node");
+            .expect("Failed to connect levels node");
           last_node_id = node_id;
         }
 
-        OperationType::Blur(radius) => {
-          let node_id = pipeline.add_node("Blur".to_string(), NodeType::Blur);
+        OperationType::Curves {
+          master,
+          red,
+          green,
+          blue,
+        } => {
+          let node_id = pipeline.add_node("Curves".to_string(), NodeType::Curves);
           if let Some(node) = pipeline.get_node_mut(node_id) {
-            node.set_params(NodeParams::Blur { radius: *radius });
+            node.set_params(NodeParams::Curves {
+              master: master.clone(),
+              red: red.clone(),
+              green: green.clone(),
+              blue: blue.clone(),
+            });
           }
           pipeline
             .connect_nodes(
@@ -401,14 +1214,14 @@ impl ProcessingConfig {
               node_id,
               "image".to_string(),
             )
-            .expect("Failed to connect blur node");
+            .expect("Failed to connect curves node");
           last_node_id = node_id;
         }
 
-        OperationType::Sharpen(amount) => {
-          let node_id = pipeline.add_node("Sharpen".to_string(), NodeType::Sharpen);
+        OperationType::Vibrance(amount) => {
+          let node_id = pipeline.add_node("Vibrance".to_string(), NodeType::Vibrance);
           if let Some(node) = pipeline.get_node_mut(node_id) {
-            node.set_params(NodeParams::Sharpen { amount: *amount });
+            node.set_params(NodeParams::Vibrance { amount: *amount });
           }
           pipeline
             .connect_nodes(
@@ -417,16 +1230,22 @@ impl ProcessingConfig {
               node_id,
               "image".to_string(),
             )
-            .expect("Failed to connect sharpen node");
+            .expect("Failed to connect vibrance node");
           last_node_id = node_id;
         }
 
-        OperationType::Noise(amount) => {
-          let node_id = pipeline.add_node("Noise".to_string(), NodeType::Noise);
+        OperationType::ColorBalance {
+          shadows,
+          midtones,
+          highlights,
+        } => {
+          let node_id =
+            pipeline.add_node("ColorBalance".to_string(), NodeType::ColorBalance);
           if let Some(node) = pipeline.get_node_mut(node_id) {
-            node.set_params(NodeParams::Noise {
-              amount: *amount,
-              seed: 42,
+            node.set_params(NodeParams::ColorBalance {
+              shadows: *shadows,
+              midtones: *midtones,
+              highlights: *highlights,
             });
           }
           pipeline
@@ -436,16 +1255,26 @@ impl ProcessingConfig {
               node_id,
               "image".to_string(),
             )
-            .expect("Failed to connect noise node");
+            .expect("Failed to connect colour balance node");
           last_node_id = node_id;
         }
 
-        OperationType::Resize { width, height } => {
-          let node_id = pipeline.add_node("Resize".to_string(), NodeType::Resize);
+        OperationType::ColorGrading {
+          shadows,
+          midtones,
+          highlights,
+          balance,
+          blending,
+        } => {
+          let node_id =
+            pipeline.add_node("ColorGrading".to_string(), NodeType::ColorGrading);
           if let Some(node) = pipeline.get_node_mut(node_id) {
-            node.set_params(NodeParams::Resize {
-              width: *width,
-              height: *height,
+            node.set_params(NodeParams::ColorGrading {
+              shadows: *shadows,
+              midtones: *midtones,
+              highlights: *highlights,
+              balance: *balance,
+              blending: *blending,
             });
           }
           pipeline
@@ -455,19 +1284,533 @@ impl ProcessingConfig {
               node_id,
               "image".to_string(),
             )
-            .expect("Failed to connect resize node");
+            .expect("Failed to connect colour grading node");
           last_node_id = node_id;
         }
-      }
-    }
 
-    // Add output node
-    let output_id = pipeline.add_node("Output".to_string(), NodeType::ImageOutput);
-    pipeline
-      .connect_nodes(
-        last_node_id,
-        "image".to_string(),
-        output_id,
+        OperationType::WhiteBalance {
+          auto_adjust,
+          temperature,
+          tint,
+          method,
+          neutral_point,
+          kelvin,
+        } => {
+          let node_id =
+            pipeline.add_node("WhiteBalance".to_string(), NodeType::WhiteBalance);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            let temp = temperature.unwrap_or(0.0);
+            let tint_val = tint.unwrap_or(0.0);
+            node.set_params(NodeParams::WhiteBalance {
+              auto_adjust: *auto_adjust,
+              temperature: temp,
+              tint: tint_val,
+              method: *method,
+              neutral_point: *neutral_point,
+              kelvin: *kelvin,
+              as_shot_kelvin: None,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect white balance node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Lut3D {
+          path,
+          intensity,
+          interpolation,
+        } => {
+          let node_id = pipeline.add_node("Lut3D".to_string(), NodeType::Lut3D);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Lut3D {
+              path: path.clone(),
+              intensity: *intensity,
+              interpolation: *interpolation,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect LUT node");
+          last_node_id = node_id;
+        }
+
+        OperationType::ToneMap {
+          operator,
+          white_point,
+          contrast,
+        } => {
+          let node_id = pipeline.add_node("ToneMap".to_string(), NodeType::ToneMap);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::ToneMap {
+              operator: *operator,
+              white_point: *white_point,
+              contrast: *contrast,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect tone map node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Denoise {
+          luminance,
+          chroma,
+          radius,
+        } => {
+          let node_id = pipeline.add_node("Denoise".to_string(), NodeType::Denoise);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Denoise {
+              luminance: *luminance,
+              chroma: *chroma,
+              radius: *radius,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect denoise node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Blur(radius) => {
+          let node_id = pipeline.add_node("Blur".to_string(), NodeType::Blur);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Blur { radius: *radius });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect blur node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Sharpen(amount) => {
+          let node_id = pipeline.add_node("Sharpen".to_string(), NodeType::Sharpen);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Sharpen { amount: *amount });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect sharpen node");
+          last_node_id = node_id;
+        }
+
+        OperationType::UnsharpMask {
+          amount,
+          radius,
+          threshold,
+        } => {
+          let node_id =
+            pipeline.add_node("UnsharpMask".to_string(), NodeType::UnsharpMask);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::UnsharpMask {
+              amount: *amount,
+              radius: *radius,
+              threshold: *threshold,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect unsharp mask node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Clarity { amount, radius } => {
+          let node_id = pipeline.add_node("Clarity".to_string(), NodeType::Clarity);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Clarity {
+              amount: *amount,
+              radius: *radius,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect clarity node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Noise(amount) => {
+          let node_id = pipeline.add_node("Noise".to_string(), NodeType::Noise);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Noise {
+              amount: *amount,
+              seed: 42,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect noise node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Vignette {
+          amount,
+          midpoint,
+          roundness,
+          feather,
+        } => {
+          let node_id = pipeline.add_node("Vignette".to_string(), NodeType::Vignette);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Vignette {
+              amount: *amount,
+              midpoint: *midpoint,
+              roundness: *roundness,
+              feather: *feather,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect vignette node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Grain { amount, size, seed } => {
+          let node_id = pipeline.add_node("Grain".to_string(), NodeType::Grain);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Grain {
+              amount: *amount,
+              size: *size,
+              seed: *seed,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect grain node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Dehaze(amount) => {
+          let node_id = pipeline.add_node("Dehaze".to_string(), NodeType::Dehaze);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Dehaze { amount: *amount });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect dehaze node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Resize { width, height } => {
+          let node_id = pipeline.add_node("Resize".to_string(), NodeType::Resize);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Resize {
+              width: *width,
+              height: *height,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect resize node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Crop {
+          x,
+          y,
+          width,
+          height,
+        } => {
+          let node_id = pipeline.add_node("Crop".to_string(), NodeType::Crop);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Crop {
+              x: *x,
+              y: *y,
+              width: *width,
+              height: *height,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect crop node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Rotate(quarter_turns) => {
+          let node_id = pipeline.add_node("Rotate".to_string(), NodeType::Rotate);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Rotate {
+              quarter_turns: *quarter_turns,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect rotate node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Flip {
+          horizontal,
+          vertical,
+        } => {
+          let node_id = pipeline.add_node("Flip".to_string(), NodeType::Flip);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Flip {
+              horizontal: *horizontal,
+              vertical: *vertical,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect flip node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Straighten {
+          angle,
+          crop,
+          resampling,
+        } => {
+          let node_id = pipeline.add_node("Straighten".to_string(), NodeType::Straighten);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Straighten {
+              angle: *angle,
+              crop: *crop,
+              resampling: *resampling,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect straighten node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Perspective {
+          corners,
+          resampling,
+        } => {
+          let node_id =
+            pipeline.add_node("Perspective".to_string(), NodeType::Perspective);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Perspective {
+              corners: *corners,
+              resampling: *resampling,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect perspective node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Invert => {
+          let node_id = pipeline.add_node("Invert".to_string(), NodeType::Invert);
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect invert node");
+          last_node_id = node_id;
+        }
+
+        OperationType::Mix(factor) => {
+          let node_id = pipeline.add_node("Mix".to_string(), NodeType::Mix);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Mix {
+              factor: *factor,
+              use_alpha: false,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              base_node_id,
+              "image".to_string(),
+              node_id,
+              "image1".to_string(),
+            )
+            .expect("Failed to connect mix node");
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              node_id,
+              "image2".to_string(),
+            )
+            .expect("Failed to connect mix node");
+          last_node_id = node_id;
+          base_node_id = node_id;
+        }
+
+        OperationType::Mask { generator, invert } => {
+          // The mask is generated from the image before the adjustments it limits
+          let generator_type = generator
+            .mask_generator()
+            .expect("Mask operation without a mask generator");
+          let generator_id =
+            pipeline.add_node(format!("{:?}", generator_type), generator_type);
+          if let Some(node) = pipeline.get_node_mut(generator_id) {
+            node.set_params(generator.clone());
+          }
+          pipeline
+            .connect_nodes(
+              base_node_id,
+              "image".to_string(),
+              generator_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect mask generator node");
+          let mut mask_output = (generator_id, "mask");
+          if *invert {
+            let invert_id =
+              pipeline.add_node("Invert Mask".to_string(), NodeType::Invert);
+            pipeline
+              .connect_nodes(
+                generator_id,
+                "mask".to_string(),
+                invert_id,
+                "image".to_string(),
+              )
+              .expect("Failed to connect mask inversion node");
+            mask_output = (invert_id, "image");
+          }
+
+          let mask_id = pipeline.add_node("Mask".to_string(), NodeType::Mask);
+          pipeline
+            .connect_nodes(
+              last_node_id,
+              "image".to_string(),
+              mask_id,
+              "image".to_string(),
+            )
+            .expect("Failed to connect mask node");
+          pipeline
+            .connect_nodes(
+              mask_output.0,
+              mask_output.1.to_string(),
+              mask_id,
+              "mask".to_string(),
+            )
+            .expect("Failed to connect mask node");
+
+          // Blend the masked adjustments over the image they were made to
+          let node_id = pipeline.add_node("Masked Blend".to_string(), NodeType::Mix);
+          if let Some(node) = pipeline.get_node_mut(node_id) {
+            node.set_params(NodeParams::Mix {
+              factor: 1.0,
+              use_alpha: true,
+            });
+          }
+          pipeline
+            .connect_nodes(
+              base_node_id,
+              "image".to_string(),
+              node_id,
+              "image1".to_string(),
+            )
+            .expect("Failed to connect masked blend node");
+          pipeline
+            .connect_nodes(mask_id, "image".to_string(), node_id, "image2".to_string())
+            .expect("Failed to connect masked blend node");
+          last_node_id = node_id;
+          base_node_id = node_id;
+        }
+      }
+
+      if operation.op_type.is_geometric() {
+        base_node_id = last_node_id;
+      }
+    }
+
+    // Add output node
+    let output_id = pipeline.add_node("Output".to_string(), NodeType::ImageOutput);
+    pipeline
+      .connect_nodes(
+        last_node_id,
+        "image".to_string(),
+        output_id,
         "image".to_string(),
       )
       .expect("Failed to connect output node");
@@ -484,27 +1827,131 @@ impl ProcessingConfig {
     if let Some(output) = &self.output_path {
       eprintln!("Output: {}", output.display());
     }
+    eprintln!(
+      "Colour:  input {}, working {}, output {}",
+      self
+        .color
+        .input_space
+        .map_or("from file".to_string(), |space| space.to_string()),
+      self.color.working_space,
+      self
+        .color
+        .output_space
+        .map_or("from format".to_string(), |space| space.to_string())
+    );
     eprintln!();
 
-    if self.pipeline_config.operations.is_empty() {
+    if let Some(graph) = &self.save_graph_path {
+      eprintln!("Saving node graph to: {}", graph.display());
+    }
+    if let Some(graph) = &self.graph_path {
+      eprintln!("Node graph: {}", graph.display());
+    } else if self.pipeline_config.operations.is_empty() {
       eprintln!("No operations specified - image will be passed through unchanged.");
     } else {
       eprintln!("Operations to apply (in command-line order):");
       for (i, operation) in self.pipeline_config.operations.iter().enumerate() {
         let description = match &operation.op_type {
+          OperationType::Exposure(stops) => format!("Exposure: {:+.2} EV", stops),
           OperationType::Brightness(value) => format!("Brightness: {:.2}", value),
           OperationType::Contrast(value) => format!("Contrast: {:.2}", value),
           OperationType::Saturation(value) => format!("Saturation: {:.2}", value),
+          OperationType::Vibrance(amount) => format!("Vibrance: {:+.2}", amount),
           OperationType::Hue(value) => format!("Hue: {:.2}°", value),
+          OperationType::Hsl(bands) => {
+            let changed = HslBand::ALL
+              .into_iter()
+              .zip(bands.to_array())
+              .filter(|(_, adjustment)| *adjustment != HslAdjustment::default())
+              .map(|(band, adjustment)| {
+                format!(
+                  "{} {:+.1}°/{:+.2}/{:+.2}",
+                  band, adjustment.hue, adjustment.saturation, adjustment.luminance
+                )
+              })
+              .collect::<Vec<_>>();
+            format!("HSL ({})", changed.join(", "))
+          }
           OperationType::Gamma(value) => format!("Gamma: {:.2}", value),
+          OperationType::Levels {
+            input_black,
+            input_white,
+            output_black,
+            output_white,
+          } => format!(
+            "Levels: input {:.2} to {:.2}, output {:.2} to {:.2}",
+            input_black, input_white, output_black, output_white
+          ),
+          OperationType::Curves {
+            master,
+            red,
+            green,
+            blue,
+          } => {
+            let channels = [
+              ("Master", master),
+              ("Red", red),
+              ("Green", green),
+              ("Blue", blue),
+            ]
+            .into_iter()
+            .filter(|(_, points)| !points.is_empty())
+            .map(|(name, points)| format!("{}: {} points", name, points.len()))
+            .collect::<Vec<_>>();
+            format!("Curves ({})", channels.join(", "))
+          }
+          OperationType::ColorBalance {
+            shadows,
+            midtones,
+            highlights,
+          } => format!(
+            "Colour Balance: shadows {:?}, midtones {:?}, highlights {:?}",
+            shadows, midtones, highlights
+          ),
+          OperationType::ColorGrading {
+            shadows,
+            midtones,
+            highlights,
+            balance,
+            blending,
+          } => {
+            let wheels = [
+              ("shadows", shadows),
+              ("midtones", midtones),
+              ("highlights", highlights),
+            ]
+            .into_iter()
+            .filter(|(_, wheel)| **wheel != GradingWheel::default())
+            .map(|(name, wheel)| {
+              format!(
+                "{} {:.0}°/{:.2}/{:+.2}",
+                name, wheel.hue, wheel.saturation, wheel.luminance
+              )
+            })
+            .collect::<Vec<_>>();
+            format!(
+              "Colour Grading ({}), balance {:+.2}, blending {:.2}",
+              wheels.join(", "),
+              balance,
+              blending
+            )
+          }
           OperationType::WhiteBalance {
             auto_adjust,
             temperature,
             tint,
+            method,
+            neutral_point,
+            kelvin,
           } => {
             let mut parts = Vec::new();
-            if *auto_adjust {
-              parts.push("Auto".to_string());
+            if let Some([x, y]) = neutral_point {
+              parts.push(format!("Neutral point: {:.2}:{:.2}", x, y));
+            } else if *auto_adjust {
+              parts.push(format!("Auto ({})", method));
+            }
+            if let Some(kelvin) = kelvin {
+              parts.push(format!("{:.0}K", kelvin));
             }
             if let Some(temp) = temperature {
               parts.push(format!("Temperature: {:.2}", temp));
@@ -514,15 +1961,106 @@ impl ProcessingConfig {
             }
             format!("White Balance ({})", parts.join(", "))
           }
+          OperationType::Lut3D {
+            path,
+            intensity,
+            interpolation,
+          } => format!(
+            "3D LUT: {} ({:?}, intensity {:.2})",
+            path.display(),
+            interpolation,
+            intensity
+          ),
+          OperationType::ToneMap {
+            operator,
+            white_point,
+            contrast,
+          } => format!(
+            "Tone Map: {:?} (white point {:.2}, contrast {:.2})",
+            operator, white_point, contrast
+          ),
+          OperationType::Denoise {
+            luminance,
+            chroma,
+            radius,
+          } => format!(
+            "Denoise: luminance {:.2}, chroma {:.2} (radius {:.2}px)",
+            luminance, chroma, radius
+          ),
           OperationType::Blur(radius) => format!("Blur: {:.2}px", radius),
           OperationType::Sharpen(amount) => format!("Sharpen: {:.2}", amount),
+          OperationType::UnsharpMask {
+            amount,
+            radius,
+            threshold,
+          } => format!(
+            "Unsharp Mask: {:.2} (radius {:.2}px, threshold {:.2})",
+            amount, radius, threshold
+          ),
+          OperationType::Clarity { amount, radius } => {
+            format!("Clarity: {:.2} (radius {:.2}px)", amount, radius)
+          }
           OperationType::Noise(amount) => format!("Noise: {:.2}", amount),
+          OperationType::Vignette {
+            amount,
+            midpoint,
+            roundness,
+            feather,
+          } => format!(
+            "Vignette: {:.2} (midpoint {:.2}, roundness {:.2}, feather {:.2})",
+            amount, midpoint, roundness, feather
+          ),
+          OperationType::Grain { amount, size, seed } => {
+            format!("Grain: {:.2} (size {:.2}, seed {})", amount, size, seed)
+          }
+          OperationType::Dehaze(amount) => format!("Dehaze: {:.2}", amount),
           OperationType::Resize { width, height } => match (width, height) {
             (Some(w), Some(h)) => format!("Resize: {}x{}", w, h),
             (Some(w), None) => format!("Resize: {}x? (maintain aspect)", w),
             (None, Some(h)) => format!("Resize: ?x{} (maintain aspect)", h),
             (None, None) => "Resize: no change".to_string(),
           },
+          OperationType::Crop {
+            x,
+            y,
+            width,
+            height,
+          } => format!("Crop: {}x{} at {},{}", width, height, x, y),
+          OperationType::Rotate(quarter_turns) => {
+            format!("Rotate: {} degrees clockwise", quarter_turns * 90)
+          }
+          OperationType::Flip {
+            horizontal,
+            vertical,
+          } => match (horizontal, vertical) {
+            (true, true) => "Flip: horizontal and vertical".to_string(),
+            (true, false) => "Flip: horizontal".to_string(),
+            (false, true) => "Flip: vertical".to_string(),
+            (false, false) => "Flip: no change".to_string(),
+          },
+          OperationType::Straighten {
+            angle,
+            crop,
+            resampling,
+          } => format!(
+            "Straighten: {:.2} degrees ({}, {:?})",
+            angle,
+            if *crop { "cropped" } else { "expanded" },
+            resampling
+          ),
+          OperationType::Perspective {
+            corners,
+            resampling,
+          } => format!("Perspective: {:?} ({:?})", corners, resampling),
+          OperationType::Invert => "Invert".to_string(),
+          OperationType::Mix(factor) => {
+            format!("Mix: {:.2} of the adjustments above", factor)
+          }
+          OperationType::Mask { generator, invert } => format!(
+            "Mask: adjustments above limited to {}{:?}",
+            if *invert { "inverted " } else { "" },
+            generator
+          ),
         };
         eprintln!("  {}. {}", i + 1, description);
       }
@@ -550,6 +2088,30 @@ fn build_cli() -> Command {
                 .required(false)
                 .value_parser(value_parser!(PathBuf)),
         )
+        .arg(
+            Arg::new("graph")
+                .long("graph")
+                .value_name("FILE")
+                .help("Path to a JSON node graph describing the whole pipeline")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("save-graph")
+                .long("save-graph")
+                .value_name("FILE")
+                .help("Write the pipeline to a JSON node graph that --graph can load")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("shader-dir")
+                .long("shader-dir")
+                .value_name("DIR")
+                .help("Directory of .wgsl shaders for custom graph nodes (default shade/shaders in the user config directory)")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
         .arg(
             Arg::new("input")
                 .short('i')
@@ -568,6 +2130,13 @@ fn build_cli() -> Command {
                 .required(false)
                 .value_parser(value_parser!(PathBuf)),
         )
+        .arg(
+            Arg::new("exposure")
+                .long("exposure")
+                .value_name("STOPS")
+                .help("Adjust exposure in stops on linear light (0.0 = no change)")
+                .value_parser(value_parser!(f32)),
+        )
         .arg(
             Arg::new("brightness")
                 .short('b')
@@ -592,6 +2161,13 @@ fn build_cli() -> Command {
                 .help("Adjust saturation (0.0 to 2.0, 1.0 = no change)")
                 .value_parser(value_parser!(f32)),
         )
+        .arg(
+            Arg::new("vibrance")
+                .long("vibrance")
+                .value_name("AMOUNT")
+                .help("Boost muted colours more than saturated ones, sparing skin tones (-1.0 to 1.0, 0.0 = no change)")
+                .value_parser(value_parser!(f32)),
+        )
         .arg(
             Arg::new("hue")
                 .short('u')
@@ -600,6 +2176,14 @@ fn build_cli() -> Command {
                 .help("Adjust hue (-180.0 to 180.0 degrees, 0.0 = no change)")
                 .value_parser(value_parser!(f32)),
         )
+        .arg(
+            Arg::new("hsl")
+                .long("hsl")
+                .value_name("BAND:H,S,L")
+                .help("Adjust one hue band: red, orange, yellow, green, aqua, blue, purple or magenta, with hue shift in degrees, saturation and luminance (-1.0 to 1.0), e.g. blue:-10,-0.3,0.1. Repeat for more bands")
+                .action(clap::ArgAction::Append)
+                .value_parser(hsl::parse_band),
+        )
         .arg(
             Arg::new("gamma")
                 .short('g')
@@ -608,6 +2192,112 @@ fn build_cli() -> Command {
                 .help("Adjust gamma (0.1 to 3.0, 1.0 = no change)")
                 .value_parser(value_parser!(f32)),
         )
+        .arg(
+            Arg::new("levels")
+                .long("levels")
+                .value_name("BLACK:WHITE[:BLACK:WHITE]")
+                .help("Map input black and white points to 0.0 and 1.0, or to the output black and white points that follow, e.g. 0.05:0.9 or 0.05:0.9:0.02:1.0")
+                .value_parser(parse_levels),
+        )
+        .arg(
+            Arg::new("curve")
+                .long("curve")
+                .value_name("POINTS")
+                .help("Apply a master tone curve through x:y control points (e.g. 0:0,0.5:0.6,1:1)")
+                .value_parser(curves::parse_points),
+        )
+        .arg(
+            Arg::new("curve-red")
+                .long("curve-red")
+                .value_name("POINTS")
+                .help("Apply a tone curve to the red channel (x:y control points)")
+                .value_parser(curves::parse_points),
+        )
+        .arg(
+            Arg::new("curve-green")
+                .long("curve-green")
+                .value_name("POINTS")
+                .help("Apply a tone curve to the green channel (x:y control points)")
+                .value_parser(curves::parse_points),
+        )
+        .arg(
+            Arg::new("curve-blue")
+                .long("curve-blue")
+                .value_name("POINTS")
+                .help("Apply a tone curve to the blue channel (x:y control points)")
+                .value_parser(curves::parse_points),
+        )
+        .arg(
+            Arg::new("color-balance-shadows")
+                .long("color-balance-shadows")
+                .value_name("R:G:B")
+                .help("Colour balance gains added to 1.0 in the shadows, e.g. 0:0:0.1 for bluer shadows")
+                .value_parser(parse_numbers::<3>),
+        )
+        .arg(
+            Arg::new("color-balance-midtones")
+                .long("color-balance-midtones")
+                .value_name("R:G:B")
+                .help("Colour balance gains added to 1.0 in the midtones")
+                .value_parser(parse_numbers::<3>),
+        )
+        .arg(
+            Arg::new("color-balance-highlights")
+                .long("color-balance-highlights")
+                .value_name("R:G:B")
+                .help("Colour balance gains added to 1.0 in the highlights")
+                .value_parser(parse_numbers::<3>),
+        )
+        .arg(
+            Arg::new("grade-shadows")
+                .long("grade-shadows")
+                .value_name("HUE:SAT[:LUM]")
+                .help("Tint the shadows towards a hue in degrees with a strength (0.0 to 1.0) and shift their luminance (-1.0 to 1.0), e.g. 210:0.3 for teal shadows")
+                .value_parser(grading::parse_wheel),
+        )
+        .arg(
+            Arg::new("grade-midtones")
+                .long("grade-midtones")
+                .value_name("HUE:SAT[:LUM]")
+                .help("Tint the midtones and shift their luminance, like --grade-shadows")
+                .value_parser(grading::parse_wheel),
+        )
+        .arg(
+            Arg::new("grade-highlights")
+                .long("grade-highlights")
+                .value_name("HUE:SAT[:LUM]")
+                .help("Tint the highlights and shift their luminance, like --grade-shadows")
+                .value_parser(grading::parse_wheel),
+        )
+        .arg(
+            Arg::new("grade-balance")
+                .long("grade-balance")
+                .value_name("VALUE")
+                .help("Move the split between the graded ranges, positive values widen the highlights (-1.0 to 1.0, default 0.0)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("grade-blending")
+                .long("grade-blending")
+                .value_name("VALUE")
+                .help("Overlap between the graded ranges (0.0 to 1.0, default 0.5)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("denoise")
+                .long("denoise")
+                .value_name("LUMINANCE:CHROMA")
+                .help("Reduce luminance and colour noise while keeping edges (0.0 to 1.0 each)")
+                .value_parser(parse_numbers::<2>),
+        )
+        .arg(
+            Arg::new("denoise-radius")
+                .long("denoise-radius")
+                .value_name("PIXELS")
+                .help("Radius of the area noise is averaged over (default 2.0)")
+                .requires("denoise")
+                .value_parser(value_parser!(f32)),
+        )
         .arg(
             Arg::new("blur")
                 .long("blur")
@@ -622,6 +2312,44 @@ fn build_cli() -> Command {
                 .help("Apply sharpen filter (0.0 to 2.0)")
                 .value_parser(value_parser!(f32)),
         )
+        .arg(
+            Arg::new("unsharp")
+                .long("unsharp")
+                .value_name("AMOUNT")
+                .help("Sharpen with an unsharp mask (1.0 adds the full detail)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("unsharp-radius")
+                .long("unsharp-radius")
+                .value_name("PIXELS")
+                .help("Blur radius of the unsharp mask (default 1.0)")
+                .requires("unsharp")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("unsharp-threshold")
+                .long("unsharp-threshold")
+                .value_name("VALUE")
+                .help("Smallest difference the unsharp mask sharpens (0.0 to 1.0, default 0.0)")
+                .requires("unsharp")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("clarity")
+                .long("clarity")
+                .value_name("AMOUNT")
+                .help("Add midtone local contrast (-1.0 to 1.0, negative values soften)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("clarity-radius")
+                .long("clarity-radius")
+                .value_name("PIXELS")
+                .help("Radius of the local contrast, small values enhance texture (default 40.0)")
+                .requires("clarity")
+                .value_parser(value_parser!(f32)),
+        )
         .arg(
             Arg::new("noise")
                 .long("noise")
@@ -629,6 +2357,67 @@ fn build_cli() -> Command {
                 .help("Add noise (0.0 to 1.0)")
                 .value_parser(value_parser!(f32)),
         )
+        .arg(
+            Arg::new("vignette")
+                .long("vignette")
+                .value_name("AMOUNT")
+                .help("Darken (negative) or brighten the corners by up to two stops (-1.0 to 1.0)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("vignette-midpoint")
+                .long("vignette-midpoint")
+                .value_name("VALUE")
+                .help("Distance from the centre where the vignette reaches half strength, as a fraction of the way to the corners (default 0.5)")
+                .requires("vignette")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("vignette-roundness")
+                .long("vignette-roundness")
+                .value_name("VALUE")
+                .help("Vignette shape from a rounded rectangle (-1.0) over the frame's ellipse (0.0) to a circle (1.0)")
+                .requires("vignette")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("vignette-feather")
+                .long("vignette-feather")
+                .value_name("VALUE")
+                .help("Width of the vignette transition (0.0 to 1.0, default 0.5)")
+                .requires("vignette")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("grain")
+                .long("grain")
+                .value_name("AMOUNT")
+                .help("Add film grain, strongest in the midtones (0.0 to 1.0)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("grain-size")
+                .long("grain-size")
+                .value_name("SIZE")
+                .help("Grain size in thousandths of the shorter image side (default 1.0)")
+                .requires("grain")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("grain-seed")
+                .long("grain-seed")
+                .value_name("SEED")
+                .help("Seed of the grain pattern (default 42)")
+                .requires("grain")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("dehaze")
+                .long("dehaze")
+                .value_name("AMOUNT")
+                .help("Remove haze (0.0 to 1.0), negative values add haze")
+                .value_parser(value_parser!(f32)),
+        )
 
 
         .arg(
@@ -638,33 +2427,203 @@ fn build_cli() -> Command {
                 .action(clap::ArgAction::SetTrue),
         )
         .arg(
-            Arg::new("wb-temperature")
-                .long("wb-temperature")
-                .value_name("VALUE")
-                .help("Manual white balance temperature (-1.0 to 1.0, 0.0 = no change)")
-                .value_parser(value_parser!(f32)),
+            Arg::new("wb-temperature")
+                .long("wb-temperature")
+                .value_name("VALUE")
+                .help("Manual white balance temperature (-1.0 to 1.0, 0.0 = no change)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("wb-tint")
+                .long("wb-tint")
+                .value_name("VALUE")
+                .help("Manual white balance tint (-1.0 to 1.0, 0.0 = no change)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("wb-method")
+                .long("wb-method")
+                .value_name("METHOD")
+                .help("Automatic white balance estimate: grey_world (default) or white_patch")
+                .value_parser(value_parser!(WhiteBalanceMethod)),
+        )
+        .arg(
+            Arg::new("wb-neutral")
+                .long("wb-neutral")
+                .value_name("X:Y")
+                .help("Make the colour at a point grey, as fractions of width and height (0.0 to 1.0)")
+                .value_parser(parse_numbers::<2>),
+        )
+        .arg(
+            Arg::new("wb-kelvin")
+                .long("wb-kelvin")
+                .value_name("KELVIN")
+                .help("Manual white balance for light of this colour temperature, e.g. 3200 for tungsten")
+                .value_parser(value_parser!(f32)),
+        )
+
+        .arg(
+            Arg::new("lut")
+                .long("lut")
+                .value_name("FILE")
+                .help("Apply a 3D LUT from a .cube or .3dl file")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("lut-intensity")
+                .long("lut-intensity")
+                .value_name("VALUE")
+                .help("Blend the LUT with the original image (0.0 to 1.0, default 1.0)")
+                .requires("lut")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("lut-interpolation")
+                .long("lut-interpolation")
+                .value_name("MODE")
+                .help("LUT interpolation: trilinear (default) or tetrahedral")
+                .requires("lut")
+                .value_parser(value_parser!(LutInterpolation)),
+        )
+        .arg(
+            Arg::new("tone-map")
+                .long("tone-map")
+                .value_name("OPERATOR")
+                .help("Map scene-linear HDR values to display values: reinhard, filmic or aces")
+                .value_parser(value_parser!(ToneMapOperator)),
+        )
+        .arg(
+            Arg::new("tone-map-white")
+                .long("tone-map-white")
+                .value_name("VALUE")
+                .help("Scene value that maps to display white (default 16.0)")
+                .requires("tone-map")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("tone-map-contrast")
+                .long("tone-map-contrast")
+                .value_name("VALUE")
+                .help("Contrast around middle grey before tone mapping (default 1.0)")
+                .requires("tone-map")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("resize-width")
+                .long("resize-width")
+                .value_name("PIXELS")
+                .help("Resize output width (pixels). Use with --resize-height or alone to maintain aspect ratio")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("resize-height")
+                .long("resize-height")
+                .value_name("PIXELS")
+                .help("Resize output height (pixels). Use with --resize-width or alone to maintain aspect ratio")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("crop")
+                .long("crop")
+                .value_name("X:Y:WIDTH:HEIGHT")
+                .help("Cut out a region in pixels, clamped to the image")
+                .value_parser(geometry::parse_crop),
+        )
+        .arg(
+            Arg::new("rotate")
+                .long("rotate")
+                .value_name("DEGREES")
+                .help("Rotate clockwise by a multiple of 90 degrees")
+                .value_parser(geometry::parse_quarter_turns),
+        )
+        .arg(
+            Arg::new("flip")
+                .long("flip")
+                .value_name("DIRECTION")
+                .help("Mirror the image: horizontal, vertical or both")
+                .value_parser(geometry::parse_flip),
+        )
+        .arg(
+            Arg::new("straighten")
+                .long("straighten")
+                .value_name("DEGREES")
+                .help("Rotate clockwise by any angle and crop to the original aspect ratio")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("straighten-expand")
+                .long("straighten-expand")
+                .help("Keep the whole straightened image with transparent corners instead of cropping")
+                .requires("straighten")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("perspective")
+                .long("perspective")
+                .value_name("CORNERS")
+                .help("Correct perspective by mapping four x:y corners (fractions of the image, clockwise from top left) to the whole image")
+                .value_parser(geometry::parse_corners),
+        )
+        .arg(
+            Arg::new("resampling")
+                .long("resampling")
+                .value_name("MODE")
+                .help("Resampling of --straighten and --perspective: bilinear (default) or bicubic")
+                .value_parser(value_parser!(Resampling)),
+        )
+        .arg(
+            Arg::new("invert")
+                .long("invert")
+                .help("Invert the colours")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("mix")
+                .long("mix")
+                .value_name("FACTOR")
+                .help("Blend the adjustments before it over the image at the previous mask, mix or geometric operation (0.0 = unchanged, 1.0 = adjusted)")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("mask-luminance")
+                .long("mask-luminance")
+                .value_name("LOW:HIGH")
+                .help("Limit the adjustments before it to pixels with a lightness between LOW and HIGH (0.0 to 1.0)")
+                .value_parser(|value: &str| parse_mask("luminance", value, None).map(|_| value.to_string())),
+        )
+        .arg(
+            Arg::new("mask-color")
+                .long("mask-color")
+                .value_name("HUE:RANGE")
+                .help("Limit the adjustments before it to colours within RANGE degrees of HUE")
+                .value_parser(|value: &str| parse_mask("color", value, None).map(|_| value.to_string())),
+        )
+        .arg(
+            Arg::new("mask-linear")
+                .long("mask-linear")
+                .value_name("X:Y:ANGLE")
+                .help("Limit the adjustments before it with a gradient through X:Y (fractions of the image) rising towards ANGLE degrees counterclockwise from the right")
+                .value_parser(|value: &str| parse_mask("linear", value, None).map(|_| value.to_string())),
         )
         .arg(
-            Arg::new("wb-tint")
-                .long("wb-tint")
-                .value_name("VALUE")
-                .help("Manual white balance tint (-1.0 to 1.0, 0.0 = no change)")
-                .value_parser(value_parser!(f32)),
+            Arg::new("mask-radial")
+                .long("mask-radial")
+                .value_name("X:Y:RADIUS")
+                .help("Limit the adjustments before it to a circle around X:Y with RADIUS relative to the shorter image side")
+                .value_parser(|value: &str| parse_mask("radial", value, None).map(|_| value.to_string())),
         )
-
         .arg(
-            Arg::new("resize-width")
-                .long("resize-width")
-                .value_name("PIXELS")
-                .help("Resize output width (pixels). Use with --resize-height or alone to maintain aspect ratio")
-                .value_parser(value_parser!(u32)),
+            Arg::new("mask-feather")
+                .long("mask-feather")
+                .value_name("AMOUNT")
+                .help("Softness of the mask edge (default 0.1 for luminance and colour masks, 0.5 of the gradient size for gradients)")
+                .value_parser(value_parser!(f32)),
         )
         .arg(
-            Arg::new("resize-height")
-                .long("resize-height")
-                .value_name("PIXELS")
-                .help("Resize output height (pixels). Use with --resize-width or alone to maintain aspect ratio")
-                .value_parser(value_parser!(u32)),
+            Arg::new("mask-invert")
+                .long("mask-invert")
+                .help("Apply the adjustments outside the mask instead")
+                .action(clap::ArgAction::SetTrue),
         )
         .arg(
             Arg::new("verbose")
@@ -685,6 +2644,68 @@ fn build_cli() -> Command {
                 .help("Show cache information (size, location)")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("input-space")
+                .long("input-space")
+                .value_name("SPACE")
+                .help("Colour space of the input image, overriding the one detected from the file: srgb, linear_srgb, acescg or prophoto")
+                .value_parser(value_parser!(ColorSpace)),
+        )
+        .arg(
+            Arg::new("working-space")
+                .long("working-space")
+                .value_name("SPACE")
+                .help("Linear colour space the pipeline works in: linear_srgb (default), acescg or prophoto")
+                .value_parser(value_parser!(ColorSpace)),
+        )
+        .arg(
+            Arg::new("output-space")
+                .long("output-space")
+                .value_name("SPACE")
+                .help("Colour space of the output image (default linear_srgb for .exr, srgb otherwise)")
+                .value_parser(value_parser!(ColorSpace)),
+        )
+        .arg(
+            Arg::new("raw-demosaic")
+                .long("raw-demosaic")
+                .value_name("ALGORITHM")
+                .help("Demosaicing of camera raw files: ppg (default, full resolution) or superpixel (half resolution, fast)")
+                .value_parser(value_parser!(DemosaicAlgorithm)),
+        )
+        .arg(
+            Arg::new("raw-highlights")
+                .long("raw-highlights")
+                .value_name("MODE")
+                .help("Clipped highlights of camera raw files: clip (default, white), unclip (keep the tint) or blend")
+                .value_parser(value_parser!(HighlightRecovery)),
+        )
+        .arg(
+            Arg::new("raw-black-level")
+                .long("raw-black-level")
+                .value_name("LEVEL")
+                .help("Override the sensor black level of camera raw files, in raw units")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("raw-white-level")
+                .long("raw-white-level")
+                .value_name("LEVEL")
+                .help("Override the sensor white level of camera raw files, in raw units")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("raw-matrix")
+                .long("raw-matrix")
+                .value_name("M1:...:M9")
+                .help("Custom input profile for camera raw files: matrix from white balanced camera RGB to linear Rec. 709, row by row, instead of the camera matrix")
+                .value_parser(parse_numbers::<9>),
+        )
+        .arg(
+            Arg::new("cpu")
+                .long("cpu")
+                .help("Process on the CPU instead of the GPU (used automatically when no GPU is available)")
+                .action(clap::ArgAction::SetTrue),
+        )
         .after_help(
             "EXAMPLES:\n    \
             Basic image processing:\n      \
@@ -805,6 +2826,49 @@ pub fn validate_config(config: &ProcessingConfig) -> Result<(), String> {
     }
   }
 
+  // A graph describes the whole pipeline, so it cannot be mixed with operations
+  if let Some(graph_path) = &config.graph_path {
+    if !graph_path.exists() {
+      return Err(format!(
+        "Graph file does not exist: {}",
+        graph_path.display()
+      ));
+    }
+    if !config.pipeline_config.operations.is_empty() {
+      return Err("--graph cannot be combined with operation flags".to_string());
+    }
+  }
+
+  if let Some(shader_dir) = &config.shader_dir {
+    if !shader_dir.is_dir() {
+      return Err(format!(
+        "Shader directory does not exist: {}",
+        shader_dir.display()
+      ));
+    }
+  }
+
+  if !config.color.working_space.is_linear() {
+    return Err(format!(
+      "Working space must be linear, got {}",
+      config.color.working_space
+    ));
+  }
+
+  config.raw.validate()?;
+
+  for operation in &config.pipeline_config.operations {
+    operation
+      .op_type
+      .validate()
+      .map_err(|e| format!("{}: {}", operation.op_type.name(), e))?;
+    if let OperationType::Lut3D { path, .. } = &operation.op_type {
+      if !path.exists() {
+        return Err(format!("LUT file does not exist: {}", path.display()));
+      }
+    }
+  }
+
   // Check input file format if input path exists
   if let Some(input_path) = &config.input_path {
     let path_str = input_path.to_string_lossy();
@@ -823,144 +2887,770 @@ pub fn validate_config(config: &ProcessingConfig) -> Result<(), String> {
         supported_formats.join(", ")
       ));
     }
-  }
-
-  Ok(())
-}
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::graph::{load_graph, save_graph};
+  use std::ffi::OsString;
+  use std::path::Path;
+
+  #[test]
+  fn test_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("-i"),
+      OsString::from("input.jpg"),
+      OsString::from("-o"),
+      OsString::from("output.jpg"),
+      OsString::from("--brightness"),
+      OsString::from("0.2"),
+      OsString::from("--contrast"),
+      OsString::from("1.1"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    assert_eq!(
+      config.input_path.clone().unwrap(),
+      PathBuf::from("input.jpg")
+    );
+    assert_eq!(
+      config.output_path.clone().unwrap(),
+      PathBuf::from("output.jpg")
+    );
+    // Check that operations are in the correct order
+    assert_eq!(config.pipeline_config.operations.len(), 2);
+    if let OperationType::Brightness(val) = config.pipeline_config.operations[0].op_type {
+      assert_eq!(val, 0.2);
+    } else {
+      panic!("Expected brightness operation first");
+    }
+    if let OperationType::Contrast(val) = config.pipeline_config.operations[1].op_type {
+      assert_eq!(val, 1.1);
+    } else {
+      panic!("Expected contrast operation second");
+    }
+  }
+
+  #[test]
+  fn test_pipeline_building() {
+    let config = ProcessingConfig {
+      input_path: Some(PathBuf::from("input.jpg")),
+      output_path: Some(PathBuf::from("output.jpg")),
+      pipeline_config: PipelineConfig {
+        operations: vec![
+          PipelineOperation {
+            op_type: OperationType::Brightness(0.2),
+            index: 0,
+          },
+          PipelineOperation {
+            op_type: OperationType::Contrast(1.1),
+            index: 1,
+          },
+          PipelineOperation {
+            op_type: OperationType::Saturation(1.3),
+            index: 2,
+          },
+        ],
+      },
+      verbose: false,
+      config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
+      clear_cache: false,
+      show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw: RawDevelopParams::default(),
+    };
+
+    let pipeline = config.build_pipeline();
+    assert_eq!(pipeline.nodes.len(), 5); // Input + Brightness + Contrast + Saturation + Output
+  }
+
+  #[test]
+  fn test_white_balance_cli_parsing() {
+    // Test auto white balance
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--input"),
+      OsString::from("input.jpg"),
+      OsString::from("--output"),
+      OsString::from("output.jpg"),
+      OsString::from("--auto-white-balance"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    // Check that white balance operation was added
+    assert_eq!(config.pipeline_config.operations.len(), 1);
+    if let OperationType::WhiteBalance {
+      auto_adjust,
+      temperature,
+      tint,
+      ..
+    } = &config.pipeline_config.operations[0].op_type
+    {
+      assert_eq!(*auto_adjust, true);
+      assert_eq!(*temperature, None);
+      assert_eq!(*tint, None);
+    } else {
+      panic!("Expected white balance operation");
+    }
+
+    // Test manual white balance
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--input"),
+      OsString::from("input.jpg"),
+      OsString::from("--output"),
+      OsString::from("output.jpg"),
+      OsString::from("--wb-temperature=0.3"),
+      OsString::from("--wb-tint=-0.1"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    // Check that white balance operation was added
+    assert_eq!(config.pipeline_config.operations.len(), 1);
+    if let OperationType::WhiteBalance {
+      auto_adjust,
+      temperature,
+      tint,
+      ..
+    } = &config.pipeline_config.operations[0].op_type
+    {
+      assert_eq!(*auto_adjust, false);
+      assert_eq!(*temperature, Some(0.3));
+      assert_eq!(*tint, Some(-0.1));
+    } else {
+      panic!("Expected white balance operation");
+    }
+
+    // Test white balance by method, neutral point and Kelvin
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--input"),
+      OsString::from("input.jpg"),
+      OsString::from("--output"),
+      OsString::from("output.jpg"),
+      OsString::from("--exposure"),
+      OsString::from("0.5"),
+      OsString::from("--wb-kelvin"),
+      OsString::from("3200"),
+      OsString::from("--wb-method"),
+      OsString::from("white_patch"),
+      OsString::from("--wb-neutral"),
+      OsString::from("0.25:0.75"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    // All white balance arguments form one operation, after the exposure
+    assert_eq!(config.pipeline_config.operations.len(), 2);
+    assert_eq!(
+      config.pipeline_config.operations[1].op_type.name(),
+      "white_balance"
+    );
+    if let OperationType::WhiteBalance {
+      auto_adjust,
+      method,
+      neutral_point,
+      kelvin,
+      ..
+    } = &config.pipeline_config.operations[1].op_type
+    {
+      assert!(*auto_adjust);
+      assert_eq!(*method, WhiteBalanceMethod::WhitePatch);
+      assert_eq!(*neutral_point, Some([0.25, 0.75]));
+      assert_eq!(*kelvin, Some(3200.0));
+    } else {
+      panic!("Expected white balance operation");
+    }
+  }
+
+  #[test]
+  fn test_curves_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--input"),
+      OsString::from("input.jpg"),
+      OsString::from("--brightness"),
+      OsString::from("0.1"),
+      OsString::from("--curve-blue"),
+      OsString::from("0:0.1,1:0.9"),
+      OsString::from("--curve"),
+      OsString::from("0:0,0.5:0.6,1:1"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    // All curve flags form one operation at the position of the first one
+    assert_eq!(config.pipeline_config.operations.len(), 2);
+    if let OperationType::Curves {
+      master,
+      red,
+      green,
+      blue,
+    } = &config.pipeline_config.operations[1].op_type
+    {
+      assert_eq!(master, &vec![[0.0, 0.0], [0.5, 0.6], [1.0, 1.0]]);
+      assert!(red.is_empty() && green.is_empty());
+      assert_eq!(blue, &vec![[0.0, 0.1], [1.0, 0.9]]);
+    } else {
+      panic!("Expected curves operation second");
+    }
+
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--curve"),
+      OsString::from("0:0;1:1"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
+  }
+
+  #[test]
+  fn test_lut_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--lut"),
+      OsString::from("look.cube"),
+      OsString::from("--lut-intensity"),
+      OsString::from("0.5"),
+      OsString::from("--lut-interpolation"),
+      OsString::from("tetrahedral"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    assert_eq!(config.pipeline_config.operations.len(), 1);
+    if let OperationType::Lut3D {
+      path,
+      intensity,
+      interpolation,
+    } = &config.pipeline_config.operations[0].op_type
+    {
+      assert_eq!(path, &PathBuf::from("look.cube"));
+      assert_eq!(*intensity, 0.5);
+      assert_eq!(*interpolation, LutInterpolation::Tetrahedral);
+    } else {
+      panic!("Expected LUT operation");
+    }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
-  use std::ffi::OsString;
+    // LUT options need a LUT file
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--lut-intensity"),
+      OsString::from("0.5"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
+  }
 
   #[test]
-  fn test_cli_parsing() {
+  fn test_hsl_cli_parsing() {
     let args = vec![
       OsString::from("shade"),
-      OsString::from("-i"),
-      OsString::from("input.jpg"),
-      OsString::from("-o"),
-      OsString::from("output.jpg"),
-      OsString::from("--brightness"),
-      OsString::from("0.2"),
       OsString::from("--contrast"),
       OsString::from("1.1"),
+      OsString::from("--hsl"),
+      OsString::from("blue:-10,-0.3,0.1"),
+      OsString::from("--hsl"),
+      OsString::from("orange:0,0.2"),
     ];
 
     let matches = build_cli().try_get_matches_from(args).unwrap();
     let config = ProcessingConfig::from_matches(matches).unwrap();
 
-    assert_eq!(
-      config.input_path.clone().unwrap(),
-      PathBuf::from("input.jpg")
-    );
-    assert_eq!(
-      config.output_path.clone().unwrap(),
-      PathBuf::from("output.jpg")
+    // All bands form one operation at the position of the first flag
+    assert_eq!(config.pipeline_config.operations.len(), 2);
+    if let OperationType::Hsl(bands) = &config.pipeline_config.operations[1].op_type {
+      assert_eq!(bands.blue.hue, -10.0);
+      assert_eq!(bands.blue.saturation, -0.3);
+      assert_eq!(bands.blue.luminance, 0.1);
+      assert_eq!(bands.orange.saturation, 0.2);
+      assert_eq!(bands.red, HslAdjustment::default());
+    } else {
+      panic!("Expected HSL operation second");
+    }
+
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--hsl"),
+      OsString::from("teal:10"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
+  }
+
+  #[test]
+  fn test_grading_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--vibrance"),
+      OsString::from("0.4"),
+      OsString::from("--grade-blending"),
+      OsString::from("0.8"),
+      OsString::from("--grade-shadows"),
+      OsString::from("210:0.3"),
+      OsString::from("--grade-highlights"),
+      OsString::from("40:0.2:0.1"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    // The wheels form one operation at the position of the first grading flag
+    let operations = &config.pipeline_config.operations;
+    assert_eq!(operations.len(), 2);
+    assert!(
+      matches!(operations[0].op_type, OperationType::Vibrance(amount) if amount == 0.4)
     );
-    // Check that operations are in the correct order
+    if let OperationType::ColorGrading {
+      shadows,
+      midtones,
+      highlights,
+      balance,
+      blending,
+    } = operations[1].op_type
+    {
+      assert_eq!((shadows.hue, shadows.saturation), (210.0, 0.3));
+      assert_eq!(midtones, GradingWheel::default());
+      assert_eq!(highlights.luminance, 0.1);
+      assert_eq!((balance, blending), (0.0, 0.8));
+    } else {
+      panic!("Expected colour grading operation second");
+    }
+
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--grade-midtones"),
+      OsString::from("210"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
+  }
+
+  #[test]
+  fn test_tone_map_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--exposure=-1.5"),
+      OsString::from("--tone-map"),
+      OsString::from("aces"),
+      OsString::from("--tone-map-white"),
+      OsString::from("8"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
     assert_eq!(config.pipeline_config.operations.len(), 2);
-    if let OperationType::Brightness(val) = config.pipeline_config.operations[0].op_type {
-      assert_eq!(val, 0.2);
+    if let OperationType::Exposure(stops) = config.pipeline_config.operations[0].op_type {
+      assert_eq!(stops, -1.5);
     } else {
-      panic!("Expected brightness operation first");
+      panic!("Expected exposure operation first");
     }
-    if let OperationType::Contrast(val) = config.pipeline_config.operations[1].op_type {
-      assert_eq!(val, 1.1);
+    if let OperationType::ToneMap {
+      operator,
+      white_point,
+      contrast,
+    } = &config.pipeline_config.operations[1].op_type
+    {
+      assert_eq!(*operator, ToneMapOperator::Aces);
+      assert_eq!(*white_point, 8.0);
+      assert_eq!(*contrast, 1.0);
     } else {
-      panic!("Expected contrast operation second");
+      panic!("Expected tone map operation");
     }
+
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--tone-map"),
+      OsString::from("hable"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
   }
 
   #[test]
-  fn test_pipeline_building() {
-    let config = ProcessingConfig {
-      input_path: Some(PathBuf::from("input.jpg")),
-      output_path: Some(PathBuf::from("output.jpg")),
-      pipeline_config: PipelineConfig {
-        operations: vec![
-          PipelineOperation {
-            op_type: OperationType::Brightness(0.2),
-            index: 0,
-          },
-          PipelineOperation {
-            op_type: OperationType::Contrast(1.1),
-            index: 1,
-          },
-          PipelineOperation {
-            op_type: OperationType::Saturation(1.3),
-            index: 2,
-          },
-        ],
-      },
-      verbose: false,
-      config_path: None,
-      clear_cache: false,
-      show_cache_info: false,
-    };
+  fn test_detail_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--clarity=-0.4"),
+      OsString::from("--unsharp"),
+      OsString::from("1.5"),
+      OsString::from("--unsharp-threshold"),
+      OsString::from("0.02"),
+      OsString::from("--denoise"),
+      OsString::from("0.3:0.6"),
+      OsString::from("--denoise-radius"),
+      OsString::from("3"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    let operations = &config.pipeline_config.operations;
+    assert_eq!(operations.len(), 3);
+    if let OperationType::Clarity { amount, radius } = operations[0].op_type {
+      assert_eq!(amount, -0.4);
+      assert_eq!(radius, 40.0);
+    } else {
+      panic!("Expected clarity operation first");
+    }
+    if let OperationType::UnsharpMask {
+      amount,
+      radius,
+      threshold,
+    } = operations[1].op_type
+    {
+      assert_eq!(amount, 1.5);
+      assert_eq!(radius, 1.0);
+      assert_eq!(threshold, 0.02);
+    } else {
+      panic!("Expected unsharp mask operation");
+    }
+    if let OperationType::Denoise {
+      luminance,
+      chroma,
+      radius,
+    } = operations[2].op_type
+    {
+      assert_eq!((luminance, chroma, radius), (0.3, 0.6, 3.0));
+    } else {
+      panic!("Expected denoise operation");
+    }
 
     let pipeline = config.build_pipeline();
-    assert_eq!(pipeline.nodes.len(), 5); // Input + Brightness + Contrast + Saturation + Output
+    assert_eq!(pipeline.nodes.len(), 5); // input + clarity + unsharp mask + denoise + output
   }
 
   #[test]
-  fn test_white_balance_cli_parsing() {
-    // Test auto white balance
+  fn test_effects_cli_parsing() {
     let args = vec![
       OsString::from("shade"),
-      OsString::from("--input"),
-      OsString::from("input.jpg"),
-      OsString::from("--output"),
-      OsString::from("output.jpg"),
-      OsString::from("--auto-white-balance"),
+      OsString::from("--dehaze"),
+      OsString::from("0.4"),
+      OsString::from("--vignette=-0.6"),
+      OsString::from("--vignette-roundness"),
+      OsString::from("1"),
+      OsString::from("--grain"),
+      OsString::from("0.3"),
+      OsString::from("--grain-size"),
+      OsString::from("2"),
     ];
 
     let matches = build_cli().try_get_matches_from(args).unwrap();
     let config = ProcessingConfig::from_matches(matches).unwrap();
 
-    // Check that white balance operation was added
-    assert_eq!(config.pipeline_config.operations.len(), 1);
-    if let OperationType::WhiteBalance {
-      auto_adjust,
-      temperature,
-      tint,
-    } = &config.pipeline_config.operations[0].op_type
+    let operations = &config.pipeline_config.operations;
+    assert_eq!(operations.len(), 3);
+    assert!(
+      matches!(operations[0].op_type, OperationType::Dehaze(amount) if amount == 0.4)
+    );
+    if let OperationType::Vignette {
+      amount,
+      midpoint,
+      roundness,
+      feather,
+    } = operations[1].op_type
     {
-      assert_eq!(*auto_adjust, true);
-      assert_eq!(*temperature, None);
-      assert_eq!(*tint, None);
+      assert_eq!(
+        (amount, midpoint, roundness, feather),
+        (-0.6, 0.5, 1.0, 0.5)
+      );
     } else {
-      panic!("Expected white balance operation");
+      panic!("Expected vignette operation second");
+    }
+    if let OperationType::Grain { amount, size, seed } = operations[2].op_type {
+      assert_eq!((amount, size, seed), (0.3, 2.0, 42));
+    } else {
+      panic!("Expected grain operation last");
     }
 
-    // Test manual white balance
     let args = vec![
       OsString::from("shade"),
-      OsString::from("--input"),
-      OsString::from("input.jpg"),
-      OsString::from("--output"),
-      OsString::from("output.jpg"),
-      OsString::from("--wb-temperature=0.3"),
-      OsString::from("--wb-tint=-0.1"),
+      OsString::from("--grain-size"),
+      OsString::from("2"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
+  }
+
+  #[test]
+  fn test_geometry_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--rotate=-90"),
+      OsString::from("--straighten"),
+      OsString::from("2.5"),
+      OsString::from("--perspective"),
+      OsString::from("0.1:0,0.9:0,1:1,0:1"),
+      OsString::from("--resampling"),
+      OsString::from("bicubic"),
+      OsString::from("--flip"),
+      OsString::from("horizontal"),
     ];
 
     let matches = build_cli().try_get_matches_from(args).unwrap();
     let config = ProcessingConfig::from_matches(matches).unwrap();
 
-    // Check that white balance operation was added
-    assert_eq!(config.pipeline_config.operations.len(), 1);
-    if let OperationType::WhiteBalance {
-      auto_adjust,
-      temperature,
-      tint,
-    } = &config.pipeline_config.operations[0].op_type
+    let operations = &config.pipeline_config.operations;
+    assert_eq!(operations.len(), 4);
+    assert!(matches!(operations[0].op_type, OperationType::Rotate(3)));
+    if let OperationType::Straighten {
+      angle,
+      crop,
+      resampling,
+    } = operations[1].op_type
     {
-      assert_eq!(*auto_adjust, false);
-      assert_eq!(*temperature, Some(0.3));
-      assert_eq!(*tint, Some(-0.1));
+      assert_eq!(angle, 2.5);
+      assert!(crop);
+      assert_eq!(resampling, Resampling::Bicubic);
     } else {
-      panic!("Expected white balance operation");
+      panic!("Expected straighten operation");
+    }
+    if let OperationType::Perspective {
+      corners,
+      resampling,
+    } = operations[2].op_type
+    {
+      assert_eq!(corners[0], [0.1, 0.0]);
+      assert_eq!(resampling, Resampling::Bicubic);
+    } else {
+      panic!("Expected perspective operation");
+    }
+    assert!(matches!(
+      operations[3].op_type,
+      OperationType::Flip {
+        horizontal: true,
+        vertical: false,
+      }
+    ));
+
+    let pipeline = config.build_pipeline();
+    assert_eq!(pipeline.nodes.len(), 6);
+
+    let args = ["shade", "--rotate", "45"].map(OsString::from);
+    assert!(build_cli().try_get_matches_from(args).is_err());
+  }
+
+  #[test]
+  fn test_compositing_cli_parsing() {
+    let args = [
+      "shade",
+      "--exposure",
+      "1.0",
+      "--mask-luminance",
+      "0.5:1",
+      "--mask-invert",
+      "--levels",
+      "0.05:0.9",
+      "--color-balance-shadows",
+      "0:0:0.1",
+      "--crop",
+      "10:20:300:200",
+      "--invert",
+      "--mix",
+      "0.5",
+    ]
+    .map(OsString::from);
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+    assert!(validate_config(&config).is_ok());
+
+    let operations: Vec<_> = config
+      .pipeline_config
+      .operations
+      .iter()
+      .map(|operation| operation.op_type.name())
+      .collect();
+    assert_eq!(
+      operations,
+      [
+        "exposure",
+        "mask",
+        "levels",
+        "color_balance",
+        "crop",
+        "invert",
+        "mix"
+      ]
+    );
+    let op_type = |i: usize| &config.pipeline_config.operations[i].op_type;
+    assert!(matches!(
+      op_type(1),
+      OperationType::Mask {
+        generator: NodeParams::LuminanceMask { .. },
+        invert: true,
+      }
+    ));
+    if let OperationType::Levels {
+      input_black,
+      input_white,
+      output_black,
+      output_white,
+    } = *op_type(2)
+    {
+      assert_eq!(
+        [input_black, input_white, output_black, output_white],
+        [0.05, 0.9, 0.0, 1.0]
+      );
+    } else {
+      panic!("Expected levels operation");
+    }
+    if let OperationType::ColorBalance {
+      shadows, midtones, ..
+    } = *op_type(3)
+    {
+      assert_eq!(shadows, [0.0, 0.0, 0.1]);
+      assert_eq!(midtones, [0.0; 3]);
+    } else {
+      panic!("Expected colour balance operation");
+    }
+    assert!(matches!(
+      op_type(4),
+      OperationType::Crop {
+        x: 10,
+        y: 20,
+        width: 300,
+        height: 200,
+      }
+    ));
+
+    // The mask adds a generator, its inversion, the mask and a blend
+    let pipeline = config.build_pipeline();
+    assert_eq!(pipeline.nodes.len(), 12);
+
+    // The mix blends over the cropped image rather than reaching back past the crop
+    let node_named = |name: &str| {
+      pipeline
+        .nodes
+        .values()
+        .find(|node| node.name == name)
+        .unwrap()
+        .id
+    };
+    assert!(pipeline.connections.iter().any(|connection| {
+      connection.from_node == node_named("Crop")
+        && connection.to_node == node_named("Mix")
+        && connection.to_input == "image1"
+    }));
+
+    for invalid in [
+      ["shade", "--levels", "0.9:0.1"],
+      ["shade", "--crop", "0:0:0:10"],
+      ["shade", "--mask-radial", "0.5:0.5"],
+    ] {
+      assert!(
+        build_cli()
+          .try_get_matches_from(invalid.map(OsString::from))
+          .is_err()
+      );
     }
+
+    let args = ["shade", "--mix", "1.5"].map(OsString::from);
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+    assert!(validate_config(&config).is_err());
+  }
+
+  #[test]
+  fn test_raw_develop_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--raw-demosaic"),
+      OsString::from("superpixel"),
+      OsString::from("--raw-highlights"),
+      OsString::from("blend"),
+      OsString::from("--raw-white-level"),
+      OsString::from("15000"),
+      OsString::from("--raw-matrix"),
+      OsString::from("1.6:-0.5:-0.1:-0.2:1.4:-0.2:0:-0.4:1.4"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    assert_eq!(config.raw.demosaic, DemosaicAlgorithm::Superpixel);
+    assert_eq!(config.raw.highlights, HighlightRecovery::Blend);
+    assert_eq!(config.raw.black_level, None);
+    assert_eq!(config.raw.white_level, Some(15000));
+    assert_eq!(
+      config.raw.input_profile,
+      InputProfile::Custom([[1.6, -0.5, -0.1], [-0.2, 1.4, -0.2], [0.0, -0.4, 1.4]])
+    );
+
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--raw-demosaic=ahd"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
+    // Overridden levels must leave a range to scale over
+    let args = [
+      "shade",
+      "--raw-black-level",
+      "16000",
+      "--raw-white-level",
+      "1000",
+    ]
+    .map(OsString::from);
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+    assert!(validate_config(&config).is_err());
+  }
+
+  #[test]
+  fn test_color_space_cli_parsing() {
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--input-space"),
+      OsString::from("linear_srgb"),
+      OsString::from("--working-space"),
+      OsString::from("acescg"),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+
+    assert_eq!(config.color.input_space, Some(ColorSpace::LinearSrgb));
+    assert_eq!(config.color.working_space, ColorSpace::AcesCg);
+    assert_eq!(config.color.output_space, None);
+    assert_eq!(
+      config.color.output_space_for(Some(Path::new("out.exr"))),
+      ColorSpace::LinearSrgb
+    );
+    assert_eq!(
+      config.color.output_space_for(Some(Path::new("out.png"))),
+      ColorSpace::Srgb
+    );
+    assert!(validate_config(&config).is_ok());
+
+    // The working space has to be linear
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--working-space"),
+      OsString::from("srgb"),
+    ];
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+    assert!(validate_config(&config).is_err());
+
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--output-space"),
+      OsString::from("rec2020"),
+    ];
+    assert!(build_cli().try_get_matches_from(args).is_err());
   }
 
   #[test]
@@ -975,14 +3665,23 @@ mod tests {
             auto_adjust: true,
             temperature: None,
             tint: None,
+            method: WhiteBalanceMethod::GreyWorld,
+            neutral_point: None,
+            kelvin: None,
           },
           index: 0,
         }],
       },
       verbose: false,
       config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
       clear_cache: false,
       show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw: RawDevelopParams::default(),
     };
 
     let pipeline = config.build_pipeline();
@@ -998,14 +3697,23 @@ mod tests {
             auto_adjust: false,
             temperature: Some(0.3),
             tint: Some(-0.1),
+            method: WhiteBalanceMethod::GreyWorld,
+            neutral_point: None,
+            kelvin: None,
           },
           index: 0,
         }],
       },
       verbose: false,
       config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
       clear_cache: false,
       show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw: RawDevelopParams::default(),
     };
 
     let pipeline = config.build_pipeline();
@@ -1084,8 +3792,14 @@ mod tests {
       },
       verbose: false,
       config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
       clear_cache: false,
       show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw: RawDevelopParams::default(),
     };
 
     assert!(validate_config(&config).is_ok());
@@ -1097,8 +3811,14 @@ mod tests {
       pipeline_config: PipelineConfig::default(),
       verbose: false,
       config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
       clear_cache: false,
       show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw: RawDevelopParams::default(),
     };
 
     assert!(validate_config(&config).is_ok());
@@ -1113,16 +3833,59 @@ mod tests {
             auto_adjust: false,
             temperature: Some(0.5),
             tint: Some(-0.3),
+            method: WhiteBalanceMethod::GreyWorld,
+            neutral_point: None,
+            kelvin: None,
           },
           index: 0,
         }],
       },
       verbose: false,
       config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
       clear_cache: false,
       show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw: RawDevelopParams::default(),
     };
 
     assert!(validate_config(&config).is_ok());
   }
+
+  #[test]
+  fn test_save_graph_cli() {
+    let graph_path = std::env::temp_dir().join("shade_test_save_graph_cli.json");
+    let args = vec![
+      OsString::from("shade"),
+      OsString::from("--blur"),
+      OsString::from("2.5"),
+      OsString::from("--save-graph"),
+      graph_path.clone().into_os_string(),
+    ];
+
+    let matches = build_cli().try_get_matches_from(args).unwrap();
+    let config = ProcessingConfig::from_matches(matches).unwrap();
+    assert_eq!(
+      config.save_graph_path.as_deref(),
+      Some(graph_path.as_path())
+    );
+
+    // The saved graph loads back into the pipeline built from the flags
+    let pipeline = config.build_pipeline();
+    save_graph(&pipeline, &graph_path).unwrap();
+    let restored = load_graph(&graph_path).unwrap();
+    std::fs::remove_file(&graph_path).unwrap();
+
+    assert_eq!(restored.nodes.len(), pipeline.nodes.len());
+    assert_eq!(restored.connections.len(), pipeline.connections.len());
+    assert!(
+      restored
+        .nodes
+        .values()
+        .any(|node| matches!(node.params, NodeParams::Blur { radius } if radius == 2.5))
+    );
+  }
 }
@@ -0,0 +1,337 @@
+//! Colour spaces and conversions between them
+//!
+//! Images are tagged with the colour space they were decoded in, converted into a
+//! linear working space before the graph runs and into an output space on export.
+//! Conversion matrices are derived from the primaries of each space and adapted to a
+//! D65 white with the Bradford transform, so any two spaces can be converted.
+//!
+//! Nodes declare whether they operate on linear light or on values encoded with the
+//! sRGB transfer curve. Encoded nodes keep the primaries of the working space and only
+//! see their inputs through the curve, which keeps adjustments like contrast and
+//! curves perceptually even.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+pub type Mat3 = [[f32; 3]; 3];
+
+/// A colour space pixel data can be tagged with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ColorSpace {
+  /// sRGB primaries with the sRGB transfer curve, as stored in most 8-bit files
+  #[serde(rename = "srgb")]
+  Srgb,
+  /// Linear light with Rec.709/sRGB primaries
+  #[serde(rename = "linear_srgb")]
+  LinearSrgb,
+  /// Linear light with ACES AP1 primaries
+  #[serde(rename = "acescg")]
+  AcesCg,
+  /// Linear light with ProPhoto (ROMM) primaries
+  #[serde(rename = "prophoto")]
+  ProPhoto,
+}
+
+impl ColorSpace {
+  /// Whether pixel values are proportional to light, so the space can be worked in
+  pub fn is_linear(self) -> bool {
+    self != ColorSpace::Srgb
+  }
+
+  /// Red, green and blue primaries and the white point as xy chromaticities
+  fn chromaticities(self) -> [[f32; 2]; 4] {
+    match self {
+      ColorSpace::Srgb | ColorSpace::LinearSrgb => {
+        [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06], D65]
+      }
+      ColorSpace::AcesCg => [
+        [0.713, 0.293],
+        [0.165, 0.830],
+        [0.128, 0.044],
+        [0.32168, 0.33767],
+      ],
+      ColorSpace::ProPhoto => [
+        [0.734699, 0.265301],
+        [0.159597, 0.840403],
+        [0.036598, 0.000105],
+        [0.3457, 0.3585],
+      ],
+    }
+  }
+
+  /// Matrix from linear RGB in this space to CIE XYZ with a D65 white
+  pub fn to_xyz(self) -> Mat3 {
+    let [red, green, blue, white] = self.chromaticities();
+    let primaries = transpose([red, green, blue].map(xy_to_xyz));
+    let scale = mul_vec(invert(primaries), xy_to_xyz(white));
+    let to_native_xyz = primaries.map(|row| [0, 1, 2].map(|i| row[i] * scale[i]));
+    mul(bradford(white, D65), to_native_xyz)
+  }
+}
+
+impl FromStr for ColorSpace {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "srgb" => Ok(ColorSpace::Srgb),
+      "linear_srgb" => Ok(ColorSpace::LinearSrgb),
+      "acescg" => Ok(ColorSpace::AcesCg),
+      "prophoto" => Ok(ColorSpace::ProPhoto),
+      _ => Err(format!(
+        "Unknown colour space '{}', expected srgb, linear_srgb, acescg or prophoto",
+        value
+      )),
+    }
+  }
+}
+
+impl fmt::Display for ColorSpace {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      ColorSpace::Srgb => "srgb",
+      ColorSpace::LinearSrgb => "linear_srgb",
+      ColorSpace::AcesCg => "acescg",
+      ColorSpace::ProPhoto => "prophoto",
+    };
+    f.write_str(name)
+  }
+}
+
+/// The values a node expects its input images to hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorEncoding {
+  /// Linear light in the working space
+  Linear,
+  /// Working space values encoded with the sRGB transfer curve
+  Encoded,
+}
+
+/// Colour spaces of a pipeline run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorSettings {
+  /// Overrides the colour space the input image was tagged with when loaded
+  pub input_space: Option<ColorSpace>,
+  /// Linear space every node works in
+  pub working_space: ColorSpace,
+  /// Space of the exported image, chosen from the output format when unset
+  pub output_space: Option<ColorSpace>,
+}
+
+impl Default for ColorSettings {
+  fn default() -> Self {
+    Self {
+      input_space: None,
+      working_space: ColorSpace::LinearSrgb,
+      output_space: None,
+    }
+  }
+}
+
+impl ColorSettings {
+  /// Output space for an exported file: linear for OpenEXR, sRGB for display formats
+  pub fn output_space_for(&self, path: Option<&Path>) -> ColorSpace {
+    self.output_space.unwrap_or_else(|| {
+      let is_exr = path
+        .and_then(|path| path.extension())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+      if is_exr {
+        ColorSpace::LinearSrgb
+      } else {
+        ColorSpace::Srgb
+      }
+    })
+  }
+}
+
+const D65: [f32; 2] = [0.3127, 0.3290];
+
+/// sRGB transfer curve, extended to negative and HDR values
+pub fn srgb_encode(c: f32) -> f32 {
+  let magnitude = c.abs();
+  let encoded = if magnitude <= 0.0031308 {
+    magnitude * 12.92
+  } else {
+    1.055 * magnitude.powf(1.0 / 2.4) - 0.055
+  };
+  encoded.copysign(c)
+}
+
+/// Inverse of `srgb_encode`
+pub fn srgb_decode(c: f32) -> f32 {
+  let magnitude = c.abs();
+  let linear = if magnitude <= 0.04045 {
+    magnitude / 12.92
+  } else {
+    ((magnitude + 0.055) / 1.055).powf(2.4)
+  };
+  linear.copysign(c)
+}
+
+/// A conversion from one colour space to another, applied to RGBA pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Conversion {
+  decode: bool,
+  matrix: Mat3,
+  encode: bool,
+}
+
+impl Conversion {
+  pub fn new(from: ColorSpace, to: ColorSpace) -> Self {
+    Self {
+      decode: !from.is_linear(),
+      matrix: mul(invert(to.to_xyz()), from.to_xyz()),
+      encode: !to.is_linear(),
+    }
+  }
+
+  pub fn apply(&self, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    let mut rgb = [r, g, b];
+    if self.decode {
+      rgb = rgb.map(srgb_decode);
+    }
+    rgb = mul_vec(self.matrix, rgb);
+    if self.encode {
+      rgb = rgb.map(srgb_encode);
+    }
+    [rgb[0], rgb[1], rgb[2], a]
+  }
+}
+
+/// Convert tightly packed little-endian RGBA32F pixel data in place
+pub fn convert_pixels(data: &mut [u8], from: ColorSpace, to: ColorSpace) {
+  if from == to {
+    return;
+  }
+  let conversion = Conversion::new(from, to);
+  for pixel in data.chunks_exact_mut(16) {
+    let channel = |i: usize| {
+      f32::from_le_bytes([
+        pixel[i * 4],
+        pixel[i * 4 + 1],
+        pixel[i * 4 + 2],
+        pixel[i * 4 + 3],
+      ])
+    };
+    let converted = conversion.apply([channel(0), channel(1), channel(2), channel(3)]);
+    for (i, value) in converted.iter().enumerate() {
+      pixel[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+  }
+}
+
+fn xy_to_xyz([x, y]: [f32; 2]) -> [f32; 3] {
+  [x / y, 1.0, (1.0 - x - y) / y]
+}
+
+/// Linear RGB in `space` of the colour with chromaticity `xy` and a luminance of 1.0
+pub fn xy_to_rgb(space: ColorSpace, xy: [f32; 2]) -> [f32; 3] {
+  mul_vec(invert(space.to_xyz()), xy_to_xyz(xy))
+}
+
+/// Chromaticity of a colour in CIE XYZ
+pub fn xyz_to_xy([x, y, z]: [f32; 3]) -> [f32; 2] {
+  let sum = x + y + z;
+  [x / sum, y / sum]
+}
+
+/// Bradford chromatic adaptation from one white point to another
+fn bradford(from: [f32; 2], to: [f32; 2]) -> Mat3 {
+  const BRADFORD: Mat3 = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+  ];
+  let source = mul_vec(BRADFORD, xy_to_xyz(from));
+  let destination = mul_vec(BRADFORD, xy_to_xyz(to));
+  let scale = [0, 1, 2].map(|i| destination[i] / source[i]);
+  let scaled = [0, 1, 2].map(|i| BRADFORD[i].map(|value| value * scale[i]));
+  mul(invert(BRADFORD), scaled)
+}
+
+pub fn mul(a: Mat3, b: Mat3) -> Mat3 {
+  [0, 1, 2].map(|row| [0, 1, 2].map(|col| (0..3).map(|k| a[row][k] * b[k][col]).sum()))
+}
+
+pub fn mul_vec(m: Mat3, v: [f32; 3]) -> [f32; 3] {
+  m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn transpose(m: Mat3) -> Mat3 {
+  [0, 1, 2].map(|row| [0, 1, 2].map(|col| m[col][row]))
+}
+
+pub fn invert(m: Mat3) -> Mat3 {
+  let cofactor = |row: usize, col: usize| {
+    let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+    let (c0, c1) = ((col + 1) % 3, (col + 2) % 3);
+    m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+  };
+  let determinant = (0..3).map(|col| m[0][col] * cofactor(0, col)).sum::<f32>();
+  [0, 1, 2].map(|row| [0, 1, 2].map(|col| cofactor(col, row) / determinant))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_transfer_curve_round_trip() {
+    for value in [-0.5, 0.0, 0.002, 0.18, 0.5, 1.0, 4.0] {
+      assert!((srgb_decode(srgb_encode(value)) - value).abs() < 1e-5);
+    }
+    assert!((srgb_decode(0.5) - 0.21404).abs() < 1e-4);
+  }
+
+  #[test]
+  fn test_white_is_preserved() {
+    let spaces = [
+      ColorSpace::Srgb,
+      ColorSpace::LinearSrgb,
+      ColorSpace::AcesCg,
+      ColorSpace::ProPhoto,
+    ];
+    for from in spaces {
+      for to in spaces {
+        let white = Conversion::new(from, to).apply([1.0, 1.0, 1.0, 0.5]);
+        assert!(
+          white[..3].iter().all(|c| (c - 1.0).abs() < 1e-4),
+          "{} to {}: {:?}",
+          from,
+          to,
+          white
+        );
+        assert_eq!(white[3], 0.5);
+      }
+    }
+  }
+
+  #[test]
+  fn test_known_conversions() {
+    // Rec.709 red in ACEScg, as published with the ACES reference transforms
+    let red = Conversion::new(ColorSpace::LinearSrgb, ColorSpace::AcesCg)
+      .apply([1.0, 0.0, 0.0, 1.0]);
+    for (value, expected) in red.iter().zip([0.6131, 0.0702, 0.0206]) {
+      assert!((value - expected).abs() < 2e-3, "{:?}", red);
+    }
+
+    // Converting there and back is lossless
+    let color = [0.2, 0.7, 0.05, 1.0];
+    let there = Conversion::new(ColorSpace::Srgb, ColorSpace::ProPhoto).apply(color);
+    let back = Conversion::new(ColorSpace::ProPhoto, ColorSpace::Srgb).apply(there);
+    for i in 0..4 {
+      assert!((back[i] - color[i]).abs() < 1e-4, "{:?}", back);
+    }
+
+    let mut data: Vec<u8> = [0.5f32, 0.5, 0.5, 1.0]
+      .iter()
+      .flat_map(|c| c.to_le_bytes())
+      .collect();
+    convert_pixels(&mut data, ColorSpace::Srgb, ColorSpace::LinearSrgb);
+    let linear = f32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    assert!((linear - 0.21404).abs() < 1e-4);
+  }
+}
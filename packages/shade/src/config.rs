@@ -2,6 +2,15 @@ use ini::Ini;
 use std::path::PathBuf;
 
 use crate::cli::{self, PipelineConfig, PipelineOperation, ProcessingConfig};
+use crate::color::{ColorSettings, ColorSpace};
+use crate::curves::{self, CurvePoints};
+use crate::geometry::{self, Resampling};
+use crate::grading;
+use crate::hsl::{HslBand, HslBands};
+use crate::lut::LutInterpolation;
+use crate::raw::{InputProfile, RawDevelopParams};
+use crate::shade::ToneMapOperator;
+use crate::white_balance::WhiteBalanceMethod;
 
 pub fn config_from_ini_path(config_path: &PathBuf) -> anyhow::Result<ProcessingConfig> {
   let conf = Ini::load_from_file(config_path)?;
@@ -16,7 +25,44 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
   let mut pipeline_config = PipelineConfig::default();
   let mut operation_index = 0;
 
-  // Parse pipeline-related parameters from ini
+  // Parse pipeline-related parameters from ini. Noise is removed first, before
+  // adjustments amplify it
+  if let Some(strengths) = section.get("denoise") {
+    let [luminance, chroma] = cli::parse_numbers::<2>(strengths)
+      .map_err(|e| anyhow::anyhow!("denoise: {}", e))?;
+    let radius = section
+      .get("denoise_radius")
+      .and_then(|r| r.parse::<f32>().ok())
+      .unwrap_or(2.0);
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Denoise {
+        luminance,
+        chroma,
+        radius,
+      },
+    });
+    operation_index += 1;
+  }
+
+  if let Some(amount) = section.get("dehaze").and_then(|a| a.parse::<f32>().ok()) {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Dehaze(amount),
+    });
+    operation_index += 1;
+  }
+
+  if let Some(exposure) = section.get("exposure") {
+    if let Ok(stops) = exposure.parse::<f32>() {
+      pipeline_config.operations.push(PipelineOperation {
+        index: operation_index,
+        op_type: cli::OperationType::Exposure(stops),
+      });
+      operation_index += 1;
+    }
+  }
+
   if let Some(brightness) = section.get("brightness") {
     if let Ok(exp_val) = brightness.parse::<f32>() {
       pipeline_config.operations.push(PipelineOperation {
@@ -47,6 +93,14 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     }
   }
 
+  if let Some(amount) = section.get("vibrance").and_then(|a| a.parse::<f32>().ok()) {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Vibrance(amount),
+    });
+    operation_index += 1;
+  }
+
   if let Some(hue) = section.get("hue") {
     if let Ok(exp_val) = hue.parse::<f32>() {
       pipeline_config.operations.push(PipelineOperation {
@@ -57,6 +111,34 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     }
   }
 
+  // Handle HSL bands, one key per band and component like hsl_blue_saturation
+  let mut bands = HslBands::default();
+  for band in HslBand::ALL {
+    let component = |name: &str| -> anyhow::Result<f32> {
+      let key = format!("hsl_{}_{}", band, name);
+      section
+        .get(key.as_str())
+        .map(|value| {
+          value
+            .parse::<f32>()
+            .map_err(|e| anyhow::anyhow!("{}: {}", key, e))
+        })
+        .unwrap_or(Ok(0.0))
+    };
+    let adjustment = bands.get_mut(band);
+    adjustment.hue = component("hue")?;
+    adjustment.saturation = component("saturation")?;
+    adjustment.luminance = component("luminance")?;
+  }
+
+  if bands != HslBands::default() {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Hsl(bands),
+    });
+    operation_index += 1;
+  }
+
   if let Some(gamma) = section.get("gamma") {
     if let Ok(exp_val) = gamma.parse::<f32>() {
       pipeline_config.operations.push(PipelineOperation {
@@ -67,6 +149,21 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     }
   }
 
+  if let Some(levels) = section.get("levels") {
+    let [input_black, input_white, output_black, output_white] =
+      cli::parse_levels(levels).map_err(|e| anyhow::anyhow!("levels: {}", e))?;
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Levels {
+        input_black,
+        input_white,
+        output_black,
+        output_white,
+      },
+    });
+    operation_index += 1;
+  }
+
   if let Some(blur) = section.get("blur") {
     if let Ok(exp_val) = blur.parse::<f32>() {
       pipeline_config.operations.push(PipelineOperation {
@@ -87,6 +184,38 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     }
   }
 
+  if let Some(amount) = section.get("unsharp").and_then(|a| a.parse::<f32>().ok()) {
+    let radius = section
+      .get("unsharp_radius")
+      .and_then(|r| r.parse::<f32>().ok())
+      .unwrap_or(1.0);
+    let threshold = section
+      .get("unsharp_threshold")
+      .and_then(|t| t.parse::<f32>().ok())
+      .unwrap_or(0.0);
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::UnsharpMask {
+        amount,
+        radius,
+        threshold,
+      },
+    });
+    operation_index += 1;
+  }
+
+  if let Some(amount) = section.get("clarity").and_then(|a| a.parse::<f32>().ok()) {
+    let radius = section
+      .get("clarity_radius")
+      .and_then(|r| r.parse::<f32>().ok())
+      .unwrap_or(40.0);
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Clarity { amount, radius },
+    });
+    operation_index += 1;
+  }
+
   if let Some(noise) = section.get("noise") {
     if let Ok(exp_val) = noise.parse::<f32>() {
       pipeline_config.operations.push(PipelineOperation {
@@ -97,6 +226,119 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     }
   }
 
+  // Handle curves, written as x:y control points like on the command line
+  let curve_points = |key: &str| -> anyhow::Result<CurvePoints> {
+    section
+      .get(key)
+      .map(|points| {
+        curves::parse_points(points).map_err(|e| anyhow::anyhow!("{}: {}", key, e))
+      })
+      .transpose()
+      .map(Option::unwrap_or_default)
+  };
+  let master = curve_points("curve")?;
+  let red = curve_points("curve_red")?;
+  let green = curve_points("curve_green")?;
+  let blue = curve_points("curve_blue")?;
+
+  if [&master, &red, &green, &blue]
+    .iter()
+    .any(|points| !points.is_empty())
+  {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Curves {
+        master,
+        red,
+        green,
+        blue,
+      },
+    });
+    operation_index += 1;
+  }
+
+  // Handle colour balance, with R:G:B gains for each tonal range
+  let gains = |key: &str| -> anyhow::Result<Option<[f32; 3]>> {
+    section
+      .get(key)
+      .map(|gains| {
+        cli::parse_numbers::<3>(gains).map_err(|e| anyhow::anyhow!("{}: {}", key, e))
+      })
+      .transpose()
+  };
+  let shadows = gains("color_balance_shadows")?;
+  let midtones = gains("color_balance_midtones")?;
+  let highlights = gains("color_balance_highlights")?;
+
+  if shadows.is_some() || midtones.is_some() || highlights.is_some() {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::ColorBalance {
+        shadows: shadows.unwrap_or_default(),
+        midtones: midtones.unwrap_or_default(),
+        highlights: highlights.unwrap_or_default(),
+      },
+    });
+    operation_index += 1;
+  }
+
+  // Handle colour grading, with a hue:saturation[:luminance] wheel for each tonal range
+  let wheel = |key: &str| -> anyhow::Result<Option<grading::GradingWheel>> {
+    section
+      .get(key)
+      .map(|wheel| {
+        grading::parse_wheel(wheel).map_err(|e| anyhow::anyhow!("{}: {}", key, e))
+      })
+      .transpose()
+  };
+  let shadows = wheel("grade_shadows")?;
+  let midtones = wheel("grade_midtones")?;
+  let highlights = wheel("grade_highlights")?;
+
+  if shadows.is_some() || midtones.is_some() || highlights.is_some() {
+    let number = |key: &str, default: f32| {
+      section
+        .get(key)
+        .and_then(|value| value.parse::<f32>().ok())
+        .unwrap_or(default)
+    };
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::ColorGrading {
+        shadows: shadows.unwrap_or_default(),
+        midtones: midtones.unwrap_or_default(),
+        highlights: highlights.unwrap_or_default(),
+        balance: number("grade_balance", 0.0),
+        blending: number("grade_blending", 0.5),
+      },
+    });
+    operation_index += 1;
+  }
+
+  // Handle 3D LUT
+  if let Some(path) = section.get("lut") {
+    let intensity = section
+      .get("lut_intensity")
+      .and_then(|i| i.parse::<f32>().ok())
+      .unwrap_or(1.0);
+    let interpolation = section
+      .get("lut_interpolation")
+      .map(|mode| mode.parse::<LutInterpolation>())
+      .transpose()
+      .map_err(|e| anyhow::anyhow!(e))?
+      .unwrap_or_default();
+
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Lut3D {
+        path: PathBuf::from(path),
+        intensity,
+        interpolation,
+      },
+    });
+    operation_index += 1;
+  }
+
   // Handle white balance
   let auto_wb = section
     .get("auto_white_balance")
@@ -106,14 +348,215 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     .get("wb_temperature")
     .and_then(|t| t.parse::<f32>().ok());
   let wb_tint = section.get("wb_tint").and_then(|t| t.parse::<f32>().ok());
+  let wb_method = section
+    .get("wb_method")
+    .map(|method| method.parse::<WhiteBalanceMethod>())
+    .transpose()
+    .map_err(|e| anyhow::anyhow!(e))?;
+  let wb_neutral = section
+    .get("wb_neutral")
+    .map(|point| {
+      cli::parse_numbers::<2>(point).map_err(|e| anyhow::anyhow!("wb_neutral: {}", e))
+    })
+    .transpose()?;
+  let wb_kelvin = section.get("wb_kelvin").and_then(|k| k.parse::<f32>().ok());
 
-  if auto_wb || wb_temp.is_some() || wb_tint.is_some() {
+  if auto_wb
+    || wb_temp.is_some()
+    || wb_tint.is_some()
+    || wb_method.is_some()
+    || wb_neutral.is_some()
+    || wb_kelvin.is_some()
+  {
     pipeline_config.operations.push(PipelineOperation {
       index: operation_index,
       op_type: cli::OperationType::WhiteBalance {
-        auto_adjust: auto_wb,
+        // Choosing a method asks for an automatic white balance
+        auto_adjust: auto_wb || wb_method.is_some(),
         temperature: wb_temp,
         tint: wb_tint,
+        method: wb_method.unwrap_or_default(),
+        neutral_point: wb_neutral,
+        kelvin: wb_kelvin,
+      },
+    });
+    operation_index += 1;
+  }
+
+  // Handle tone mapping, after the scene-referred adjustments
+  if let Some(operator) = section.get("tone_map") {
+    let operator = operator
+      .parse::<ToneMapOperator>()
+      .map_err(|e| anyhow::anyhow!(e))?;
+    let white_point = section
+      .get("tone_map_white")
+      .and_then(|w| w.parse::<f32>().ok())
+      .unwrap_or(16.0);
+    let contrast = section
+      .get("tone_map_contrast")
+      .and_then(|c| c.parse::<f32>().ok())
+      .unwrap_or(1.0);
+
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::ToneMap {
+        operator,
+        white_point,
+        contrast,
+      },
+    });
+    operation_index += 1;
+  }
+
+  if section.get("invert").is_some_and(|v| v == "true") {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Invert,
+    });
+    operation_index += 1;
+  }
+
+  // Handle masks, which limit the adjustments above to part of the image
+  let mask_feather = section
+    .get("mask_feather")
+    .and_then(|f| f.parse::<f32>().ok());
+  let mask_invert = section
+    .get("mask_invert")
+    .map(|v| v == "true")
+    .unwrap_or(false);
+  for (kind, _) in cli::MASK_KINDS {
+    let key = format!("mask_{}", kind);
+    if let Some(value) = section.get(key.as_str()) {
+      let generator = cli::parse_mask(kind, value, mask_feather)
+        .map_err(|e| anyhow::anyhow!("{}: {}", key, e))?;
+      pipeline_config.operations.push(PipelineOperation {
+        index: operation_index,
+        op_type: cli::OperationType::Mask {
+          generator,
+          invert: mask_invert,
+        },
+      });
+      operation_index += 1;
+    }
+  }
+
+  if let Some(factor) = section.get("mix").and_then(|f| f.parse::<f32>().ok()) {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Mix(factor),
+    });
+    operation_index += 1;
+  }
+
+  // Handle geometric transforms, straighten and perspective share the resampling
+  let resampling = section
+    .get("resampling")
+    .map(|mode| mode.parse::<Resampling>())
+    .transpose()
+    .map_err(|e| anyhow::anyhow!(e))?
+    .unwrap_or_default();
+
+  if let Some(region) = section.get("crop") {
+    let [x, y, width, height] =
+      geometry::parse_crop(region).map_err(|e| anyhow::anyhow!("crop: {}", e))?;
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Crop {
+        x,
+        y,
+        width,
+        height,
+      },
+    });
+    operation_index += 1;
+  }
+
+  if let Some(degrees) = section.get("rotate") {
+    let quarter_turns =
+      geometry::parse_quarter_turns(degrees).map_err(|e| anyhow::anyhow!(e))?;
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Rotate(quarter_turns),
+    });
+    operation_index += 1;
+  }
+
+  if let Some(direction) = section.get("flip") {
+    let (horizontal, vertical) =
+      geometry::parse_flip(direction).map_err(|e| anyhow::anyhow!(e))?;
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Flip {
+        horizontal,
+        vertical,
+      },
+    });
+    operation_index += 1;
+  }
+
+  if let Some(angle) = section
+    .get("straighten")
+    .and_then(|a| a.parse::<f32>().ok())
+  {
+    let crop = section
+      .get("straighten_crop")
+      .map(|v| v == "true")
+      .unwrap_or(true);
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Straighten {
+        angle,
+        crop,
+        resampling,
+      },
+    });
+    operation_index += 1;
+  }
+
+  if let Some(corners) = section.get("perspective") {
+    let corners = geometry::parse_corners(corners)
+      .map_err(|e| anyhow::anyhow!("perspective: {}", e))?;
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Perspective {
+        corners,
+        resampling,
+      },
+    });
+    operation_index += 1;
+  }
+
+  // Handle finishing effects, after the geometry so the vignette follows the crop
+  let number = |key: &str, default: f32| {
+    section
+      .get(key)
+      .and_then(|v| v.parse::<f32>().ok())
+      .unwrap_or(default)
+  };
+  if let Some(amount) = section.get("vignette").and_then(|a| a.parse::<f32>().ok()) {
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Vignette {
+        amount,
+        midpoint: number("vignette_midpoint", 0.5),
+        roundness: number("vignette_roundness", 0.0),
+        feather: number("vignette_feather", 0.5),
+      },
+    });
+    operation_index += 1;
+  }
+
+  if let Some(amount) = section.get("grain").and_then(|a| a.parse::<f32>().ok()) {
+    let seed = section
+      .get("grain_seed")
+      .and_then(|s| s.parse::<u32>().ok())
+      .unwrap_or(42);
+    pipeline_config.operations.push(PipelineOperation {
+      index: operation_index,
+      op_type: cli::OperationType::Grain {
+        amount,
+        size: number("grain_size", 1.0),
+        seed,
       },
     });
     operation_index += 1;
@@ -137,6 +580,44 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     });
   }
 
+  // Colour management
+  let parse_space = |key: &str| {
+    section
+      .get(key)
+      .map(|space| space.parse::<ColorSpace>())
+      .transpose()
+      .map_err(|e| anyhow::anyhow!(e))
+  };
+  let mut color = ColorSettings::default();
+  color.input_space = parse_space("input_space")?;
+  if let Some(space) = parse_space("working_space")? {
+    color.working_space = space;
+  }
+  color.output_space = parse_space("output_space")?;
+
+  // Development of camera raw files
+  let mut raw = RawDevelopParams::default();
+  if let Some(demosaic) = section.get("raw_demosaic") {
+    raw.demosaic = demosaic.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+  }
+  if let Some(highlights) = section.get("raw_highlights") {
+    raw.highlights = highlights.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+  }
+  let parse_level = |key: &str| {
+    section
+      .get(key)
+      .map(|level| level.parse::<u32>())
+      .transpose()
+      .map_err(|e| anyhow::anyhow!("{}: {}", key, e))
+  };
+  raw.black_level = parse_level("raw_black_level")?;
+  raw.white_level = parse_level("raw_white_level")?;
+  if let Some(matrix) = section.get("raw_matrix") {
+    let matrix = cli::parse_numbers::<9>(matrix)
+      .map_err(|e| anyhow::anyhow!("raw_matrix: {}", e))?;
+    raw.input_profile = InputProfile::custom(matrix);
+  }
+
   Ok(ProcessingConfig {
     input_path: section
       .get("input_path")
@@ -147,7 +628,13 @@ fn parse_ini_config(conf: Ini) -> anyhow::Result<ProcessingConfig> {
     pipeline_config,
     verbose: section.get("verbose").map(|v| v == "true").unwrap_or(false),
     config_path: None,
+    graph_path: section.get("graph").map(PathBuf::from),
+    save_graph_path: None,
+    shader_dir: section.get("shader_dir").map(PathBuf::from),
     clear_cache: false,
     show_cache_info: false,
+    use_cpu: section.get("cpu").map(|v| v == "true").unwrap_or(false),
+    color,
+    raw,
   })
 }
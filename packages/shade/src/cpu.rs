@@ -0,0 +1,2507 @@
+//! CPU reference implementation of the processing nodes
+//!
+//! Every node type is implemented with the same math as its WGSL shader, so results
+//! are numerically comparable to the GPU backend. This backend runs on machines
+//! without a usable GPU adapter and doubles as the oracle for shader tests.
+//!
+//! The `Noise` node is the one exception to bit-level agreement: its hash amplifies
+//! differences between the `sin` implementations of the CPU and the GPU driver.
+
+use crate::color::{self, ColorEncoding};
+use crate::curves;
+use crate::dehaze;
+use crate::geometry::{self, Resampling};
+use crate::grading;
+use crate::lut;
+use crate::shade::{
+  BYTES_PER_PIXEL, NodeParams, NodeType, ToneMapOperator, blur_extent, denoise_extent,
+};
+use crate::white_balance;
+
+/// An RGBA32F image held in CPU memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuImage {
+  pub width: u32,
+  pub height: u32,
+  pub pixels: Vec<[f32; 4]>,
+}
+
+impl CpuImage {
+  /// Decode tightly packed little-endian RGBA32F bytes
+  pub fn from_bytes(data: &[u8], dimensions: (u32, u32)) -> Self {
+    let pixels = data
+      .chunks_exact(BYTES_PER_PIXEL as usize)
+      .map(|pixel| {
+        let channel = |i: usize| {
+          f32::from_le_bytes([
+            pixel[i * 4],
+            pixel[i * 4 + 1],
+            pixel[i * 4 + 2],
+            pixel[i * 4 + 3],
+          ])
+        };
+        [channel(0), channel(1), channel(2), channel(3)]
+      })
+      .collect();
+
+    Self {
+      width: dimensions.0,
+      height: dimensions.1,
+      pixels,
+    }
+  }
+
+  /// Encode as tightly packed little-endian RGBA32F bytes
+  pub fn to_bytes(&self) -> Vec<u8> {
+    self
+      .pixels
+      .iter()
+      .flat_map(|pixel| pixel.iter().flat_map(|channel| channel.to_le_bytes()))
+      .collect()
+  }
+
+  pub fn dimensions(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  /// Equivalent of `textureLoad`; coordinates must be inside the image
+  fn load(&self, x: i32, y: i32) -> [f32; 4] {
+    self.pixels[y as usize * self.width as usize + x as usize]
+  }
+
+  /// `textureLoad` with coordinates clamped to the image bounds
+  fn load_clamped(&self, x: i32, y: i32) -> [f32; 4] {
+    self.load(
+      x.clamp(0, self.width as i32 - 1),
+      y.clamp(0, self.height as i32 - 1),
+    )
+  }
+}
+
+/// Run a single node on the CPU.
+///
+/// `output_dimensions` only differs from the input size for nodes that change the
+/// image size, such as `Resize`. Nodes without a shader pass their input through.
+///
+/// Images are linear; nodes working on encoded values see them through the sRGB
+/// curve, like the shaders wrapped with `encoded.wgsl`.
+pub fn process_node(
+  node_type: &NodeType,
+  params: &NodeParams,
+  input: &CpuImage,
+  secondary: Option<&CpuImage>,
+  output_dimensions: (u32, u32),
+) -> Result<CpuImage, String> {
+  if node_type.encoding() == ColorEncoding::Linear {
+    return run_node(node_type, params, input, secondary, output_dimensions);
+  }
+
+  let encode = |image: &CpuImage| CpuImage {
+    width: image.width,
+    height: image.height,
+    pixels: image
+      .pixels
+      .iter()
+      .map(|&[r, g, b, a]| {
+        let [r, g, b] = [r, g, b].map(color::srgb_encode);
+        [r, g, b, a]
+      })
+      .collect(),
+  };
+  let secondary = secondary.map(encode);
+  let mut output = run_node(
+    node_type,
+    params,
+    &encode(input),
+    secondary.as_ref(),
+    output_dimensions,
+  )?;
+  for pixel in &mut output.pixels {
+    for channel in &mut pixel[..3] {
+      *channel = color::srgb_decode(*channel);
+    }
+  }
+  Ok(output)
+}
+
+fn run_node(
+  node_type: &NodeType,
+  params: &NodeParams,
+  input: &CpuImage,
+  secondary: Option<&CpuImage>,
+  output_dimensions: (u32, u32),
+) -> Result<CpuImage, String> {
+  let (width, height) = input.dimensions();
+  let invalid = || format!("Invalid parameters for {:?} node", node_type);
+
+  let pixels = match node_type {
+    NodeType::Exposure => {
+      let NodeParams::Exposure { stops } = *params else {
+        return Err(invalid());
+      };
+      let scale = stops.exp2();
+      map_pixels(input, |[r, g, b, a]| [r * scale, g * scale, b * scale, a])
+    }
+    NodeType::Brightness => {
+      let NodeParams::Brightness { value } = *params else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, a]| {
+        [r * (1.0 + value), g * (1.0 + value), b * (1.0 + value), a]
+      })
+    }
+    NodeType::Contrast => {
+      let NodeParams::Contrast { value } = *params else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, a]| {
+        [
+          (r - 0.5) * value + 0.5,
+          (g - 0.5) * value + 0.5,
+          (b - 0.5) * value + 0.5,
+          a,
+        ]
+      })
+    }
+    NodeType::Saturation => {
+      let NodeParams::Saturation { value } = *params else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, a]| {
+        let luminance = 0.299 * r + 0.587 * g + 0.114 * b;
+        [
+          mix(luminance, r, value).max(0.0),
+          mix(luminance, g, value).max(0.0),
+          mix(luminance, b, value).max(0.0),
+          a,
+        ]
+      })
+    }
+    NodeType::Vibrance => {
+      let NodeParams::Vibrance { amount } = *params else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, a]| {
+        let [h, s, _] = rgb_to_hsv([r, g, b]);
+        let skin_offset = fract(h - 25.0 / 360.0 + 0.5) - 0.5;
+        let skin = 1.0 - smoothstep(0.0, 35.0, skin_offset.abs() * 360.0);
+        let weight = (1.0 - s.clamp(0.0, 1.0)) * (1.0 - 0.75 * skin);
+
+        let luminance = 0.299 * r + 0.587 * g + 0.114 * b;
+        let value = 1.0 + amount * weight;
+        [
+          mix(luminance, r, value).max(0.0),
+          mix(luminance, g, value).max(0.0),
+          mix(luminance, b, value).max(0.0),
+          a,
+        ]
+      })
+    }
+    NodeType::Hue => {
+      let NodeParams::Hue { value } = *params else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, a]| {
+        let [h, s, v] = rgb_to_hsv([r, g, b]);
+        let [r, g, b] = hsv_to_rgb([fract(h + value / 360.0), s, v]);
+        [r, g, b, a]
+      })
+    }
+    NodeType::Hsl => {
+      let NodeParams::Hsl { bands } = params else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, a]| {
+        let [h, s, v] = rgb_to_hsv([r, g, b]);
+        let adjustment = bands.blend(h * 360.0);
+        let hue = fract(h + adjustment.hue / 360.0);
+        let saturation = (s * (1.0 + adjustment.saturation)).clamp(0.0, 1.0);
+        // Luminance changes fade out towards greys
+        let value = v * (1.0 + adjustment.luminance * s).max(0.0);
+        let [r, g, b] = hsv_to_rgb([hue, saturation, value]);
+        [r, g, b, a]
+      })
+    }
+    NodeType::Gamma => {
+      let NodeParams::Gamma { value } = *params else {
+        return Err(invalid());
+      };
+      let inv_gamma = 1.0 / value.max(0.01);
+      map_pixels(input, |[r, g, b, a]| {
+        [
+          r.powf(inv_gamma).max(0.0),
+          g.powf(inv_gamma).max(0.0),
+          b.powf(inv_gamma).max(0.0),
+          a,
+        ]
+      })
+    }
+    NodeType::Levels => {
+      let NodeParams::Levels {
+        input_black,
+        input_white,
+        output_black,
+        output_white,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let input_range = input_white - input_black;
+      let output_range = output_white - output_black;
+      let level = |c: f32| {
+        let normalized = ((c - input_black) / input_range).max(0.0);
+        (normalized * output_range + output_black).max(0.0)
+      };
+      map_pixels(input, |[r, g, b, a]| [level(r), level(g), level(b), a])
+    }
+    NodeType::Curves => {
+      let NodeParams::Curves {
+        master,
+        red,
+        green,
+        blue,
+      } = params
+      else {
+        return Err(invalid());
+      };
+      let lut = curves::bake_lut(master, red, green, blue);
+      map_pixels(input, |[r, g, b, a]| {
+        [
+          curves::sample_lut(&lut, r, 0),
+          curves::sample_lut(&lut, g, 1),
+          curves::sample_lut(&lut, b, 2),
+          a,
+        ]
+      })
+    }
+    NodeType::ColorBalance => {
+      let NodeParams::ColorBalance {
+        shadows,
+        midtones,
+        highlights,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, a]| {
+        let lum = 0.299 * r + 0.587 * g + 0.114 * b;
+        let shadow_weight = 1.0 - smoothstep(0.0, 0.5, lum);
+        let midtone_weight =
+          smoothstep(0.0, 0.5, lum) * (1.0 - smoothstep(0.5, 1.0, lum));
+        let highlight_weight = smoothstep(0.5, 1.0, lum);
+
+        let mut rgb = [r, g, b];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+          let shadow_adjustment = *channel * (1.0 + shadows[i] * shadow_weight);
+          let midtone_adjustment =
+            shadow_adjustment * (1.0 + midtones[i] * midtone_weight);
+          let highlight_adjustment =
+            midtone_adjustment * (1.0 + highlights[i] * highlight_weight);
+          *channel = highlight_adjustment.max(0.0);
+        }
+        [rgb[0], rgb[1], rgb[2], a]
+      })
+    }
+    NodeType::ColorGrading => {
+      let NodeParams::ColorGrading {
+        shadows,
+        midtones,
+        highlights,
+        balance,
+        blending,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let offsets = [shadows.offset(), midtones.offset(), highlights.offset()];
+      map_pixels(input, |[r, g, b, a]| {
+        let luma = r * grading::LUMA[0] + g * grading::LUMA[1] + b * grading::LUMA[2];
+        let weights = grading::tonal_weights(luma, balance, blending);
+        let mut rgb = [r, g, b];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+          let offset: f32 = (0..3).map(|range| offsets[range][i] * weights[range]).sum();
+          *channel = (*channel + offset).max(0.0);
+        }
+        [rgb[0], rgb[1], rgb[2], a]
+      })
+    }
+    NodeType::WhiteBalance => {
+      let NodeParams::WhiteBalance {
+        auto_adjust,
+        temperature,
+        tint,
+        method,
+        neutral_point,
+        kelvin,
+        as_shot_kelvin,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let gains = white_balance::image_gains(
+        input.width,
+        input.height,
+        auto_adjust.then_some(method),
+        neutral_point,
+        white_balance::manual_gains(
+          temperature,
+          tint,
+          kelvin,
+          as_shot_kelvin.unwrap_or(white_balance::D65_KELVIN),
+        ),
+        |x, y| {
+          let [r, g, b, _] = input.load(x as i32, y as i32);
+          [r, g, b]
+        },
+      );
+      map_pixels(input, |[r, g, b, a]| {
+        [
+          (r * gains[0]).max(0.0),
+          (g * gains[1]).max(0.0),
+          (b * gains[2]).max(0.0),
+          a,
+        ]
+      })
+    }
+    NodeType::Lut3D => {
+      let NodeParams::Lut3D {
+        path,
+        intensity,
+        interpolation,
+      } = params
+      else {
+        return Err(invalid());
+      };
+      let lut = lut::load(path)?;
+      map_pixels(input, |[r, g, b, a]| {
+        let graded = lut.sample([r, g, b], *interpolation);
+        [
+          mix(r, graded[0], *intensity),
+          mix(g, graded[1], *intensity),
+          mix(b, graded[2], *intensity),
+          a,
+        ]
+      })
+    }
+    NodeType::ToneMap => {
+      let NodeParams::ToneMap {
+        operator,
+        white_point,
+        contrast,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let curve = |rgb: [f32; 3]| {
+        let rgb = rgb.map(|c| MIDDLE_GREY * (c.max(0.0) / MIDDLE_GREY).powf(contrast));
+        match operator {
+          ToneMapOperator::Reinhard => rgb.map(|c| c / (1.0 + c)),
+          ToneMapOperator::Filmic => agx(rgb),
+          ToneMapOperator::Aces => aces(rgb),
+        }
+      };
+      let white = curve([white_point.max(1e-4); 3]);
+      map_pixels(input, |[r, g, b, a]| {
+        let mapped = curve([r, g, b]);
+        let [r, g, b] = [0, 1, 2].map(|i| (mapped[i] / white[i]).clamp(0.0, 1.0));
+        [r, g, b, a]
+      })
+    }
+    NodeType::Denoise => {
+      let NodeParams::Denoise {
+        luminance: luminance_strength,
+        chroma: chroma_strength,
+        radius,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let extent = denoise_extent(radius) as i32;
+      let luma_spatial = 2.0 * (radius * 0.5).max(0.5).powi(2);
+      let chroma_spatial = 2.0 * radius.max(0.5).powi(2);
+      let luma_range = 2.0 * (luminance_strength * 0.15).max(1e-4).powi(2);
+      let chroma_range = 2.0 * (chroma_strength * 0.15).max(1e-4).powi(2);
+      render(width, height, |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        let [r, g, b, a] = input.load(x, y);
+        let center_luma = luminance([r, g, b]);
+        let center_chroma = [r, g, b].map(|c| c - center_luma);
+
+        let (mut luma_sum, mut luma_weight) = (0.0, 0.0);
+        let (mut chroma_sum, mut chroma_weight) = ([0.0; 3], 0.0);
+        for dy in -extent..=extent {
+          for dx in -extent..=extent {
+            let [r, g, b, _] = input.load_clamped(x + dx, y + dy);
+            let luma = luminance([r, g, b]);
+            let chroma = [r, g, b].map(|c| c - luma);
+            let distance = (dx * dx + dy * dy) as f32;
+
+            let luma_difference = luma - center_luma;
+            let weight = (-distance / luma_spatial
+              - luma_difference * luma_difference / luma_range)
+              .exp();
+            luma_sum += luma * weight;
+            luma_weight += weight;
+
+            let chroma_difference: f32 =
+              (0..3).map(|i| (chroma[i] - center_chroma[i]).powi(2)).sum();
+            let weight =
+              (-distance / chroma_spatial - chroma_difference / chroma_range).exp();
+            for i in 0..3 {
+              chroma_sum[i] += chroma[i] * weight;
+            }
+            chroma_weight += weight;
+          }
+        }
+
+        let luma = luma_sum / luma_weight;
+        let [r, g, b] = chroma_sum.map(|c| luma + c / chroma_weight);
+        [r, g, b, a]
+      })
+    }
+    NodeType::Blur => {
+      let NodeParams::Blur { radius } = *params else {
+        return Err(invalid());
+      };
+      gaussian_blur(input, radius).pixels
+    }
+    NodeType::Sharpen => {
+      let NodeParams::Sharpen { amount } = *params else {
+        return Err(invalid());
+      };
+      render(width, height, |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        let center = input.load(x, y);
+        let left = input.load_clamped(x - 1, y);
+        let right = input.load_clamped(x + 1, y);
+        let top = input.load_clamped(x, y - 1);
+        let bottom = input.load_clamped(x, y + 1);
+
+        let mut sharpened = [0.0; 4];
+        for i in 0..4 {
+          let laplacian = center[i] * 4.0 - left[i] - right[i] - top[i] - bottom[i];
+          sharpened[i] = (center[i] + laplacian * amount).clamp(0.0, 1.0);
+        }
+        sharpened
+      })
+    }
+    NodeType::UnsharpMask => {
+      let NodeParams::UnsharpMask {
+        amount,
+        radius,
+        threshold,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let base = gaussian_blur(input, radius);
+      render(width, height, |x, y| {
+        let [r, g, b, a] = input.load(x as i32, y as i32);
+        let [base_r, base_g, base_b, _] = base.load(x as i32, y as i32);
+        let encoded = [r, g, b].map(color::srgb_encode);
+        let base = [base_r, base_g, base_b].map(color::srgb_encode);
+        let detail = [0, 1, 2].map(|i| encoded[i] - base[i]);
+
+        let weight = if threshold > 0.0 {
+          smoothstep(threshold * 0.5, threshold, luminance(detail).abs())
+        } else {
+          1.0
+        };
+        let [r, g, b] =
+          [0, 1, 2].map(|i| color::srgb_decode(encoded[i] + detail[i] * amount * weight));
+        [r, g, b, a]
+      })
+    }
+    NodeType::Clarity => {
+      let NodeParams::Clarity { amount, radius } = *params else {
+        return Err(invalid());
+      };
+      let base = gaussian_blur(input, radius);
+      render(width, height, |x, y| {
+        let [r, g, b, a] = input.load(x as i32, y as i32);
+        let [base_r, base_g, base_b, _] = base.load(x as i32, y as i32);
+        let encoded = [r, g, b].map(color::srgb_encode);
+        let lightness = luminance(encoded);
+        let detail =
+          lightness - luminance([base_r, base_g, base_b].map(color::srgb_encode));
+
+        // Local contrast is added to the midtones, as in the shader
+        let centered = 2.0 * lightness - 1.0;
+        let midtones = (1.0 - centered * centered).clamp(0.0, 1.0);
+        let [r, g, b] =
+          encoded.map(|channel| color::srgb_decode(channel + detail * amount * midtones));
+        [r, g, b, a]
+      })
+    }
+    NodeType::Noise => {
+      let NodeParams::Noise { amount, seed } = *params else {
+        return Err(invalid());
+      };
+      let seed = seed as f32;
+      render(width, height, |x, y| {
+        let [r, g, b, a] = input.load(x as i32, y as i32);
+        let coord = [
+          x as f32 / width as f32 + seed,
+          y as f32 / height as f32 + seed,
+        ];
+        let random = fract((coord[0] * 12.9898 + coord[1] * 78.233).sin() * 43758.5453);
+        let noise_value = (random * 2.0 - 1.0) * amount;
+        [
+          (r + noise_value).clamp(0.0, 1.0),
+          (g + noise_value).clamp(0.0, 1.0),
+          (b + noise_value).clamp(0.0, 1.0),
+          a.clamp(0.0, 1.0),
+        ]
+      })
+    }
+    NodeType::Vignette => {
+      let NodeParams::Vignette {
+        amount,
+        midpoint,
+        roundness,
+        feather,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let size = [width as f32, height as f32];
+      let roundness = roundness.clamp(-1.0, 1.0);
+      let scale =
+        size.map(|side| mix(1.0, side / size[0].max(size[1]), roundness.max(0.0)));
+      let exponent = 2.0 + 6.0 * (-roundness).max(0.0);
+      let shape_length = |[x, y]: [f32; 2]| {
+        (x.abs().max(1e-6).powf(exponent) + y.abs().max(1e-6).powf(exponent))
+          .powf(1.0 / exponent)
+      };
+      let corner = shape_length(scale);
+      let feather = feather.max(1e-3);
+      render(width, height, |x, y| {
+        let [r, g, b, a] = input.load(x as i32, y as i32);
+        // -1.0 to 1.0 across the image, measured from pixel centres
+        let position = [x, y];
+        let offset =
+          [0, 1].map(|i| ((position[i] as f32 + 0.5) / size[i] - 0.5) * 2.0 * scale[i]);
+        let distance = shape_length(offset) / corner;
+        let weight =
+          smoothstep(midpoint - feather * 0.5, midpoint + feather * 0.5, distance);
+        let gain = (amount * 2.0 * weight).exp2();
+        [r * gain, g * gain, b * gain, a]
+      })
+    }
+    NodeType::Grain => {
+      let NodeParams::Grain { amount, size, seed } = *params else {
+        return Err(invalid());
+      };
+      let grain_size = size.max(0.01) * width.min(height) as f32 / 1000.0;
+      render(width, height, |x, y| {
+        let [r, g, b, a] = input.load(x as i32, y as i32);
+        let position = [x, y].map(|v| (v as f32 + 0.5) / grain_size);
+        let [px, py] = position;
+        let rotated = [0.8 * px - 0.6 * py, 0.6 * px + 0.8 * py];
+        let grain = 0.75 * value_noise(position, seed)
+          + 0.25 * value_noise(rotated.map(|v| v * 2.0), seed.wrapping_add(1));
+
+        let centered = 2.0 * luminance([r, g, b]).clamp(0.0, 1.0) - 1.0;
+        let visibility = 0.2 + 0.8 * (1.0 - centered * centered);
+        let offset = grain * amount * 0.2 * visibility;
+        [r + offset, g + offset, b + offset, a]
+      })
+    }
+    NodeType::Dehaze => {
+      let NodeParams::Dehaze { amount } = *params else {
+        return Err(invalid());
+      };
+      let samples = dehaze::sample_positions(width, height).map(|(x, y)| {
+        let [r, g, b, _] = input.load(x as i32, y as i32);
+        [r, g, b]
+      });
+      let light = dehaze::atmospheric_light(samples);
+
+      let step = dehaze::dark_channel_step(width, height) as i32;
+      render(width, height, |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        let [r, g, b, a] = input.load(x, y);
+        let mut dark = f32::MAX;
+        for dy in -2..=2 {
+          for dx in -2..=2 {
+            let [r, g, b, _] = input.load_clamped(x + dx * step, y + dy * step);
+            let [r, g, b] = [r / light[0], g / light[1], b / light[2]];
+            dark = dark.min(r.min(g).min(b));
+          }
+        }
+
+        let rgb = [r, g, b];
+        let [r, g, b] = [0, 1, 2].map(|i| {
+          let value = if amount >= 0.0 {
+            let transmission = (1.0 - 0.95 * amount * dark.clamp(0.0, 1.0)).max(0.1);
+            (rgb[i] - light[i]) / transmission + light[i]
+          } else {
+            let transmission = 1.0 + 0.5 * amount;
+            rgb[i] * transmission + light[i] * (1.0 - transmission)
+          };
+          value.max(0.0)
+        });
+        [r, g, b, a]
+      })
+    }
+    NodeType::Resize => {
+      let (target_width, target_height) = output_dimensions;
+      let scale_x = width as f32 / target_width as f32;
+      let scale_y = height as f32 / target_height as f32;
+      let pixels = render(target_width, target_height, |x, y| {
+        sample_bilinear(input, x as f32 * scale_x, y as f32 * scale_y)
+      });
+      return Ok(CpuImage {
+        width: target_width,
+        height: target_height,
+        pixels,
+      });
+    }
+    NodeType::Crop => {
+      let NodeParams::Crop {
+        x: left, y: top, ..
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let (output_width, output_height) = output_dimensions;
+      let pixels = render(output_width, output_height, |x, y| {
+        input.load((left + x) as i32, (top + y) as i32)
+      });
+      return Ok(CpuImage {
+        width: output_width,
+        height: output_height,
+        pixels,
+      });
+    }
+    NodeType::Rotate => {
+      let NodeParams::Rotate { quarter_turns } = *params else {
+        return Err(invalid());
+      };
+      let (last_x, last_y) = (width as i32 - 1, height as i32 - 1);
+      let (output_width, output_height) = output_dimensions;
+      let pixels = render(output_width, output_height, |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        match quarter_turns % 4 {
+          1 => input.load(y, last_y - x),
+          2 => input.load(last_x - x, last_y - y),
+          3 => input.load(last_x - y, x),
+          _ => input.load(x, y),
+        }
+      });
+      return Ok(CpuImage {
+        width: output_width,
+        height: output_height,
+        pixels,
+      });
+    }
+    NodeType::Flip => {
+      let NodeParams::Flip {
+        horizontal,
+        vertical,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      render(width, height, |x, y| {
+        let source_x = if horizontal { width - 1 - x } else { x };
+        let source_y = if vertical { height - 1 - y } else { y };
+        input.load(source_x as i32, source_y as i32)
+      })
+    }
+    NodeType::Straighten => {
+      let NodeParams::Straighten {
+        angle, resampling, ..
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let (sin, cos) = angle.to_radians().sin_cos();
+      let (output_width, output_height) = output_dimensions;
+      let pixels = render(output_width, output_height, |x, y| {
+        let offset_x = x as f32 + 0.5 - output_width as f32 * 0.5;
+        let offset_y = y as f32 + 0.5 - output_height as f32 * 0.5;
+        let position = [
+          cos * offset_x + sin * offset_y + width as f32 * 0.5,
+          cos * offset_y - sin * offset_x + height as f32 * 0.5,
+        ];
+        resample(input, position, resampling)
+      });
+      return Ok(CpuImage {
+        width: output_width,
+        height: output_height,
+        pixels,
+      });
+    }
+    NodeType::Perspective => {
+      let NodeParams::Perspective {
+        corners,
+        resampling,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let [row_x, row_y, row_w] = geometry::perspective_matrix(&corners)?;
+      let (output_width, output_height) = output_dimensions;
+      let pixels = render(output_width, output_height, |x, y| {
+        let u = (x as f32 + 0.5) / output_width as f32;
+        let v = (y as f32 + 0.5) / output_height as f32;
+        let dot = |row: [f32; 3]| row[0] * u + row[1] * v + row[2];
+        let w = dot(row_w);
+        let position = [
+          dot(row_x) / w * width as f32,
+          dot(row_y) / w * height as f32,
+        ];
+        resample(input, position, resampling)
+      });
+      return Ok(CpuImage {
+        width: output_width,
+        height: output_height,
+        pixels,
+      });
+    }
+    NodeType::Mix => {
+      let NodeParams::Mix { factor, use_alpha } = *params else {
+        return Err(invalid());
+      };
+      // Like the shader binding, a missing second input reads the first one
+      let other = secondary.unwrap_or(input);
+      render(width, height, |x, y| {
+        let color = input.load(x as i32, y as i32);
+        let other_color = load_nearest(other, x, y, (width, height));
+        if use_alpha {
+          let weight = factor * other_color[3];
+          let [r, g, b] = [0, 1, 2].map(|i| mix(color[i], other_color[i], weight));
+          [r, g, b, color[3]]
+        } else {
+          [0, 1, 2, 3].map(|i| mix(color[i], other_color[i], factor))
+        }
+      })
+    }
+    NodeType::Mask => {
+      let mask = secondary.unwrap_or(input);
+      render(width, height, |x, y| {
+        let [r, g, b, a] = input.load(x as i32, y as i32);
+        let [mask_r, mask_g, mask_b, _] = load_nearest(mask, x, y, (width, height));
+        let mask_value = luminance([mask_r, mask_g, mask_b]).clamp(0.0, 1.0);
+        [r, g, b, a * mask_value]
+      })
+    }
+    NodeType::LuminanceMask => {
+      let NodeParams::LuminanceMask { low, high, feather } = *params else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, _]| {
+        let lightness = color::srgb_encode(luminance([r, g, b]).max(0.0));
+        let mask_value = range_weight(lightness, low, high, feather);
+        [mask_value, mask_value, mask_value, 1.0]
+      })
+    }
+    NodeType::ColorRangeMask => {
+      let NodeParams::ColorRangeMask {
+        hue,
+        hue_range,
+        hue_feather,
+        saturation,
+        luminance: lightness_range,
+        feather,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      map_pixels(input, |[r, g, b, _]| {
+        let linear = [r, g, b].map(|c| c.max(0.0));
+        let [pixel_hue, pixel_saturation, _] = rgb_to_hsv(linear.map(color::srgb_encode));
+        let lightness = color::srgb_encode(luminance(linear));
+
+        let hue_distance = (fract(pixel_hue - hue / 360.0 + 0.5) - 0.5).abs() * 360.0;
+        let hue_weight =
+          1.0 - smoothstep(hue_range, hue_range + hue_feather.max(1e-5), hue_distance);
+        let mask_value = hue_weight
+          * range_weight(pixel_saturation, saturation[0], saturation[1], feather)
+          * range_weight(lightness, lightness_range[0], lightness_range[1], feather);
+        [mask_value, mask_value, mask_value, 1.0]
+      })
+    }
+    NodeType::PaintedMask => {
+      let NodeParams::PaintedMask { bitmap } = params else {
+        return Err(invalid());
+      };
+      let bitmap = CpuImage {
+        width: bitmap.width,
+        height: bitmap.height,
+        pixels: bitmap.to_pixels()?,
+      };
+      render(width, height, |x, y| {
+        let source_x = x as f32 * bitmap.width as f32 / width as f32;
+        let source_y = y as f32 * bitmap.height as f32 / height as f32;
+        let mask_value = sample_bilinear(&bitmap, source_x, source_y)[0];
+        [mask_value, mask_value, mask_value, 1.0]
+      })
+    }
+    NodeType::LinearGradient => {
+      let NodeParams::LinearGradient {
+        center,
+        angle,
+        feather,
+        invert,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let (sin, cos) = angle.to_radians().sin_cos();
+      let half_feather = feather.max(1e-5) * 0.5;
+      render(width, height, |x, y| {
+        let [offset_x, offset_y] = gradient_offset(x, y, (width, height), center);
+        // Image rows grow downwards, so the angle is measured with y flipped
+        let distance = offset_x * cos - offset_y * sin;
+        let value = smoothstep(-half_feather, half_feather, distance);
+        let mask_value = if invert { 1.0 - value } else { value };
+        [mask_value, mask_value, mask_value, 1.0]
+      })
+    }
+    NodeType::RadialGradient => {
+      let NodeParams::RadialGradient {
+        center,
+        radius,
+        angle,
+        feather,
+        invert,
+      } = *params
+      else {
+        return Err(invalid());
+      };
+      let (sin, cos) = angle.to_radians().sin_cos();
+      let feather = feather.clamp(1e-5, 1.0);
+      render(width, height, |x, y| {
+        let [offset_x, offset_y] = gradient_offset(x, y, (width, height), center);
+        let local_x = (cos * offset_x - sin * offset_y) / radius[0].max(1e-5);
+        let local_y = (sin * offset_x + cos * offset_y) / radius[1].max(1e-5);
+        let distance = (local_x * local_x + local_y * local_y).sqrt();
+        let value = 1.0 - smoothstep(1.0 - feather, 1.0, distance);
+        let mask_value = if invert { 1.0 - value } else { value };
+        [mask_value, mask_value, mask_value, 1.0]
+      })
+    }
+    NodeType::Invert => map_pixels(input, |[r, g, b, a]| [1.0 - r, 1.0 - g, 1.0 - b, a]),
+    NodeType::Custom => {
+      let NodeParams::Custom { shader, .. } = params else {
+        return Err(invalid());
+      };
+      return Err(format!("Custom shader '{}' needs a GPU", shader));
+    }
+    NodeType::ImageInput | NodeType::ImageOutput => input.pixels.clone(),
+  };
+
+  Ok(CpuImage {
+    width,
+    height,
+    pixels,
+  })
+}
+
+/// Evaluate `shade` for every output pixel, spreading rows across threads
+fn render(
+  width: u32,
+  height: u32,
+  shade: impl Fn(u32, u32) -> [f32; 4] + Sync,
+) -> Vec<[f32; 4]> {
+  let mut pixels = vec![[0.0; 4]; width as usize * height as usize];
+  if pixels.is_empty() {
+    return pixels;
+  }
+
+  let threads = std::thread::available_parallelism()
+    .map(|n| n.get())
+    .unwrap_or(1);
+  let rows_per_chunk = (height as usize).div_ceil(threads);
+
+  std::thread::scope(|scope| {
+    for (chunk_index, chunk) in pixels
+      .chunks_mut(rows_per_chunk * width as usize)
+      .enumerate()
+    {
+      let shade = &shade;
+      scope.spawn(move || {
+        let first_row = chunk_index * rows_per_chunk;
+        for (i, pixel) in chunk.iter_mut().enumerate() {
+          let x = (i % width as usize) as u32;
+          let y = (first_row + i / width as usize) as u32;
+          *pixel = shade(x, y);
+        }
+      });
+    }
+  });
+
+  pixels
+}
+
+/// Apply a per-pixel color transform
+fn map_pixels(
+  input: &CpuImage,
+  transform: impl Fn([f32; 4]) -> [f32; 4] + Sync,
+) -> Vec<[f32; 4]> {
+  render(input.width, input.height, |x, y| {
+    transform(input.load(x as i32, y as i32))
+  })
+}
+
+/// Separable Gaussian blur whose radius spans two standard deviations, run as a
+/// horizontal and a vertical pass like the shader
+fn gaussian_blur(input: &CpuImage, radius: f32) -> CpuImage {
+  let extent = blur_extent(radius) as i32;
+  if extent == 0 {
+    return input.clone();
+  }
+
+  // Gaussian weights are identical for every pixel
+  let sigma = radius * 0.5;
+  let kernel: Vec<(i32, f32)> = (-extent..=extent)
+    .map(|i| (i, (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()))
+    .collect();
+  let weight_sum: f32 = kernel.iter().map(|(_, weight)| weight).sum();
+
+  let blur_pass = |image: &CpuImage, (dx, dy): (i32, i32)| CpuImage {
+    width: image.width,
+    height: image.height,
+    pixels: render(image.width, image.height, |x, y| {
+      let mut color_sum = [0.0; 4];
+      for &(i, weight) in &kernel {
+        let sample = image.load_clamped(x as i32 + dx * i, y as i32 + dy * i);
+        for c in 0..4 {
+          color_sum[c] += sample[c] * weight;
+        }
+      }
+      color_sum.map(|channel| channel / weight_sum)
+    }),
+  };
+  blur_pass(&blur_pass(input, (1, 0)), (0, 1))
+}
+
+/// Nearest neighbour sample of `image` stretched to `dimensions`, as the `mix` and
+/// `mask` shaders read their second input
+fn load_nearest(
+  image: &CpuImage,
+  x: u32,
+  y: u32,
+  (width, height): (u32, u32),
+) -> [f32; 4] {
+  let source_x = (x * image.width / width).min(image.width - 1);
+  let source_y = (y * image.height / height).min(image.height - 1);
+  image.load(source_x as i32, source_y as i32)
+}
+
+/// Offset of a pixel from a `center` given in fractions of the image size, in units of
+/// the shorter image side as the gradient shaders measure it
+fn gradient_offset(
+  x: u32,
+  y: u32,
+  (width, height): (u32, u32),
+  center: [f32; 2],
+) -> [f32; 2] {
+  let (width, height) = (width as f32, height as f32);
+  let short_side = width.min(height);
+  [
+    (x as f32 / width - center[0]) * width / short_side,
+    (y as f32 / height - center[1]) * height / short_side,
+  ]
+}
+
+/// Bilinear sample shared by the resize and crop shaders.
+///
+/// Falls back to nearest neighbour on the last row and column and returns opaque
+/// black outside the image.
+fn sample_bilinear(input: &CpuImage, source_x: f32, source_y: f32) -> [f32; 4] {
+  let x = source_x as i32;
+  let y = source_y as i32;
+
+  if x < 0 || x >= input.width as i32 || y < 0 || y >= input.height as i32 {
+    return [0.0, 0.0, 0.0, 1.0];
+  }
+  if x + 1 >= input.width as i32 || y + 1 >= input.height as i32 {
+    return input.load(x, y);
+  }
+
+  let frac_x = fract(source_x);
+  let frac_y = fract(source_y);
+  let tl = input.load(x, y);
+  let tr = input.load(x + 1, y);
+  let bl = input.load(x, y + 1);
+  let br = input.load(x + 1, y + 1);
+
+  [0, 1, 2, 3].map(|i| {
+    let top = mix(tl[i], tr[i], frac_x);
+    let bottom = mix(bl[i], br[i], frac_x);
+    mix(top, bottom, frac_y)
+  })
+}
+
+/// Read `input` at a position in pixels with centres at half-integers, as
+/// `resample.wgsl` does. Positions outside the image are transparent black.
+fn resample(input: &CpuImage, [x, y]: [f32; 2], resampling: Resampling) -> [f32; 4] {
+  if x < 0.0 || y < 0.0 || x > input.width as f32 || y > input.height as f32 {
+    return [0.0; 4];
+  }
+
+  let (texel_x, texel_y) = (x - 0.5, y - 0.5);
+  let (base_x, base_y) = (texel_x.floor(), texel_y.floor());
+  let (t_x, t_y) = (texel_x - base_x, texel_y - base_y);
+  let (origin_x, origin_y) = (base_x as i32, base_y as i32);
+
+  match resampling {
+    Resampling::Bilinear => {
+      let tl = input.load_clamped(origin_x, origin_y);
+      let tr = input.load_clamped(origin_x + 1, origin_y);
+      let bl = input.load_clamped(origin_x, origin_y + 1);
+      let br = input.load_clamped(origin_x + 1, origin_y + 1);
+      [0, 1, 2, 3].map(|i| mix(mix(tl[i], tr[i], t_x), mix(bl[i], br[i], t_x), t_y))
+    }
+    Resampling::Bicubic => {
+      let (weights_x, weights_y) = (cubic_weights(t_x), cubic_weights(t_y));
+      let mut color = [0.0; 4];
+      for (j, weight_y) in weights_y.iter().enumerate() {
+        let mut row = [0.0; 4];
+        for (i, weight_x) in weights_x.iter().enumerate() {
+          let sample =
+            input.load_clamped(origin_x + i as i32 - 1, origin_y + j as i32 - 1);
+          for c in 0..4 {
+            row[c] += sample[c] * weight_x;
+          }
+        }
+        for c in 0..4 {
+          color[c] += row[c] * weight_y;
+        }
+      }
+      color
+    }
+  }
+}
+
+/// Catmull-Rom weights of the four pixels around a position `t` past the second one
+fn cubic_weights(t: f32) -> [f32; 4] {
+  let t2 = t * t;
+  let t3 = t2 * t;
+  [
+    -0.5 * t3 + t2 - 0.5 * t,
+    1.5 * t3 - 2.5 * t2 + 1.0,
+    -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+    0.5 * t3 - 0.5 * t2,
+  ]
+}
+
+fn rgb_to_hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+  let max_val = r.max(g).max(b);
+  let min_val = r.min(g).min(b);
+  let delta = max_val - min_val;
+
+  let mut hue = 0.0;
+  let saturation = if max_val != 0.0 { delta / max_val } else { 0.0 };
+
+  if delta != 0.0 {
+    if max_val == r {
+      hue = (g - b) / delta;
+      if g < b {
+        hue += 6.0;
+      }
+    } else if max_val == g {
+      hue = (b - r) / delta + 2.0;
+    } else {
+      hue = (r - g) / delta + 4.0;
+    }
+    hue /= 6.0;
+  }
+
+  [hue, saturation, max_val]
+}
+
+fn hsv_to_rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+  let h = h * 6.0;
+  let c = v * s;
+  let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+  let m = v - c;
+
+  let rgb = if h < 1.0 {
+    [c, x, 0.0]
+  } else if h < 2.0 {
+    [x, c, 0.0]
+  } else if h < 3.0 {
+    [0.0, c, x]
+  } else if h < 4.0 {
+    [0.0, x, c]
+  } else if h < 5.0 {
+    [x, 0.0, c]
+  } else {
+    [c, 0.0, x]
+  };
+
+  rgb.map(|channel| channel + m)
+}
+
+const MIDDLE_GREY: f32 = 0.18;
+
+/// Multiply a color by a matrix given as columns, like a WGSL `mat3x3`
+fn mat3_mul(columns: [[f32; 3]; 3], [r, g, b]: [f32; 3]) -> [f32; 3] {
+  [0, 1, 2].map(|i| columns[0][i] * r + columns[1][i] * g + columns[2][i] * b)
+}
+
+/// Minimal AgX, as `agx` in `tone_map.wgsl`
+fn agx(rgb: [f32; 3]) -> [f32; 3] {
+  const MIN_EV: f32 = -12.47393;
+  const MAX_EV: f32 = 4.026069;
+  let inset = [
+    [0.842479062253094, 0.0423282422610123, 0.0423756549057051],
+    [0.0784335999999992, 0.878468636469772, 0.0784336],
+    [0.0792237451477643, 0.0791661274605434, 0.879142973793104],
+  ];
+  let outset = [
+    [1.19687900512017, -0.0528968517574562, -0.0529716355144438],
+    [-0.0980208811401368, 1.15190312990417, -0.0980434501171241],
+    [-0.0990297440797205, -0.0989611768448433, 1.15107367264116],
+  ];
+
+  let encoded = mat3_mul(inset, rgb).map(|x| {
+    let x = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+      + 0.4298 * x2
+      + 0.1191 * x
+      - 0.00232
+  });
+  mat3_mul(outset, encoded).map(|x| x.max(0.0).powf(2.2))
+}
+
+/// Fit of the ACES RRT and sRGB ODT, as `aces` in `tone_map.wgsl`
+fn aces(rgb: [f32; 3]) -> [f32; 3] {
+  let input_matrix = [
+    [0.59719, 0.07600, 0.02840],
+    [0.35458, 0.90834, 0.13383],
+    [0.04823, 0.01566, 0.83777],
+  ];
+  let output_matrix = [
+    [1.60475, -0.10208, -0.00327],
+    [-0.53108, 1.10813, -0.07276],
+    [-0.07367, -0.00605, 1.07602],
+  ];
+
+  let fitted = mat3_mul(input_matrix, rgb).map(|v| {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    a / b
+  });
+  mat3_mul(output_matrix, fitted)
+}
+
+/// Rec. 709 luminance of linear RGB
+/// Integer hash of a lattice point to -1.0..1.0, as in the grain shader
+fn hash(x: u32, y: u32, seed: u32) -> f32 {
+  let mut h = x.wrapping_mul(0x8da6b343)
+    ^ y.wrapping_mul(0xd8163841)
+    ^ seed.wrapping_mul(0xcb1ab31f);
+  h = (h ^ (h >> 16)).wrapping_mul(0x7feb352d);
+  h = (h ^ (h >> 15)).wrapping_mul(0x846ca68b);
+  h ^= h >> 16;
+  (h >> 8) as f32 / 8388607.5 - 1.0
+}
+
+/// Smoothly interpolated noise between the hashed lattice points
+fn value_noise([x, y]: [f32; 2], seed: u32) -> f32 {
+  let (cell_x, cell_y) = (x.floor(), y.floor());
+  let t = [x - cell_x, y - cell_y].map(|f| f * f * (3.0 - 2.0 * f));
+  let (x, y) = (cell_x as i32 as u32, cell_y as i32 as u32);
+
+  let (next_x, next_y) = (x.wrapping_add(1), y.wrapping_add(1));
+  let top = mix(hash(x, y, seed), hash(next_x, y, seed), t[0]);
+  let bottom = mix(hash(x, next_y, seed), hash(next_x, next_y, seed), t[0]);
+  mix(top, bottom, t[1])
+}
+
+fn luminance([r, g, b]: [f32; 3]) -> f32 {
+  0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// 1.0 inside `low..high`, fading to 0.0 over `feather` outside of it, as in the mask
+/// generator shaders
+fn range_weight(value: f32, low: f32, high: f32, feather: f32) -> f32 {
+  let softness = feather.max(1e-5);
+  smoothstep(low - softness, low, value)
+    * (1.0 - smoothstep(high, high + softness, value))
+}
+
+/// WGSL `mix`
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+  a * (1.0 - t) + b * t
+}
+
+/// WGSL `fract`
+fn fract(x: f32) -> f32 {
+  x - x.floor()
+}
+
+/// WGSL `smoothstep`
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+  let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::grading::GradingWheel;
+  use crate::hsl::{HslAdjustment, HslBands};
+  use crate::mask::MaskBitmap;
+  use crate::shade::{ImagePipeline, request_device};
+  use crate::white_balance::WhiteBalanceMethod;
+  use std::path::{Path, PathBuf};
+  use wgpu::{Device, Queue};
+
+  fn gradient(width: u32, height: u32) -> CpuImage {
+    let pixels = (0..width * height)
+      .map(|i| {
+        let (x, y) = (i % width, i / width);
+        [
+          x as f32 / width as f32 * 1.2,
+          y as f32 / height as f32,
+          (x + y) as f32 / (width + height) as f32,
+          1.0 - x as f32 / (width * 2) as f32,
+        ]
+      })
+      .collect();
+    CpuImage {
+      width,
+      height,
+      pixels,
+    }
+  }
+
+  #[test]
+  fn test_byte_roundtrip() {
+    let image = gradient(5, 3);
+    let bytes = image.to_bytes();
+    assert_eq!(bytes.len(), 5 * 3 * BYTES_PER_PIXEL as usize);
+    assert_eq!(CpuImage::from_bytes(&bytes, (5, 3)), image);
+  }
+
+  #[test]
+  fn test_invert_and_mix() {
+    let image = gradient(4, 4);
+
+    let inverted =
+      process_node(&NodeType::Invert, &NodeParams::None, &image, None, (4, 4)).unwrap();
+    assert_eq!(inverted.pixels[5][0], 1.0 - image.pixels[5][0]);
+    assert_eq!(inverted.pixels[5][3], image.pixels[5][3]);
+
+    // Mixing an image halfway with its inverse gives mid grey
+    let mixed = process_node(
+      &NodeType::Mix,
+      &NodeParams::Mix {
+        factor: 0.5,
+        use_alpha: false,
+      },
+      &image,
+      Some(&inverted),
+      (4, 4),
+    )
+    .unwrap();
+    for pixel in &mixed.pixels {
+      assert!(pixel[..3].iter().all(|c| (c - 0.5).abs() < 1e-6));
+    }
+  }
+
+  #[test]
+  fn test_masked_local_adjustment() {
+    let image = gradient(4, 4);
+    let inverted =
+      process_node(&NodeType::Invert, &NodeParams::None, &image, None, (4, 4)).unwrap();
+
+    // Select the left half of the image with a painted mask
+    let bitmap = MaskBitmap {
+      width: 2,
+      height: 1,
+      data: vec![255, 0],
+    };
+    let mask = process_node(
+      &NodeType::PaintedMask,
+      &NodeParams::PaintedMask { bitmap },
+      &image,
+      None,
+      (4, 4),
+    )
+    .unwrap();
+    let masked = process_node(
+      &NodeType::Mask,
+      &NodeParams::None,
+      &inverted,
+      Some(&mask),
+      (4, 4),
+    )
+    .unwrap();
+    let mixed = process_node(
+      &NodeType::Mix,
+      &NodeParams::Mix {
+        factor: 1.0,
+        use_alpha: true,
+      },
+      &image,
+      Some(&masked),
+      (4, 4),
+    )
+    .unwrap();
+
+    // Only the selected pixels are inverted, and the alpha of the image is kept
+    assert_eq!(mixed.pixels[0][..3], inverted.pixels[0][..3]);
+    assert_eq!(mixed.pixels[0][3], image.pixels[0][3]);
+    assert_eq!(mixed.pixels[3], image.pixels[3]);
+  }
+
+  #[test]
+  fn test_mask_generators() {
+    let image = CpuImage {
+      width: 3,
+      height: 1,
+      pixels: vec![
+        [0.9, 0.05, 0.05, 1.0],
+        [0.05, 0.05, 0.9, 1.0],
+        [0.01, 0.01, 0.01, 1.0],
+      ],
+    };
+
+    let reds = process_node(
+      &NodeType::ColorRangeMask,
+      &NodeParams::ColorRangeMask {
+        hue: 0.0,
+        hue_range: 30.0,
+        hue_feather: 20.0,
+        saturation: [0.1, 1.0],
+        luminance: [0.0, 1.0],
+        feather: 0.1,
+      },
+      &image,
+      None,
+      (3, 1),
+    )
+    .unwrap();
+    assert_eq!(reds.pixels[0], [1.0; 4]);
+    assert_eq!(reds.pixels[1][0], 0.0);
+    assert_eq!(reds.pixels[2][0], 0.0);
+
+    let shadows = process_node(
+      &NodeType::LuminanceMask,
+      &NodeParams::LuminanceMask {
+        low: 0.0,
+        high: 0.2,
+        feather: 0.05,
+      },
+      &image,
+      None,
+      (3, 1),
+    )
+    .unwrap();
+    assert_eq!(shadows.pixels[0][0], 0.0);
+    assert_eq!(shadows.pixels[2][0], 1.0);
+  }
+
+  #[test]
+  fn test_resize_dimensions() {
+    let image = gradient(8, 6);
+    let resized = process_node(
+      &NodeType::Resize,
+      &NodeParams::Resize {
+        width: Some(4),
+        height: Some(3),
+      },
+      &image,
+      None,
+      (4, 3),
+    )
+    .unwrap();
+
+    assert_eq!(resized.dimensions(), (4, 3));
+    assert_eq!(resized.pixels.len(), 12);
+  }
+
+  #[test]
+  fn test_geometric_transforms() {
+    let image = gradient(5, 3);
+    let run = |node_type: NodeType, params: NodeParams, input: &CpuImage| {
+      let dimensions = match &params {
+        NodeParams::Crop {
+          x,
+          y,
+          width,
+          height,
+        } => geometry::cropped_dimensions(input.dimensions(), [*x, *y, *width, *height])
+          .unwrap(),
+        NodeParams::Rotate { quarter_turns } => {
+          geometry::rotated_dimensions(input.dimensions(), *quarter_turns)
+        }
+        NodeParams::Straighten { angle, crop, .. } => {
+          geometry::straightened_dimensions(input.dimensions(), *angle, *crop)
+        }
+        NodeParams::Perspective { corners, .. } => {
+          geometry::perspective_dimensions(input.dimensions(), corners)
+        }
+        _ => input.dimensions(),
+      };
+      process_node(&node_type, &params, input, None, dimensions).unwrap()
+    };
+
+    // A crop reaching past the bottom edge is clamped to the image
+    let cropped = run(
+      NodeType::Crop,
+      NodeParams::Crop {
+        x: 1,
+        y: 1,
+        width: 3,
+        height: 5,
+      },
+      &image,
+    );
+    assert_eq!(cropped.dimensions(), (3, 2));
+    assert_eq!(cropped.load(0, 0), image.load(1, 1));
+    assert_eq!(cropped.load(2, 1), image.load(3, 2));
+
+    // A clockwise quarter turn moves the top left pixel to the top right
+    let turned = run(
+      NodeType::Rotate,
+      NodeParams::Rotate { quarter_turns: 1 },
+      &image,
+    );
+    assert_eq!(turned.dimensions(), (3, 5));
+    assert_eq!(turned.load(2, 0), image.load(0, 0));
+    assert_eq!(turned.load(0, 4), image.load(4, 2));
+    let back = run(
+      NodeType::Rotate,
+      NodeParams::Rotate { quarter_turns: 3 },
+      &turned,
+    );
+    assert_eq!(back, image);
+
+    let flipped = run(
+      NodeType::Flip,
+      NodeParams::Flip {
+        horizontal: true,
+        vertical: true,
+      },
+      &image,
+    );
+    assert_eq!(flipped.load(0, 0), image.load(4, 2));
+    assert_eq!(flipped.load(3, 1), image.load(1, 1));
+
+    // Transforms that keep every pixel centre in place reproduce the image
+    for resampling in [Resampling::Bilinear, Resampling::Bicubic] {
+      let straightened = run(
+        NodeType::Straighten,
+        NodeParams::Straighten {
+          angle: 0.0,
+          crop: true,
+          resampling,
+        },
+        &image,
+      );
+      assert_eq!(straightened, image);
+
+      let corrected = run(
+        NodeType::Perspective,
+        NodeParams::Perspective {
+          corners: geometry::FULL_FRAME,
+          resampling,
+        },
+        &image,
+      );
+      for (pixel, expected) in corrected.pixels.iter().zip(&image.pixels) {
+        for i in 0..4 {
+          assert!((pixel[i] - expected[i]).abs() < 1e-5, "{:?}", resampling);
+        }
+      }
+    }
+
+    // Straightening by a right angle matches a quarter turn
+    let straightened = run(
+      NodeType::Straighten,
+      NodeParams::Straighten {
+        angle: 90.0,
+        crop: false,
+        resampling: Resampling::Bilinear,
+      },
+      &image,
+    );
+    assert_eq!(straightened.dimensions(), turned.dimensions());
+    for (pixel, expected) in straightened.pixels.iter().zip(&turned.pixels) {
+      for i in 0..4 {
+        assert!((pixel[i] - expected[i]).abs() < 1e-5);
+      }
+    }
+
+    // Corners uncovered by a rotation without crop are transparent
+    let expanded = run(
+      NodeType::Straighten,
+      NodeParams::Straighten {
+        angle: 30.0,
+        crop: false,
+        resampling: Resampling::Bicubic,
+      },
+      &gradient(40, 30),
+    );
+    assert_eq!(expanded.dimensions(), (50, 46));
+    assert_eq!(expanded.load(0, 0), [0.0; 4]);
+    assert!(expanded.load(25, 23)[3] > 0.0);
+  }
+
+  #[test]
+  fn test_hsl_changes_selected_band() {
+    let image = CpuImage {
+      width: 3,
+      height: 1,
+      pixels: vec![
+        [0.9, 0.1, 0.1, 1.0],
+        [0.1, 0.1, 0.9, 1.0],
+        [0.5, 0.5, 0.5, 1.0],
+      ],
+    };
+    let mut bands = HslBands::default();
+    bands.blue.saturation = -1.0;
+
+    let adjusted = process_node(
+      &NodeType::Hsl,
+      &NodeParams::Hsl { bands },
+      &image,
+      None,
+      (3, 1),
+    )
+    .unwrap();
+
+    // Blue loses all colour, while reds and greys are left alone
+    let [r, g, b, _] = adjusted.pixels[1];
+    assert!((r - b).abs() < 1e-5 && (g - b).abs() < 1e-5);
+    for i in [0, 2] {
+      let unchanged =
+        (0..4).all(|c| (adjusted.pixels[i][c] - image.pixels[i][c]).abs() < 1e-5);
+      assert!(unchanged, "{:?}", adjusted.pixels[i]);
+    }
+  }
+
+  #[test]
+  fn test_vibrance_spares_saturated_colours_and_skin() {
+    let image = CpuImage {
+      width: 3,
+      height: 1,
+      pixels: vec![
+        [0.4, 0.55, 0.8, 1.0],
+        [0.8, 0.55, 0.4, 1.0],
+        [0.9, 0.05, 0.05, 1.0],
+      ],
+    };
+    let adjusted = process_node(
+      &NodeType::Vibrance,
+      &NodeParams::Vibrance { amount: 1.0 },
+      &image,
+      None,
+      (3, 1),
+    )
+    .unwrap();
+
+    let spread = |[r, g, b, _]: [f32; 4]| r.max(g).max(b) - r.min(g).min(b);
+    let gain = |i: usize| spread(adjusted.pixels[i]) / spread(image.pixels[i]);
+    // The muted sky gains more than the skin tone of the same saturation, and more
+    // than the already saturated red
+    assert!(gain(0) > gain(1) + 0.1, "{} {}", gain(0), gain(1));
+    assert!(gain(0) > gain(2) + 0.1, "{} {}", gain(0), gain(2));
+    assert!(gain(1) >= 1.0 && gain(2) >= 1.0);
+  }
+
+  #[test]
+  fn test_color_grading_wheels() {
+    let image = CpuImage {
+      width: 3,
+      height: 1,
+      pixels: vec![
+        [0.03, 0.03, 0.03, 1.0],
+        [0.2, 0.2, 0.2, 1.0],
+        [0.9, 0.9, 0.9, 1.0],
+      ],
+    };
+    let params = NodeParams::ColorGrading {
+      shadows: GradingWheel {
+        hue: 210.0,
+        saturation: 1.0,
+        luminance: 0.0,
+      },
+      midtones: GradingWheel::default(),
+      highlights: GradingWheel {
+        hue: 0.0,
+        saturation: 0.0,
+        luminance: 0.5,
+      },
+      balance: 0.0,
+      blending: 0.2,
+    };
+    let graded =
+      process_node(&NodeType::ColorGrading, &params, &image, None, (3, 1)).unwrap();
+
+    // Shadows turn blue, midtones are left alone and highlights get brighter
+    let [r, _, b, _] = graded.pixels[0];
+    assert!(b > r + 0.01, "{:?}", graded.pixels[0]);
+    let unchanged =
+      (0..4).all(|c| (graded.pixels[1][c] - image.pixels[1][c]).abs() < 1e-5);
+    assert!(unchanged, "{:?}", graded.pixels[1]);
+    assert!(graded.pixels[2].iter().take(3).all(|&c| c > 0.95));
+  }
+
+  #[test]
+  fn test_white_balance_estimation() {
+    // A warm cast over a range of brightness, with a grey card on the left
+    let width = 32;
+    let pixels = (0..width * width)
+      .map(|i| {
+        let (x, y) = (i % width, i / width);
+        let level = 0.2 + 0.5 * y as f32 / width as f32;
+        if x < 8 {
+          [0.5, 0.4, 0.3, 1.0]
+        } else {
+          [level * 1.2, level, level * 0.8, 1.0]
+        }
+      })
+      .collect();
+    let image = CpuImage {
+      width,
+      height: width,
+      pixels,
+    };
+    let balance = |auto_adjust, method, neutral_point, kelvin| {
+      let params = NodeParams::WhiteBalance {
+        auto_adjust,
+        temperature: 0.0,
+        tint: 0.0,
+        method,
+        neutral_point,
+        kelvin,
+        as_shot_kelvin: None,
+      };
+      process_node(
+        &NodeType::WhiteBalance,
+        &params,
+        &image,
+        None,
+        (width, width),
+      )
+      .unwrap()
+    };
+    let spread = |[r, g, b, _]: [f32; 4]| r.max(g).max(b) - r.min(g).min(b);
+
+    // The cast covers most of the image, so both estimates nearly remove it
+    for method in [
+      WhiteBalanceMethod::GreyWorld,
+      WhiteBalanceMethod::WhitePatch,
+    ] {
+      let balanced = balance(true, method, None, None);
+      let pixel = balanced.load(20, 16);
+      assert!(
+        spread(pixel) < 0.2 * spread(image.load(20, 16)),
+        "{:?}",
+        pixel
+      );
+    }
+
+    // Picking the grey card makes it exactly grey
+    let picked = balance(false, WhiteBalanceMethod::GreyWorld, Some([0.1, 0.5]), None);
+    assert!(
+      spread(picked.load(3, 16)) < 1e-6,
+      "{:?}",
+      picked.load(3, 16)
+    );
+
+    // Balancing a D65 image for D65 light changes nothing, for tungsten light cools
+    let unchanged = balance(false, WhiteBalanceMethod::GreyWorld, None, Some(6500.0));
+    assert!((spread(unchanged.load(3, 0)) - spread(image.load(3, 0))).abs() < 1e-4);
+    let tungsten = balance(false, WhiteBalanceMethod::GreyWorld, None, Some(3200.0));
+    let [r, _, b, _] = tungsten.load(3, 0);
+    assert!(b > r, "{:?}", tungsten.load(3, 0));
+  }
+
+  #[test]
+  fn test_blur() {
+    let image = gradient(6, 5);
+    let unchanged = process_node(
+      &NodeType::Blur,
+      &NodeParams::Blur { radius: 0.0 },
+      &image,
+      None,
+      (6, 5),
+    )
+    .unwrap();
+    assert_eq!(unchanged, image);
+
+    // A single point spreads into a symmetric kernel that keeps its energy
+    let mut point = CpuImage {
+      width: 9,
+      height: 9,
+      pixels: vec![[0.0; 4]; 81],
+    };
+    point.pixels[40] = [1.0; 4];
+    let blurred = process_node(
+      &NodeType::Blur,
+      &NodeParams::Blur { radius: 2.0 },
+      &point,
+      None,
+      (9, 9),
+    )
+    .unwrap();
+    let total: f32 = blurred.pixels.iter().map(|pixel| pixel[0]).sum();
+    assert!((total - 1.0).abs() < 1e-5);
+    assert!((blurred.pixels[39][0] - blurred.pixels[31][0]).abs() < 1e-7);
+    assert!(blurred.pixels[40][0] > blurred.pixels[39][0]);
+  }
+
+  #[test]
+  fn test_unsharp_mask_and_clarity() {
+    // A soft vertical edge between two greys
+    let edge = CpuImage {
+      width: 8,
+      height: 1,
+      pixels: (0..8)
+        .map(|x| {
+          let value = [0.2, 0.2, 0.2, 0.25, 0.35, 0.4, 0.4, 0.4][x];
+          [value, value, value, 1.0]
+        })
+        .collect(),
+    };
+
+    let sharpen = |threshold: f32| {
+      process_node(
+        &NodeType::UnsharpMask,
+        &NodeParams::UnsharpMask {
+          amount: 1.0,
+          radius: 2.0,
+          threshold,
+        },
+        &edge,
+        None,
+        (8, 1),
+      )
+      .unwrap()
+    };
+
+    // The dark side of the edge gets darker and the bright side brighter
+    let sharpened = sharpen(0.0);
+    assert!(sharpened.pixels[2][0] < edge.pixels[2][0]);
+    assert!(sharpened.pixels[5][0] > edge.pixels[5][0]);
+    assert_eq!(sharpened.pixels[0][3], 1.0);
+
+    // Above the threshold the edge is left alone
+    let thresholded = sharpen(0.5);
+    for (pixel, original) in thresholded.pixels.iter().zip(&edge.pixels) {
+      assert!((pixel[0] - original[0]).abs() < 1e-5);
+    }
+
+    let unchanged = process_node(
+      &NodeType::Clarity,
+      &NodeParams::Clarity {
+        amount: 0.0,
+        radius: 4.0,
+      },
+      &edge,
+      None,
+      (8, 1),
+    )
+    .unwrap();
+    for (pixel, original) in unchanged.pixels.iter().zip(&edge.pixels) {
+      assert!((pixel[0] - original[0]).abs() < 1e-5);
+    }
+  }
+
+  #[test]
+  fn test_denoise() {
+    // Grain on a dark grey next to a hard edge to a light grey
+    let noisy = CpuImage {
+      width: 8,
+      height: 4,
+      pixels: (0..32)
+        .map(|i| {
+          let grain = if (i + i / 8) % 2 == 0 { 0.02 } else { -0.02 };
+          let value = if i % 8 < 4 { 0.1 + grain } else { 0.6 };
+          [value, value, value, 1.0]
+        })
+        .collect(),
+    };
+    let denoised = process_node(
+      &NodeType::Denoise,
+      &NodeParams::Denoise {
+        luminance: 0.5,
+        chroma: 0.5,
+        radius: 2.0,
+      },
+      &noisy,
+      None,
+      (8, 4),
+    )
+    .unwrap();
+
+    // The grain is smoothed out while the edge stays sharp
+    let spread = |image: &CpuImage| {
+      let dark = image.pixels.iter().enumerate().filter(|(i, _)| i % 8 < 4);
+      let values: Vec<f32> = dark.map(|(_, pixel)| pixel[1]).collect();
+      values.iter().cloned().fold(f32::MIN, f32::max)
+        - values.iter().cloned().fold(f32::MAX, f32::min)
+    };
+    assert!(spread(&denoised) < spread(&noisy) * 0.5);
+    assert!(denoised.pixels[3][1] < 0.15);
+    assert!((denoised.pixels[4][1] - 0.6).abs() < 0.01);
+    assert_eq!(denoised.pixels[0][3], 1.0);
+  }
+
+  #[test]
+  fn test_effects_scale_with_the_image() {
+    let grey = |width: u32, height: u32| CpuImage {
+      width,
+      height,
+      pixels: vec![[0.2, 0.2, 0.2, 1.0]; (width * height) as usize],
+    };
+    let run = |node_type: NodeType, params: NodeParams, image: &CpuImage| {
+      process_node(&node_type, &params, image, None, image.dimensions()).unwrap()
+    };
+
+    // The vignette darkens the corners by the same amount on a proxy
+    let vignette = NodeParams::Vignette {
+      amount: -0.5,
+      midpoint: 0.5,
+      roundness: 0.0,
+      feather: 0.5,
+    };
+    let proxy = run(NodeType::Vignette, vignette.clone(), &grey(40, 30));
+    let full = run(NodeType::Vignette, vignette, &grey(160, 120));
+    assert!(proxy.pixels[0][0] < 0.15);
+    assert!((proxy.pixels[0][0] - full.pixels[0][0]).abs() < 0.01);
+    assert_eq!(proxy.load(20, 15)[0], 0.2);
+
+    // Grain follows the image: a proxy pixel matches the four pixels around the same
+    // point at four times the resolution, to well within the grain strength
+    let grain = NodeParams::Grain {
+      amount: 0.5,
+      size: 50.0,
+      seed: 3,
+    };
+    let proxy = run(NodeType::Grain, grain.clone(), &grey(40, 30));
+    let full = run(NodeType::Grain, grain, &grey(160, 120));
+    let (mut difference, mut strength) = (0.0, 0.0);
+    for y in 0..30 {
+      for x in 0..40 {
+        let block = [(1, 1), (2, 1), (1, 2), (2, 2)];
+        let full_pixel: f32 = block
+          .iter()
+          .map(|(dx, dy)| full.load(x * 4 + dx, y * 4 + dy)[1])
+          .sum();
+        difference += (proxy.load(x, y)[1] - full_pixel / 4.0).abs();
+        strength += (proxy.load(x, y)[1] - 0.2).abs();
+      }
+    }
+    assert!(difference < strength * 0.1);
+
+    // A hazy sky over a dark red seen through half the haze gets its colour back
+    let mut hazy = grey(32, 32);
+    for (i, pixel) in hazy.pixels.iter_mut().enumerate() {
+      *pixel = if i < 16 * 32 {
+        [0.8, 0.8, 0.8, 1.0]
+      } else {
+        [0.55, 0.45, 0.4, 1.0]
+      };
+    }
+    let dehazed = run(NodeType::Dehaze, NodeParams::Dehaze { amount: 1.0 }, &hazy);
+    let [r, g, b, _] = dehazed.load(16, 28);
+    assert!((r - 0.324).abs() < 0.01 && (g - 0.133).abs() < 0.01 && b < 0.05);
+    assert!((dehazed.load(16, 2)[0] - 0.8).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_gradient_masks() {
+    let image = gradient(8, 6);
+    let top = process_node(
+      &NodeType::LinearGradient,
+      &NodeParams::LinearGradient {
+        center: [0.5, 0.5],
+        angle: 90.0,
+        feather: 0.2,
+        invert: false,
+      },
+      &image,
+      None,
+      (8, 6),
+    )
+    .unwrap();
+    assert_eq!(top.pixels[0], [1.0; 4]);
+    assert_eq!(top.pixels[47][0], 0.0);
+
+    let radial = NodeParams::RadialGradient {
+      center: [0.5, 0.5],
+      radius: [0.4, 0.3],
+      angle: 30.0,
+      feather: 0.5,
+      invert: true,
+    };
+    let vignette =
+      process_node(&NodeType::RadialGradient, &radial, &image, None, (8, 6)).unwrap();
+    assert_eq!(vignette.pixels[3 * 8 + 4][0], 0.0);
+    assert_eq!(vignette.pixels[0][0], 1.0);
+
+    // Parameters are normalised, so a proxy gets the same mask as the full image
+    let full = gradient(16, 12);
+    let full_vignette =
+      process_node(&NodeType::RadialGradient, &radial, &full, None, (16, 12)).unwrap();
+    for y in 0..6 {
+      for x in 0..8 {
+        let proxy = vignette.pixels[y * 8 + x][0];
+        let full = full_vignette.pixels[y * 2 * 16 + x * 2][0];
+        assert!((proxy - full).abs() < 1e-5);
+      }
+    }
+  }
+
+  #[test]
+  fn test_mismatched_params() {
+    let image = gradient(2, 2);
+    let result = process_node(
+      &NodeType::Brightness,
+      &NodeParams::None,
+      &image,
+      None,
+      (2, 2),
+    );
+    assert!(result.is_err());
+  }
+
+  /// Write a size 5 table with a non-linear response, so the interpolation modes differ
+  fn write_lut(name: &str) -> PathBuf {
+    let lut_path = std::env::temp_dir().join(name);
+    let mut cube = "LUT_3D_SIZE 5\nDOMAIN_MAX 1.2 1.0 1.0\n".to_string();
+    for i in 0..125 {
+      let [r, g, b] = [i % 5, i / 5 % 5, i / 25].map(|v| v as f32 / 4.0);
+      cube.push_str(&format!("{} {} {}\n", g * g, b.sqrt(), r * 0.5 + b * 0.5));
+    }
+    std::fs::write(&lut_path, cube).unwrap();
+    lut_path
+  }
+
+  /// Every node type, with parameters covering its modes
+  fn node_cases(lut_path: &Path) -> Vec<(NodeType, NodeParams)> {
+    vec![
+      (NodeType::Exposure, NodeParams::Exposure { stops: 1.5 }),
+      (NodeType::Brightness, NodeParams::Brightness { value: 0.3 }),
+      (NodeType::Contrast, NodeParams::Contrast { value: 1.4 }),
+      (NodeType::Saturation, NodeParams::Saturation { value: 1.5 }),
+      (NodeType::Vibrance, NodeParams::Vibrance { amount: 0.8 }),
+      (NodeType::Vibrance, NodeParams::Vibrance { amount: -0.5 }),
+      (NodeType::Hue, NodeParams::Hue { value: 45.0 }),
+      (
+        NodeType::Hsl,
+        NodeParams::Hsl {
+          bands: HslBands {
+            red: HslAdjustment {
+              hue: 10.0,
+              saturation: 0.3,
+              luminance: -0.2,
+            },
+            blue: HslAdjustment {
+              hue: -20.0,
+              saturation: -0.5,
+              luminance: 0.4,
+            },
+            ..HslBands::default()
+          },
+        },
+      ),
+      (NodeType::Gamma, NodeParams::Gamma { value: 2.2 }),
+      (
+        NodeType::Levels,
+        NodeParams::Levels {
+          input_black: 0.1,
+          input_white: 0.9,
+          output_black: 0.05,
+          output_white: 0.95,
+        },
+      ),
+      (
+        NodeType::Curves,
+        NodeParams::Curves {
+          master: vec![[0.0, 0.05], [0.4, 0.5], [1.0, 0.9]],
+          red: vec![[0.0, 0.0], [0.5, 0.6], [1.0, 1.0]],
+          green: Vec::new(),
+          blue: vec![[0.0, 0.1], [1.0, 0.8]],
+        },
+      ),
+      (
+        NodeType::ColorBalance,
+        NodeParams::ColorBalance {
+          shadows: [0.1, -0.2, 0.3],
+          midtones: [-0.1, 0.2, 0.0],
+          highlights: [0.2, 0.1, -0.3],
+        },
+      ),
+      (
+        NodeType::ColorGrading,
+        NodeParams::ColorGrading {
+          shadows: GradingWheel {
+            hue: 220.0,
+            saturation: 0.6,
+            luminance: 0.1,
+          },
+          midtones: GradingWheel {
+            hue: -40.0,
+            saturation: 0.3,
+            luminance: -0.2,
+          },
+          highlights: GradingWheel {
+            hue: 45.0,
+            saturation: 0.8,
+            luminance: 0.3,
+          },
+          balance: 0.4,
+          blending: 0.8,
+        },
+      ),
+      (
+        NodeType::WhiteBalance,
+        NodeParams::WhiteBalance {
+          auto_adjust: true,
+          temperature: 0.0,
+          tint: 0.0,
+          method: WhiteBalanceMethod::GreyWorld,
+          neutral_point: None,
+          kelvin: None,
+          as_shot_kelvin: None,
+        },
+      ),
+      (
+        NodeType::WhiteBalance,
+        NodeParams::WhiteBalance {
+          auto_adjust: true,
+          temperature: 0.0,
+          tint: 0.0,
+          method: WhiteBalanceMethod::WhitePatch,
+          neutral_point: None,
+          kelvin: None,
+          as_shot_kelvin: None,
+        },
+      ),
+      (
+        NodeType::WhiteBalance,
+        NodeParams::WhiteBalance {
+          auto_adjust: false,
+          temperature: 0.0,
+          tint: 0.0,
+          method: WhiteBalanceMethod::GreyWorld,
+          neutral_point: Some([0.3, 0.6]),
+          kelvin: None,
+          as_shot_kelvin: None,
+        },
+      ),
+      (
+        NodeType::WhiteBalance,
+        NodeParams::WhiteBalance {
+          auto_adjust: false,
+          temperature: 0.4,
+          tint: -0.2,
+          method: WhiteBalanceMethod::GreyWorld,
+          neutral_point: None,
+          kelvin: None,
+          as_shot_kelvin: None,
+        },
+      ),
+      (
+        NodeType::WhiteBalance,
+        NodeParams::WhiteBalance {
+          auto_adjust: false,
+          temperature: 0.0,
+          tint: 0.1,
+          method: WhiteBalanceMethod::GreyWorld,
+          neutral_point: None,
+          kelvin: Some(4200.0),
+          as_shot_kelvin: Some(5200.0),
+        },
+      ),
+      (
+        NodeType::Lut3D,
+        NodeParams::Lut3D {
+          path: lut_path.to_path_buf(),
+          intensity: 0.8,
+          interpolation: lut::LutInterpolation::Trilinear,
+        },
+      ),
+      (
+        NodeType::Lut3D,
+        NodeParams::Lut3D {
+          path: lut_path.to_path_buf(),
+          intensity: 1.0,
+          interpolation: lut::LutInterpolation::Tetrahedral,
+        },
+      ),
+      (
+        NodeType::ToneMap,
+        NodeParams::ToneMap {
+          operator: ToneMapOperator::Reinhard,
+          white_point: 4.0,
+          contrast: 1.2,
+        },
+      ),
+      (
+        NodeType::ToneMap,
+        NodeParams::ToneMap {
+          operator: ToneMapOperator::Filmic,
+          white_point: 16.0,
+          contrast: 1.0,
+        },
+      ),
+      (
+        NodeType::ToneMap,
+        NodeParams::ToneMap {
+          operator: ToneMapOperator::Aces,
+          white_point: 8.0,
+          contrast: 0.9,
+        },
+      ),
+      (
+        NodeType::Denoise,
+        NodeParams::Denoise {
+          luminance: 0.5,
+          chroma: 0.8,
+          radius: 2.0,
+        },
+      ),
+      (
+        NodeType::Denoise,
+        NodeParams::Denoise {
+          luminance: 0.0,
+          chroma: 1.0,
+          radius: 3.5,
+        },
+      ),
+      (NodeType::Blur, NodeParams::Blur { radius: 2.5 }),
+      (NodeType::Blur, NodeParams::Blur { radius: 0.0 }),
+      (NodeType::Blur, NodeParams::Blur { radius: 14.0 }),
+      (NodeType::Sharpen, NodeParams::Sharpen { amount: 0.5 }),
+      (
+        NodeType::UnsharpMask,
+        NodeParams::UnsharpMask {
+          amount: 1.5,
+          radius: 3.0,
+          threshold: 0.0,
+        },
+      ),
+      (
+        NodeType::UnsharpMask,
+        NodeParams::UnsharpMask {
+          amount: 0.8,
+          radius: 1.5,
+          threshold: 0.05,
+        },
+      ),
+      (
+        NodeType::Clarity,
+        NodeParams::Clarity {
+          amount: 0.6,
+          radius: 8.0,
+        },
+      ),
+      (
+        NodeType::Clarity,
+        NodeParams::Clarity {
+          amount: -0.5,
+          radius: 20.0,
+        },
+      ),
+      (
+        NodeType::Vignette,
+        NodeParams::Vignette {
+          amount: -0.8,
+          midpoint: 0.4,
+          roundness: 0.6,
+          feather: 0.7,
+        },
+      ),
+      (
+        NodeType::Vignette,
+        NodeParams::Vignette {
+          amount: 0.5,
+          midpoint: 0.6,
+          roundness: -0.7,
+          feather: 0.2,
+        },
+      ),
+      (
+        NodeType::Grain,
+        NodeParams::Grain {
+          amount: 0.8,
+          size: 150.0,
+          seed: 7,
+        },
+      ),
+      (NodeType::Dehaze, NodeParams::Dehaze { amount: 0.6 }),
+      (NodeType::Dehaze, NodeParams::Dehaze { amount: -0.4 }),
+      (
+        NodeType::Resize,
+        NodeParams::Resize {
+          width: Some(19),
+          height: Some(13),
+        },
+      ),
+      (
+        NodeType::Crop,
+        NodeParams::Crop {
+          x: 3,
+          y: 2,
+          width: 10,
+          height: 40,
+        },
+      ),
+      (NodeType::Rotate, NodeParams::Rotate { quarter_turns: 1 }),
+      (NodeType::Rotate, NodeParams::Rotate { quarter_turns: 2 }),
+      (NodeType::Rotate, NodeParams::Rotate { quarter_turns: 3 }),
+      (
+        NodeType::Flip,
+        NodeParams::Flip {
+          horizontal: true,
+          vertical: true,
+        },
+      ),
+      (
+        NodeType::Straighten,
+        NodeParams::Straighten {
+          angle: 7.0,
+          crop: true,
+          resampling: Resampling::Bilinear,
+        },
+      ),
+      (
+        NodeType::Straighten,
+        NodeParams::Straighten {
+          angle: -20.0,
+          crop: false,
+          resampling: Resampling::Bicubic,
+        },
+      ),
+      (
+        NodeType::Perspective,
+        NodeParams::Perspective {
+          corners: [[0.2, 0.05], [0.85, 0.0], [1.0, 1.0], [0.0, 0.9]],
+          resampling: Resampling::Bilinear,
+        },
+      ),
+      (
+        NodeType::Perspective,
+        NodeParams::Perspective {
+          corners: [[0.1, 0.1], [0.9, 0.0], [1.0, 1.0], [0.0, 0.95]],
+          resampling: Resampling::Bicubic,
+        },
+      ),
+      (
+        NodeType::Mix,
+        NodeParams::Mix {
+          factor: 0.3,
+          use_alpha: false,
+        },
+      ),
+      (
+        NodeType::Mix,
+        NodeParams::Mix {
+          factor: 0.8,
+          use_alpha: true,
+        },
+      ),
+      (NodeType::Mask, NodeParams::None),
+      (
+        NodeType::LuminanceMask,
+        NodeParams::LuminanceMask {
+          low: 0.3,
+          high: 0.7,
+          feather: 0.2,
+        },
+      ),
+      (
+        NodeType::ColorRangeMask,
+        NodeParams::ColorRangeMask {
+          hue: 20.0,
+          hue_range: 40.0,
+          hue_feather: 30.0,
+          saturation: [0.2, 0.9],
+          luminance: [0.1, 0.8],
+          feather: 0.15,
+        },
+      ),
+      (
+        NodeType::LinearGradient,
+        NodeParams::LinearGradient {
+          center: [0.3, 0.6],
+          angle: 120.0,
+          feather: 0.4,
+          invert: false,
+        },
+      ),
+      (
+        NodeType::RadialGradient,
+        NodeParams::RadialGradient {
+          center: [0.6, 0.4],
+          radius: [0.5, 0.25],
+          angle: -35.0,
+          feather: 0.6,
+          invert: true,
+        },
+      ),
+      (
+        NodeType::PaintedMask,
+        NodeParams::PaintedMask {
+          bitmap: MaskBitmap {
+            width: 5,
+            height: 3,
+            data: (0..15).map(|i| i * 17).collect(),
+          },
+        },
+      ),
+      (NodeType::Invert, NodeParams::None),
+    ]
+  }
+
+  /// Dimensions of each case's result in `node_cases`, and its pixel a third across and
+  /// halfway down when run on `gradient(23, 17)`
+  const REFERENCE: [((u32, u32), [f32; 4]); 57] = [
+    ((23, 17), [1.0329907, 1.3310245, 1.0606601, 0.8478261]),
+    ((23, 17), [0.4747826, 0.61176467, 0.48749998, 0.8478261]),
+    ((23, 17), [0.43914846, 0.60705155, 0.45443383, 0.8478261]),
+    ((23, 17), [0.33668366, 0.49364483, 0.3507371, 0.8478261]),
+    ((23, 17), [0.32490227, 0.50369376, 0.34065714, 0.8478261]),
+    ((23, 17), [0.39182097, 0.45056102, 0.39744693, 0.8478261]),
+    ((23, 17), [0.36521736, 0.4705882, 0.4537206, 0.8478261]),
+    ((23, 17), [0.36521736, 0.4705882, 0.37499997, 0.8478261]),
+    ((23, 17), [0.6303717, 0.7085554, 0.6381233, 0.8478261]),
+    ((23, 17), [0.38746905, 0.5110642, 0.39886934, 0.8478261]),
+    ((23, 17), [0.53471965, 0.48691964, 0.2941051, 0.8478261]),
+    ((23, 17), [0.35579142, 0.67342335, 0.3028092, 0.8478261]),
+    ((23, 17), [0.46874714, 0.5172148, 0.31550643, 0.8478261]),
+    ((23, 17), [0.34416205, 0.47058824, 0.40172213, 0.8478261]),
+    ((23, 17), [0.3915554, 0.47058824, 0.43007767, 0.8478261]),
+    ((23, 17), [0.68627465, 0.47058824, 0.55147076, 0.8478261]),
+    ((23, 17), [0.43826088, 0.44235295, 0.3, 0.8478261]),
+    ((23, 17), [0.30599794, 0.48470587, 0.50028837, 0.8478261]),
+    ((23, 17), [0.25635263, 0.57557744, 0.3190665, 0.8478261]),
+    ((23, 17), [0.23258747, 0.6037663, 0.30590066, 0.8478261]),
+    ((23, 17), [0.3359561, 0.4120178, 0.34350497, 0.8478261]),
+    ((23, 17), [0.35620588, 0.41385466, 0.3618876, 0.8478261]),
+    ((23, 17), [0.27196833, 0.33947876, 0.27786267, 0.8478261]),
+    ((23, 17), [0.3635962, 0.469371, 0.37515804, 0.8478261]),
+    ((23, 17), [0.36357185, 0.4707591, 0.3784539, 0.8478261]),
+    ((23, 17), [0.36521745, 0.47058824, 0.37500003, 0.8478262]),
+    ((23, 17), [0.3652174, 0.47058824, 0.375, 0.8478261]),
+    ((23, 17), [0.39304113, 0.4705882, 0.38833222, 0.8362328]),
+    ((23, 17), [0.36741042, 0.47274703, 0.37597424, 0.8478261]),
+    ((23, 17), [0.36521727, 0.47058803, 0.37499982, 0.8478261]),
+    ((23, 17), [0.36521736, 0.4705882, 0.37499997, 0.8478261]),
+    ((23, 17), [0.36484292, 0.470154, 0.37461963, 0.8478261]),
+    ((23, 17), [0.37115872, 0.47747305, 0.38103327, 0.8478261]),
+    ((23, 17), [0.28404096, 0.36599115, 0.2916492, 0.8478261]),
+    ((23, 17), [0.3652174, 0.47058824, 0.375, 0.8478261]),
+    ((23, 17), [0.38460073, 0.49302644, 0.39468122, 0.8478261]),
+    ((23, 17), [0.24874556, 0.40055293, 0.2894256, 0.8478261]),
+    ((23, 17), [0.5217391, 0.56470585, 0.49, 0.8478261]),
+    ((19, 13), [0.37894738, 0.46153843, 0.37773278, 0.84210527]),
+    ((10, 15), [0.31304348, 0.5294118, 0.375, 0.86956525]),
+    ((17, 23), [0.5739131, 0.64705884, 0.55, 0.76086956]),
+    ((23, 17), [0.78260875, 0.47058824, 0.575, 0.673913]),
+    ((17, 23), [0.5739131, 0.29411766, 0.4, 0.76086956]),
+    ((23, 17), [0.78260875, 0.47058824, 0.575, 0.673913]),
+    ((19, 14), [0.4217372, 0.52128714, 0.42362943, 0.8242762]),
+    ((27, 24), [0.36888102, 0.41775095, 0.35429963, 0.8462995]),
+    ((19, 16), [0.40612048, 0.38605466, 0.35867262, 0.8307831]),
+    ((21, 16), [0.37913802, 0.46650463, 0.3799348, 0.84202576]),
+    ((23, 17), [0.3652174, 0.47058827, 0.375, 0.8478261]),
+    ((23, 17), [0.3652174, 0.47058827, 0.375, 0.8478261]),
+    ((23, 17), [0.3652174, 0.47058824, 0.375, 0.3741329]),
+    ((23, 17), [1.0, 1.0, 1.0, 1.0]),
+    ((23, 17), [0.0, 0.0, 0.0, 1.0]),
+    ((23, 17), [0.86863005, 0.86863005, 0.86863005, 1.0]),
+    ((23, 17), [1.0, 1.0, 1.0, 1.0]),
+    ((23, 17), [0.5720375, 0.5720375, 0.5720375, 1.0]),
+    ((23, 17), [0.6347826, 0.5294118, 0.625, 0.8478261]),
+  ];
+
+  /// Run `image` through a pipeline of a single node, on the GPU when a device is given
+  fn run_single_node(
+    node_type: NodeType,
+    params: &NodeParams,
+    image: &CpuImage,
+    gpu: Option<(Device, Queue)>,
+  ) -> CpuImage {
+    let mut pipeline = ImagePipeline::new();
+    let node_id = pipeline.add_node(format!("{:?}", node_type), node_type);
+    pipeline
+      .get_node_mut(node_id)
+      .unwrap()
+      .set_params(params.clone());
+    if let Some((device, queue)) = gpu {
+      pipeline.init_gpu(device, queue);
+    }
+    let (data, dimensions) =
+      pollster::block_on(pipeline.process(image.to_bytes(), image.dimensions())).unwrap();
+    CpuImage::from_bytes(&data, dimensions)
+  }
+
+  /// Pin the CPU result of every node, so changes to it show up without a GPU
+  #[test]
+  fn test_reference_values() {
+    let lut_path = write_lut("shade_test_reference_values.cube");
+    let image = gradient(23, 17);
+    let cases = node_cases(&lut_path);
+    assert_eq!(cases.len(), REFERENCE.len());
+
+    for ((node_type, params), (dimensions, expected)) in cases.iter().zip(REFERENCE) {
+      let result = run_single_node(*node_type, params, &image, None);
+      assert_eq!(result.dimensions(), dimensions, "{:?}", node_type);
+      let (width, height) = dimensions;
+      let pixel = result.pixels[(height / 2 * width + width / 3) as usize];
+      for i in 0..4 {
+        assert!(
+          (pixel[i] - expected[i]).abs() < 1e-4,
+          "{:?}: {:?} differs from the reference {:?}",
+          node_type,
+          pixel,
+          expected
+        );
+      }
+    }
+
+    std::fs::remove_file(&lut_path).ok();
+  }
+
+  /// Compare every node against its WGSL shader on the GPU
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_matches_gpu() {
+    let (device, queue) =
+      pollster::block_on(request_device()).expect("No GPU adapter available");
+    let lut_path = write_lut("shade_test_matches_gpu.cube");
+
+    let image = gradient(23, 17);
+    for (node_type, params) in node_cases(&lut_path) {
+      let gpu = run_single_node(
+        node_type,
+        &params,
+        &image,
+        Some((device.clone(), queue.clone())),
+      );
+      let cpu =
+        process_node(&node_type, &params, &image, None, gpu.dimensions()).unwrap();
+
+      assert_eq!(cpu.dimensions(), gpu.dimensions(), "{:?}", node_type);
+      for (cpu_pixel, gpu_pixel) in cpu.pixels.iter().zip(&gpu.pixels) {
+        for i in 0..4 {
+          assert!(
+            (cpu_pixel[i] - gpu_pixel[i]).abs() < 1e-3,
+            "{:?}: CPU {:?} differs from GPU {:?}",
+            node_type,
+            cpu_pixel,
+            gpu_pixel
+          );
+        }
+      }
+    }
+
+    std::fs::remove_file(&lut_path).ok();
+  }
+}
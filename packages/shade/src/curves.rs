@@ -0,0 +1,239 @@
+//! Tone curves defined by control points
+//!
+//! Curves are interpolated with monotone cubic splines (Fritsch-Carlson), which never
+//! overshoot between control points. The master and per-channel curves of a `Curves`
+//! node are baked into one lookup table that both the shader and the CPU backend
+//! sample with linear interpolation.
+
+/// Number of entries in a baked curve lookup table
+pub const CURVE_LUT_SIZE: usize = 1024;
+
+/// Control points of a single curve as `[input, output]` pairs, usually in 0..1
+pub type CurvePoints = Vec<[f32; 2]>;
+
+/// A monotone cubic spline through a set of control points.
+///
+/// Fewer than two points describe the identity curve. Inputs outside the range of the
+/// control points are clamped to the first and last point.
+#[derive(Debug, Clone)]
+pub struct MonotoneCurve {
+  xs: Vec<f32>,
+  ys: Vec<f32>,
+  tangents: Vec<f32>,
+}
+
+impl MonotoneCurve {
+  pub fn new(points: &[[f32; 2]]) -> Self {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a[0].total_cmp(&b[0]));
+    // A later point on the same input replaces the earlier one
+    sorted.dedup_by(|later, earlier| {
+      let duplicate = later[0] == earlier[0];
+      if duplicate {
+        *earlier = *later;
+      }
+      duplicate
+    });
+
+    if sorted.len() < 2 {
+      sorted = vec![[0.0, 0.0], [1.0, 1.0]];
+    }
+
+    let xs: Vec<f32> = sorted.iter().map(|point| point[0]).collect();
+    let ys: Vec<f32> = sorted.iter().map(|point| point[1]).collect();
+    let tangents = Self::tangents(&xs, &ys);
+
+    Self { xs, ys, tangents }
+  }
+
+  /// Fritsch-Carlson tangents, limited so every segment stays monotone
+  fn tangents(xs: &[f32], ys: &[f32]) -> Vec<f32> {
+    let n = xs.len();
+    let slopes: Vec<f32> = (0..n - 1)
+      .map(|k| (ys[k + 1] - ys[k]) / (xs[k + 1] - xs[k]))
+      .collect();
+
+    let mut tangents = vec![0.0; n];
+    tangents[0] = slopes[0];
+    tangents[n - 1] = slopes[n - 2];
+    for k in 1..n - 1 {
+      // Local extrema get a flat tangent
+      if slopes[k - 1] * slopes[k] > 0.0 {
+        tangents[k] = (slopes[k - 1] + slopes[k]) / 2.0;
+      }
+    }
+
+    for k in 0..n - 1 {
+      if slopes[k] == 0.0 {
+        tangents[k] = 0.0;
+        tangents[k + 1] = 0.0;
+        continue;
+      }
+      let alpha = tangents[k] / slopes[k];
+      let beta = tangents[k + 1] / slopes[k];
+      let magnitude = alpha * alpha + beta * beta;
+      if magnitude > 9.0 {
+        let scale = 3.0 / magnitude.sqrt();
+        tangents[k] = scale * alpha * slopes[k];
+        tangents[k + 1] = scale * beta * slopes[k];
+      }
+    }
+
+    tangents
+  }
+
+  pub fn evaluate(&self, x: f32) -> f32 {
+    let last = self.xs.len() - 1;
+    if x <= self.xs[0] {
+      return self.ys[0];
+    }
+    if x >= self.xs[last] {
+      return self.ys[last];
+    }
+
+    let k = self.xs.partition_point(|&point_x| point_x <= x) - 1;
+    let h = self.xs[k + 1] - self.xs[k];
+    let t = (x - self.xs[k]) / h;
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    // Cubic Hermite basis
+    let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+    let h10 = t3 - 2.0 * t2 + t;
+    let h01 = -2.0 * t3 + 3.0 * t2;
+    let h11 = t3 - t2;
+
+    h00 * self.ys[k]
+      + h10 * h * self.tangents[k]
+      + h01 * self.ys[k + 1]
+      + h11 * h * self.tangents[k + 1]
+  }
+}
+
+/// Bake the curves of a `Curves` node into a lookup table over the 0..1 input range.
+///
+/// Each entry holds the red, green and blue outputs of the master curve followed by
+/// the channel curve. The alpha component is unused.
+pub fn bake_lut(
+  master: &[[f32; 2]],
+  red: &[[f32; 2]],
+  green: &[[f32; 2]],
+  blue: &[[f32; 2]],
+) -> Vec<[f32; 4]> {
+  let master = MonotoneCurve::new(master);
+  let channels = [red, green, blue].map(MonotoneCurve::new);
+
+  (0..CURVE_LUT_SIZE)
+    .map(|i| {
+      let value = master.evaluate(i as f32 / (CURVE_LUT_SIZE - 1) as f32);
+      let [r, g, b] = channels.each_ref().map(|curve| curve.evaluate(value));
+      [r, g, b, 1.0]
+    })
+    .collect()
+}
+
+/// Look up one channel of a baked table, interpolating linearly between entries.
+///
+/// Values outside 0..1 keep their distance to the range so HDR highlights are not
+/// clipped. Mirrors `apply_curve` in `curves.wgsl`.
+pub fn sample_lut(lut: &[[f32; 4]], value: f32, channel: usize) -> f32 {
+  let clamped = value.clamp(0.0, 1.0);
+  let position = clamped * (lut.len() - 1) as f32;
+  let index = position.floor() as usize;
+  let next = (index + 1).min(lut.len() - 1);
+  let t = position - index as f32;
+  let curved = lut[index][channel] * (1.0 - t) + lut[next][channel] * t;
+  curved + (value - clamped)
+}
+
+/// Parse control points written as `x:y` pairs separated by commas, e.g. `0:0,0.5:0.6,1:1`
+pub fn parse_points(value: &str) -> Result<CurvePoints, String> {
+  let points = value
+    .split(',')
+    .map(|pair| {
+      let (x, y) = pair
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("Curve point '{}' is not in x:y form", pair.trim()))?;
+      let parse = |component: &str| {
+        component
+          .trim()
+          .parse::<f32>()
+          .map_err(|e| format!("Invalid curve point '{}': {}", pair.trim(), e))
+      };
+      Ok([parse(x)?, parse(y)?])
+    })
+    .collect::<Result<CurvePoints, String>>()?;
+
+  if points.len() < 2 {
+    return Err("A curve needs at least two points".to_string());
+  }
+  if let Some(point) = points.iter().find(|point| !(0.0..=1.0).contains(&point[0])) {
+    return Err(format!("Curve input {} is outside 0..1", point[0]));
+  }
+
+  Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_identity_curve() {
+    let curve = MonotoneCurve::new(&[]);
+    for x in [0.0, 0.25, 0.5, 1.0] {
+      assert!((curve.evaluate(x) - x).abs() < 1e-6);
+    }
+
+    let lut = bake_lut(&[], &[], &[], &[]);
+    assert_eq!(lut.len(), CURVE_LUT_SIZE);
+    assert!((sample_lut(&lut, 0.3, 1) - 0.3).abs() < 1e-5);
+    // HDR values pass the end of the table unchanged
+    assert!((sample_lut(&lut, 2.5, 0) - 2.5).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_curve_is_monotone_and_hits_points() {
+    let points = [
+      [0.0, 0.0],
+      [0.25, 0.1],
+      [0.5, 0.7],
+      [0.75, 0.72],
+      [1.0, 1.0],
+    ];
+    let curve = MonotoneCurve::new(&points);
+
+    for [x, y] in points {
+      assert!((curve.evaluate(x) - y).abs() < 1e-6);
+    }
+
+    let mut previous = curve.evaluate(0.0);
+    for i in 1..=1000 {
+      let value = curve.evaluate(i as f32 / 1000.0);
+      assert!(value >= previous - 1e-6, "curve decreases at {}", i);
+      previous = value;
+    }
+  }
+
+  #[test]
+  fn test_master_applies_before_channels() {
+    let invert = [[0.0, 1.0], [1.0, 0.0]];
+    let lut = bake_lut(&invert, &invert, &[], &[]);
+
+    // Red is inverted twice, green and blue once
+    assert!((sample_lut(&lut, 0.2, 0) - 0.2).abs() < 1e-5);
+    assert!((sample_lut(&lut, 0.2, 1) - 0.8).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_parse_points() {
+    assert_eq!(
+      parse_points("0:0, 0.5:0.6,1:1").unwrap(),
+      vec![[0.0, 0.0], [0.5, 0.6], [1.0, 1.0]]
+    );
+    assert!(parse_points("0:0").is_err());
+    assert!(parse_points("0:0,0.5").is_err());
+    assert!(parse_points("0:0,1.5:1").is_err());
+  }
+}
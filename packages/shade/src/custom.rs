@@ -0,0 +1,305 @@
+//! User-defined WGSL shaders for `Custom` nodes, loaded at runtime
+//!
+//! Every `.wgsl` file in a shader directory becomes a shader named after the file.
+//! Shaders follow the binding layout of the built-in nodes: the input image as a
+//! `texture_2d<f32>` at binding 0, the output as a `texture_storage_2d<rgba32float,
+//! write>` at binding 1 and optionally the parameter block as a `var<uniform>` struct at
+//! binding 2, all in group 0. Members of the parameter block must be `f32` and are 0.0
+//! unless a node sets them. The entry point is a compute `main` with
+//! `@workgroup_size(8, 8)`.
+//!
+//! Shaders are validated with naga when they are loaded, so mistakes are reported with
+//! their file and line instead of failing when the GPU pipeline is created.
+
+use std::path::{Path, PathBuf};
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{
+  AddressSpace, ImageClass, ImageDimension, Scalar, ScalarKind, ShaderStage,
+  StorageAccess, StorageFormat, TypeInner,
+};
+
+/// Workgroup size the pipeline dispatches every shader with
+const WORKGROUP_SIZE: [u32; 3] = [8, 8, 1];
+
+/// A validated shader for `Custom` nodes
+#[derive(Debug, Clone)]
+pub struct CustomShader {
+  /// File name without the extension, referenced by `NodeParams::Custom`
+  pub name: String,
+  pub source: String,
+  /// Members of the parameter block in declaration order
+  pub params: Vec<String>,
+}
+
+impl CustomShader {
+  /// Parse and validate WGSL `source`. Errors point to the line in `path`.
+  pub fn from_source(name: &str, source: &str, path: &Path) -> Result<Self, String> {
+    let module = naga::front::wgsl::parse_str(source)
+      .map_err(|e| e.emit_to_string_with_path(source, path))?;
+    Validator::new(ValidationFlags::all(), Capabilities::default())
+      .validate(&module)
+      .map_err(|e| e.emit_to_string_with_path(source, path))?;
+    let params = check_interface(&module, source, path)?;
+
+    Ok(Self {
+      name: name.to_string(),
+      source: source.to_string(),
+      params,
+    })
+  }
+
+  /// Load and validate a `.wgsl` file
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let source = std::fs::read_to_string(path)
+      .map_err(|e| format!("Failed to read shader {}: {}", path.display(), e))?;
+    let name = path
+      .file_stem()
+      .map(|stem| stem.to_string_lossy().into_owned())
+      .ok_or_else(|| format!("Shader {} has no file name", path.display()))?;
+    Self::from_source(&name, &source, path)
+  }
+}
+
+/// Check that the module fits the node binding layout and return the names of its
+/// parameters
+fn check_interface(
+  module: &naga::Module,
+  source: &str,
+  path: &Path,
+) -> Result<Vec<String>, String> {
+  let at_line = |span: naga::Span, message: String| {
+    let line = span.location(source).line_number;
+    format!("{}:{}: {}", path.display(), line, message)
+  };
+
+  let entry_point = module
+    .entry_points
+    .iter()
+    .find(|entry_point| entry_point.name == "main")
+    .filter(|entry_point| entry_point.stage == ShaderStage::Compute)
+    .ok_or_else(|| format!("{}: no compute entry point named main", path.display()))?;
+  if entry_point.workgroup_size != WORKGROUP_SIZE {
+    return Err(format!(
+      "{}: main must use @workgroup_size(8, 8), not {:?}",
+      path.display(),
+      entry_point.workgroup_size
+    ));
+  }
+
+  let mut params = Vec::new();
+  let mut has_output = false;
+  for (handle, variable) in module.global_variables.iter() {
+    let Some(binding) = &variable.binding else {
+      continue;
+    };
+    let span = module.global_variables.get_span(handle);
+    let inner = &module.types[variable.ty].inner;
+
+    let (expected, matches) = match (binding.group, binding.binding) {
+      (0, 0) => (
+        "texture_2d<f32>",
+        matches!(
+          inner,
+          TypeInner::Image {
+            dim: ImageDimension::D2,
+            arrayed: false,
+            class: ImageClass::Sampled {
+              kind: ScalarKind::Float,
+              multi: false,
+            },
+          }
+        ),
+      ),
+      (0, 1) => {
+        has_output = true;
+        (
+          "texture_storage_2d<rgba32float, write>",
+          matches!(
+            inner,
+            TypeInner::Image {
+              dim: ImageDimension::D2,
+              arrayed: false,
+              class: ImageClass::Storage {
+                format: StorageFormat::Rgba32Float,
+                access,
+              },
+            } if *access == StorageAccess::STORE
+          ),
+        )
+      }
+      (0, 2) => {
+        let names = match inner {
+          TypeInner::Struct { members, .. }
+            if variable.space == AddressSpace::Uniform =>
+          {
+            members
+              .iter()
+              .map(|member| {
+                let is_float =
+                  module.types[member.ty].inner == TypeInner::Scalar(Scalar::F32);
+                member.name.clone().filter(|_| is_float)
+              })
+              .collect::<Option<Vec<String>>>()
+          }
+          _ => None,
+        };
+        let matches = names.is_some();
+        params = names.unwrap_or_default();
+        ("a var<uniform> struct of f32 members", matches)
+      }
+      (group, index) => {
+        return Err(at_line(
+          span,
+          format!(
+            "@group({}) @binding({}) is not part of the node layout, which binds \
+             the input at 0, the output at 1 and the parameters at 2 of group 0",
+            group, index
+          ),
+        ));
+      }
+    };
+
+    if !matches {
+      return Err(at_line(
+        span,
+        format!("@binding({}) must be {}", binding.binding, expected),
+      ));
+    }
+  }
+
+  if !has_output {
+    return Err(format!(
+      "{}: no output texture at @group(0) @binding(1)",
+      path.display()
+    ));
+  }
+  Ok(params)
+}
+
+/// Directory shaders are loaded from when none is given, `shade/shaders` in the
+/// user's configuration directory
+fn default_shader_dir() -> Option<PathBuf> {
+  dirs::config_dir().map(|dir| dir.join("shade").join("shaders"))
+}
+
+/// Load the shaders of `dir`, or of the default directory if it exists
+pub fn load_shaders(dir: Option<&Path>) -> (Vec<CustomShader>, Vec<String>) {
+  match dir {
+    Some(dir) => load_shader_dir(dir),
+    None => match default_shader_dir().filter(|dir| dir.is_dir()) {
+      Some(dir) => load_shader_dir(&dir),
+      None => (Vec::new(), Vec::new()),
+    },
+  }
+}
+
+/// Load every `.wgsl` file in `dir`, sorted by name. Shaders that fail to load are
+/// returned as errors, leaving the others usable.
+pub fn load_shader_dir(dir: &Path) -> (Vec<CustomShader>, Vec<String>) {
+  let entries = match std::fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(e) => {
+      return (
+        Vec::new(),
+        vec![format!(
+          "Failed to read shader directory {}: {}",
+          dir.display(),
+          e
+        )],
+      );
+    }
+  };
+  let mut paths: Vec<PathBuf> = entries
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| {
+      path
+        .extension()
+        .is_some_and(|extension| extension == "wgsl")
+    })
+    .collect();
+  paths.sort();
+
+  let mut shaders = Vec::new();
+  let mut errors = Vec::new();
+  for path in paths {
+    match CustomShader::load(&path) {
+      Ok(shader) => shaders.push(shader),
+      Err(e) => errors.push(e),
+    }
+  }
+  (shaders, errors)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const GAIN: &str = "struct Params {
+    gain: f32,
+    offset: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let coords = vec2<i32>(global_id.xy);
+    let color = textureLoad(input_texture, coords, 0);
+    textureStore(output_texture, coords, vec4<f32>(color.rgb * params.gain + params.offset, color.a));
+}
+";
+
+  fn load(source: &str) -> Result<CustomShader, String> {
+    CustomShader::from_source("test", source, Path::new("test.wgsl"))
+  }
+
+  #[test]
+  fn test_valid_shader() {
+    let shader = load(GAIN).unwrap();
+    assert_eq!(shader.name, "test");
+    assert_eq!(shader.params, vec!["gain", "offset"]);
+
+    // A shader without parameters leaves out the uniform block
+    let source = GAIN.replace(
+      "@group(0) @binding(2)\nvar<uniform> params: Params;",
+      "const params = Params(2.0, 0.0);",
+    );
+    assert!(load(&source).unwrap().params.is_empty());
+  }
+
+  #[test]
+  fn test_errors_name_the_line() {
+    // Syntax and type errors come with naga's line-numbered report
+    let error =
+      load(&GAIN.replace("color.rgb * params.gain", "color.rgb * ")).unwrap_err();
+    assert!(error.contains("test.wgsl:19"), "{}", error);
+    let error = load(&GAIN.replace("params.gain", "params.missing")).unwrap_err();
+    assert!(error.contains("test.wgsl:19"), "{}", error);
+
+    // Bindings that do not fit the node layout point to their declaration
+    let error =
+      load(&GAIN.replace("rgba32float, write", "rgba8unorm, write")).unwrap_err();
+    assert!(error.starts_with("test.wgsl:10: @binding(1)"), "{}", error);
+    let error =
+      load(&GAIN.replace("offset: f32,", "offset: f32, count: u32,")).unwrap_err();
+    assert!(error.starts_with("test.wgsl:13: @binding(2)"), "{}", error);
+    let error = load(&GAIN.replace("@binding(2)", "@binding(6)")).unwrap_err();
+    assert!(
+      error.starts_with("test.wgsl:13: @group(0) @binding(6)"),
+      "{}",
+      error
+    );
+
+    let error =
+      load(&GAIN.replace("@workgroup_size(8, 8)", "@workgroup_size(16)")).unwrap_err();
+    assert!(error.contains("@workgroup_size(8, 8)"), "{}", error);
+  }
+}
@@ -0,0 +1,65 @@
+//! Haze colour for the dark channel prior
+//!
+//! Dehazing needs the colour of the haze, the atmospheric light. It is taken from the
+//! sample of a grid over the image whose darkest channel is the brightest, as the
+//! haziest part of a scene has no dark channel left. The light is measured once per
+//! image and `shaders/dehaze.wgsl` only applies it.
+
+/// Samples per side of the grid the haze colour is taken from
+pub const SAMPLE_GRID: u32 = 16;
+
+/// Spacing of the 5x5 grid the dark channel of a pixel is the minimum over. It spans
+/// 1% of the shorter side on either side, so it covers the same part of the scene at
+/// every resolution.
+pub fn dark_channel_step(width: u32, height: u32) -> u32 {
+  ((width.min(height) as f32 * 0.005) as u32).max(1)
+}
+
+/// Pixel positions of the samples, at the centres of the cells of a `SAMPLE_GRID`
+/// square grid
+pub fn sample_positions(width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+  (0..SAMPLE_GRID).flat_map(move |y| {
+    (0..SAMPLE_GRID).map(move |x| {
+      (
+        (2 * x + 1) * width / (2 * SAMPLE_GRID),
+        (2 * y + 1) * height / (2 * SAMPLE_GRID),
+      )
+    })
+  })
+}
+
+/// Haze colour from the samples in linear light, white when there are none. Channels
+/// are kept above zero, as the dark channel divides by them.
+pub fn atmospheric_light(samples: impl IntoIterator<Item = [f32; 3]>) -> [f32; 3] {
+  let min_channel = |[r, g, b]: [f32; 3]| r.min(g).min(b);
+  let mut light = [1.0; 3];
+  let mut brightest = -1.0;
+  for sample in samples {
+    if min_channel(sample) > brightest {
+      brightest = min_channel(sample);
+      light = sample;
+    }
+  }
+  light.map(|c| c.max(1e-3))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_atmospheric_light() {
+    let samples = [[0.9, 0.2, 0.1], [0.6, 0.7, 0.8], [0.5, 0.5, 0.0]];
+    assert_eq!(atmospheric_light(samples), [0.6, 0.7, 0.8]);
+    assert_eq!(atmospheric_light([[0.0, 0.5, 0.5]]), [1e-3, 0.5, 0.5]);
+    assert_eq!(atmospheric_light([]), [1.0; 3]);
+
+    let positions = sample_positions(32, 3).collect::<Vec<_>>();
+    assert_eq!(positions.len(), (SAMPLE_GRID * SAMPLE_GRID) as usize);
+    assert_eq!(positions[0], (1, 0));
+    assert_eq!(positions.last(), Some(&(31, 2)));
+
+    assert_eq!(dark_channel_step(32, 3), 1);
+    assert_eq!(dark_channel_step(6000, 4000), 20);
+  }
+}
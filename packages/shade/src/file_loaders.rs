@@ -1,7 +1,12 @@
-use crate::utils::convert_to_float;
-use image::DynamicImage;
+use crate::color::{self, ColorSpace};
+use crate::raw::{self, CameraImage, DemosaicAlgorithm, InputProfile, RawDevelopParams};
+use crate::white_balance;
 use rawler::{
-  decoders::RawDecodeParams, imgop::develop::RawDevelop, rawsource::RawSource,
+  RawImage,
+  decoders::RawDecodeParams,
+  imgop::develop::{Intermediate, ProcessingStep, RawDevelop},
+  rawimage::{BlackLevel, WhiteLevel},
+  rawsource::RawSource,
 };
 use std::error::Error;
 use std::fmt;
@@ -52,6 +57,9 @@ pub trait ImageLoader {
   //   filename: Option<&str>,
   // ) -> Result<(Vec<u8>, (usize, usize)), FileLoaderError>;
 
+  /// Colour space of the pixel data returned by `load`
+  fn color_space() -> ColorSpace;
+
   /// Get the name of this loader for debugging
   fn loader_name() -> &'static str;
 }
@@ -138,6 +146,11 @@ impl ImageLoader for ExrLoader {
     }
   }
 
+  fn color_space() -> ColorSpace {
+    // OpenEXR stores scene-linear values with Rec.709 primaries by convention
+    ColorSpace::LinearSrgb
+  }
+
   fn loader_name() -> &'static str {
     "OpenEXR"
   }
@@ -146,34 +159,259 @@ impl ImageLoader for ExrLoader {
 /// Camera raw file loader (CR3, CR2, NEF, ARW, etc.)
 pub struct RawLoader;
 
-/// Apply EXIF orientation correction to an image
-fn apply_orientation(
-  img: DynamicImage,
+/// Width and height of an image after EXIF orientation correction
+fn oriented_dimensions(
   orientation: rawler::Orientation,
-) -> DynamicImage {
+  (width, height): (usize, usize),
+) -> (usize, usize) {
   use rawler::Orientation;
 
   match orientation {
-    Orientation::Normal => img, // No transformation needed
-    Orientation::HorizontalFlip => img.fliph(), // Flip horizontal
-    Orientation::Rotate180 => img.rotate180(), // Rotate 180°
-    Orientation::VerticalFlip => img.flipv(), // Flip vertical
-    Orientation::Transpose => img.rotate90().fliph(), // Rotate 90° CW + flip horizontal
-    Orientation::Rotate90 => img.rotate90(), // Rotate 90° CW
-    Orientation::Transverse => img.rotate270().fliph(), // Rotate 270° CW + flip horizontal
-    Orientation::Rotate270 => img.rotate270(),          // Rotate 270° CW
-    Orientation::Unknown => {
-      log::warn!("Unknown orientation, applying no transformation");
-      img
-    }
+    Orientation::Transpose
+    | Orientation::Rotate90
+    | Orientation::Transverse
+    | Orientation::Rotate270 => (height, width),
+    _ => (width, height),
+  }
+}
+
+/// Pixel of an image of `width` and `height` that EXIF orientation correction moves to
+/// `(x, y)`
+fn oriented_source(
+  orientation: rawler::Orientation,
+  (x, y): (usize, usize),
+  (width, height): (usize, usize),
+) -> (usize, usize) {
+  use rawler::Orientation;
+
+  match orientation {
+    Orientation::Normal | Orientation::Unknown => (x, y),
+    Orientation::HorizontalFlip => (width - 1 - x, y),
+    Orientation::Rotate180 => (width - 1 - x, height - 1 - y),
+    Orientation::VerticalFlip => (x, height - 1 - y),
+    // Rotate 90° CW + flip horizontal
+    Orientation::Transpose => (y, x),
+    Orientation::Rotate90 => (y, height - 1 - x),
+    // Rotate 270° CW + flip horizontal
+    Orientation::Transverse => (width - 1 - y, height - 1 - x),
+    Orientation::Rotate270 => (width - 1 - y, x),
   }
 }
 
 impl RawLoader {
-  fn get_cache_params() -> String {
+  fn get_cache_params(params: &RawDevelopParams) -> String {
     // Include processing parameters that would affect the final image
     // This ensures different processing settings get separate cache entries
-    "default_raw_develop".to_string()
+    format!("raw_develop {}", params)
+  }
+
+  /// XYZ to camera matrix of a raw file, preferring the one calibrated closest to
+  /// daylight
+  fn color_matrix(rawimage: &RawImage) -> Option<&Vec<f32>> {
+    use rawler::imgop::xyz::Illuminant;
+
+    [
+      Illuminant::D65,
+      Illuminant::D55,
+      Illuminant::D50,
+      Illuminant::D75,
+      Illuminant::Daylight,
+      Illuminant::A,
+    ]
+    .iter()
+    .find_map(|illuminant| rawimage.color_matrix.get(illuminant))
+  }
+
+  /// Temperature in Kelvin of the camera's as-shot white balance, which the
+  /// development applies. Only decodes the metadata, not the pixels.
+  pub fn as_shot_kelvin(buffer: &[u8]) -> Option<f32> {
+    let rawsource = RawSource::new_from_slice(buffer);
+    let decoder = rawler::get_decoder(&rawsource).ok()?;
+    let rawimage = decoder
+      .raw_image(&rawsource, &RawDecodeParams::default(), true)
+      .ok()?;
+    white_balance::as_shot_kelvin(rawimage.wb_coeffs, Self::color_matrix(&rawimage)?)
+  }
+
+  /// Develop a camera raw file at full resolution into f32 RGBA data in linear light
+  /// with Rec. 709 primaries
+  pub fn develop(
+    buffer: &[u8],
+    filename: Option<&str>,
+    params: &RawDevelopParams,
+  ) -> Result<(Vec<u8>, (usize, usize)), FileLoaderError> {
+    let load_start = Instant::now();
+
+    use crate::cache::ImageCache;
+
+    // Developments are cached by file and settings, as half float RGB
+    let cache = ImageCache::new()
+      .map_err(|e| {
+        log::warn!(
+          "Failed to initialize cache: {}, proceeding without cache",
+          e
+        );
+      })
+      .ok();
+    let cache_key = cache
+      .as_ref()
+      .map(|cache| cache.generate_cache_key(buffer, &Self::get_cache_params(params)));
+
+    if let (Some(cache), Some(cache_key)) = (&cache, &cache_key)
+      && let Some(cached_image) = cache.load_from_cache(cache_key)
+    {
+      let (width, height) = cached_image.dimensions;
+      if cached_image.data.len() == width * height * 6 {
+        log::info!("Load from cache in {}ms", load_start.elapsed().as_millis());
+        return Ok((
+          raw::unpack_half(&cached_image.data),
+          cached_image.dimensions,
+        ));
+      }
+      log::warn!("Cached raw development has the wrong size, developing again");
+    }
+
+    log::info!(
+      "Loading camera raw from buffer (filename: {:?}, {})",
+      filename,
+      params
+    );
+
+    let rawsource = RawSource::new_from_slice(buffer);
+    let mut rawimage =
+      rawler::decode(&rawsource, &RawDecodeParams::default()).map_err(|e| {
+        FileLoaderError::DecodeError(format!("Could not decode source: {}", e))
+      })?;
+
+    log::info!("Image dimensions {:?}", rawimage.dim());
+    log::info!("Image orientation {:?}", rawimage.orientation);
+    log::info!(
+      "Decoded raw image at {}ms",
+      load_start.elapsed().as_millis()
+    );
+
+    // Levels the rescaling maps to 0.0 and 1.0
+    if let Some(black) = params.black_level {
+      rawimage.blacklevel =
+        BlackLevel::new(&vec![black; rawimage.cpp], 1, 1, rawimage.cpp);
+    }
+    if let Some(white) = params.white_level {
+      rawimage.whitelevel = WhiteLevel::new(vec![white; rawimage.cpp]);
+    }
+
+    let profile = match params.input_profile {
+      InputProfile::Custom(matrix) => matrix,
+      InputProfile::CameraMatrix => Self::color_matrix(&rawimage)
+        .and_then(|matrix| raw::camera_to_rgb(matrix))
+        .ok_or_else(|| {
+          FileLoaderError::DecodeError(
+            "Raw file has no usable colour matrix, set a custom input profile"
+              .to_string(),
+          )
+        })?,
+    };
+
+    // The white balance and colour conversion are applied below in float
+    let (pixels, (width, height)) = match params.demosaic {
+      DemosaicAlgorithm::Ppg => {
+        let dev = RawDevelop {
+          steps: vec![
+            ProcessingStep::Rescale,
+            ProcessingStep::Demosaic,
+            ProcessingStep::CropActiveArea,
+            ProcessingStep::CropDefault,
+          ],
+        };
+        Self::camera_rgb(dev.develop_intermediate(&rawimage).map_err(|e| {
+          FileLoaderError::DecodeError(format!("Raw development error: {}", e))
+        })?)?
+      }
+      DemosaicAlgorithm::Superpixel => {
+        let dev = RawDevelop {
+          steps: vec![ProcessingStep::Rescale],
+        };
+        match dev.develop_intermediate(&rawimage).map_err(|e| {
+          FileLoaderError::DecodeError(format!("Raw development error: {}", e))
+        })? {
+          Intermediate::Monochrome(mosaic) => {
+            // The same crop as the full resolution development, which crops to the
+            // default crop within the active area
+            let crop = rawimage
+              .crop_area
+              .or(rawimage.active_area)
+              .map_or((0, 0, mosaic.width, mosaic.height), |area| {
+                (area.p.x, area.p.y, area.d.w, area.d.h)
+              });
+            let area = raw::superpixel_area(crop);
+            let cfa = &rawimage.camera.cfa;
+            raw::superpixel(&mosaic.data, mosaic.width, area, |row, col| {
+              cfa.color_at(row, col)
+            })
+          }
+          // Sensors without a colour filter mosaic need no demosaicing
+          intermediate => Self::camera_rgb(intermediate)?,
+        }
+      }
+    };
+
+    log::info!(
+      "Developed raw image at {}ms",
+      load_start.elapsed().as_millis()
+    );
+
+    let gains = raw::white_balance_gains(rawimage.wb_coeffs);
+    let orientation = rawimage.orientation;
+    if matches!(orientation, rawler::Orientation::Unknown) {
+      log::warn!("Unknown orientation, applying no transformation");
+    }
+    drop(rawimage);
+
+    // Balanced, converted and oriented straight into the output, so the full size
+    // image exists only twice, as camera RGB and as the output
+    let (source_width, source_height) = (width, height);
+    let (width, height) = oriented_dimensions(orientation, (width, height));
+    let mut float_data = Vec::with_capacity(width * height * 16);
+    for y in 0..height {
+      for x in 0..width {
+        let (source_x, source_y) =
+          oriented_source(orientation, (x, y), (source_width, source_height));
+        let rgb = pixels[source_y * source_width + source_x];
+        let rgb = color::mul_vec(profile, raw::balance(rgb, gains, params.highlights));
+        for value in [rgb[0], rgb[1], rgb[2], 1.0] {
+          float_data.extend_from_slice(&value.to_le_bytes());
+        }
+      }
+    }
+
+    log::info!(
+      "Successfully loaded raw image: {}x{} at {}ms",
+      width,
+      height,
+      load_start.elapsed().as_millis()
+    );
+
+    if let (Some(cache), Some(cache_key)) = (&cache, &cache_key)
+      && let Err(e) =
+        cache.save_to_cache(cache_key, &raw::pack_half(&float_data), (width, height))
+    {
+      log::warn!("Failed to save raw development to cache: {}", e);
+    }
+
+    Ok((float_data, (width, height)))
+  }
+
+  /// Camera RGB pixels of a developed raw image
+  fn camera_rgb(intermediate: Intermediate) -> Result<CameraImage, FileLoaderError> {
+    match intermediate {
+      Intermediate::ThreeColor(image) => Ok((image.data, (image.width, image.height))),
+      Intermediate::Monochrome(image) => Ok((
+        image.data.iter().map(|&value| [value; 3]).collect(),
+        (image.width, image.height),
+      )),
+      Intermediate::FourColor(_) => Err(FileLoaderError::UnsupportedFormat(
+        "Raw files with four colour filters are not supported".to_string(),
+      )),
+    }
   }
 }
 
@@ -228,104 +466,12 @@ impl ImageLoader for RawLoader {
     buffer: &[u8],
     filename: Option<&str>,
   ) -> Result<(Vec<u8>, (usize, usize)), FileLoaderError> {
-    let load_start = Instant::now();
-
-    use rawler::decoders::cr3;
-
-    use crate::cache::ImageCache;
-
-    // Try to load from cache first
-    let cache = ImageCache::new().map_err(|e| {
-      log::warn!(
-        "Failed to initialize cache: {}, proceeding without cache",
-        e
-      );
-      e
-    })?;
-
-    // let cache_key = cache.generate_cache_key(buffer, &Self::get_cache_params());
-
-    log::info!(
-      "Generate cache key in {}ms",
-      load_start.elapsed().as_millis()
-    );
-    let load_start = Instant::now();
-
-    // if let Some(cached_image) = cache.load_from_cache(&cache_key) {
-    //   log::info!("Load from cache in {}ms", load_start.elapsed().as_millis());
-    //   return Ok((cached_image.data, cached_image.dimensions));
-    // }
-
-    log::info!("Loading camera raw from buffer (filename: {:?})", filename);
-
-    let rawsource = RawSource::new_from_slice(buffer);
-
-    log::info!(
-      "Loaded raw source at {}ms",
-      load_start.elapsed().as_millis()
-    );
-
-    let rawimage =
-      rawler::decode(&rawsource, &RawDecodeParams::default()).map_err(|e| {
-        FileLoaderError::DecodeError(format!("Could not decode source: {}", e))
-      })?;
-
-    let orientation = rawimage.orientation;
-
-    log::info!("Image dimensions {:?}", rawimage.dim());
-    log::info!("Image orientation {:?}", orientation);
-
-    log::info!(
-      "Decoded raw image at {}ms",
-      load_start.elapsed().as_millis()
-    );
-
-    let pixels = rawimage.pixels_u16();
-    log::info!("Raw pixels: {} CPP: {:?}", pixels.len(), rawimage.cpp);
-
-    let (width, height) = (rawimage.width as usize, rawimage.height as usize);
-    log::info!("Raw image dimensions: {}x{}", width, height);
-
-    let dev = RawDevelop::default();
-    let image = dev.develop_intermediate(&rawimage).map_err(|e| {
-      FileLoaderError::DecodeError(format!("Raw development error: {}", e))
-    })?;
-
-    log::info!(
-      "Developed raw image at {}ms",
-      load_start.elapsed().as_millis()
-    );
-
-    let img = image.to_dynamic_image().ok_or_else(|| {
-      FileLoaderError::DecodeError("Failed to convert to dynamic image".to_string())
-    })?;
-
-    // TODO: load full res images
-    let img = img.resize(2560, 2560, image::imageops::FilterType::CatmullRom);
-
-    // Apply orientation correction
-    let corrected_img = apply_orientation(img, orientation);
-
-    let rgba_img = corrected_img.to_rgba8();
-    let (width, height) = rgba_img.dimensions();
-    let data = rgba_img.into_raw();
-
-    log::info!("Successfully loaded raw image: {}x{}", width, height);
-
-    // Convert 8-bit RGBA to 32-bit float
-    let float_data = convert_to_float(&data);
-
-    // 50ms for conversions
-
-    log::info!(
-      "Converted raw image at {}ms",
-      load_start.elapsed().as_millis()
-    );
-
-    // Save to cache if cache is available
-    // cache.save_to_cache(&cache_key, &float_data, (width as usize, height as usize))?;
+    Self::develop(buffer, filename, &RawDevelopParams::default())
+  }
 
-    Ok((float_data, (width as usize, height as usize)))
+  fn color_space() -> ColorSpace {
+    // The development ends in linear light with Rec. 709 primaries
+    ColorSpace::LinearSrgb
   }
 
   fn loader_name() -> &'static str {
@@ -439,15 +585,21 @@ impl ImageLoader for StandardLoader {
     }
   }
 
+  fn color_space() -> ColorSpace {
+    ColorSpace::Srgb
+  }
+
   fn loader_name() -> &'static str {
     "Standard Image"
   }
 }
 
-/// Factory function to load an image using the appropriate loader
+/// Factory function to load an image using the appropriate loader, developing camera
+/// raw files with `raw`
 pub fn load_image(
   buffer: &[u8],
   filename: Option<&str>,
+  raw: &RawDevelopParams,
 ) -> Result<(Vec<u8>, (usize, usize)), FileLoaderError> {
   // Provide more detailed error information
   let extension = filename
@@ -469,7 +621,7 @@ pub fn load_image(
       filename,
       extension
     );
-    RawLoader::load(buffer, filename)
+    RawLoader::develop(buffer, filename, raw)
   } else if StandardLoader::can_load(buffer, filename) {
     log::info!(
       "Using {} loader for: {:?} ({})",
@@ -506,6 +658,28 @@ pub fn detect_file_type(buffer: &[u8], filename: Option<&str>) -> Option<&'stati
   }
 }
 
+/// Detect the colour space the appropriate loader decodes a buffer into
+pub fn detect_color_space(buffer: &[u8], filename: Option<&str>) -> Option<ColorSpace> {
+  if ExrLoader::can_load(buffer, filename) {
+    Some(ExrLoader::color_space())
+  } else if RawLoader::can_load(buffer, filename) {
+    Some(RawLoader::color_space())
+  } else if StandardLoader::can_load(buffer, filename) {
+    Some(StandardLoader::color_space())
+  } else {
+    None
+  }
+}
+
+/// Temperature in Kelvin the loaded image is balanced for, known for camera raw files
+pub fn detect_as_shot_kelvin(buffer: &[u8], filename: Option<&str>) -> Option<f32> {
+  if RawLoader::can_load(buffer, filename) {
+    RawLoader::as_shot_kelvin(buffer)
+  } else {
+    None
+  }
+}
+
 /// Get file extension from path
 pub fn get_file_extension(path: &str) -> Option<String> {
   std::path::Path::new(path)
@@ -578,6 +752,41 @@ mod tests {
     assert!(!RawLoader::can_load(&empty_buffer, None));
   }
 
+  #[test]
+  fn test_raw_orientation() {
+    use rawler::Orientation;
+
+    // Every pixel of a 3x2 image is distinct, so the mapping is checked in full
+    let image = image::GrayImage::from_fn(3, 2, |x, y| image::Luma([(y * 3 + x) as u8]));
+    let image = image::DynamicImage::ImageLuma8(image);
+    for (orientation, expected) in [
+      (Orientation::Normal, image.clone()),
+      (Orientation::HorizontalFlip, image.fliph()),
+      (Orientation::Rotate180, image.rotate180()),
+      (Orientation::VerticalFlip, image.flipv()),
+      (Orientation::Transpose, image.rotate90().fliph()),
+      (Orientation::Rotate90, image.rotate90()),
+      (Orientation::Transverse, image.rotate270().fliph()),
+      (Orientation::Rotate270, image.rotate270()),
+    ] {
+      let expected = expected.into_luma8();
+      let (width, height) = oriented_dimensions(orientation, (3, 2));
+      assert_eq!((width as u32, height as u32), expected.dimensions());
+      for (x, y, pixel) in expected.enumerate_pixels() {
+        let (source_x, source_y) =
+          oriented_source(orientation, (x as usize, y as usize), (3, 2));
+        assert_eq!(
+          (source_y * 3 + source_x) as u8,
+          pixel[0],
+          "{:?} at {}, {}",
+          orientation,
+          x,
+          y
+        );
+      }
+    }
+  }
+
   #[test]
   fn test_standard_can_load() {
     // Test with JPEG magic number
@@ -668,4 +877,29 @@ mod tests {
     assert!(!is_supported_format(&empty_buffer, Some("test.unknown")));
     assert!(!is_supported_format(&empty_buffer, None));
   }
+
+  #[test]
+  fn test_detect_color_space() {
+    let exr_header = [0x76, 0x2f, 0x31, 0x01];
+    assert_eq!(
+      detect_color_space(&exr_header, None),
+      Some(ColorSpace::LinearSrgb)
+    );
+
+    let jpeg_header = [0xFF, 0xD8, 0xFF, 0xE0];
+    assert_eq!(
+      detect_color_space(&jpeg_header, Some("test.jpg")),
+      Some(ColorSpace::Srgb)
+    );
+
+    let empty_buffer = [];
+    assert_eq!(
+      detect_color_space(&empty_buffer, Some("test.cr3")),
+      Some(ColorSpace::LinearSrgb)
+    );
+    assert_eq!(
+      detect_color_space(&empty_buffer, Some("test.unknown")),
+      None
+    );
+  }
 }
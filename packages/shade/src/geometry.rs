@@ -0,0 +1,315 @@
+//! Geometric transforms: crops, quarter turns, straightening and perspective correction
+//!
+//! Transforms map every output pixel back to a position in the input image, which is
+//! then resampled. Positions are continuous pixel coordinates with pixel centres at
+//! half-integers. The shaders receive the values computed here, so the CPU and GPU
+//! backends agree on sizes and mappings.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Filter used to read an image between pixel centres
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resampling {
+  #[default]
+  Bilinear,
+  /// Catmull-Rom spline through the surrounding 4x4 pixels, which keeps edges sharper
+  Bicubic,
+}
+
+impl FromStr for Resampling {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "bilinear" => Ok(Resampling::Bilinear),
+      "bicubic" => Ok(Resampling::Bicubic),
+      _ => Err(format!(
+        "Unknown resampling '{}', expected bilinear or bicubic",
+        value
+      )),
+    }
+  }
+}
+
+/// Corners of the whole image as fractions of width and height, in the order top left,
+/// top right, bottom right, bottom left
+pub const FULL_FRAME: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+/// Size of an image turned by `quarter_turns` times 90 degrees
+pub fn rotated_dimensions((width, height): (u32, u32), quarter_turns: u32) -> (u32, u32) {
+  if quarter_turns % 2 == 1 {
+    (height, width)
+  } else {
+    (width, height)
+  }
+}
+
+/// Size of an image rotated by `angle` degrees.
+///
+/// A cropped result is the largest rectangle with the aspect ratio of the input that
+/// fits inside the rotated image. Otherwise it is the bounding box of the rotated image.
+pub fn straightened_dimensions(
+  (width, height): (u32, u32),
+  angle: f32,
+  crop: bool,
+) -> (u32, u32) {
+  let (sin, cos) = angle.to_radians().sin_cos();
+  let (sin, cos) = (sin.abs(), cos.abs());
+  let (width, height) = (width as f32, height as f32);
+
+  let (width, height) = if crop {
+    let scale =
+      (width / (width * cos + height * sin)).min(height / (width * sin + height * cos));
+    // Rounding down keeps every pixel centre inside the rotated image
+    (
+      (width * scale + 1e-3).floor(),
+      (height * scale + 1e-3).floor(),
+    )
+  } else {
+    (
+      (width * cos + height * sin).round(),
+      (width * sin + height * cos).round(),
+    )
+  };
+  ((width as u32).max(1), (height as u32).max(1))
+}
+
+/// Size of the region `[x, y, width, height]` cut out of an image, in pixels. A region
+/// reaching past the right or bottom edge is clamped to the image.
+pub fn cropped_dimensions(
+  (width, height): (u32, u32),
+  [x, y, crop_width, crop_height]: [u32; 4],
+) -> Result<(u32, u32), String> {
+  if x >= width || y >= height || crop_width == 0 || crop_height == 0 {
+    return Err(format!(
+      "Crop region {}x{} at {},{} lies outside the {}x{} image",
+      crop_width, crop_height, x, y, width, height
+    ));
+  }
+  Ok((crop_width.min(width - x), crop_height.min(height - y)))
+}
+
+/// Size of the rectangle a perspective correction maps `corners` to: the mean length
+/// of opposite edges of the quadrilateral in input pixels
+pub fn perspective_dimensions(
+  (width, height): (u32, u32),
+  corners: &[[f32; 2]; 4],
+) -> (u32, u32) {
+  let length = |from: [f32; 2], to: [f32; 2]| {
+    ((to[0] - from[0]) * width as f32).hypot((to[1] - from[1]) * height as f32)
+  };
+  let [top_left, top_right, bottom_right, bottom_left] = *corners;
+  let width = (length(top_left, top_right) + length(bottom_left, bottom_right)) * 0.5;
+  let height = (length(top_left, bottom_left) + length(top_right, bottom_right)) * 0.5;
+  (
+    (width.round() as u32).max(1),
+    (height.round() as u32).max(1),
+  )
+}
+
+/// Projective transform taking the unit square onto `corners`, as the rows of a matrix
+/// applied to `[u, v, 1]` followed by a division by the last component.
+///
+/// Uses Heckbert's closed form of the square to quadrilateral mapping. Corners of a
+/// parallelogram give an affine transform.
+pub fn perspective_matrix(corners: &[[f32; 2]; 4]) -> Result<[[f32; 3]; 3], String> {
+  // Corners outside the image would give outputs larger than any texture
+  if let Some(corner) = corners
+    .iter()
+    .find(|corner| !corner.iter().all(|c| (0.0..=1.0).contains(c)))
+  {
+    return Err(format!(
+      "Perspective corner {:?} is outside the image, 0.0 to 1.0",
+      corner
+    ));
+  }
+  let [[x0, y0], [x1, y1], [x2, y2], [x3, y3]] = *corners;
+  let (dx1, dy1) = (x1 - x2, y1 - y2);
+  let (dx2, dy2) = (x3 - x2, y3 - y2);
+  let (sum_x, sum_y) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+
+  let determinant = dx1 * dy2 - dx2 * dy1;
+  if determinant.abs() < 1e-6 {
+    return Err(format!(
+      "Perspective corners {:?} do not form a quadrilateral",
+      corners
+    ));
+  }
+  let g = (sum_x * dy2 - dx2 * sum_y) / determinant;
+  let h = (dx1 * sum_y - sum_x * dy1) / determinant;
+
+  Ok([
+    [x1 - x0 + g * x1, x3 - x0 + h * x3, x0],
+    [y1 - y0 + g * y1, y3 - y0 + h * y3, y0],
+    [g, h, 1.0],
+  ])
+}
+
+/// Clockwise quarter turns of a rotation by `degrees`, which must be a multiple of 90
+pub fn quarter_turns(degrees: i32) -> Result<u32, String> {
+  if degrees % 90 != 0 {
+    return Err(format!(
+      "Rotation {} is not a multiple of 90 degrees, use straighten for other angles",
+      degrees
+    ));
+  }
+  Ok((degrees / 90).rem_euclid(4) as u32)
+}
+
+/// Parse a rotation in degrees into clockwise quarter turns, e.g. `90` or `-90`
+pub fn parse_quarter_turns(value: &str) -> Result<u32, String> {
+  let degrees = value
+    .trim()
+    .parse::<i32>()
+    .map_err(|e| format!("Invalid rotation '{}': {}", value, e))?;
+  quarter_turns(degrees)
+}
+
+/// Parse a flip direction into horizontal and vertical flags
+pub fn parse_flip(value: &str) -> Result<(bool, bool), String> {
+  match value {
+    "horizontal" => Ok((true, false)),
+    "vertical" => Ok((false, true)),
+    "both" => Ok((true, true)),
+    _ => Err(format!(
+      "Unknown flip '{}', expected horizontal, vertical or both",
+      value
+    )),
+  }
+}
+
+/// Parse a crop region in pixels written as `x:y:width:height`
+pub fn parse_crop(value: &str) -> Result<[u32; 4], String> {
+  let values = value
+    .split(':')
+    .map(|component| {
+      component
+        .trim()
+        .parse::<u32>()
+        .map_err(|e| format!("Invalid crop '{}': {}", value, e))
+    })
+    .collect::<Result<Vec<u32>, String>>()?;
+  let region: [u32; 4] = values
+    .try_into()
+    .map_err(|_| format!("Crop '{}' is not in x:y:width:height form", value))?;
+  if region[2] == 0 || region[3] == 0 {
+    return Err(format!("Crop '{}' has no area", value));
+  }
+  Ok(region)
+}
+
+/// Parse four corners written as `x:y` pairs separated by commas, in the order top
+/// left, top right, bottom right, bottom left
+pub fn parse_corners(value: &str) -> Result<[[f32; 2]; 4], String> {
+  let points = value
+    .split(',')
+    .map(|pair| {
+      let (x, y) = pair
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("Corner '{}' is not in x:y form", pair.trim()))?;
+      let parse = |component: &str| {
+        component
+          .trim()
+          .parse::<f32>()
+          .map_err(|e| format!("Invalid corner '{}': {}", pair.trim(), e))
+      };
+      Ok([parse(x)?, parse(y)?])
+    })
+    .collect::<Result<Vec<[f32; 2]>, String>>()?;
+
+  let corners: [[f32; 2]; 4] = points
+    .try_into()
+    .map_err(|points: Vec<_>| format!("Expected 4 corners, got {}", points.len()))?;
+  perspective_matrix(&corners)?;
+  Ok(corners)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn apply(matrix: &[[f32; 3]; 3], [u, v]: [f32; 2]) -> [f32; 2] {
+    let [x, y, w] = matrix.map(|row| row[0] * u + row[1] * v + row[2]);
+    [x / w, y / w]
+  }
+
+  #[test]
+  fn test_perspective_matrix_maps_corners() {
+    let corners = [[0.1, 0.05], [0.8, 0.0], [1.0, 0.9], [0.0, 1.0]];
+    let matrix = perspective_matrix(&corners).unwrap();
+    for (square, corner) in [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+      .into_iter()
+      .zip(corners)
+    {
+      let mapped = apply(&matrix, square);
+      assert!(
+        (mapped[0] - corner[0]).abs() < 1e-5 && (mapped[1] - corner[1]).abs() < 1e-5,
+        "{:?} mapped to {:?} instead of {:?}",
+        square,
+        mapped,
+        corner
+      );
+    }
+
+    let identity = perspective_matrix(&FULL_FRAME).unwrap();
+    assert_eq!(apply(&identity, [0.25, 0.75]), [0.25, 0.75]);
+    assert!(
+      perspective_matrix(&[[0.0, 0.0], [0.3, 0.0], [0.6, 0.0], [0.9, 0.0]]).is_err()
+    );
+    // Corners outside the image, which would give an enormous output
+    assert!(
+      perspective_matrix(&[[0.0, 0.0], [40.0, 0.0], [40.0, 40.0], [0.0, 40.0]]).is_err()
+    );
+    assert!(
+      perspective_matrix(&[[0.0, 0.0], [1.0, f32::NAN], [1.0, 1.0], [0.0, 1.0]]).is_err()
+    );
+  }
+
+  #[test]
+  fn test_transformed_dimensions() {
+    assert_eq!(rotated_dimensions((40, 30), 1), (30, 40));
+    assert_eq!(rotated_dimensions((40, 30), 2), (40, 30));
+
+    assert_eq!(straightened_dimensions((40, 30), 0.0, true), (40, 30));
+    assert_eq!(straightened_dimensions((40, 30), 0.0, false), (40, 30));
+    assert_eq!(straightened_dimensions((40, 30), 90.0, false), (30, 40));
+    // A landscape image turned upright only holds a much smaller landscape crop
+    assert_eq!(straightened_dimensions((40, 30), 90.0, true), (30, 22));
+    let (width, height) = straightened_dimensions((400, 300), 5.0, true);
+    assert!(width < 400 && height < 300);
+    assert!((width as f32 / height as f32 - 4.0 / 3.0).abs() < 0.01);
+
+    assert_eq!(cropped_dimensions((40, 30), [10, 5, 20, 10]), Ok((20, 10)));
+    assert_eq!(cropped_dimensions((40, 30), [30, 20, 20, 20]), Ok((10, 10)));
+    assert!(cropped_dimensions((40, 30), [40, 0, 10, 10]).is_err());
+
+    assert_eq!(perspective_dimensions((40, 30), &FULL_FRAME), (40, 30));
+    let keystone = [[0.25, 0.0], [0.75, 0.0], [1.0, 1.0], [0.0, 1.0]];
+    assert_eq!(perspective_dimensions((40, 30), &keystone), (30, 32));
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!(parse_quarter_turns("90"), Ok(1));
+    assert_eq!(parse_quarter_turns("-90"), Ok(3));
+    assert_eq!(parse_quarter_turns("540"), Ok(2));
+    assert!(parse_quarter_turns("45").is_err());
+
+    assert_eq!(parse_flip("both"), Ok((true, true)));
+    assert!(parse_flip("diagonal").is_err());
+
+    assert_eq!(parse_crop("10:20:300:200"), Ok([10, 20, 300, 200]));
+    assert!(parse_crop("10:20:300").is_err());
+    assert!(parse_crop("10:20:0:200").is_err());
+
+    assert_eq!(
+      parse_corners("0.1:0, 0.9:0, 1:1, 0:1"),
+      Ok([[0.1, 0.0], [0.9, 0.0], [1.0, 1.0], [0.0, 1.0]])
+    );
+    assert!(parse_corners("0:0,1:0,1:1").is_err());
+    assert!(parse_corners("0:0,1:0,1:1,0;1").is_err());
+  }
+}
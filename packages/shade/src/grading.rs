@@ -0,0 +1,137 @@
+//! Colour grading wheels for shadows, midtones and highlights
+//!
+//! Every wheel pushes its tonal range towards a hue and lifts or lowers it. The three
+//! ranges are split by the pixel's luma at a third and two thirds, after `balance`
+//! bends luma so the split moves towards the shadows or the highlights. `blending`
+//! sets how far the ranges overlap around these splits. The same math is implemented
+//! in `shaders/color_grading.wgsl`.
+
+use serde::{Deserialize, Serialize};
+
+/// Rec. 709 luma weights. Tints have no luma, so they change colour, not brightness.
+pub const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Largest offset of encoded values a wheel at full saturation or luminance adds
+const STRENGTH: f32 = 0.2;
+
+/// Grading of a single tonal range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GradingWheel {
+  /// Hue the range is tinted towards, in degrees
+  pub hue: f32,
+  /// Strength of the tint (0.0 to 1.0)
+  pub saturation: f32,
+  /// Brightness offset of the range (-1.0 to 1.0)
+  pub luminance: f32,
+}
+
+impl GradingWheel {
+  /// Offset the wheel adds to encoded values at full weight
+  pub fn offset(&self) -> [f32; 3] {
+    let color = hue_color(self.hue);
+    let luma: f32 = (0..3).map(|i| color[i] * LUMA[i]).sum();
+    color.map(|c| ((c - luma) * self.saturation + self.luminance) * STRENGTH)
+  }
+}
+
+/// Fully saturated colour of a hue in degrees
+fn hue_color(hue: f32) -> [f32; 3] {
+  let hue = hue / 360.0;
+  [0.0, 2.0 / 3.0, 1.0 / 3.0].map(|offset| {
+    let t = hue + offset;
+    (((t - t.floor()) * 6.0 - 3.0).abs() - 1.0).clamp(0.0, 1.0)
+  })
+}
+
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+  let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+/// Weights of the shadows, midtones and highlights for an encoded `luma`, adding up
+/// to one. Positive `balance` (-1.0 to 1.0) hands more of the tones to the
+/// highlights, `blending` (0.0 to 1.0) widens the overlap between neighbouring ranges.
+pub fn tonal_weights(luma: f32, balance: f32, blending: f32) -> [f32; 3] {
+  let x = luma.clamp(1e-6, 1.0).powf((-balance).exp2());
+  let overlap = blending.clamp(0.01, 1.0) / 3.0;
+  let shadows = 1.0 - smoothstep(1.0 / 3.0 - overlap, 1.0 / 3.0 + overlap, x);
+  let highlights = smoothstep(2.0 / 3.0 - overlap, 2.0 / 3.0 + overlap, x);
+  // Highlights rise a third later than shadows fade, so the two never add up to more
+  // than one and the midtones take the rest
+  [shadows, 1.0 - shadows - highlights, highlights]
+}
+
+/// Parse a wheel written as `hue:saturation[:luminance]`
+pub fn parse_wheel(value: &str) -> Result<GradingWheel, String> {
+  let values = value
+    .split(':')
+    .map(|number| {
+      number
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("Invalid number '{}' in '{}'", number, value))
+    })
+    .collect::<Result<Vec<f32>, String>>()?;
+  if !(2..=3).contains(&values.len()) {
+    return Err(format!(
+      "Expected hue:saturation or hue:saturation:luminance, got '{}'",
+      value
+    ));
+  }
+
+  Ok(GradingWheel {
+    hue: values[0],
+    saturation: values[1],
+    luminance: values.get(2).copied().unwrap_or(0.0),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_hue_color() {
+    assert_eq!(hue_color(0.0), [1.0, 0.0, 0.0]);
+    assert_eq!(hue_color(120.0), [0.0, 1.0, 0.0]);
+    assert_eq!(hue_color(-120.0), [0.0, 0.0, 1.0]);
+    assert_eq!(hue_color(30.0), [1.0, 0.5, 0.0]);
+
+    // A tint changes colour but not luma
+    let wheel = GradingWheel {
+      hue: 210.0,
+      saturation: 1.0,
+      luminance: 0.0,
+    };
+    let offset = wheel.offset();
+    assert!((0..3).map(|i| offset[i] * LUMA[i]).sum::<f32>().abs() < 1e-6);
+    assert!(offset[2] > offset[0]);
+  }
+
+  #[test]
+  fn test_tonal_weights() {
+    assert_eq!(tonal_weights(0.0, 0.0, 0.5), [1.0, 0.0, 0.0]);
+    assert_eq!(tonal_weights(0.5, 0.0, 0.5), [0.0, 1.0, 0.0]);
+    assert_eq!(tonal_weights(1.0, 0.0, 0.5), [0.0, 0.0, 1.0]);
+
+    // Weights are never negative, and balance moves the split
+    for i in 0..=20 {
+      let weights = tonal_weights(i as f32 / 20.0, 0.3, 1.0);
+      assert!(weights.iter().all(|&weight| weight >= 0.0), "{:?}", weights);
+    }
+    assert!(tonal_weights(0.6, 1.0, 0.5)[2] > tonal_weights(0.6, 0.0, 0.5)[2]);
+  }
+
+  #[test]
+  fn test_parse_wheel() {
+    let wheel = parse_wheel("200:0.4").unwrap();
+    assert_eq!(
+      (wheel.hue, wheel.saturation, wheel.luminance),
+      (200.0, 0.4, 0.0)
+    );
+    assert_eq!(parse_wheel("30:0.2:-0.1").unwrap().luminance, -0.1);
+    assert!(parse_wheel("30").is_err());
+    assert!(parse_wheel("30:x").is_err());
+  }
+}
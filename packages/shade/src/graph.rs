@@ -0,0 +1,282 @@
+//! Serializable node graph format
+//!
+//! A graph document stores the nodes of an `ImagePipeline` with their parameters
+//! and enabled flags, plus the connections between them, as versioned JSON. The
+//! layout is documented in the "Graph Format" section of the README.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::shade::{Connection, ImagePipeline, ProcessingNode};
+
+/// Version written to new documents and the only version accepted when loading
+pub const GRAPH_FORMAT_VERSION: u32 = 1;
+
+/// A complete pipeline graph as stored on disk or sent over the socket protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphDocument {
+  pub version: u32,
+  pub nodes: Vec<ProcessingNode>,
+  #[serde(default)]
+  pub connections: Vec<Connection>,
+}
+
+impl GraphDocument {
+  /// Capture the nodes and connections of a pipeline, ordered by node id
+  pub fn from_pipeline(pipeline: &ImagePipeline) -> Self {
+    let mut nodes: Vec<ProcessingNode> = pipeline.nodes.values().cloned().collect();
+    nodes.sort_by_key(|node| node.id);
+
+    Self {
+      version: GRAPH_FORMAT_VERSION,
+      nodes,
+      connections: pipeline.connections.clone(),
+    }
+  }
+
+  /// Build a pipeline from the document, validating node parameters and connections
+  pub fn into_pipeline(self) -> Result<ImagePipeline, String> {
+    if self.version != GRAPH_FORMAT_VERSION {
+      return Err(format!(
+        "Unsupported graph format version {} (expected {})",
+        self.version, GRAPH_FORMAT_VERSION
+      ));
+    }
+
+    let mut pipeline = ImagePipeline::new();
+    for node in self.nodes {
+      pipeline.insert_node(node)?;
+    }
+    for connection in self.connections {
+      pipeline.connect_nodes(
+        connection.from_node,
+        connection.from_output,
+        connection.to_node,
+        connection.to_input,
+      )?;
+    }
+
+    Ok(pipeline)
+  }
+
+  pub fn from_json(json: &str) -> Result<Self, String> {
+    serde_json::from_str(json).map_err(|e| format!("Invalid graph document: {}", e))
+  }
+
+  pub fn to_json(&self) -> Result<String, String> {
+    serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+  }
+}
+
+/// Load a pipeline from a graph document file
+pub fn load_graph(path: &Path) -> Result<ImagePipeline, String> {
+  let json = std::fs::read_to_string(path)
+    .map_err(|e| format!("Failed to read graph {}: {}", path.display(), e))?;
+  GraphDocument::from_json(&json)?.into_pipeline()
+}
+
+/// Write the nodes and connections of a pipeline to a graph document file
+pub fn save_graph(pipeline: &ImagePipeline, path: &Path) -> Result<(), String> {
+  let json = GraphDocument::from_pipeline(pipeline).to_json()?;
+  std::fs::write(path, json)
+    .map_err(|e| format!("Failed to write graph {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::shade::{NodeParams, NodeType};
+
+  #[test]
+  fn test_graph_roundtrip() {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let blur_id = pipeline.add_node("Blur".to_string(), NodeType::Blur);
+    let mix_id = pipeline.add_node("Mix".to_string(), NodeType::Mix);
+    pipeline
+      .get_node_mut(blur_id)
+      .unwrap()
+      .set_params(NodeParams::Blur { radius: 3.5 });
+    pipeline.get_node_mut(mix_id).unwrap().set_enabled(false);
+    for (from, to, to_input) in [
+      (input_id, blur_id, "image"),
+      (input_id, mix_id, "image1"),
+      (blur_id, mix_id, "image2"),
+    ] {
+      pipeline
+        .connect_nodes(from, "image".to_string(), to, to_input.to_string())
+        .unwrap();
+    }
+
+    let json = GraphDocument::from_pipeline(&pipeline).to_json().unwrap();
+    let restored = GraphDocument::from_json(&json)
+      .unwrap()
+      .into_pipeline()
+      .unwrap();
+
+    assert_eq!(restored.nodes.len(), 3);
+    assert_eq!(restored.input_node_id, Some(input_id));
+    assert!(matches!(
+      restored.nodes[&blur_id].params,
+      NodeParams::Blur { radius } if radius == 3.5
+    ));
+    assert!(!restored.nodes[&mix_id].enabled);
+    assert_eq!(restored.nodes[&mix_id].inputs, vec!["image1", "image2"]);
+    assert_eq!(restored.connections.len(), 3);
+
+    // Serializing the restored pipeline gives the same document
+    assert_eq!(
+      GraphDocument::from_pipeline(&restored).to_json().unwrap(),
+      json
+    );
+  }
+
+  #[test]
+  fn test_save_graph() {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let blur_id = pipeline.add_node("Blur".to_string(), NodeType::Blur);
+    pipeline
+      .get_node_mut(blur_id)
+      .unwrap()
+      .set_params(NodeParams::Blur { radius: 2.0 });
+    pipeline
+      .connect_nodes(input_id, "image".to_string(), blur_id, "image".to_string())
+      .unwrap();
+
+    let path = std::env::temp_dir().join("shade_test_save_graph.json");
+    save_graph(&pipeline, &path).unwrap();
+    let restored = load_graph(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+      GraphDocument::from_pipeline(&restored).to_json().unwrap(),
+      GraphDocument::from_pipeline(&pipeline).to_json().unwrap()
+    );
+  }
+
+  #[test]
+  fn test_readme_example() {
+    let readme = include_str!("../README.md");
+    let section = &readme[readme.find("### Graph Format").unwrap()..];
+    let start = section.find("```json\n").unwrap() + "```json\n".len();
+    let json = &section[start..start + section[start..].find("```").unwrap()];
+
+    let pipeline = GraphDocument::from_json(json)
+      .unwrap()
+      .into_pipeline()
+      .unwrap();
+    assert_eq!(pipeline.nodes.len(), 3);
+    assert_eq!(pipeline.connections.len(), 2);
+  }
+
+  #[test]
+  fn test_graph_json_layout() {
+    let json = r#"{
+      "version": 1,
+      "nodes": [
+        {"id": 0, "name": "Input", "type": "image_input", "params": "none"},
+        {"id": 4, "name": "Warm", "type": "white_balance",
+         "params": {"white_balance": {"auto_adjust": false, "temperature": 0.2, "tint": 0.0}}},
+        {"id": 5, "name": "Small", "type": "resize", "params": {"resize": {"width": 800}}}
+      ],
+      "connections": [
+        {"from_node": 0, "from_output": "image", "to_node": 4, "to_input": "image"},
+        {"from_node": 4, "from_output": "image", "to_node": 5, "to_input": "image"}
+      ]
+    }"#;
+
+    let mut pipeline = GraphDocument::from_json(json)
+      .unwrap()
+      .into_pipeline()
+      .unwrap();
+    assert!(pipeline.nodes[&4].enabled);
+    assert!(matches!(
+      pipeline.nodes[&5].params,
+      NodeParams::Resize {
+        width: Some(800),
+        height: None
+      }
+    ));
+
+    // New nodes continue after the highest loaded id
+    assert_eq!(pipeline.add_node("Blur".to_string(), NodeType::Blur), 6);
+  }
+
+  #[test]
+  fn test_masked_graph() {
+    let json = r#"{
+      "version": 1,
+      "nodes": [
+        {"id": 0, "name": "Input", "type": "image_input", "params": "none"},
+        {"id": 1, "name": "Brush", "type": "painted_mask",
+         "params": {"painted_mask": {"bitmap": {"width": 2, "height": 1, "data": "/wA="}}}},
+        {"id": 2, "name": "Darken", "type": "exposure", "params": {"exposure": {"stops": -1.0}}},
+        {"id": 3, "name": "Mask", "type": "mask", "params": "none"},
+        {"id": 4, "name": "Blend", "type": "mix",
+         "params": {"mix": {"factor": 1.0, "use_alpha": true}}}
+      ],
+      "connections": [
+        {"from_node": 0, "from_output": "image", "to_node": 1, "to_input": "image"},
+        {"from_node": 0, "from_output": "image", "to_node": 2, "to_input": "image"},
+        {"from_node": 2, "from_output": "image", "to_node": 3, "to_input": "image"},
+        {"from_node": 1, "from_output": "mask", "to_node": 3, "to_input": "mask"},
+        {"from_node": 0, "from_output": "image", "to_node": 4, "to_input": "image1"},
+        {"from_node": 3, "from_output": "image", "to_node": 4, "to_input": "image2"}
+      ]
+    }"#;
+
+    let pipeline = GraphDocument::from_json(json)
+      .unwrap()
+      .into_pipeline()
+      .unwrap();
+    assert!(matches!(
+      &pipeline.nodes[&1].params,
+      NodeParams::PaintedMask { bitmap } if bitmap.data == [255, 0]
+    ));
+    assert_eq!(pipeline.nodes[&3].inputs, vec!["image", "mask"]);
+  }
+
+  #[test]
+  fn test_graph_validation() {
+    let wrong_version = r#"{"version": 2, "nodes": []}"#;
+    assert!(
+      GraphDocument::from_json(wrong_version)
+        .unwrap()
+        .into_pipeline()
+        .is_err()
+    );
+
+    let mismatched_params = r#"{"version": 1, "nodes": [
+      {"id": 0, "name": "Blur", "type": "blur", "params": {"sharpen": {"amount": 1.0}}}
+    ]}"#;
+    assert!(
+      GraphDocument::from_json(mismatched_params)
+        .unwrap()
+        .into_pipeline()
+        .is_err()
+    );
+
+    let unknown_input = r#"{"version": 1, "nodes": [
+      {"id": 0, "name": "Input", "type": "image_input", "params": "none"},
+      {"id": 1, "name": "Blur", "type": "blur", "params": {"blur": {"radius": 1.0}}}
+    ], "connections": [
+      {"from_node": 0, "from_output": "image", "to_node": 1, "to_input": "mask"}
+    ]}"#;
+    assert!(
+      GraphDocument::from_json(unknown_input)
+        .unwrap()
+        .into_pipeline()
+        .is_err()
+    );
+    let largest_id = r#"{"version": 1, "nodes": [
+      {"id": 18446744073709551615, "name": "Input", "type": "image_input", "params": "none"}
+    ]}"#;
+    assert!(
+      GraphDocument::from_json(largest_id)
+        .unwrap()
+        .into_pipeline()
+        .is_err()
+    );
+  }
+}
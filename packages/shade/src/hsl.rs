@@ -0,0 +1,241 @@
+//! Selective colour adjustments in eight hue bands
+//!
+//! Every band is centred on a hue. A pixel's hue falls between two neighbouring
+//! centres and the adjustments of both bands are blended with a smoothstep, so the
+//! bands fade into each other without seams. The same blend is implemented in
+//! `shaders/hsl.wgsl`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A hue band of an `Hsl` node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HslBand {
+  Red,
+  Orange,
+  Yellow,
+  Green,
+  Aqua,
+  Blue,
+  Purple,
+  Magenta,
+}
+
+impl HslBand {
+  /// All bands in order of their centre hue
+  pub const ALL: [HslBand; 8] = [
+    HslBand::Red,
+    HslBand::Orange,
+    HslBand::Yellow,
+    HslBand::Green,
+    HslBand::Aqua,
+    HslBand::Blue,
+    HslBand::Purple,
+    HslBand::Magenta,
+  ];
+
+  /// Hue the band is centred on, in degrees
+  pub fn center(self) -> f32 {
+    match self {
+      HslBand::Red => 0.0,
+      HslBand::Orange => 30.0,
+      HslBand::Yellow => 60.0,
+      HslBand::Green => 120.0,
+      HslBand::Aqua => 180.0,
+      HslBand::Blue => 240.0,
+      HslBand::Purple => 270.0,
+      HslBand::Magenta => 300.0,
+    }
+  }
+}
+
+impl FromStr for HslBand {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    HslBand::ALL
+      .into_iter()
+      .find(|band| band.to_string() == value)
+      .ok_or_else(|| {
+        format!(
+          "Unknown hue band '{}', expected red, orange, yellow, green, aqua, blue, \
+           purple or magenta",
+          value
+        )
+      })
+  }
+}
+
+impl fmt::Display for HslBand {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      HslBand::Red => "red",
+      HslBand::Orange => "orange",
+      HslBand::Yellow => "yellow",
+      HslBand::Green => "green",
+      HslBand::Aqua => "aqua",
+      HslBand::Blue => "blue",
+      HslBand::Purple => "purple",
+      HslBand::Magenta => "magenta",
+    };
+    f.write_str(name)
+  }
+}
+
+/// Adjustment of a single hue band
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HslAdjustment {
+  /// Hue shift in degrees
+  pub hue: f32,
+  /// Relative saturation change, -1.0 removes all colour
+  pub saturation: f32,
+  /// Relative brightness change (-1.0 to 1.0), scaled by the pixel's saturation
+  pub luminance: f32,
+}
+
+/// Adjustments of all eight bands. Bands that are left out stay unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HslBands {
+  pub red: HslAdjustment,
+  pub orange: HslAdjustment,
+  pub yellow: HslAdjustment,
+  pub green: HslAdjustment,
+  pub aqua: HslAdjustment,
+  pub blue: HslAdjustment,
+  pub purple: HslAdjustment,
+  pub magenta: HslAdjustment,
+}
+
+impl HslBands {
+  pub fn get_mut(&mut self, band: HslBand) -> &mut HslAdjustment {
+    match band {
+      HslBand::Red => &mut self.red,
+      HslBand::Orange => &mut self.orange,
+      HslBand::Yellow => &mut self.yellow,
+      HslBand::Green => &mut self.green,
+      HslBand::Aqua => &mut self.aqua,
+      HslBand::Blue => &mut self.blue,
+      HslBand::Purple => &mut self.purple,
+      HslBand::Magenta => &mut self.magenta,
+    }
+  }
+
+  /// Adjustments in the order of `HslBand::ALL`
+  pub fn to_array(&self) -> [HslAdjustment; 8] {
+    [
+      self.red,
+      self.orange,
+      self.yellow,
+      self.green,
+      self.aqua,
+      self.blue,
+      self.purple,
+      self.magenta,
+    ]
+  }
+
+  /// Adjustment for a hue in degrees, blended between the two nearest bands
+  pub fn blend(&self, hue: f32) -> HslAdjustment {
+    let bands = self.to_array();
+    let segment = (0..8)
+      .rev()
+      .find(|&i| hue >= HslBand::ALL[i].center())
+      .unwrap_or(0);
+    let start = HslBand::ALL[segment].center();
+    let end = HslBand::ALL
+      .get(segment + 1)
+      .map_or(360.0, |band| band.center());
+
+    let t = ((hue - start) / (end - start)).clamp(0.0, 1.0);
+    let weight = t * t * (3.0 - 2.0 * t);
+    let (from, to) = (bands[segment], bands[(segment + 1) % 8]);
+    let mix = |a: f32, b: f32| a + (b - a) * weight;
+    HslAdjustment {
+      hue: mix(from.hue, to.hue),
+      saturation: mix(from.saturation, to.saturation),
+      luminance: mix(from.luminance, to.luminance),
+    }
+  }
+}
+
+/// Parse a band adjustment written as `band:hue,saturation,luminance`.
+///
+/// Trailing values may be left out, so `blue:-10` only shifts the hue of blues.
+pub fn parse_band(value: &str) -> Result<(HslBand, HslAdjustment), String> {
+  let (band, values) = value
+    .split_once(':')
+    .ok_or_else(|| format!("Expected band:hue,saturation,luminance, got '{}'", value))?;
+  let band = band.trim().parse::<HslBand>()?;
+
+  let values = values
+    .split(',')
+    .map(|number| {
+      number
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("Invalid number '{}' in '{}'", number, value))
+    })
+    .collect::<Result<Vec<f32>, String>>()?;
+  if values.len() > 3 {
+    return Err(format!(
+      "Expected at most hue, saturation and luminance in '{}'",
+      value
+    ));
+  }
+
+  let component = |i: usize| values.get(i).copied().unwrap_or(0.0);
+  Ok((
+    band,
+    HslAdjustment {
+      hue: component(0),
+      saturation: component(1),
+      luminance: component(2),
+    },
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_band() {
+    let (band, adjustment) = parse_band("blue:-10,-0.5,0.2").unwrap();
+    assert_eq!(band, HslBand::Blue);
+    assert_eq!(adjustment.hue, -10.0);
+    assert_eq!(adjustment.saturation, -0.5);
+    assert_eq!(adjustment.luminance, 0.2);
+
+    let (band, adjustment) = parse_band("orange:5").unwrap();
+    assert_eq!(band, HslBand::Orange);
+    assert_eq!(adjustment.saturation, 0.0);
+
+    assert!(parse_band("teal:10").is_err());
+    assert!(parse_band("blue").is_err());
+    assert!(parse_band("blue:1,2,3,4").is_err());
+    assert!(parse_band("blue:x").is_err());
+  }
+
+  #[test]
+  fn test_bands_blend_smoothly() {
+    let mut bands = HslBands::default();
+    bands.green.hue = 20.0;
+
+    // Halfway between the yellow and green centres half the shift applies
+    assert!((bands.blend(90.0).hue - 10.0).abs() < 1e-4);
+    assert!((bands.blend(120.0).hue - 20.0).abs() < 1e-4);
+    assert_eq!(bands.blend(60.0).hue, 0.0);
+
+    // The weights change without jumps across the whole hue circle
+    let mut previous = bands.blend(0.0).hue;
+    for step in 1..=720 {
+      let current = bands.blend(step as f32 * 0.5).hue;
+      assert!((current - previous).abs() < 0.5);
+      previous = current;
+    }
+  }
+}
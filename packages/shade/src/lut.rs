@@ -0,0 +1,427 @@
+//! 3D lookup tables loaded from `.cube` and `.3dl` files
+//!
+//! Tables are stored with the red index changing fastest, the layout of `.cube` files
+//! and of the 3D texture the `Lut3D` shader samples. Parsed files are cached by path
+//! and modification time, since a pipeline run reads the same table several times.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::SystemTime;
+
+/// How a color is interpolated between the eight surrounding table entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LutInterpolation {
+  #[default]
+  Trilinear,
+  /// Blends four entries of the tetrahedron containing the color, which keeps the
+  /// neutral axis of the table neutral
+  Tetrahedral,
+}
+
+impl std::str::FromStr for LutInterpolation {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "trilinear" => Ok(LutInterpolation::Trilinear),
+      "tetrahedral" => Ok(LutInterpolation::Tetrahedral),
+      _ => Err(format!(
+        "Unknown LUT interpolation '{}', expected trilinear or tetrahedral",
+        value
+      )),
+    }
+  }
+}
+
+/// A cubic RGB lookup table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3D {
+  pub size: u32,
+  /// Input values mapped to the first and last table entry of each channel
+  pub domain_min: [f32; 3],
+  pub domain_max: [f32; 3],
+  /// `size³` output colors, red index fastest
+  pub table: Vec<[f32; 3]>,
+}
+
+static LOADED: LazyLock<Mutex<HashMap<PathBuf, (Option<SystemTime>, Arc<Lut3D>)>>> =
+  LazyLock::new(Default::default);
+
+/// Load a `.cube` or `.3dl` file, reusing the parsed table while the file is unchanged
+pub fn load(path: &Path) -> Result<Arc<Lut3D>, String> {
+  let modified = std::fs::metadata(path)
+    .and_then(|metadata| metadata.modified())
+    .ok();
+
+  let mut loaded = LOADED.lock().map_err(|e| e.to_string())?;
+  if let Some((loaded_modified, lut)) = loaded.get(path) {
+    if *loaded_modified == modified {
+      return Ok(lut.clone());
+    }
+  }
+
+  let contents = std::fs::read_to_string(path)
+    .map_err(|e| format!("Failed to read LUT {}: {}", path.display(), e))?;
+  let extension = path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase());
+  let lut = match extension.as_deref() {
+    Some("cube") => Lut3D::parse_cube(&contents),
+    Some("3dl") => Lut3D::parse_3dl(&contents),
+    _ => Err("Unsupported LUT format, expected .cube or .3dl".to_string()),
+  }
+  .map(Arc::new)
+  .map_err(|e| format!("Invalid LUT {}: {}", path.display(), e))?;
+
+  loaded.insert(path.to_path_buf(), (modified, lut.clone()));
+  Ok(lut)
+}
+
+impl Lut3D {
+  /// Parse an Adobe/Resolve `.cube` file
+  pub fn parse_cube(contents: &str) -> Result<Self, String> {
+    let mut size = None;
+    let mut domain_min = [0.0; 3];
+    let mut domain_max = [1.0; 3];
+    let mut table = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let error = |message: &str| format!("line {}: {}", line_number + 1, message);
+
+      let mut fields = line.split_whitespace();
+      let keyword = fields.next().unwrap_or_default();
+      let values = || {
+        line
+          .split_whitespace()
+          .skip(1)
+          .map(|value| value.parse::<f32>())
+          .collect::<Result<Vec<f32>, _>>()
+          .map_err(|e| error(&e.to_string()))
+      };
+
+      match keyword {
+        "TITLE" => {}
+        "LUT_3D_SIZE" => {
+          let value = fields
+            .next()
+            .and_then(|value| value.parse::<u32>().ok())
+            .ok_or_else(|| error("LUT_3D_SIZE needs a size"))?;
+          size = Some(value);
+        }
+        "LUT_1D_SIZE" => return Err(error("1D LUTs are not supported")),
+        "DOMAIN_MIN" | "DOMAIN_MAX" => {
+          let values = values()?;
+          let domain: [f32; 3] = values
+            .try_into()
+            .map_err(|_| error("domain needs three values"))?;
+          if keyword == "DOMAIN_MIN" {
+            domain_min = domain;
+          } else {
+            domain_max = domain;
+          }
+        }
+        "LUT_3D_INPUT_RANGE" => {
+          let values = values()?;
+          let [min, max]: [f32; 2] = values
+            .try_into()
+            .map_err(|_| error("input range needs two values"))?;
+          domain_min = [min; 3];
+          domain_max = [max; 3];
+        }
+        _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+          log::debug!("Ignoring unknown .cube keyword {}", keyword);
+        }
+        _ => {
+          let color = line
+            .split_whitespace()
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| error(&e.to_string()))?;
+          let color: [f32; 3] = color
+            .try_into()
+            .map_err(|_| error("table entries need three values"))?;
+          table.push(color);
+        }
+      }
+    }
+
+    let size = size.ok_or("missing LUT_3D_SIZE")?;
+    Self::new(size, domain_min, domain_max, table)
+  }
+
+  /// Parse an Autodesk/Lustre `.3dl` file.
+  ///
+  /// The first numeric line lists the input levels of the mesh, which gives the table
+  /// size. Entries are integers with the blue index changing fastest and are scaled by
+  /// the output bit depth from a `Mesh` line, or else by the smallest common depth
+  /// that holds the largest entry.
+  pub fn parse_3dl(contents: &str) -> Result<Self, String> {
+    let mut mesh: Option<u32> = None;
+    let mut output_bits = None;
+    let mut entries = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') || line == "3DMESH" {
+        continue;
+      }
+      let error = |message: &str| format!("line {}: {}", line_number + 1, message);
+
+      if let Some(depths) = line.strip_prefix("Mesh") {
+        let bits = depths
+          .split_whitespace()
+          .nth(1)
+          .and_then(|bits| bits.parse::<u32>().ok())
+          .ok_or_else(|| error("Mesh needs input and output bit depths"))?;
+        output_bits = Some(bits);
+        continue;
+      }
+
+      let values = line
+        .split_whitespace()
+        .map(|value| value.parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|e| error(&e.to_string()))?;
+
+      if mesh.is_none() {
+        mesh = Some(values.len() as u32);
+        continue;
+      }
+      let entry: [u32; 3] = values
+        .try_into()
+        .map_err(|_| error("table entries need three values"))?;
+      entries.push(entry);
+    }
+
+    let size = mesh.ok_or("missing mesh line")?;
+    let largest = entries.iter().flatten().copied().max().unwrap_or(0);
+    let bits = output_bits.unwrap_or_else(|| {
+      [8, 10, 12, 14, 16]
+        .into_iter()
+        .find(|bits| largest < 1 << bits)
+        .unwrap_or(32)
+    });
+    let scale = ((1u64 << bits) - 1) as f32;
+
+    // Reorder from blue fastest to red fastest
+    let n = size as usize;
+    let mut table = vec![[0.0; 3]; entries.len()];
+    for (i, entry) in entries.iter().enumerate() {
+      let (r, g, b) = (i / (n * n), (i / n) % n, i % n);
+      if let Some(slot) = table.get_mut(r + g * n + b * n * n) {
+        *slot = entry.map(|value| value as f32 / scale);
+      }
+    }
+
+    Self::new(size, [0.0; 3], [1.0; 3], table)
+  }
+
+  fn new(
+    size: u32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    table: Vec<[f32; 3]>,
+  ) -> Result<Self, String> {
+    if size < 2 {
+      return Err(format!("LUT size {} is too small", size));
+    }
+    let expected = (size as usize).pow(3);
+    if table.len() != expected {
+      return Err(format!(
+        "expected {} entries for size {}, found {}",
+        expected,
+        size,
+        table.len()
+      ));
+    }
+    if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+      return Err("domain max must be greater than domain min".to_string());
+    }
+
+    Ok(Self {
+      size,
+      domain_min,
+      domain_max,
+      table,
+    })
+  }
+
+  fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+    let n = self.size as usize;
+    self.table[r + g * n + b * n * n]
+  }
+
+  /// Look up a color. Mirrors `apply_lut` in `lut3d.wgsl`.
+  pub fn sample(&self, color: [f32; 3], interpolation: LutInterpolation) -> [f32; 3] {
+    let last = (self.size - 1) as f32;
+    let mut base = [0usize; 3];
+    let mut f = [0.0f32; 3];
+    for i in 0..3 {
+      let normalized = ((color[i] - self.domain_min[i])
+        / (self.domain_max[i] - self.domain_min[i]))
+        .clamp(0.0, 1.0);
+      let position = normalized * last;
+      let index = position.floor().min(last - 1.0);
+      base[i] = index as usize;
+      f[i] = position - index;
+    }
+
+    let [r, g, b] = base;
+    let c000 = self.entry(r, g, b);
+    let c111 = self.entry(r + 1, g + 1, b + 1);
+    let [fx, fy, fz] = f;
+
+    match interpolation {
+      LutInterpolation::Trilinear => {
+        let c100 = self.entry(r + 1, g, b);
+        let c010 = self.entry(r, g + 1, b);
+        let c110 = self.entry(r + 1, g + 1, b);
+        let c001 = self.entry(r, g, b + 1);
+        let c101 = self.entry(r + 1, g, b + 1);
+        let c011 = self.entry(r, g + 1, b + 1);
+        [0, 1, 2].map(|i| {
+          let c00 = mix(c000[i], c100[i], fx);
+          let c10 = mix(c010[i], c110[i], fx);
+          let c01 = mix(c001[i], c101[i], fx);
+          let c11 = mix(c011[i], c111[i], fx);
+          mix(mix(c00, c10, fy), mix(c01, c11, fy), fz)
+        })
+      }
+      LutInterpolation::Tetrahedral => {
+        // The two inner corners and the weights of the tetrahedron holding the color
+        let (c1, c2, weights) = if fx > fy {
+          if fy > fz {
+            (
+              self.entry(r + 1, g, b),
+              self.entry(r + 1, g + 1, b),
+              [1.0 - fx, fx - fy, fy - fz, fz],
+            )
+          } else if fx > fz {
+            (
+              self.entry(r + 1, g, b),
+              self.entry(r + 1, g, b + 1),
+              [1.0 - fx, fx - fz, fz - fy, fy],
+            )
+          } else {
+            (
+              self.entry(r, g, b + 1),
+              self.entry(r + 1, g, b + 1),
+              [1.0 - fz, fz - fx, fx - fy, fy],
+            )
+          }
+        } else if fz > fy {
+          (
+            self.entry(r, g, b + 1),
+            self.entry(r, g + 1, b + 1),
+            [1.0 - fz, fz - fy, fy - fx, fx],
+          )
+        } else if fz > fx {
+          (
+            self.entry(r, g + 1, b),
+            self.entry(r, g + 1, b + 1),
+            [1.0 - fy, fy - fz, fz - fx, fx],
+          )
+        } else {
+          (
+            self.entry(r, g + 1, b),
+            self.entry(r + 1, g + 1, b),
+            [1.0 - fy, fy - fx, fx - fz, fz],
+          )
+        };
+        [0, 1, 2].map(|i| {
+          weights[0] * c000[i]
+            + weights[1] * c1[i]
+            + weights[2] * c2[i]
+            + weights[3] * c111[i]
+        })
+      }
+    }
+  }
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+  a * (1.0 - t) + b * t
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A size 3 table that swaps red and blue and darkens green
+  fn swap_cube() -> String {
+    let mut contents = "TITLE \"swap\"\n# comment\nLUT_3D_SIZE 3\n".to_string();
+    for b in 0..3 {
+      for g in 0..3 {
+        for r in 0..3 {
+          let [r, g, b] = [r, g, b].map(|v| v as f32 / 2.0);
+          contents.push_str(&format!("{} {} {}\n", b, g * g, r));
+        }
+      }
+    }
+    contents
+  }
+
+  #[test]
+  fn test_parse_cube() {
+    let lut = Lut3D::parse_cube(&swap_cube()).unwrap();
+    assert_eq!(lut.size, 3);
+    assert_eq!(lut.table.len(), 27);
+    assert_eq!(lut.entry(2, 1, 0), [0.0, 0.25, 1.0]);
+
+    let lut = Lut3D::parse_cube(
+      "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n\
+       0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n",
+    )
+    .unwrap();
+    assert_eq!(lut.domain_max, [2.0; 3]);
+    // Inputs are normalized to the domain before the lookup
+    let color = lut.sample([1.0, 0.5, 2.0], LutInterpolation::Trilinear);
+    assert!((color[0] - 0.5).abs() < 1e-6 && (color[2] - 1.0).abs() < 1e-6);
+
+    assert!(Lut3D::parse_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+    assert!(Lut3D::parse_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+  }
+
+  #[test]
+  fn test_parse_3dl_reorders_entries() {
+    // Blue fastest, 10 bit output inferred from the largest value
+    let mut contents = "0 1023\n".to_string();
+    for r in 0..2 {
+      for g in 0..2 {
+        for b in 0..2 {
+          contents.push_str(&format!("{} {} {}\n", r * 1023, g * 512, b * 1023));
+        }
+      }
+    }
+    let lut = Lut3D::parse_3dl(&contents).unwrap();
+    assert_eq!(lut.size, 2);
+    assert_eq!(lut.entry(1, 0, 1), [1.0, 0.0, 1.0]);
+    assert!((lut.entry(0, 1, 0)[1] - 512.0 / 1023.0).abs() < 1e-6);
+
+    // An explicit 12 bit output depth
+    let lut = Lut3D::parse_3dl(&format!("3DMESH\nMesh 1 12\n{}", contents)).unwrap();
+    assert!((lut.entry(1, 0, 0)[0] - 1023.0 / 4095.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_interpolation_agrees_on_grid_and_neutral_axis() {
+    let lut = Lut3D::parse_cube(&swap_cube()).unwrap();
+    for interpolation in [LutInterpolation::Trilinear, LutInterpolation::Tetrahedral] {
+      let color = lut.sample([1.0, 0.5, 0.0], interpolation);
+      assert_eq!(color, [0.0, 0.25, 1.0]);
+    }
+
+    // Both blend linearly along the diagonal of a cell
+    let trilinear = lut.sample([0.3, 0.3, 0.3], LutInterpolation::Trilinear);
+    let tetrahedral = lut.sample([0.3, 0.3, 0.3], LutInterpolation::Tetrahedral);
+    assert!((trilinear[0] - tetrahedral[0]).abs() < 1e-6);
+    assert!((tetrahedral[0] - 0.3).abs() < 1e-6);
+  }
+}
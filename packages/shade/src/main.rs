@@ -1,20 +1,39 @@
 mod cache;
 mod cli;
+mod color;
 mod config;
+mod cpu;
+mod curves;
+mod custom;
+mod dehaze;
 mod file_loaders;
+mod geometry;
+mod grading;
+mod graph;
+mod hsl;
+mod lut;
+mod mask;
 mod protocol;
+mod raw;
+mod schema;
+mod scopes;
 mod server;
 mod shade;
 mod utils;
+mod white_balance;
 
 use crate::cache::ImageCache;
+use crate::color::ColorSpace;
 use crate::config::config_from_ini_path;
-use crate::file_loaders::load_image;
+use crate::custom::load_shaders;
+use crate::file_loaders::{detect_as_shot_kelvin, detect_color_space, load_image};
+use crate::graph::{load_graph, save_graph};
 #[cfg(target_arch = "wasm32")]
 use crate::utils::output_image_wasm;
 use anyhow::Result;
 use cli::ProcessingConfig;
 use server::ImageProcessingServer;
+use shade::{Backend, request_device};
 #[cfg(not(target_arch = "wasm32"))]
 use utils::write_image;
 
@@ -23,6 +42,9 @@ const TEXTURE_DIMS: (usize, usize) = (512, 512);
 struct LoadedImage {
   texture_data: Vec<u8>,
   actual_dims: (usize, usize),
+  color_space: ColorSpace,
+  /// White balance of a camera raw file, in Kelvin
+  as_shot_kelvin: Option<f32>,
 }
 
 #[derive(Default)]
@@ -79,7 +101,13 @@ pub fn main() -> Result<()> {
       match config_from_ini_path(config_path) {
         Ok(ini_config) => {
           log::info!("Loaded config from: {:?}", config_path);
-          ini_config
+          ProcessingConfig {
+            use_cpu: ini_config.use_cpu || config.use_cpu,
+            graph_path: ini_config.graph_path.or(config.graph_path.clone()),
+            save_graph_path: config.save_graph_path.clone(),
+            shader_dir: ini_config.shader_dir.or(config.shader_dir.clone()),
+            ..ini_config
+          }
         }
         Err(e) => {
           eprintln!("Error loading config from {}: {}", config_path.display(), e);
@@ -177,6 +205,10 @@ async fn run(config: &ProcessingConfig) -> Result<()> {
   // load image
   log::info!("Loading image: {:?}", config.input_path);
 
+  // The default texture is black, which reads the same in every space
+  let mut color_space = ColorSpace::Srgb;
+  let mut as_shot_kelvin = None;
+
   // Load input image if provided
   let (texture_data, actual_dims) = if let Some(input_path) = &config.input_path {
     #[cfg(not(target_arch = "wasm32"))]
@@ -187,9 +219,20 @@ async fn run(config: &ProcessingConfig) -> Result<()> {
       let load_time = run_start.elapsed();
       timing.image_load_ms = load_time.as_secs_f64() * 1000.0;
 
-      match load_image(&image_file, Some(&input_path.to_string_lossy())) {
+      match load_image(
+        &image_file,
+        Some(&input_path.to_string_lossy()),
+        &config.raw,
+      ) {
         Ok((image_data, (width, height))) => {
           log::info!("Successfully loaded image: {}x{}", width, height);
+          if let Some(space) =
+            detect_color_space(&image_file, Some(&input_path.to_string_lossy()))
+          {
+            color_space = space;
+          }
+          as_shot_kelvin =
+            detect_as_shot_kelvin(&image_file, Some(&input_path.to_string_lossy()));
           (image_data, (width, height))
         }
         Err(e) => {
@@ -224,6 +267,8 @@ async fn run(config: &ProcessingConfig) -> Result<()> {
   let loaded_image = LoadedImage {
     actual_dims: actual_dims,
     texture_data: texture_data,
+    color_space,
+    as_shot_kelvin,
   };
 
   // decode image
@@ -233,30 +278,55 @@ async fn run(config: &ProcessingConfig) -> Result<()> {
 
   let gpu_setup_start = std::time::Instant::now();
 
-  let mut image_pipeline = config.build_pipeline();
+  let mut image_pipeline = match &config.graph_path {
+    Some(graph_path) => {
+      let mut pipeline = load_graph(graph_path).map_err(|e| anyhow::anyhow!("{}", e))?;
+      // Graphs can use custom shaders, those that fail to load are reported and left out
+      let (shaders, errors) = load_shaders(config.shader_dir.as_deref());
+      for error in errors {
+        eprintln!("{}", error);
+      }
+      for shader in shaders {
+        pipeline.add_custom_shader(shader);
+      }
+      pipeline
+    }
+    None => config.build_pipeline(),
+  };
+
+  if let Some(save_graph_path) = &config.save_graph_path {
+    save_graph(&image_pipeline, save_graph_path).map_err(|e| anyhow::anyhow!("{}", e))?;
+    log::info!("Saved graph to {}", save_graph_path.display());
+  }
+
+  // Nodes run in the working space, between the tagged input and the output space
+  image_pipeline
+    .set_input_space(config.color.input_space.unwrap_or(loaded_image.color_space));
+  if let Some(kelvin) = loaded_image.as_shot_kelvin {
+    log::info!("As-shot white balance: {:.0}K", kelvin);
+    image_pipeline.set_as_shot_kelvin(kelvin);
+  }
+  image_pipeline
+    .set_working_space(config.color.working_space)
+    .map_err(|e| anyhow::anyhow!("{}", e))?;
+  image_pipeline
+    .set_output_space(config.color.output_space_for(config.output_path.as_deref()));
 
   let mut texture_data = loaded_image.texture_data;
   let mut actual_dims = loaded_image.actual_dims;
 
-  // Initialize the pipeline with GPU resources (moved device and queue so we need to recreate them)
-  let instance = wgpu::Instance::default();
-  let adapter = instance
-    .request_adapter(&wgpu::RequestAdapterOptions::default())
-    .await
-    .unwrap();
-
-  let (device, queue) = adapter
-    .request_device(&wgpu::DeviceDescriptor {
-      label: None,
-      required_features: wgpu::Features::empty(),
-      required_limits: wgpu::Limits::defaults(),
-      memory_hints: wgpu::MemoryHints::MemoryUsage,
-      trace: wgpu::Trace::Off,
-    })
-    .await
-    .unwrap();
-
-  image_pipeline.init_gpu(device.clone(), queue.clone());
+  // Initialize the pipeline with GPU resources, falling back to the CPU without a GPU
+  if config.use_cpu {
+    image_pipeline.set_backend(Backend::Cpu);
+  } else {
+    match request_device().await {
+      Ok((device, queue)) => image_pipeline.init_gpu(device, queue),
+      Err(e) => {
+        log::warn!("{}, processing on the CPU", e);
+        image_pipeline.set_backend(Backend::Cpu);
+      }
+    }
+  }
 
   let gpu_setup_time = gpu_setup_start.elapsed();
   timing.gpu_setup_ms = gpu_setup_time.as_secs_f64() * 1000.0;
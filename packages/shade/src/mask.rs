@@ -0,0 +1,134 @@
+//! Painted mask bitmaps
+//!
+//! A `PaintedMask` node carries a greyscale bitmap painted by a client, for example
+//! a brush mask drawn in an editor. It is stored with one byte per pixel and written
+//! as base64 in graph documents, so it can be sent over the socket protocol as part
+//! of a graph.
+
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A greyscale mask with one byte per pixel, 255 selecting a pixel fully
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaskBitmap {
+  pub width: u32,
+  pub height: u32,
+  /// Row-major mask values, base64 encoded in JSON
+  #[serde(
+    serialize_with = "serialize_base64",
+    deserialize_with = "deserialize_base64"
+  )]
+  pub data: Vec<u8>,
+}
+
+// A single selected pixel, so a new node leaves the image unmasked
+impl Default for MaskBitmap {
+  fn default() -> Self {
+    Self {
+      width: 1,
+      height: 1,
+      data: vec![255],
+    }
+  }
+}
+
+impl MaskBitmap {
+  /// The mask as RGBA32F rows, with the mask value in the colour channels
+  pub fn to_pixels(&self) -> Result<Vec<[f32; 4]>, String> {
+    if self.width == 0 || self.height == 0 {
+      return Err("Painted mask has no pixels".to_string());
+    }
+    if self.data.len() != self.width as usize * self.height as usize {
+      return Err(format!(
+        "Painted mask of {}x{} needs {} values, got {}",
+        self.width,
+        self.height,
+        self.width as u64 * self.height as u64,
+        self.data.len()
+      ));
+    }
+
+    Ok(
+      self
+        .data
+        .iter()
+        .map(|&value| {
+          let value = value as f32 / 255.0;
+          [value, value, value, 1.0]
+        })
+        .collect(),
+    )
+  }
+}
+
+// Node parameters are hashed through their debug output, so the values are summarized
+impl fmt::Debug for MaskBitmap {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut hasher = DefaultHasher::new();
+    self.data.hash(&mut hasher);
+    f.debug_struct("MaskBitmap")
+      .field("width", &self.width)
+      .field("height", &self.height)
+      .field("data_hash", &hasher.finish())
+      .finish()
+  }
+}
+
+fn serialize_base64<S: Serializer>(
+  data: &[u8],
+  serializer: S,
+) -> Result<S::Ok, S::Error> {
+  serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+}
+
+fn deserialize_base64<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+  let encoded = String::deserialize(deserializer)?;
+  base64::engine::general_purpose::STANDARD
+    .decode(encoded)
+    .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_bitmap_json_roundtrip() {
+    let bitmap = MaskBitmap {
+      width: 2,
+      height: 2,
+      data: vec![0, 64, 128, 255],
+    };
+    let json = serde_json::to_string(&bitmap).unwrap();
+    assert!(json.contains("\"data\":\"AECA/w==\""));
+    assert_eq!(serde_json::from_str::<MaskBitmap>(&json).unwrap(), bitmap);
+
+    let pixels = bitmap.to_pixels().unwrap();
+    assert_eq!(pixels[3], [1.0, 1.0, 1.0, 1.0]);
+    assert!((pixels[2][0] - 128.0 / 255.0).abs() < 1e-6);
+
+    let truncated = MaskBitmap {
+      data: vec![0, 64],
+      ..bitmap
+    };
+    assert!(truncated.to_pixels().is_err());
+
+    // The size in the error does not overflow
+    let oversized = MaskBitmap {
+      width: u32::MAX,
+      height: u32::MAX,
+      data: vec![0],
+    };
+    assert!(
+      oversized
+        .to_pixels()
+        .unwrap_err()
+        .contains("needs 18446744065119617025")
+    );
+  }
+}
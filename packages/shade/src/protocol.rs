@@ -1,12 +1,24 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use wgpu::hal::Attachment;
 use std::collections::HashMap;
 use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
 use tokio::io::{
   AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader as TokioBufReader,
 };
 
 use crate::cli::OperationType;
+use crate::color::ColorSpace;
+use crate::curves::CurvePoints;
+use crate::geometry::{self, Resampling};
+use crate::grading::GradingWheel;
+use crate::graph::GraphDocument;
+use crate::hsl::HslBands;
+use crate::lut::LutInterpolation;
+use crate::raw::RawDevelopParams;
+use crate::schema::NodeSchema;
+use crate::shade::{NodeParams, ToneMapOperator};
+use crate::white_balance::WhiteBalanceMethod;
 
 /// # Usage Example: Process Image and Retrieve as Blob
 ///
@@ -93,11 +105,34 @@ pub struct ProcessImageParams {
   /// Input image data as base64 string or file path
   pub image: ImageInput,
   /// Pipeline operations to apply
+  #[serde(default)]
   pub operations: Vec<OperationSpec>,
+  /// Node graph to apply instead of a linear list of operations
+  #[serde(default)]
+  pub graph: Option<GraphDocument>,
+  /// Colour space of the input image, detected from the file when unset
+  #[serde(default)]
+  pub input_space: Option<ColorSpace>,
+  /// Linear colour space to process in, linear sRGB when unset
+  #[serde(default)]
+  pub working_space: Option<ColorSpace>,
+  /// Development of camera raw input files, the default development when unset
+  #[serde(default, deserialize_with = "deserialize_raw")]
+  pub raw: Option<RawDevelopParams>,
   /// Output format (optional, defaults to "png")
   pub output_format: Option<String>,
 }
 
+fn deserialize_raw<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Option<RawDevelopParams>, D::Error> {
+  let raw = Option::<RawDevelopParams>::deserialize(deserializer)?;
+  if let Some(raw) = &raw {
+    raw.validate().map_err(serde::de::Error::custom)?;
+  }
+  Ok(raw)
+}
+
 /// Input image specification
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -142,6 +177,10 @@ pub struct ServerCapabilities {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitializeParams {
   pub client_info: Option<ClientInfo>,
+  /// Directory of `.wgsl` shaders for `Custom` nodes, the default shader directory
+  /// when unset
+  #[serde(default)]
+  pub shader_dir: Option<PathBuf>,
 }
 
 /// Client information
@@ -190,6 +229,63 @@ pub struct GetAttachmentResult {
   pub size: usize,
 }
 
+/// Histogram and scopes request parameters
+///
+/// Takes the image and pipeline of a `process_image` request. Node results of the
+/// previous request are reused, so analysing the image that was just processed does
+/// not run the pipeline again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnalyzeImageParams {
+  #[serde(flatten)]
+  pub process: ProcessImageParams,
+  /// Graph node whose output is analysed, the pipeline output when unset
+  #[serde(default)]
+  pub node_id: Option<usize>,
+  /// Number of waveform columns, 256 when unset
+  #[serde(default)]
+  pub waveform_width: Option<u32>,
+}
+
+/// A scope sent as a binary attachment of little-endian `u32` pixel counts
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScopeAttachment {
+  pub attachment_id: String,
+  /// Dimensions of the counts, outermost first
+  pub shape: Vec<u32>,
+}
+
+/// Histogram and scopes response result
+///
+/// The histogram counts `[channel][level]` and the waveform `[channel][level][column]`
+/// for the channels in `channels`. The vectorscope counts `[cr][cb]`, both axes
+/// running from -0.5 to 0.5. Levels split display values from 0 to 1.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScopesResult {
+  /// Dimensions of the analysed image
+  pub width: u32,
+  pub height: u32,
+  pub channels: Vec<String>,
+  pub histogram: ScopeAttachment,
+  /// Only sent by `get_scopes`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub waveform: Option<ScopeAttachment>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub vectorscope: Option<ScopeAttachment>,
+}
+
+/// Describe operations response result
+///
+/// Lists every node type with its ports and parameters, so clients can build their
+/// controls without repeating ranges and defaults. Loaded custom shaders are listed as
+/// `Custom` nodes.
+#[derive(Debug, Serialize)]
+pub struct DescribeOperationsResult {
+  pub node_types: Vec<NodeSchema>,
+  /// Errors of shaders in the shader directory that failed to load, naming the file
+  /// and line
+  pub shader_errors: Vec<String>,
+}
+
 impl Message {
   /// Create a new request message
   pub fn new_request(id: MessageId, method: String, params: serde_json::Value) -> Self {
@@ -307,7 +403,12 @@ impl TryFrom<&OperationSpec> for OperationType {
   type Error = String;
 
   fn try_from(spec: &OperationSpec) -> Result<Self, Self::Error> {
-    match spec.operation.as_str() {
+    let operation = match spec.operation.as_str() {
+      "exposure" => {
+        let value: f32 = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid exposure parameter: {}", e))?;
+        Ok(OperationType::Exposure(value))
+      }
       "brightness" => {
         let value: f32 = serde_json::from_value(spec.params.clone())
           .map_err(|e| format!("Invalid brightness parameter: {}", e))?;
@@ -323,22 +424,113 @@ impl TryFrom<&OperationSpec> for OperationType {
           .map_err(|e| format!("Invalid saturation parameter: {}", e))?;
         Ok(OperationType::Saturation(value))
       }
+      "vibrance" => {
+        let amount: f32 = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid vibrance parameter: {}", e))?;
+        Ok(OperationType::Vibrance(amount))
+      }
       "hue" => {
         let value: f32 = serde_json::from_value(spec.params.clone())
           .map_err(|e| format!("Invalid hue parameter: {}", e))?;
         Ok(OperationType::Hue(value))
       }
+      "hsl" => {
+        let bands: HslBands = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid hsl parameters: {}", e))?;
+        Ok(OperationType::Hsl(bands))
+      }
       "gamma" => {
         let value: f32 = serde_json::from_value(spec.params.clone())
           .map_err(|e| format!("Invalid gamma parameter: {}", e))?;
         Ok(OperationType::Gamma(value))
       }
+      "levels" => {
+        #[derive(Deserialize)]
+        struct LevelsParams {
+          input_black: Option<f32>,
+          input_white: Option<f32>,
+          output_black: Option<f32>,
+          output_white: Option<f32>,
+        }
+        let params: LevelsParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid levels parameters: {}", e))?;
+        Ok(OperationType::Levels {
+          input_black: params.input_black.unwrap_or(0.0),
+          input_white: params.input_white.unwrap_or(1.0),
+          output_black: params.output_black.unwrap_or(0.0),
+          output_white: params.output_white.unwrap_or(1.0),
+        })
+      }
+      "curves" => {
+        #[derive(Deserialize)]
+        struct CurvesParams {
+          #[serde(default)]
+          master: CurvePoints,
+          #[serde(default)]
+          red: CurvePoints,
+          #[serde(default)]
+          green: CurvePoints,
+          #[serde(default)]
+          blue: CurvePoints,
+        }
+        let params: CurvesParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid curves parameters: {}", e))?;
+        Ok(OperationType::Curves {
+          master: params.master,
+          red: params.red,
+          green: params.green,
+          blue: params.blue,
+        })
+      }
+      "color_balance" => {
+        #[derive(Deserialize)]
+        struct ColorBalanceParams {
+          #[serde(default)]
+          shadows: [f32; 3],
+          #[serde(default)]
+          midtones: [f32; 3],
+          #[serde(default)]
+          highlights: [f32; 3],
+        }
+        let params: ColorBalanceParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid color_balance parameters: {}", e))?;
+        Ok(OperationType::ColorBalance {
+          shadows: params.shadows,
+          midtones: params.midtones,
+          highlights: params.highlights,
+        })
+      }
+      "color_grading" => {
+        #[derive(Deserialize)]
+        struct ColorGradingParams {
+          #[serde(default)]
+          shadows: GradingWheel,
+          #[serde(default)]
+          midtones: GradingWheel,
+          #[serde(default)]
+          highlights: GradingWheel,
+          balance: Option<f32>,
+          blending: Option<f32>,
+        }
+        let params: ColorGradingParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid color_grading parameters: {}", e))?;
+        Ok(OperationType::ColorGrading {
+          shadows: params.shadows,
+          midtones: params.midtones,
+          highlights: params.highlights,
+          balance: params.balance.unwrap_or(0.0),
+          blending: params.blending.unwrap_or(0.5),
+        })
+      }
       "white_balance" => {
         #[derive(Deserialize)]
         struct WhiteBalanceParams {
           auto_adjust: Option<bool>,
           temperature: Option<f32>,
           tint: Option<f32>,
+          method: Option<WhiteBalanceMethod>,
+          neutral_point: Option<[f32; 2]>,
+          kelvin: Option<f32>,
         }
         let params: WhiteBalanceParams = serde_json::from_value(spec.params.clone())
           .map_err(|e| format!("Invalid white_balance parameters: {}", e))?;
@@ -346,6 +538,54 @@ impl TryFrom<&OperationSpec> for OperationType {
           auto_adjust: params.auto_adjust.unwrap_or(false),
           temperature: params.temperature,
           tint: params.tint,
+          method: params.method.unwrap_or_default(),
+          neutral_point: params.neutral_point,
+          kelvin: params.kelvin,
+        })
+      }
+      "lut" => {
+        #[derive(Deserialize)]
+        struct LutParams {
+          path: PathBuf,
+          intensity: Option<f32>,
+          interpolation: Option<LutInterpolation>,
+        }
+        let params: LutParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid lut parameters: {}", e))?;
+        Ok(OperationType::Lut3D {
+          path: params.path,
+          intensity: params.intensity.unwrap_or(1.0),
+          interpolation: params.interpolation.unwrap_or_default(),
+        })
+      }
+      "tone_map" => {
+        #[derive(Deserialize)]
+        struct ToneMapParams {
+          operator: Option<ToneMapOperator>,
+          white_point: Option<f32>,
+          contrast: Option<f32>,
+        }
+        let params: ToneMapParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid tone_map parameters: {}", e))?;
+        Ok(OperationType::ToneMap {
+          operator: params.operator.unwrap_or_default(),
+          white_point: params.white_point.unwrap_or(16.0),
+          contrast: params.contrast.unwrap_or(1.0),
+        })
+      }
+      "denoise" => {
+        #[derive(Deserialize)]
+        struct DenoiseParams {
+          luminance: Option<f32>,
+          chroma: Option<f32>,
+          radius: Option<f32>,
+        }
+        let params: DenoiseParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid denoise parameters: {}", e))?;
+        Ok(OperationType::Denoise {
+          luminance: params.luminance.unwrap_or(0.0),
+          chroma: params.chroma.unwrap_or(0.0),
+          radius: params.radius.unwrap_or(2.0),
         })
       }
       "blur" => {
@@ -358,11 +598,76 @@ impl TryFrom<&OperationSpec> for OperationType {
           .map_err(|e| format!("Invalid sharpen parameter: {}", e))?;
         Ok(OperationType::Sharpen(value))
       }
+      "unsharp_mask" => {
+        #[derive(Deserialize)]
+        struct UnsharpMaskParams {
+          amount: f32,
+          radius: Option<f32>,
+          threshold: Option<f32>,
+        }
+        let params: UnsharpMaskParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid unsharp_mask parameters: {}", e))?;
+        Ok(OperationType::UnsharpMask {
+          amount: params.amount,
+          radius: params.radius.unwrap_or(1.0),
+          threshold: params.threshold.unwrap_or(0.0),
+        })
+      }
+      "clarity" => {
+        #[derive(Deserialize)]
+        struct ClarityParams {
+          amount: f32,
+          radius: Option<f32>,
+        }
+        let params: ClarityParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid clarity parameters: {}", e))?;
+        Ok(OperationType::Clarity {
+          amount: params.amount,
+          radius: params.radius.unwrap_or(40.0),
+        })
+      }
       "noise" => {
         let value: f32 = serde_json::from_value(spec.params.clone())
           .map_err(|e| format!("Invalid noise parameter: {}", e))?;
         Ok(OperationType::Noise(value))
       }
+      "vignette" => {
+        #[derive(Deserialize)]
+        struct VignetteParams {
+          amount: f32,
+          midpoint: Option<f32>,
+          roundness: Option<f32>,
+          feather: Option<f32>,
+        }
+        let params: VignetteParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid vignette parameters: {}", e))?;
+        Ok(OperationType::Vignette {
+          amount: params.amount,
+          midpoint: params.midpoint.unwrap_or(0.5),
+          roundness: params.roundness.unwrap_or(0.0),
+          feather: params.feather.unwrap_or(0.5),
+        })
+      }
+      "grain" => {
+        #[derive(Deserialize)]
+        struct GrainParams {
+          amount: f32,
+          size: Option<f32>,
+          seed: Option<u32>,
+        }
+        let params: GrainParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid grain parameters: {}", e))?;
+        Ok(OperationType::Grain {
+          amount: params.amount,
+          size: params.size.unwrap_or(1.0),
+          seed: params.seed.unwrap_or(42),
+        })
+      }
+      "dehaze" => {
+        let value: f32 = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid dehaze parameter: {}", e))?;
+        Ok(OperationType::Dehaze(value))
+      }
       "resize" => {
         #[derive(serde::Deserialize)]
         struct ResizeParams {
@@ -376,9 +681,106 @@ impl TryFrom<&OperationSpec> for OperationType {
           height: params.height,
         })
       }
+      "crop" => {
+        #[derive(Deserialize)]
+        struct CropParams {
+          #[serde(default)]
+          x: u32,
+          #[serde(default)]
+          y: u32,
+          width: u32,
+          height: u32,
+        }
+        let params: CropParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid crop parameters: {}", e))?;
+        Ok(OperationType::Crop {
+          x: params.x,
+          y: params.y,
+          width: params.width,
+          height: params.height,
+        })
+      }
+      "rotate" => {
+        let degrees: f32 = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid rotate parameter: {}", e))?;
+        if degrees.fract() != 0.0 {
+          return Err(format!(
+            "Rotation {} is not a multiple of 90 degrees, use straighten for other angles",
+            degrees
+          ));
+        }
+        Ok(OperationType::Rotate(geometry::quarter_turns(
+          degrees as i32,
+        )?))
+      }
+      "flip" => {
+        #[derive(Deserialize)]
+        struct FlipParams {
+          #[serde(default)]
+          horizontal: bool,
+          #[serde(default)]
+          vertical: bool,
+        }
+        let params: FlipParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid flip parameters: {}", e))?;
+        Ok(OperationType::Flip {
+          horizontal: params.horizontal,
+          vertical: params.vertical,
+        })
+      }
+      "straighten" => {
+        #[derive(Deserialize)]
+        struct StraightenParams {
+          angle: f32,
+          crop: Option<bool>,
+          resampling: Option<Resampling>,
+        }
+        let params: StraightenParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid straighten parameters: {}", e))?;
+        Ok(OperationType::Straighten {
+          angle: params.angle,
+          crop: params.crop.unwrap_or(true),
+          resampling: params.resampling.unwrap_or_default(),
+        })
+      }
+      "perspective" => {
+        #[derive(Deserialize)]
+        struct PerspectiveParams {
+          corners: [[f32; 2]; 4],
+          resampling: Option<Resampling>,
+        }
+        let params: PerspectiveParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid perspective parameters: {}", e))?;
+        Ok(OperationType::Perspective {
+          corners: params.corners,
+          resampling: params.resampling.unwrap_or_default(),
+        })
+      }
+      "invert" => Ok(OperationType::Invert),
+      "mix" => {
+        let factor: f32 = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid mix parameter: {}", e))?;
+        Ok(OperationType::Mix(factor))
+      }
+      "mask" => {
+        #[derive(Deserialize)]
+        struct MaskParams {
+          generator: NodeParams,
+          #[serde(default)]
+          invert: bool,
+        }
+        let params: MaskParams = serde_json::from_value(spec.params.clone())
+          .map_err(|e| format!("Invalid mask parameters: {}", e))?;
+        Ok(OperationType::Mask {
+          generator: params.generator,
+          invert: params.invert,
+        })
+      }
 
       _ => Err(format!("Unknown operation: {}", spec.operation)),
-    }
+    }?;
+    operation.validate()?;
+    Ok(operation)
   }
 }
 
@@ -530,13 +932,338 @@ mod tests {
         auto_adjust,
         temperature,
         tint,
+        method,
+        neutral_point,
+        kelvin,
       } => {
         assert!(auto_adjust);
         assert_eq!(temperature, Some(5500.0));
         assert_eq!(tint, Some(0.2));
+        assert_eq!(method, WhiteBalanceMethod::GreyWorld);
+        assert_eq!(neutral_point, None);
+        assert_eq!(kelvin, None);
+      }
+      _ => panic!("Wrong operation type"),
+    }
+
+    let spec = OperationSpec {
+      operation: "white_balance".to_string(),
+      params: serde_json::json!({
+          "method": "white_patch",
+          "neutral_point": [0.25, 0.75],
+          "kelvin": 3200.0
+      }),
+    };
+
+    let op: OperationType = (&spec).try_into().unwrap();
+    match op {
+      OperationType::WhiteBalance {
+        auto_adjust,
+        method,
+        neutral_point,
+        kelvin,
+        ..
+      } => {
+        assert!(!auto_adjust);
+        assert_eq!(method, WhiteBalanceMethod::WhitePatch);
+        assert_eq!(neutral_point, Some([0.25, 0.75]));
+        assert_eq!(kelvin, Some(3200.0));
+      }
+      _ => panic!("Wrong operation type"),
+    }
+  }
+
+  #[test]
+  fn test_lut_conversion() {
+    let spec = OperationSpec {
+      operation: "lut".to_string(),
+      params: serde_json::json!({
+          "path": "/looks/film.cube",
+          "interpolation": "tetrahedral"
+      }),
+    };
+
+    let op: OperationType = (&spec).try_into().unwrap();
+    match op {
+      OperationType::Lut3D {
+        path,
+        intensity,
+        interpolation,
+      } => {
+        assert_eq!(path, PathBuf::from("/looks/film.cube"));
+        assert_eq!(intensity, 1.0);
+        assert_eq!(interpolation, LutInterpolation::Tetrahedral);
+      }
+      _ => panic!("Wrong operation type"),
+    }
+  }
+
+  #[test]
+  fn test_grading_conversion() {
+    let spec = OperationSpec {
+      operation: "color_grading".to_string(),
+      params: serde_json::json!({
+        "highlights": {"hue": 40.0, "luminance": 0.1},
+        "balance": -0.2
+      }),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    match op {
+      OperationType::ColorGrading {
+        shadows,
+        highlights,
+        balance,
+        blending,
+        ..
+      } => {
+        assert_eq!(shadows, GradingWheel::default());
+        assert_eq!(
+          (highlights.hue, highlights.saturation, highlights.luminance),
+          (40.0, 0.0, 0.1)
+        );
+        assert_eq!((balance, blending), (-0.2, 0.5));
       }
       _ => panic!("Wrong operation type"),
     }
+
+    let spec = OperationSpec {
+      operation: "vibrance".to_string(),
+      params: serde_json::json!({"amount": 0.3}),
+    };
+    assert!(OperationType::try_from(&spec).is_err());
+  }
+
+  #[test]
+  fn test_detail_conversion() {
+    let spec = OperationSpec {
+      operation: "unsharp_mask".to_string(),
+      params: serde_json::json!({"amount": 1.2, "threshold": 0.03}),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    match op {
+      OperationType::UnsharpMask {
+        amount,
+        radius,
+        threshold,
+      } => {
+        assert_eq!(amount, 1.2);
+        assert_eq!(radius, 1.0);
+        assert_eq!(threshold, 0.03);
+      }
+      _ => panic!("Wrong operation type"),
+    }
+
+    let spec = OperationSpec {
+      operation: "clarity".to_string(),
+      params: serde_json::json!({"amount": 0.4, "radius": 8.0}),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(
+      op,
+      OperationType::Clarity { amount, radius } if amount == 0.4 && radius == 8.0
+    ));
+
+    let spec = OperationSpec {
+      operation: "clarity".to_string(),
+      params: serde_json::json!(0.4),
+    };
+    assert!(OperationType::try_from(&spec).is_err());
+
+    let spec = OperationSpec {
+      operation: "vignette".to_string(),
+      params: serde_json::json!({"amount": -0.5, "roundness": 1.0}),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(
+      op,
+      OperationType::Vignette { amount, midpoint, roundness, feather }
+        if amount == -0.5 && midpoint == 0.5 && roundness == 1.0 && feather == 0.5
+    ));
+
+    let spec = OperationSpec {
+      operation: "grain".to_string(),
+      params: serde_json::json!({"size": 2.0}),
+    };
+    assert!(OperationType::try_from(&spec).is_err());
+
+    // A strength left out is not reduced at all
+    let spec = OperationSpec {
+      operation: "denoise".to_string(),
+      params: serde_json::json!({"chroma": 0.7}),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(
+      op,
+      OperationType::Denoise { luminance, chroma, radius }
+        if luminance == 0.0 && chroma == 0.7 && radius == 2.0
+    ));
+  }
+
+  #[test]
+  fn test_geometry_conversion() {
+    let spec = OperationSpec {
+      operation: "rotate".to_string(),
+      params: serde_json::json!(-90),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(op, OperationType::Rotate(3)));
+
+    let spec = OperationSpec {
+      operation: "rotate".to_string(),
+      params: serde_json::json!(90.0),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(op, OperationType::Rotate(1)));
+
+    let spec = OperationSpec {
+      operation: "rotate".to_string(),
+      params: serde_json::json!(30),
+    };
+    assert!(OperationType::try_from(&spec).is_err());
+
+    let spec = OperationSpec {
+      operation: "flip".to_string(),
+      params: serde_json::json!({"vertical": true}),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(
+      op,
+      OperationType::Flip {
+        horizontal: false,
+        vertical: true
+      }
+    ));
+
+    let spec = OperationSpec {
+      operation: "straighten".to_string(),
+      params: serde_json::json!({"angle": -1.5, "resampling": "bicubic"}),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(
+      op,
+      OperationType::Straighten {
+        angle,
+        crop: true,
+        resampling: Resampling::Bicubic
+      } if angle == -1.5
+    ));
+
+    let spec = OperationSpec {
+      operation: "perspective".to_string(),
+      params: serde_json::json!({
+        "corners": [[0.1, 0.0], [0.9, 0.0], [1.0, 1.0], [0.0, 1.0]]
+      }),
+    };
+    let op: OperationType = (&spec).try_into().unwrap();
+    assert!(matches!(
+      op,
+      OperationType::Perspective {
+        resampling: Resampling::Bilinear,
+        ..
+      }
+    ));
+
+    // Corners on a line do not describe a perspective
+    let spec = OperationSpec {
+      operation: "perspective".to_string(),
+      params: serde_json::json!({
+        "corners": [[0.0, 0.0], [0.5, 0.0], [1.0, 0.0], [0.2, 0.0]]
+      }),
+    };
+    assert!(OperationType::try_from(&spec).is_err());
+  }
+
+  #[test]
+  fn test_every_operation_converts() {
+    let examples = [
+      ("exposure", serde_json::json!(1.0)),
+      ("brightness", serde_json::json!(0.1)),
+      ("contrast", serde_json::json!(1.2)),
+      ("saturation", serde_json::json!(1.1)),
+      ("vibrance", serde_json::json!(0.3)),
+      ("hue", serde_json::json!(10.0)),
+      ("hsl", serde_json::json!({"blue": {"saturation": -0.2}})),
+      ("gamma", serde_json::json!(1.1)),
+      ("levels", serde_json::json!({"input_black": 0.05})),
+      (
+        "curves",
+        serde_json::json!({"master": [[0.0, 0.0], [1.0, 1.0]]}),
+      ),
+      (
+        "color_balance",
+        serde_json::json!({"shadows": [0.0, 0.0, 0.1]}),
+      ),
+      (
+        "color_grading",
+        serde_json::json!({"shadows": {"hue": 210.0, "saturation": 0.3}}),
+      ),
+      ("white_balance", serde_json::json!({"auto_adjust": true})),
+      ("lut", serde_json::json!({"path": "look.cube"})),
+      ("tone_map", serde_json::json!({})),
+      (
+        "denoise",
+        serde_json::json!({"luminance": 0.3, "chroma": 0.5}),
+      ),
+      ("blur", serde_json::json!(2.0)),
+      ("sharpen", serde_json::json!(0.5)),
+      ("unsharp_mask", serde_json::json!({"amount": 1.0})),
+      ("clarity", serde_json::json!({"amount": 0.3})),
+      ("noise", serde_json::json!(0.1)),
+      ("vignette", serde_json::json!({"amount": -0.4})),
+      ("grain", serde_json::json!({"amount": 0.2, "size": 1.5})),
+      ("dehaze", serde_json::json!(0.3)),
+      ("resize", serde_json::json!({"width": 800})),
+      ("crop", serde_json::json!({"width": 300, "height": 200})),
+      ("rotate", serde_json::json!(90)),
+      ("flip", serde_json::json!({"horizontal": true})),
+      ("straighten", serde_json::json!({"angle": 1.5})),
+      (
+        "perspective",
+        serde_json::json!({"corners": geometry::FULL_FRAME}),
+      ),
+      ("invert", serde_json::Value::Null),
+      ("mix", serde_json::json!(0.5)),
+      (
+        "mask",
+        serde_json::json!({
+          "generator": {"luminance_mask": {"low": 0.5, "high": 1.0, "feather": 0.1}},
+          "invert": true
+        }),
+      ),
+    ];
+
+    // The advertised operations are exactly those the protocol converts
+    let names: Vec<&str> = examples.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, OperationType::NAMES);
+    for (name, params) in examples {
+      let spec = OperationSpec {
+        operation: name.to_string(),
+        params,
+      };
+      let op =
+        OperationType::try_from(&spec).unwrap_or_else(|e| panic!("{}: {}", name, e));
+      assert_eq!(op.name(), name);
+    }
+
+    let invalid = [
+      (
+        "levels",
+        serde_json::json!({"input_black": 0.8, "input_white": 0.2}),
+      ),
+      ("crop", serde_json::json!({"width": 0, "height": 200})),
+      ("mix", serde_json::json!(2.0)),
+      (
+        "mask",
+        serde_json::json!({"generator": {"exposure": {"stops": 1.0}}}),
+      ),
+    ];
+    for (name, params) in invalid {
+      let spec = OperationSpec {
+        operation: name.to_string(),
+        params,
+      };
+      assert!(OperationType::try_from(&spec).is_err(), "{}", name);
+    }
   }
 
   #[test]
@@ -643,4 +1370,74 @@ mod tests {
     assert_eq!(deserialized.content_type, "image/png");
     assert_eq!(deserialized.size, 1024);
   }
+
+  #[test]
+  fn test_analyze_image_params() {
+    let params: AnalyzeImageParams = serde_json::from_value(serde_json::json!({
+      "image": {"type": "file", "path": "photo.jpg"},
+      "operations": [{"operation": "exposure", "params": 0.5}],
+      "node_id": 2,
+      "waveform_width": 128
+    }))
+    .unwrap();
+    assert_eq!(params.process.operations.len(), 1);
+    assert!(params.process.graph.is_none());
+    assert_eq!(params.node_id, Some(2));
+    assert_eq!(params.waveform_width, Some(128));
+
+    let params: AnalyzeImageParams = serde_json::from_value(serde_json::json!({
+      "image": {"type": "file", "path": "photo.jpg"}
+    }))
+    .unwrap();
+    assert!(params.process.operations.is_empty());
+    assert_eq!((params.node_id, params.waveform_width), (None, None));
+  }
+
+  #[test]
+  fn test_raw_develop_params() {
+    use crate::raw::{DemosaicAlgorithm, HighlightRecovery, InputProfile};
+
+    let params: ProcessImageParams = serde_json::from_value(serde_json::json!({
+      "image": {"type": "file", "path": "IMG_1234.CR3"},
+      "raw": {
+        "demosaic": "superpixel",
+        "highlights": "blend",
+        "black_level": 512,
+        "input_profile": {
+          "custom": [[1.6, -0.5, -0.1], [-0.2, 1.4, -0.2], [0.0, -0.4, 1.4]]
+        }
+      }
+    }))
+    .unwrap();
+    let raw = params.raw.unwrap();
+    assert_eq!(raw.demosaic, DemosaicAlgorithm::Superpixel);
+    assert_eq!(raw.highlights, HighlightRecovery::Blend);
+    assert_eq!((raw.black_level, raw.white_level), (Some(512), None));
+    assert_eq!(
+      raw.input_profile,
+      InputProfile::custom([1.6, -0.5, -0.1, -0.2, 1.4, -0.2, 0.0, -0.4, 1.4])
+    );
+
+    // Settings that are left out keep their defaults
+    let params: ProcessImageParams = serde_json::from_value(serde_json::json!({
+      "image": {"type": "file", "path": "IMG_1234.CR3"},
+      "raw": {"input_profile": "camera_matrix"}
+    }))
+    .unwrap();
+    assert_eq!(params.raw, Some(RawDevelopParams::default()));
+    assert!(
+      serde_json::from_value::<ProcessImageParams>(serde_json::json!({
+        "image": {"type": "file", "path": "IMG_1234.CR3"},
+        "raw": {"demosaic": "ahd"}
+      }))
+      .is_err()
+    );
+    assert!(
+      serde_json::from_value::<ProcessImageParams>(serde_json::json!({
+        "image": {"type": "file", "path": "IMG_1234.CR3"},
+        "raw": {"black_level": 16000, "white_level": 1000}
+      }))
+      .is_err()
+    );
+  }
 }
@@ -0,0 +1,431 @@
+//! Development of camera raw files
+//!
+//! rawler scales the sensor data by its black and white levels and demosaics it. The
+//! white balance, highlight recovery and input profile are applied here on float
+//! camera RGB, so raw files load at full resolution without losing precision. The
+//! result is linear light with Rec. 709 primaries.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::color::{self, ColorSpace, Mat3};
+
+/// Algorithm turning the colour filter mosaic into RGB pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DemosaicAlgorithm {
+  /// Patterned pixel grouping by rawler, at full resolution
+  #[default]
+  Ppg,
+  /// One pixel for each 2x2 block of the mosaic, at half resolution without
+  /// interpolation, for fast previews
+  Superpixel,
+}
+
+impl FromStr for DemosaicAlgorithm {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "ppg" => Ok(DemosaicAlgorithm::Ppg),
+      "superpixel" => Ok(DemosaicAlgorithm::Superpixel),
+      _ => Err(format!(
+        "Unknown demosaic algorithm '{}', expected ppg or superpixel",
+        value
+      )),
+    }
+  }
+}
+
+impl fmt::Display for DemosaicAlgorithm {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DemosaicAlgorithm::Ppg => write!(f, "ppg"),
+      DemosaicAlgorithm::Superpixel => write!(f, "superpixel"),
+    }
+  }
+}
+
+/// Treatment of highlights where a channel of the sensor clipped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightRecovery {
+  /// Clip every channel where the first one clips, so clipped highlights are white
+  #[default]
+  Clip,
+  /// Keep the channels that did not clip, which tints clipped highlights
+  Unclip,
+  /// White like `Clip`, at the brightness of the channels that did not clip
+  Blend,
+}
+
+impl FromStr for HighlightRecovery {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "clip" => Ok(HighlightRecovery::Clip),
+      "unclip" => Ok(HighlightRecovery::Unclip),
+      "blend" => Ok(HighlightRecovery::Blend),
+      _ => Err(format!(
+        "Unknown highlight recovery '{}', expected clip, unclip or blend",
+        value
+      )),
+    }
+  }
+}
+
+impl fmt::Display for HighlightRecovery {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      HighlightRecovery::Clip => write!(f, "clip"),
+      HighlightRecovery::Unclip => write!(f, "unclip"),
+      HighlightRecovery::Blend => write!(f, "blend"),
+    }
+  }
+}
+
+/// Conversion from white balanced camera RGB to linear Rec. 709 RGB
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputProfile {
+  /// Derived from the XYZ to camera matrix in the raw metadata
+  #[default]
+  CameraMatrix,
+  /// A matrix given by the user, applied to camera RGB row by row
+  Custom(Mat3),
+}
+
+impl InputProfile {
+  /// A custom profile from the nine values of its matrix, row by row
+  pub fn custom(values: [f32; 9]) -> Self {
+    InputProfile::Custom([0, 1, 2].map(|row| [0, 1, 2].map(|col| values[row * 3 + col])))
+  }
+}
+
+impl fmt::Display for InputProfile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      InputProfile::CameraMatrix => write!(f, "camera"),
+      InputProfile::Custom(matrix) => {
+        let values = matrix.iter().flatten().map(|value| value.to_string());
+        write!(f, "{}", values.collect::<Vec<_>>().join(":"))
+      }
+    }
+  }
+}
+
+/// Settings of a raw development
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RawDevelopParams {
+  pub demosaic: DemosaicAlgorithm,
+  pub highlights: HighlightRecovery,
+  /// Overrides the black level of the sensor, in raw units
+  pub black_level: Option<u32>,
+  /// Overrides the level the sensor clips at, in raw units
+  pub white_level: Option<u32>,
+  pub input_profile: InputProfile,
+}
+
+impl RawDevelopParams {
+  /// Check that overridden levels leave a range to scale the sensor data over
+  pub fn validate(&self) -> Result<(), String> {
+    match (self.black_level, self.white_level) {
+      (Some(black), Some(white)) if white <= black => Err(format!(
+        "Raw white level {} must be above black level {}",
+        white, black
+      )),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Lists every setting, so developments with different settings never share a cache
+/// entry
+impl fmt::Display for RawDevelopParams {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let level = |level: Option<u32>| level.map_or("auto".to_string(), |l| l.to_string());
+    write!(
+      f,
+      "demosaic={} highlights={} black={} white={} profile={}",
+      self.demosaic,
+      self.highlights,
+      level(self.black_level),
+      level(self.white_level),
+      self.input_profile
+    )
+  }
+}
+
+/// Camera RGB pixels and their width and height
+pub type CameraImage = (Vec<[f32; 3]>, (usize, usize));
+
+/// Developed RGBA f32 data as little-endian half float RGB, the form developments are
+/// cached on disk in. Alpha is always 1.0 and left out, so a pixel takes 6 bytes.
+pub fn pack_half(data: &[u8]) -> Vec<u8> {
+  let mut packed = Vec::with_capacity(data.len() / 16 * 6);
+  for pixel in data.chunks_exact(16) {
+    for channel in pixel[..12].chunks_exact(4) {
+      let value = f32::from_le_bytes([channel[0], channel[1], channel[2], channel[3]]);
+      packed.extend_from_slice(&half::f16::from_f32(value).to_le_bytes());
+    }
+  }
+  packed
+}
+
+/// RGBA f32 data from a development packed by `pack_half`
+pub fn unpack_half(packed: &[u8]) -> Vec<u8> {
+  let mut data = Vec::with_capacity(packed.len() / 6 * 16);
+  for pixel in packed.chunks_exact(6) {
+    for channel in pixel.chunks_exact(2) {
+      let value = half::f16::from_le_bytes([channel[0], channel[1]]).to_f32();
+      data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&1.0f32.to_le_bytes());
+  }
+  data
+}
+
+/// Area of the mosaic (x, y, width and height) a superpixel development covers: the
+/// crop of the full resolution development in whole 2x2 blocks starting at even
+/// coordinates, so both developments have the same framing at half the size
+pub fn superpixel_area(
+  (x, y, width, height): (usize, usize, usize, usize),
+) -> (usize, usize, usize, usize) {
+  (x & !1, y & !1, width & !1, height & !1)
+}
+
+/// Average the 2x2 blocks of a scaled mosaic within `area` (x, y, width and height)
+/// into one pixel each. `color_at` gives the colour of the filter at a row and column
+/// of the mosaic: 0 red, 1 green, 2 blue, and 3 for a second green.
+pub fn superpixel(
+  mosaic: &[f32],
+  mosaic_width: usize,
+  (x, y, width, height): (usize, usize, usize, usize),
+  color_at: impl Fn(usize, usize) -> usize,
+) -> CameraImage {
+  let (out_width, out_height) = (width / 2, height / 2);
+  let mut pixels = Vec::with_capacity(out_width * out_height);
+  for block_y in 0..out_height {
+    for block_x in 0..out_width {
+      let mut sum = [0.0f32; 3];
+      let mut count = [0.0f32; 3];
+      for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        let (col, row) = (x + block_x * 2 + dx, y + block_y * 2 + dy);
+        let channel = match color_at(row, col) {
+          0 => 0,
+          2 => 2,
+          _ => 1,
+        };
+        sum[channel] += mosaic[row * mosaic_width + col];
+        count[channel] += 1.0;
+      }
+      pixels.push([0, 1, 2].map(|i| sum[i] / count[i].max(1.0)));
+    }
+  }
+  (pixels, (out_width, out_height))
+}
+
+/// Gains of the camera's white balance, normalised to green. Neutral when the raw
+/// file has no usable multipliers.
+pub fn white_balance_gains(multipliers: [f32; 4]) -> [f32; 3] {
+  let [r, g, b, _] = multipliers;
+  if [r, g, b].iter().all(|&m| m.is_finite() && m > 0.0) {
+    [r / g, 1.0, b / g]
+  } else {
+    [1.0; 3]
+  }
+}
+
+/// Apply the white balance `gains` to camera RGB scaled to clip at 1.0 and treat the
+/// clipped highlights
+pub fn balance(
+  rgb: [f32; 3],
+  gains: [f32; 3],
+  highlights: HighlightRecovery,
+) -> [f32; 3] {
+  let balanced = [0, 1, 2].map(|i| rgb[i] * gains[i]);
+  // Where the first channel clips after white balance
+  let clip = gains.iter().copied().fold(f32::MAX, f32::min);
+  let clipped = balanced.map(|c| c.min(clip));
+  match highlights {
+    HighlightRecovery::Clip => clipped,
+    HighlightRecovery::Unclip => balanced,
+    HighlightRecovery::Blend => {
+      let brightness =
+        balanced.iter().sum::<f32>() / clipped.iter().sum::<f32>().max(1e-6);
+      clipped.map(|c| c * brightness)
+    }
+  }
+}
+
+/// Matrix from white balanced camera RGB to linear Rec. 709 RGB, from the XYZ to camera
+/// matrix of the raw metadata row by row. Rows are scaled so white stays white.
+pub fn camera_to_rgb(xyz_to_camera: &[f32]) -> Option<Mat3> {
+  if xyz_to_camera.len() < 9 {
+    return None;
+  }
+  let xyz_to_camera: Mat3 =
+    [0, 1, 2].map(|row| [0, 1, 2].map(|col| xyz_to_camera[row * 3 + col]));
+  let rgb_to_camera = color::mul(xyz_to_camera, ColorSpace::LinearSrgb.to_xyz());
+  let rgb_to_camera = rgb_to_camera.map(|row| {
+    let sum = row.iter().sum::<f32>();
+    row.map(|value| value / sum)
+  });
+  let matrix = color::invert(rgb_to_camera);
+  matrix
+    .iter()
+    .flatten()
+    .all(|value| value.is_finite())
+    .then_some(matrix)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+    assert!(
+      (0..3).all(|i| (actual[i] - expected[i]).abs() < 1e-5),
+      "{:?} != {:?}",
+      actual,
+      expected
+    );
+  }
+
+  #[test]
+  fn test_superpixel() {
+    // An RGGB mosaic of 4x2 with one row of padding above the area
+    #[rustfmt::skip]
+    let mosaic = [
+      9.0, 9.0, 9.0, 9.0,
+      0.1, 0.2, 0.5, 0.6,
+      0.4, 0.3, 0.8, 0.7,
+    ];
+    let rggb = |row: usize, col: usize| [[1, 2], [0, 1]][row % 2][col % 2];
+    let (pixels, dimensions) = superpixel(&mosaic, 4, (0, 1, 4, 2), rggb);
+    assert_eq!(dimensions, (2, 1));
+    assert_close(pixels[0], [0.1, 0.3, 0.3]);
+    assert_close(pixels[1], [0.5, 0.7, 0.7]);
+  }
+
+  #[test]
+  fn test_superpixel_framing() {
+    // Default crops of the full resolution development, at even and odd offsets
+    for crop in [
+      (0, 0, 6000, 4000),
+      (12, 8, 6000, 4000),
+      (1, 3, 11, 7),
+      (3, 2, 8, 5),
+    ] {
+      let area = superpixel_area(crop);
+      assert_eq!((area.0 % 2, area.1 % 2), (0, 0));
+
+      // Shifted by at most one pixel to the start of a block
+      let (x, y, width, height) = crop;
+      assert!(area.0 + 1 >= x && area.0 + area.2 <= x + width);
+      assert!(area.1 + 1 >= y && area.1 + area.3 <= y + height);
+
+      let mosaic = vec![0.5; (x + width) * (y + height)];
+      let (_, dimensions) = superpixel(&mosaic, x + width, area, |_, _| 0);
+      assert_eq!(dimensions, (width / 2, height / 2), "crop {:?}", crop);
+    }
+  }
+
+  #[test]
+  fn test_half_packing() {
+    let pixels = [[0.0, 0.5, 1.0, 1.0], [0.18, 2.5, 1e-3, 1.0]];
+    let data: Vec<u8> = pixels
+      .iter()
+      .flatten()
+      .flat_map(|value: &f32| value.to_le_bytes())
+      .collect();
+
+    let packed = pack_half(&data);
+    assert_eq!(packed.len(), 2 * 6);
+    let unpacked = unpack_half(&packed);
+    assert_eq!(unpacked.len(), data.len());
+    for (value, expected) in unpacked.chunks_exact(4).zip(pixels.iter().flatten()) {
+      let value = f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+      // Half floats keep 11 significant bits, highlights above 1.0 included
+      assert!(
+        (value - expected).abs() <= expected * 1e-3,
+        "{} != {}",
+        value,
+        expected
+      );
+    }
+  }
+
+  #[test]
+  fn test_highlight_recovery() {
+    let gains = white_balance_gains([2.0, 1.0, 1.5, f32::NAN]);
+    assert_eq!(gains, [2.0, 1.0, 1.5]);
+    assert_eq!(white_balance_gains([f32::NAN; 4]), [1.0; 3]);
+
+    // Green clipped on the sensor, red and blue did not
+    let rgb = [0.6, 1.0, 0.8];
+    assert_close(
+      balance(rgb, gains, HighlightRecovery::Unclip),
+      [1.2, 1.0, 1.2],
+    );
+    assert_close(balance(rgb, gains, HighlightRecovery::Clip), [1.0; 3]);
+    assert_close(
+      balance(rgb, gains, HighlightRecovery::Blend),
+      [3.4 / 3.0; 3],
+    );
+
+    // Colours below the clip level are only balanced
+    let shadow = [0.1, 0.2, 0.1];
+    for highlights in [HighlightRecovery::Clip, HighlightRecovery::Blend] {
+      assert_close(balance(shadow, gains, highlights), [0.2, 0.2, 0.15]);
+    }
+  }
+
+  #[test]
+  fn test_camera_to_rgb() {
+    // A camera recording linear sRGB needs no conversion
+    let xyz_to_rgb = color::invert(ColorSpace::LinearSrgb.to_xyz());
+    let matrix = camera_to_rgb(&xyz_to_rgb.concat()).unwrap();
+    assert_close(matrix[0], [1.0, 0.0, 0.0]);
+    assert_close(matrix[1], [0.0, 1.0, 0.0]);
+    assert_close(matrix[2], [0.0, 0.0, 1.0]);
+    assert_eq!(camera_to_rgb(&[1.0; 6]), None);
+  }
+
+  #[test]
+  fn test_cache_params() {
+    let params = RawDevelopParams {
+      demosaic: DemosaicAlgorithm::Superpixel,
+      black_level: Some(512),
+      ..Default::default()
+    };
+    assert_eq!(
+      params.to_string(),
+      "demosaic=superpixel highlights=clip black=512 white=auto profile=camera"
+    );
+    assert_ne!(params.to_string(), RawDevelopParams::default().to_string());
+    assert!(params.validate().is_ok());
+    let inverted = RawDevelopParams {
+      white_level: Some(1000),
+      black_level: Some(16000),
+      ..params
+    };
+    assert!(inverted.validate().is_err());
+    assert_eq!("superpixel".parse(), Ok(DemosaicAlgorithm::Superpixel));
+    assert_eq!("blend".parse(), Ok(HighlightRecovery::Blend));
+    assert!("ahd".parse::<DemosaicAlgorithm>().is_err());
+
+    let custom = RawDevelopParams {
+      input_profile: InputProfile::custom([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.5, 0.5]),
+      ..Default::default()
+    };
+    assert!(
+      custom
+        .to_string()
+        .ends_with("profile=1:0:0:0:1:0:0:0.5:0.5")
+    );
+  }
+}
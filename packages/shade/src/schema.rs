@@ -0,0 +1,592 @@
+//! Descriptions of the node types and their parameters for clients
+//!
+//! Clients build their controls from these descriptions instead of repeating ranges
+//! and defaults. Ports and defaults are taken from `ProcessingNode`, so only labels,
+//! value types and ranges are listed here. The ranges are those a control should
+//! offer, mirroring the CLI help. Where the pipeline has a limit of its own, like the
+//! temperatures white balance covers or the largest texture, the range is taken from
+//! it. `Custom` nodes are described once for every loaded shader.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::custom::CustomShader;
+use crate::hsl::HslBand;
+use crate::shade::{NodeType, ProcessingNode, required_limits};
+use crate::white_balance::{MAX_KELVIN, MIN_KELVIN};
+
+/// A node type as returned by `describe_operations`
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeSchema {
+  pub node_type: NodeType,
+  pub label: String,
+  /// Shader of a `Custom` node, whose parameters go in the `params` object of
+  /// `NodeParams::Custom`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub shader: Option<String>,
+  pub inputs: Vec<String>,
+  pub outputs: Vec<String>,
+  pub params: Vec<ParamSchema>,
+}
+
+/// A parameter of a node type. A `null` default marks an optional parameter.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamSchema {
+  pub name: String,
+  pub label: String,
+  #[serde(flatten)]
+  pub param_type: ParamType,
+  pub default: Value,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub min: Option<f64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max: Option<f64>,
+}
+
+/// Kinds of parameter values, serialized as the `type` of a parameter
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParamType {
+  Float,
+  Integer,
+  Bool,
+  /// One of a fixed set of names
+  Enum {
+    options: Vec<&'static str>,
+  },
+  /// A fixed number of floats, like an RGB triple or a position. `min` and `max`
+  /// apply to every component.
+  Vector {
+    length: usize,
+  },
+  /// A list of `[x, y]` points with coordinates in 0..1, exactly `count` of them
+  /// when set
+  Points {
+    count: Option<usize>,
+  },
+  /// A file path on the machine running shade
+  Path,
+  /// A `MaskBitmap` with base64 encoded data
+  Bitmap,
+  /// Nested parameters
+  Object {
+    fields: Vec<ParamSchema>,
+  },
+}
+
+impl ParamSchema {
+  fn new(name: &str, label: &str, param_type: ParamType) -> Self {
+    Self {
+      name: name.to_string(),
+      label: label.to_string(),
+      param_type,
+      default: Value::Null,
+      min: None,
+      max: None,
+    }
+  }
+
+  fn range(mut self, min: f64, max: f64) -> Self {
+    self.min = Some(min);
+    self.max = Some(max);
+    self
+  }
+
+  /// Take the defaults of this parameter and its fields from the serialized defaults
+  /// of the parameter's parent
+  fn fill_default(&mut self, defaults: &Value) {
+    self.default = defaults.get(&self.name).cloned().unwrap_or(Value::Null);
+    if let ParamType::Object { fields } = &mut self.param_type {
+      for field in fields {
+        field.fill_default(&self.default);
+      }
+    }
+  }
+}
+
+fn float(name: &str, label: &str, min: f64, max: f64) -> ParamSchema {
+  ParamSchema::new(name, label, ParamType::Float).range(min, max)
+}
+
+fn integer(name: &str, label: &str) -> ParamSchema {
+  ParamSchema::new(name, label, ParamType::Integer)
+}
+
+fn boolean(name: &str, label: &str) -> ParamSchema {
+  ParamSchema::new(name, label, ParamType::Bool)
+}
+
+fn choice(name: &str, label: &str, options: &[&'static str]) -> ParamSchema {
+  let options = options.to_vec();
+  ParamSchema::new(name, label, ParamType::Enum { options })
+}
+
+fn vector(name: &str, label: &str, length: usize, min: f64, max: f64) -> ParamSchema {
+  ParamSchema::new(name, label, ParamType::Vector { length }).range(min, max)
+}
+
+fn points(name: &str, label: &str, count: Option<usize>) -> ParamSchema {
+  ParamSchema::new(name, label, ParamType::Points { count })
+}
+
+fn hsl_bands() -> ParamSchema {
+  let fields = HslBand::ALL
+    .iter()
+    .map(|band| {
+      let name = band.to_string();
+      let label = label_from_name(&name);
+      let fields = vec![
+        float("hue", "Hue", -180.0, 180.0),
+        float("saturation", "Saturation", -1.0, 1.0),
+        float("luminance", "Luminance", -1.0, 1.0),
+      ];
+      ParamSchema::new(&name, &label, ParamType::Object { fields })
+    })
+    .collect();
+  ParamSchema::new("bands", "Bands", ParamType::Object { fields })
+}
+
+fn grading_wheel(name: &str, label: &str) -> ParamSchema {
+  let fields = vec![
+    float("hue", "Hue", 0.0, 360.0),
+    float("saturation", "Saturation", 0.0, 1.0),
+    float("luminance", "Luminance", -1.0, 1.0),
+  ];
+  ParamSchema::new(name, label, ParamType::Object { fields })
+}
+
+/// A label for a parameter that only has a name, like `hue_shift` becoming `Hue shift`
+fn label_from_name(name: &str) -> String {
+  let words = name.replace('_', " ");
+  let mut chars = words.chars();
+  chars
+    .next()
+    .map(|first| first.to_uppercase().chain(chars).collect())
+    .unwrap_or_default()
+}
+
+/// Label and parameters of a node type, without defaults
+fn describe(node_type: NodeType) -> (&'static str, Vec<ParamSchema>) {
+  const RESAMPLING: &[&str] = &["bilinear", "bicubic"];
+  let kelvin = |name, label| float(name, label, MIN_KELVIN as f64, MAX_KELVIN as f64);
+  let dimension = |name, label| {
+    integer(name, label).range(1.0, required_limits().max_texture_dimension_2d as f64)
+  };
+
+  match node_type {
+    NodeType::ImageInput => ("Input", vec![]),
+    NodeType::ImageOutput => ("Output", vec![]),
+    NodeType::Exposure => ("Exposure", vec![float("stops", "Stops", -5.0, 5.0)]),
+    NodeType::Brightness => ("Brightness", vec![float("value", "Brightness", -1.0, 1.0)]),
+    NodeType::Contrast => ("Contrast", vec![float("value", "Contrast", 0.0, 2.0)]),
+    NodeType::Saturation => ("Saturation", vec![float("value", "Saturation", 0.0, 2.0)]),
+    NodeType::Vibrance => ("Vibrance", vec![float("amount", "Amount", -1.0, 1.0)]),
+    NodeType::Hue => ("Hue", vec![float("value", "Hue", -180.0, 180.0)]),
+    NodeType::Hsl => ("HSL", vec![hsl_bands()]),
+    NodeType::Gamma => ("Gamma", vec![float("value", "Gamma", 0.1, 3.0)]),
+    NodeType::Levels => (
+      "Levels",
+      vec![
+        float("input_black", "Input black", 0.0, 1.0),
+        float("input_white", "Input white", 0.0, 1.0),
+        float("output_black", "Output black", 0.0, 1.0),
+        float("output_white", "Output white", 0.0, 1.0),
+      ],
+    ),
+    NodeType::Curves => (
+      "Curves",
+      vec![
+        points("master", "Master", None),
+        points("red", "Red", None),
+        points("green", "Green", None),
+        points("blue", "Blue", None),
+      ],
+    ),
+    NodeType::ColorBalance => (
+      "Colour Balance",
+      vec![
+        vector("shadows", "Shadows", 3, -1.0, 1.0),
+        vector("midtones", "Midtones", 3, -1.0, 1.0),
+        vector("highlights", "Highlights", 3, -1.0, 1.0),
+      ],
+    ),
+    NodeType::ColorGrading => (
+      "Colour Grading",
+      vec![
+        grading_wheel("shadows", "Shadows"),
+        grading_wheel("midtones", "Midtones"),
+        grading_wheel("highlights", "Highlights"),
+        float("balance", "Balance", -1.0, 1.0),
+        float("blending", "Blending", 0.0, 1.0),
+      ],
+    ),
+    NodeType::WhiteBalance => (
+      "White Balance",
+      vec![
+        boolean("auto_adjust", "Automatic"),
+        float("temperature", "Temperature", -1.0, 1.0),
+        float("tint", "Tint", -1.0, 1.0),
+        choice("method", "Method", &["grey_world", "white_patch"]),
+        vector("neutral_point", "Neutral Point", 2, 0.0, 1.0),
+        kelvin("kelvin", "Temperature (K)"),
+        kelvin("as_shot_kelvin", "As-Shot Temperature (K)"),
+      ],
+    ),
+    NodeType::Lut3D => (
+      "3D LUT",
+      vec![
+        ParamSchema::new("path", "File", ParamType::Path),
+        float("intensity", "Intensity", 0.0, 1.0),
+        choice(
+          "interpolation",
+          "Interpolation",
+          &["trilinear", "tetrahedral"],
+        ),
+      ],
+    ),
+    NodeType::ToneMap => (
+      "Tone Map",
+      vec![
+        choice("operator", "Operator", &["reinhard", "filmic", "aces"]),
+        float("white_point", "White point", 1.0, 64.0),
+        float("contrast", "Contrast", 0.5, 2.0),
+      ],
+    ),
+    NodeType::Denoise => (
+      "Denoise",
+      vec![
+        float("luminance", "Luminance", 0.0, 1.0),
+        float("chroma", "Chroma", 0.0, 1.0),
+        float("radius", "Radius", 0.0, 8.0),
+      ],
+    ),
+    NodeType::Blur => ("Blur", vec![float("radius", "Radius", 0.0, 100.0)]),
+    NodeType::Sharpen => ("Sharpen", vec![float("amount", "Amount", 0.0, 2.0)]),
+    NodeType::UnsharpMask => (
+      "Unsharp Mask",
+      vec![
+        float("amount", "Amount", 0.0, 2.0),
+        float("radius", "Radius", 0.0, 10.0),
+        float("threshold", "Threshold", 0.0, 1.0),
+      ],
+    ),
+    NodeType::Clarity => (
+      "Clarity",
+      vec![
+        float("amount", "Amount", -1.0, 1.0),
+        float("radius", "Radius", 1.0, 200.0),
+      ],
+    ),
+    NodeType::Noise => (
+      "Noise",
+      vec![float("amount", "Amount", 0.0, 1.0), integer("seed", "Seed")],
+    ),
+    NodeType::Vignette => (
+      "Vignette",
+      vec![
+        float("amount", "Amount", -1.0, 1.0),
+        float("midpoint", "Midpoint", 0.0, 1.0),
+        float("roundness", "Roundness", -1.0, 1.0),
+        float("feather", "Feather", 0.0, 1.0),
+      ],
+    ),
+    NodeType::Grain => (
+      "Grain",
+      vec![
+        float("amount", "Amount", 0.0, 1.0),
+        float("size", "Size", 0.1, 10.0),
+        integer("seed", "Seed"),
+      ],
+    ),
+    NodeType::Dehaze => ("Dehaze", vec![float("amount", "Amount", -1.0, 1.0)]),
+    NodeType::Resize => (
+      "Resize",
+      vec![dimension("width", "Width"), dimension("height", "Height")],
+    ),
+    NodeType::Crop => (
+      "Crop",
+      vec![
+        integer("x", "X"),
+        integer("y", "Y"),
+        dimension("width", "Width"),
+        dimension("height", "Height"),
+      ],
+    ),
+    NodeType::Rotate => (
+      "Rotate",
+      vec![integer("quarter_turns", "Quarter turns").range(0.0, 3.0)],
+    ),
+    NodeType::Flip => (
+      "Flip",
+      vec![
+        boolean("horizontal", "Horizontal"),
+        boolean("vertical", "Vertical"),
+      ],
+    ),
+    NodeType::Straighten => (
+      "Straighten",
+      vec![
+        float("angle", "Angle", -45.0, 45.0),
+        boolean("crop", "Crop"),
+        choice("resampling", "Resampling", RESAMPLING),
+      ],
+    ),
+    NodeType::Perspective => (
+      "Perspective",
+      vec![
+        points("corners", "Corners", Some(4)).range(0.0, 1.0),
+        choice("resampling", "Resampling", RESAMPLING),
+      ],
+    ),
+    NodeType::Mix => (
+      "Mix",
+      vec![
+        float("factor", "Factor", 0.0, 1.0),
+        boolean("use_alpha", "Use alpha"),
+      ],
+    ),
+    NodeType::Mask => ("Mask", vec![]),
+    NodeType::Invert => ("Invert", vec![]),
+    NodeType::LuminanceMask => (
+      "Luminance Mask",
+      vec![
+        float("low", "Low", 0.0, 1.0),
+        float("high", "High", 0.0, 1.0),
+        float("feather", "Feather", 0.0, 1.0),
+      ],
+    ),
+    NodeType::ColorRangeMask => (
+      "Colour Range Mask",
+      vec![
+        float("hue", "Hue", 0.0, 360.0),
+        float("hue_range", "Hue range", 0.0, 180.0),
+        float("hue_feather", "Hue feather", 0.0, 180.0),
+        vector("saturation", "Saturation", 2, 0.0, 1.0),
+        vector("luminance", "Luminance", 2, 0.0, 1.0),
+        float("feather", "Feather", 0.0, 1.0),
+      ],
+    ),
+    NodeType::PaintedMask => (
+      "Painted Mask",
+      vec![ParamSchema::new("bitmap", "Bitmap", ParamType::Bitmap)],
+    ),
+    NodeType::LinearGradient => (
+      "Linear Gradient",
+      vec![
+        vector("center", "Centre", 2, 0.0, 1.0),
+        float("angle", "Angle", -180.0, 180.0),
+        float("feather", "Feather", 0.0, 2.0),
+        boolean("invert", "Invert"),
+      ],
+    ),
+    NodeType::RadialGradient => (
+      "Radial Gradient",
+      vec![
+        vector("center", "Centre", 2, 0.0, 1.0),
+        vector("radius", "Radius", 2, 0.0, 2.0),
+        float("angle", "Angle", -180.0, 180.0),
+        float("feather", "Feather", 0.0, 1.0),
+        boolean("invert", "Invert"),
+      ],
+    ),
+    // Described for every shader by `describe_custom`
+    NodeType::Custom => ("Custom", vec![]),
+  }
+}
+
+/// Describe a `Custom` node running `shader`
+fn describe_custom(shader: &CustomShader) -> NodeSchema {
+  let node = ProcessingNode::new(0, String::new(), NodeType::Custom);
+  let params = shader
+    .params
+    .iter()
+    .map(|name| ParamSchema {
+      default: Value::from(0.0),
+      ..ParamSchema::new(name, &label_from_name(name), ParamType::Float)
+    })
+    .collect();
+
+  NodeSchema {
+    node_type: NodeType::Custom,
+    label: label_from_name(&shader.name),
+    shader: Some(shader.name.clone()),
+    inputs: node.inputs,
+    outputs: node.outputs,
+    params,
+  }
+}
+
+/// Describe every node type with its ports and parameters, followed by a `Custom` node
+/// for each of `custom_shaders`
+pub fn describe_node_types(custom_shaders: &[CustomShader]) -> Vec<NodeSchema> {
+  let built_in = NodeType::ALL
+    .iter()
+    .filter(|&&node_type| node_type != NodeType::Custom)
+    .map(|&node_type| {
+      let node = ProcessingNode::new(0, String::new(), node_type);
+      // Parameters serialize as `{"<node type>": {<name>: <value>, ..}}`
+      let defaults = serde_json::to_value(&node.params).unwrap_or(Value::Null);
+      let defaults = defaults
+        .as_object()
+        .and_then(|variant| variant.values().next())
+        .cloned()
+        .unwrap_or(Value::Null);
+
+      let (label, mut params) = describe(node_type);
+      for param in &mut params {
+        param.fill_default(&defaults);
+      }
+      NodeSchema {
+        node_type,
+        label: label.to_string(),
+        shader: None,
+        inputs: node.inputs,
+        outputs: node.outputs,
+        params,
+      }
+    });
+
+  built_in
+    .chain(custom_shaders.iter().map(describe_custom))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::geometry;
+  use crate::shade::NodeParams;
+
+  /// Values a control for `param` could send, besides its default
+  fn sample_values(param: &ParamSchema) -> Vec<Value> {
+    let bounds = [param.min, param.max].into_iter().flatten();
+    match &param.param_type {
+      ParamType::Integer => bounds.map(|bound| Value::from(bound as u32)).collect(),
+      ParamType::Float => bounds.map(Value::from).collect(),
+      ParamType::Vector { length } => bounds
+        .map(|bound| Value::from(vec![bound; *length]))
+        .collect(),
+      ParamType::Enum { options } => {
+        options.iter().map(|&option| option.into()).collect()
+      }
+      ParamType::Bool => vec![true.into(), false.into()],
+      ParamType::Points { count } => {
+        vec![Value::from(vec![vec![0.5, 0.5]; count.unwrap_or(2)])]
+      }
+      ParamType::Path | ParamType::Bitmap | ParamType::Object { .. } => vec![],
+    }
+  }
+
+  #[test]
+  fn test_schemas_match_params() {
+    let schemas = describe_node_types(&[]);
+    assert_eq!(schemas.len(), NodeType::ALL.len() - 1);
+
+    for schema in &schemas {
+      let node = ProcessingNode::new(0, String::new(), schema.node_type);
+      let defaults = serde_json::to_value(&node.params).unwrap();
+      let tag = defaults
+        .as_object()
+        .map(|variant| variant.keys().next().unwrap());
+
+      // Every parameter is described and has a default
+      let empty = serde_json::Map::new();
+      let fields = defaults.as_object().map_or(&empty, |variant| {
+        variant.values().next().unwrap().as_object().unwrap()
+      });
+      let mut names: Vec<&str> = schema.params.iter().map(|p| p.name.as_str()).collect();
+      let mut expected: Vec<&str> = fields.keys().map(|name| name.as_str()).collect();
+      names.sort();
+      expected.sort();
+      assert_eq!(names, expected, "{:?}", schema.node_type);
+
+      // Every value in the described ranges and options is accepted
+      for param in &schema.params {
+        for value in sample_values(param) {
+          let mut params = defaults.clone();
+          params[tag.unwrap()][&param.name] = value.clone();
+          assert!(
+            serde_json::from_value::<NodeParams>(params).is_ok(),
+            "{:?} rejects {} = {}",
+            schema.node_type,
+            param.name,
+            value
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_schema_ranges_match_limits() {
+    let schemas = describe_node_types(&[]);
+    let range = |node_type: NodeType, name: &str| {
+      let schema = schemas.iter().find(|s| s.node_type == node_type).unwrap();
+      let param = schema.params.iter().find(|p| p.name == name).unwrap();
+      (param.min.unwrap(), param.max.unwrap())
+    };
+
+    let kelvin = (MIN_KELVIN as f64, MAX_KELVIN as f64);
+    assert_eq!(range(NodeType::WhiteBalance, "kelvin"), kelvin);
+    assert_eq!(range(NodeType::WhiteBalance, "as_shot_kelvin"), kelvin);
+
+    let dimensions = (1.0, required_limits().max_texture_dimension_2d as f64);
+    for node_type in [NodeType::Resize, NodeType::Crop] {
+      assert_eq!(range(node_type, "width"), dimensions);
+      assert_eq!(range(node_type, "height"), dimensions);
+    }
+
+    // Perspective corners are accepted up to the bounds and rejected beyond them
+    let (min, max) = range(NodeType::Perspective, "corners");
+    let square = |min: f64, max: f64| {
+      let (min, max) = (min as f32, max as f32);
+      [[min, min], [max, min], [max, max], [min, max]]
+    };
+    assert!(geometry::perspective_matrix(&square(min, max)).is_ok());
+    assert!(geometry::perspective_matrix(&square(min - 0.1, max)).is_err());
+    assert!(geometry::perspective_matrix(&square(min, max + 0.1)).is_err());
+  }
+
+  #[test]
+  fn test_schema_serialization() {
+    let shader = CustomShader {
+      name: "film_grain".to_string(),
+      source: String::new(),
+      params: vec!["amount".to_string(), "grain_size".to_string()],
+    };
+    let schemas = serde_json::to_value(describe_node_types(&[shader])).unwrap();
+    let mix = schemas
+      .as_array()
+      .unwrap()
+      .iter()
+      .find(|schema| schema["node_type"] == "mix")
+      .unwrap();
+    assert_eq!(mix["inputs"], serde_json::json!(["image1", "image2"]));
+    assert_eq!(
+      mix["params"][0],
+      serde_json::json!({
+        "name": "factor",
+        "label": "Factor",
+        "type": "float",
+        "default": 0.5,
+        "min": 0.0,
+        "max": 1.0,
+      })
+    );
+
+    let hsl = &schemas[NodeType::ALL
+      .iter()
+      .position(|t| *t == NodeType::Hsl)
+      .unwrap()];
+    let blue = &hsl["params"][0]["fields"][5];
+    assert_eq!(blue["name"], "blue");
+    assert_eq!(blue["fields"][0]["default"], 0.0);
+
+    let custom = schemas.as_array().unwrap().last().unwrap();
+    assert_eq!(custom["node_type"], "custom");
+    assert_eq!(custom["label"], "Film grain");
+    assert_eq!(custom["shader"], "film_grain");
+    assert_eq!(custom["params"][1]["label"], "Grain size");
+    assert_eq!(custom["params"][1]["default"], 0.0);
+  }
+}
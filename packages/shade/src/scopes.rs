@@ -0,0 +1,160 @@
+//! Histograms, waveforms and vectorscopes of processed images
+//!
+//! Scopes count display values, so they are taken from an image after it has been
+//! converted to the output colour space. Values are sorted into `LEVELS` bins over
+//! 0..1, and values outside that range land in the first or last bin. The GPU
+//! computes the same counts with `shaders/scopes.wgsl`.
+
+/// Number of bins of the histogram and of the waveform and vectorscope axes
+pub const LEVELS: u32 = 256;
+
+/// Channels of the histogram and the waveform, in order
+pub const CHANNELS: [&str; 4] = ["red", "green", "blue", "luma"];
+
+/// Waveform columns when the client does not ask for a number
+pub const DEFAULT_WAVEFORM_COLUMNS: u32 = 256;
+pub const MAX_WAVEFORM_COLUMNS: u32 = 4096;
+
+/// Rec. 709 luma weights, also used for the chroma axes of the vectorscope
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Pixel counts of an image. Every scope is a flat array of counts in row-major
+/// order, so it can be sent to clients as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scopes {
+  /// Counts indexed by `[channel][level]`, for the channels in `CHANNELS`
+  pub histogram: Vec<u32>,
+  /// Counts indexed by `[channel][level][column]`. Columns split the image width
+  /// evenly, so a column gathers the pixels of a vertical strip of the image
+  pub waveform: Vec<u32>,
+  pub waveform_columns: u32,
+  /// Counts indexed by `[cr][cb]` with both axes running from -0.5 to 0.5, so
+  /// neutral pixels are counted in the centre
+  pub vectorscope: Vec<u32>,
+}
+
+/// Bin of a value in 0..1
+fn level(value: f32) -> usize {
+  (value * LEVELS as f32).clamp(0.0, (LEVELS - 1) as f32) as usize
+}
+
+/// Number of values in the buffer the scopes are counted into, in the order
+/// histogram, waveform, vectorscope
+pub fn counts_len(waveform_columns: u32) -> usize {
+  let levels = LEVELS as usize;
+  CHANNELS.len() * levels * (1 + waveform_columns as usize) + levels * levels
+}
+
+/// Check the number of waveform columns a client asked for
+pub fn waveform_columns(columns: Option<u32>) -> Result<u32, String> {
+  match columns.unwrap_or(DEFAULT_WAVEFORM_COLUMNS) {
+    columns @ 1..=MAX_WAVEFORM_COLUMNS => Ok(columns),
+    columns => Err(format!(
+      "Waveform width {} is not between 1 and {}",
+      columns, MAX_WAVEFORM_COLUMNS
+    )),
+  }
+}
+
+impl Scopes {
+  /// Count tightly packed little-endian RGBA32F pixels on the CPU
+  pub fn from_pixels(data: &[u8], (width, _): (u32, u32), waveform_columns: u32) -> Self {
+    let mut counts = vec![0; counts_len(waveform_columns)];
+    let levels = LEVELS as usize;
+    let columns = waveform_columns as usize;
+    let waveform_offset = CHANNELS.len() * levels;
+    let vectorscope_offset = waveform_offset + CHANNELS.len() * levels * columns;
+
+    for (index, pixel) in data.chunks_exact(16).enumerate() {
+      let channel =
+        |i: usize| f32::from_le_bytes(pixel[i * 4..i * 4 + 4].try_into().unwrap());
+      let [r, g, b] = [channel(0), channel(1), channel(2)];
+      let luma = LUMA[0] * r + LUMA[1] * g + LUMA[2] * b;
+
+      let x = index as u64 % width as u64;
+      let column = (x * waveform_columns as u64 / width as u64) as usize;
+      for (channel, value) in [r, g, b, luma].into_iter().enumerate() {
+        let bin = level(value);
+        counts[channel * levels + bin] += 1;
+        counts[waveform_offset + (channel * levels + bin) * columns + column] += 1;
+      }
+
+      let cb = (b - luma) / (2.0 * (1.0 - LUMA[2]));
+      let cr = (r - luma) / (2.0 * (1.0 - LUMA[0]));
+      counts[vectorscope_offset + level(cr + 0.5) * levels + level(cb + 0.5)] += 1;
+    }
+
+    Self::from_counts(counts, waveform_columns)
+  }
+
+  /// Split a buffer laid out as described by `counts_len` into the scopes
+  pub fn from_counts(mut counts: Vec<u32>, waveform_columns: u32) -> Self {
+    let levels = LEVELS as usize;
+    let vectorscope = counts.split_off(counts.len() - levels * levels);
+    let waveform = counts.split_off(CHANNELS.len() * levels);
+    Self {
+      histogram: counts,
+      waveform,
+      waveform_columns,
+      vectorscope,
+    }
+  }
+}
+
+/// Encode counts as little-endian `u32`s for a binary attachment
+pub fn to_bytes(counts: &[u32]) -> Vec<u8> {
+  counts
+    .iter()
+    .flat_map(|count| count.to_le_bytes())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_counts() {
+    // Black, white and pure red pixels in a row
+    let pixels: Vec<u8> = [
+      [0.0f32, 0.0, 0.0, 1.0],
+      [1.0, 1.0, 1.0, 1.0],
+      [1.0, 0.0, 0.0, 1.0],
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(f32::to_le_bytes)
+    .collect();
+    let scopes = Scopes::from_pixels(&pixels, (3, 1), 3);
+    let levels = LEVELS as usize;
+
+    let histogram =
+      |channel: usize, level: usize| scopes.histogram[channel * levels + level];
+    assert_eq!((histogram(0, 0), histogram(0, 255)), (1, 2));
+    assert_eq!((histogram(1, 0), histogram(1, 255)), (2, 1));
+    assert_eq!(histogram(3, level(0.2126)), 1);
+    assert_eq!(scopes.histogram.iter().sum::<u32>(), 12);
+
+    // Each pixel has a column of its own
+    let waveform = |channel: usize, level: usize, column: usize| {
+      scopes.waveform[(channel * levels + level) * 3 + column]
+    };
+    assert_eq!(waveform(1, 0, 0), 1);
+    assert_eq!(waveform(1, 255, 1), 1);
+    assert_eq!(waveform(1, 0, 2), 1);
+    assert_eq!(scopes.waveform.iter().sum::<u32>(), 12);
+
+    // Black and white are neutral, red sits at the top of the Cr axis
+    let vectorscope = |cr: usize, cb: usize| scopes.vectorscope[cr * levels + cb];
+    assert_eq!(vectorscope(128, 128), 2);
+    assert_eq!(vectorscope(255, level(0.5 - 0.2126 / 1.8556)), 1);
+  }
+
+  #[test]
+  fn test_waveform_columns() {
+    assert_eq!(waveform_columns(None), Ok(DEFAULT_WAVEFORM_COLUMNS));
+    assert_eq!(waveform_columns(Some(64)), Ok(64));
+    assert!(waveform_columns(Some(0)).is_err());
+    assert!(waveform_columns(Some(MAX_WAVEFORM_COLUMNS + 1)).is_err());
+  }
+}
@@ -3,19 +3,26 @@ use std::hash::{Hash, Hasher};
 use std::io::{stdin, stdout};
 
 use crate::Performance;
-use crate::cli::{PipelineConfig, PipelineOperation, ProcessingConfig};
+use crate::cli::{OperationType, PipelineConfig, PipelineOperation, ProcessingConfig};
+use crate::color::{ColorSettings, ColorSpace};
 use crate::protocol::{
-  BinaryAttachment, GetAttachmentParams, GetAttachmentResult, ImageInput,
-  InitializeParams, InitializeResult, Message, MessageTransport, ProcessImageParams,
-  ProcessImageResult, ResponseError, ServerCapabilities, ServerInfo,
+  AnalyzeImageParams, BinaryAttachment, DescribeOperationsResult, GetAttachmentParams,
+  GetAttachmentResult, ImageInput, InitializeParams, InitializeResult, Message,
+  MessageTransport, ProcessImageParams, ProcessImageResult, ResponseError,
+  ScopeAttachment, ScopesResult, ServerCapabilities, ServerInfo,
 };
+use crate::raw::RawDevelopParams;
+use crate::schema::describe_node_types;
+use crate::scopes::{self, LEVELS};
 use anyhow::Result;
 use anyhow::anyhow;
 use base64::Engine;
 use image::{ImageBuffer, ImageFormat, Rgba};
 use wgpu::{Device, Queue};
 
-use crate::file_loaders::load_image;
+use crate::custom::{self, CustomShader};
+use crate::file_loaders::{detect_as_shot_kelvin, detect_color_space, load_image};
+use crate::shade::{Backend, ImagePipeline, ResultCache, request_device};
 use std::collections::HashMap;
 
 /// Cached image data
@@ -24,6 +31,9 @@ struct CachedImage {
   hash: u64,
   texture_data: Vec<u8>,
   dimensions: (usize, usize),
+  color_space: ColorSpace,
+  /// White balance of a camera raw file, in Kelvin
+  as_shot_kelvin: Option<f32>,
 }
 
 /// Image processing server that handles socket communication
@@ -34,6 +44,12 @@ pub struct ImageProcessingServer {
   device: Option<Device>,
   /// Storage for binary attachments that can be retrieved later
   attachments: HashMap<String, (Vec<u8>, String)>, // attachment_id -> (data, content_type)
+  /// Node results of the previous request, reused for unchanged parts of the pipeline
+  result_cache: Option<ResultCache>,
+  /// Shaders for `Custom` nodes loaded on initialize, and the errors of those that
+  /// failed to load
+  custom_shaders: Vec<CustomShader>,
+  shader_errors: Vec<String>,
 }
 
 impl ImageProcessingServer {
@@ -44,6 +60,9 @@ impl ImageProcessingServer {
       queue: None,
       device: None,
       attachments: HashMap::new(),
+      result_cache: None,
+      custom_shaders: Vec::new(),
+      shader_errors: Vec::new(),
     }
   }
 
@@ -103,6 +122,20 @@ impl ImageProcessingServer {
         let (response, binary_data) = self.handle_process_image(message).await;
         Some((response, binary_data))
       }
+      // describes the node types and their parameters
+      Some("describe_operations") => {
+        let response = self.handle_describe_operations(message);
+        Some((response, HashMap::new()))
+      }
+      // counts the pixels of a node's output
+      Some("get_histogram") => {
+        let (response, binary_data) = self.handle_analyze_image(message, false).await;
+        Some((response, binary_data))
+      }
+      Some("get_scopes") => {
+        let (response, binary_data) = self.handle_analyze_image(message, true).await;
+        Some((response, binary_data))
+      }
       // retrieves a stored attachment as blob
       Some("get_attachment") => {
         let (response, binary_data) = self.handle_get_attachment(message).await;
@@ -136,46 +169,33 @@ impl ImageProcessingServer {
 
     log::error!("Handle initialize request");
 
-    // Initialize the pipeline with GPU resources (moved device and queue so we need to recreate them)
-    let instance = wgpu::Instance::default();
-    let adapter = instance
-      .request_adapter(&wgpu::RequestAdapterOptions::default())
-      .await
-      .unwrap();
-
-    let (device, queue) = adapter
-      .request_device(&wgpu::DeviceDescriptor {
-        label: None,
-        required_features: wgpu::Features::empty(),
-        required_limits: wgpu::Limits::defaults(),
-        memory_hints: wgpu::MemoryHints::MemoryUsage,
-        trace: wgpu::Trace::Off,
-      })
-      .await
-      .unwrap();
-
-    self.device = Some(device);
-    self.queue = Some(queue);
+    // Initialize GPU resources; requests are processed on the CPU without a GPU
+    match request_device().await {
+      Ok((device, queue)) => {
+        self.device = Some(device);
+        self.queue = Some(queue);
+        // Cached node results can hold textures of the previous device
+        self.result_cache = None;
+      }
+      Err(e) => log::error!("{}, processing on the CPU", e),
+    }
 
     match message.params {
       Some(params) => match serde_json::from_value::<InitializeParams>(params) {
-        Ok(_init_params) => {
+        Ok(init_params) => {
           self.initialized = true;
 
+          (self.custom_shaders, self.shader_errors) =
+            custom::load_shaders(init_params.shader_dir.as_deref());
+          for error in &self.shader_errors {
+            log::error!("{}", error);
+          }
+
           let capabilities = ServerCapabilities {
-            supported_operations: vec![
-              "brightness".to_string(),
-              "contrast".to_string(),
-              "saturation".to_string(),
-              "hue".to_string(),
-              "gamma".to_string(),
-              "white_balance".to_string(),
-              "blur".to_string(),
-              "sharpen".to_string(),
-              "noise".to_string(),
-              "scale".to_string(),
-              "rotate".to_string(),
-            ],
+            supported_operations: OperationType::NAMES
+              .iter()
+              .map(|name| name.to_string())
+              .collect(),
             supported_input_formats: vec![
               "png".to_string(),
               "jpg".to_string(),
@@ -195,6 +215,9 @@ impl ImageProcessingServer {
             supported_methods: vec![
               "initialize".to_string(),
               "process_image".to_string(),
+              "describe_operations".to_string(),
+              "get_histogram".to_string(),
+              "get_scopes".to_string(),
               "get_attachment".to_string(),
               "shutdown".to_string(),
             ],
@@ -231,6 +254,27 @@ impl ImageProcessingServer {
     }
   }
 
+  /// Handle describe_operations request
+  ///
+  /// Returns every node type with its inputs, outputs and parameters, including their
+  /// types, defaults and ranges. Takes no parameters.
+  fn handle_describe_operations(&self, message: Message) -> Message {
+    let id = message.id.unwrap_or(0);
+
+    if !self.initialized {
+      return Message::new_error_response(
+        Some(id),
+        ResponseError::new(-32002, "Server not initialized".to_string()),
+      );
+    }
+
+    let result = DescribeOperationsResult {
+      node_types: describe_node_types(&self.custom_shaders),
+      shader_errors: self.shader_errors.clone(),
+    };
+    Message::new_response(id, serde_json::to_value(result).unwrap())
+  }
+
   /// Handle process_image request
   async fn handle_process_image(
     &mut self,
@@ -303,13 +347,200 @@ impl ImageProcessingServer {
     }
   }
 
+  /// Handle get_histogram and get_scopes requests
+  ///
+  /// Runs the pipeline of the request and counts the output of the requested node
+  /// into its histogram and, with `with_scopes`, its waveform and vectorscope. The
+  /// counts are sent as binary attachments of little-endian `u32`s.
+  async fn handle_analyze_image(
+    &mut self,
+    message: Message,
+    with_scopes: bool,
+  ) -> (Message, HashMap<String, Vec<u8>>) {
+    let id = message.id.unwrap_or(0);
+
+    if !self.initialized {
+      return (
+        Message::new_error_response(
+          Some(id),
+          ResponseError::new(-32002, "Server not initialized".to_string()),
+        ),
+        HashMap::new(),
+      );
+    }
+
+    let params = match message
+      .params
+      .map(serde_json::from_value::<AnalyzeImageParams>)
+    {
+      Some(Ok(params)) => params,
+      Some(Err(e)) => {
+        return (
+          Message::new_error_response(
+            Some(id),
+            ResponseError::invalid_params(format!("Invalid analysis params: {}", e)),
+          ),
+          HashMap::new(),
+        );
+      }
+      None => {
+        return (
+          Message::new_error_response(
+            Some(id),
+            ResponseError::invalid_params("Missing analysis parameters".to_string()),
+          ),
+          HashMap::new(),
+        );
+      }
+    };
+
+    match self.analyze_image_internal(params, with_scopes).await {
+      Ok((result, binary_data)) => {
+        let attachments = [
+          Some(&result.histogram),
+          result.waveform.as_ref(),
+          result.vectorscope.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|scope| {
+          let data = &binary_data[&scope.attachment_id];
+          self.attachments.insert(
+            scope.attachment_id.clone(),
+            (data.clone(), "application/octet-stream".to_string()),
+          );
+          BinaryAttachment {
+            id: scope.attachment_id.clone(),
+            content_type: "application/octet-stream".to_string(),
+            size: data.len(),
+          }
+        })
+        .collect();
+
+        (
+          Message::new_response_with_binary(
+            id,
+            serde_json::to_value(result).unwrap(),
+            attachments,
+          ),
+          binary_data,
+        )
+      }
+      Err(e) => (
+        Message::new_error_response(
+          Some(id),
+          ResponseError::internal_error(e.to_string()),
+        ),
+        HashMap::new(),
+      ),
+    }
+  }
+
+  /// Count the output of the node a request names, or of the pipeline
+  async fn analyze_image_internal(
+    &mut self,
+    params: AnalyzeImageParams,
+    with_scopes: bool,
+  ) -> Result<(ScopesResult, HashMap<String, Vec<u8>>)> {
+    let mut timing = Performance::default();
+    // The histogram alone needs no waveform resolution
+    let waveform_columns = if with_scopes {
+      scopes::waveform_columns(params.waveform_width)
+        .map_err(|e| anyhow!("Error {}", e))?
+    } else {
+      1
+    };
+
+    let (image_pipeline, data, (width, height)) = self
+      .run_pipeline(params.process, params.node_id, &mut timing)
+      .await?;
+    let counts = image_pipeline
+      .analyze(&data, (width, height), waveform_columns)
+      .await
+      .map_err(|e| anyhow!("Error {}", e))?;
+
+    let mut binary_data = HashMap::new();
+    let mut attach = |attachment_id: &str, shape: Vec<u32>, counts: &[u32]| {
+      binary_data.insert(attachment_id.to_string(), scopes::to_bytes(counts));
+      ScopeAttachment {
+        attachment_id: attachment_id.to_string(),
+        shape,
+      }
+    };
+    let channels = scopes::CHANNELS.len() as u32;
+    let histogram = attach("histogram", vec![channels, LEVELS], &counts.histogram);
+    let (waveform, vectorscope) = if with_scopes {
+      (
+        Some(attach(
+          "waveform",
+          vec![channels, LEVELS, waveform_columns],
+          &counts.waveform,
+        )),
+        Some(attach(
+          "vectorscope",
+          vec![LEVELS, LEVELS],
+          &counts.vectorscope,
+        )),
+      )
+    } else {
+      (None, None)
+    };
+
+    let result = ScopesResult {
+      width,
+      height,
+      channels: scopes::CHANNELS
+        .iter()
+        .map(|channel| channel.to_string())
+        .collect(),
+      histogram,
+      waveform,
+      vectorscope,
+    };
+    Ok((result, binary_data))
+  }
+
   /// Internal image processing logic
   async fn process_image_internal(
     &mut self,
     params: ProcessImageParams,
   ) -> Result<(ProcessImageResult, Vec<u8>)> {
-    let time = std::time::Instant::now();
     let mut timing = Performance::default();
+    let output_format = params
+      .output_format
+      .clone()
+      .unwrap_or_else(|| "png".to_string());
+
+    let (_, processed_data, final_dimensions) =
+      self.run_pipeline(params, None, &mut timing).await?;
+    let time = std::time::Instant::now();
+
+    // Convert processed data to output format
+    let binary_data = self.convert_to_binary(&processed_data, final_dimensions)?;
+
+    let result = ProcessImageResult {
+      image_attachment_id: "processed_image".to_string(),
+      width: final_dimensions.0,
+      height: final_dimensions.1,
+      format: output_format,
+    };
+
+    timing.output_ms = time.elapsed().as_secs_f64() * 1000.0;
+    timing.print_all();
+
+    Ok((result, binary_data))
+  }
+
+  /// Load the image of a request and run its pipeline, up to `node_id` if given.
+  ///
+  /// Returns the pipeline with the result, which is encoded as sRGB.
+  async fn run_pipeline(
+    &mut self,
+    params: ProcessImageParams,
+    node_id: Option<usize>,
+    timing: &mut Performance,
+  ) -> Result<(ImagePipeline, Vec<u8>, (u32, u32))> {
+    let time = std::time::Instant::now();
 
     // Build pipeline from operations
     let pipeline_operations: Result<Vec<PipelineOperation>, String> = params
@@ -324,6 +555,8 @@ impl ImageProcessingServer {
       })
       .collect();
 
+    let raw = params.raw.unwrap_or_default();
+
     // Create a temporary config for pipeline building
     let config = ProcessingConfig {
       input_path: None,
@@ -333,8 +566,14 @@ impl ImageProcessingServer {
       },
       verbose: false,
       config_path: None,
+      graph_path: None,
+      save_graph_path: None,
+      shader_dir: None,
       clear_cache: false,
       show_cache_info: false,
+      use_cpu: false,
+      color: ColorSettings::default(),
+      raw,
     };
 
     // load image
@@ -351,7 +590,7 @@ impl ImageProcessingServer {
 
     // Check if we have this image cached
     let cached_image = self
-      .load_and_cache_image(image_file, &mut timing, time)
+      .load_and_cache_image(image_file, &config.raw, timing, time)
       .await?;
 
     // decode image
@@ -359,25 +598,64 @@ impl ImageProcessingServer {
     timing.image_decode_ms = time.elapsed().as_secs_f64() * 1000.0;
     let time = std::time::Instant::now();
 
-    let mut image_pipeline = config.build_pipeline();
+    // A graph describes the whole pipeline, so it replaces the operations list
+    let has_graph = params.graph.is_some();
+    let mut image_pipeline = match params.graph {
+      Some(_) if !params.operations.is_empty() => {
+        return Err(anyhow!("Error graph and operations cannot be combined"));
+      }
+      Some(graph) => graph.into_pipeline().map_err(|e| anyhow!("Error {}", e))?,
+      None => config.build_pipeline(),
+    };
+    if let Some(node_id) = node_id {
+      if !has_graph {
+        return Err(anyhow!("Error node_id requires a graph"));
+      }
+      image_pipeline
+        .set_target_node(node_id)
+        .map_err(|e| anyhow!("Error {}", e))?;
+    }
 
-    image_pipeline.init_gpu(
-      self.device.clone().unwrap().clone(),
-      self.queue.clone().unwrap().clone(),
-    );
+    for shader in &self.custom_shaders {
+      image_pipeline.add_custom_shader(shader.clone());
+    }
+    match (self.device.clone(), self.queue.clone()) {
+      (Some(device), Some(queue)) => image_pipeline.init_gpu(device, queue),
+      _ => image_pipeline.set_backend(Backend::Cpu),
+    }
 
     timing.gpu_setup_ms = time.elapsed().as_secs_f64() * 1000.0;
     let time = std::time::Instant::now();
 
     let mut actual_dims = cached_image.dimensions;
 
-    let (processed_data, final_dimensions) = image_pipeline
+    // Images are returned as PNG, so the result is encoded as sRGB
+    image_pipeline
+      .set_input_space(params.input_space.unwrap_or(cached_image.color_space));
+    if let Some(kelvin) = cached_image.as_shot_kelvin {
+      image_pipeline.set_as_shot_kelvin(kelvin);
+    }
+    if let Some(space) = params.working_space {
+      image_pipeline
+        .set_working_space(space)
+        .map_err(|e| anyhow!("Error {}", e))?;
+    }
+    image_pipeline.set_output_space(ColorSpace::Srgb);
+
+    // Reuse node results of the previous request where nothing upstream changed
+    image_pipeline.set_result_cache(self.result_cache.take().unwrap_or_default());
+    image_pipeline.set_input_key(cached_image.hash);
+
+    let processed = image_pipeline
       .process(
         cached_image.texture_data.clone(),
         (actual_dims.0 as u32, actual_dims.1 as u32),
       )
-      .await
-      .map_err(|e: String| anyhow!("Operation {}", e))?;
+      .await;
+    self.result_cache = image_pipeline.take_result_cache();
+
+    let (processed_data, final_dimensions) =
+      processed.map_err(|e: String| anyhow!("Operation {}", e))?;
 
     actual_dims = (final_dimensions.0 as usize, final_dimensions.1 as usize);
     log::error!(
@@ -387,23 +665,8 @@ impl ImageProcessingServer {
     );
 
     timing.processing_ms = time.elapsed().as_secs_f64() * 1000.0;
-    let time = std::time::Instant::now();
 
-    // Convert processed data to output format
-    let output_format = params.output_format.unwrap_or_else(|| "png".to_string());
-    let binary_data = self.convert_to_binary(&processed_data, final_dimensions)?;
-
-    let result = ProcessImageResult {
-      image_attachment_id: "processed_image".to_string(),
-      width: actual_dims.0 as u32,
-      height: actual_dims.1 as u32,
-      format: output_format,
-    };
-
-    timing.output_ms = time.elapsed().as_secs_f64() * 1000.0;
-    timing.print_all();
-
-    Ok((result, binary_data))
+    Ok((image_pipeline, processed_data, final_dimensions))
   }
 
   /// Load image data from various input formats
@@ -428,10 +691,12 @@ impl ImageProcessingServer {
     }
   }
 
-  async fn load_image(&mut self, image_file: Vec<u8>) -> Result<CachedImage> {
-    let mut hasher = DefaultHasher::new();
-    image_file.hash(&mut hasher);
-    let image_hash = hasher.finish();
+  async fn load_image(
+    &mut self,
+    image_file: Vec<u8>,
+    raw: &RawDevelopParams,
+  ) -> Result<CachedImage> {
+    let image_hash = Self::image_hash(&image_file, raw);
 
     if let Some(cached_image) = self.cached_image.clone()
       && image_hash == cached_image.hash
@@ -439,7 +704,9 @@ impl ImageProcessingServer {
       return Ok(cached_image);
     }
 
-    let (image_data, (width, height)) = load_image(&image_file, None)?;
+    let (image_data, (width, height)) = load_image(&image_file, None, raw)?;
+    let color_space = detect_color_space(&image_file, None).unwrap_or(ColorSpace::Srgb);
+    let as_shot_kelvin = detect_as_shot_kelvin(&image_file, None);
 
     log::error!("Successfully loaded image: {}x{}", width, height);
 
@@ -447,22 +714,32 @@ impl ImageProcessingServer {
       dimensions: (width, height),
       texture_data: image_data,
       hash: image_hash,
+      color_space,
+      as_shot_kelvin,
     })
   }
 
+  /// Key of a loaded image. Raw files are developed with `raw`, so the settings are
+  /// part of the key and changing them loads the file again.
+  fn image_hash(image_file: &[u8], raw: &RawDevelopParams) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image_file.hash(&mut hasher);
+    raw.to_string().hash(&mut hasher);
+    hasher.finish()
+  }
+
   /// Load image and cache it for future requests
   async fn load_and_cache_image(
     &mut self,
     image_file: Vec<u8>,
+    raw: &RawDevelopParams,
     timing: &mut Performance,
     time: std::time::Instant,
   ) -> Result<CachedImage> {
     // Load input image if provided
     timing.image_load_ms = time.elapsed().as_secs_f64() * 1000.0;
 
-    let mut hasher = DefaultHasher::new();
-    image_file.hash(&mut hasher);
-    let image_hash = hasher.finish();
+    let image_hash = Self::image_hash(&image_file, raw);
 
     if let Some(cached_image) = self.cached_image.clone()
       && image_hash == cached_image.hash
@@ -471,7 +748,9 @@ impl ImageProcessingServer {
       return Ok(cached_image);
     }
 
-    let (image_data, (width, height)) = load_image(&image_file, None)?;
+    let (image_data, (width, height)) = load_image(&image_file, None, raw)?;
+    let color_space = detect_color_space(&image_file, None).unwrap_or(ColorSpace::Srgb);
+    let as_shot_kelvin = detect_as_shot_kelvin(&image_file, None);
 
     log::error!("Successfully loaded image: {}x{}", width, height);
 
@@ -479,6 +758,8 @@ impl ImageProcessingServer {
       dimensions: (width, height),
       texture_data: image_data,
       hash: image_hash,
+      color_space,
+      as_shot_kelvin,
     };
 
     // Cache the loaded image
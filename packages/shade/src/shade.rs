@@ -4,8 +4,25 @@
 //! that mimics node-based compositing software like Blender's shader editor
 //! or DaVinci Resolve's node graph.
 
+use crate::color::{self, ColorEncoding, ColorSpace};
+use crate::cpu::{self, CpuImage};
+use crate::curves::{self, CurvePoints};
+use crate::custom::CustomShader;
+use crate::dehaze;
+use crate::geometry::{self, Resampling};
+use crate::grading::GradingWheel;
+use crate::hsl::HslBands;
+use crate::lut::{self, LutInterpolation};
+use crate::mask::MaskBitmap;
+use crate::scopes::{self, Scopes};
+use crate::white_balance::{self, WhiteBalanceMethod};
 use flume;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use wgpu::util::DeviceExt;
 use wgpu::{ComputePipeline, Device, Queue, Texture, TextureView};
 
@@ -18,41 +35,329 @@ pub const SHADER_FORMAT: &str = "rgba32float";
 pub const MAX_BUFFER_SIZE: u64 = 268_435_456; // 256 MB - WebGPU limit
 pub const MAX_TILE_SIZE: u32 = 2048; // Maximum tile dimension for processing large images
 
+/// Image data flowing between nodes: RGBA32F pixel bytes and their dimensions
+type NodeOutput = (Vec<u8>, (u32, u32));
+
+/// Where the nodes of a pipeline are executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+  /// WGSL compute shaders, falling back to the CPU when no device is initialized
+  #[default]
+  Gpu,
+  /// The CPU reference implementation in `cpu.rs`
+  Cpu,
+}
+
+/// Node results kept between pipeline runs, keyed by a hash of everything that
+/// produced them.
+///
+/// A pipeline only holds the entries of its latest run, so the cache never grows
+/// beyond one result per node. Owners processing a sequence of similar pipelines,
+/// such as the socket server, hand the cache from one pipeline to the next.
+#[derive(Default)]
+pub struct ResultCache {
+  entries: HashMap<u64, CachedResult>,
+}
+
+enum CachedResult {
+  Texture(Texture, TextureView, (u32, u32)),
+  Image(CpuImage),
+}
+
+impl ResultCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  fn insert(&mut self, hash: u64, result: CachedResult) {
+    self.entries.insert(hash, result);
+  }
+
+  /// Drop every entry that is not a result of the latest run
+  fn retain_hashes(&mut self, hashes: &HashMap<Option<usize>, u64>) {
+    let current: HashSet<u64> = hashes.values().copied().collect();
+    self.entries.retain(|hash, _| current.contains(hash));
+  }
+}
+
+/// Limits the GPU device is requested with, which bound the size of every texture
+pub fn required_limits() -> wgpu::Limits {
+  wgpu::Limits::defaults()
+}
+
+/// Request a GPU device with the features and limits the pipeline is written against
+pub async fn request_device() -> Result<(Device, Queue), String> {
+  let instance = wgpu::Instance::default();
+  let adapter = instance
+    .request_adapter(&wgpu::RequestAdapterOptions::default())
+    .await
+    .map_err(|e| format!("No GPU adapter available: {}", e))?;
+
+  adapter
+    .request_device(&wgpu::DeviceDescriptor {
+      label: None,
+      required_features: wgpu::Features::empty(),
+      required_limits: required_limits(),
+      memory_hints: wgpu::MemoryHints::MemoryUsage,
+      trace: wgpu::Trace::Off,
+    })
+    .await
+    .map_err(|e| format!("Failed to create GPU device: {}", e))
+}
+
 // Define the types of processing nodes available
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NodeType {
   // Input/Output nodes
   ImageInput,
   ImageOutput,
 
   // Color adjustments
+  Exposure,
   Brightness,
   Contrast,
   Saturation,
+  Vibrance,
   Hue,
+  Hsl,
   Gamma,
   Levels,
+  Curves,
   ColorBalance,
+  ColorGrading,
   WhiteBalance,
+  #[serde(rename = "lut3d")]
+  Lut3D,
+  ToneMap,
 
   // Filters
+  Denoise,
   Blur,
   Sharpen,
+  UnsharpMask,
+  Clarity,
   Noise,
 
+  // Effects
+  Vignette,
+  Grain,
+  Dehaze,
+
   // Transformations
   Resize,
   Crop,
+  Rotate,
+  Flip,
+  Straighten,
+  Perspective,
 
   // Compositing
   Mix,
   Mask,
   Invert,
+
+  // Mask generators
+  LuminanceMask,
+  ColorRangeMask,
+  PaintedMask,
+  LinearGradient,
+  RadialGradient,
+
+  // User-defined shaders
+  Custom,
+}
+
+impl NodeType {
+  /// Every node type, in declaration order
+  pub const ALL: &[NodeType] = &[
+    NodeType::ImageInput,
+    NodeType::ImageOutput,
+    NodeType::Exposure,
+    NodeType::Brightness,
+    NodeType::Contrast,
+    NodeType::Saturation,
+    NodeType::Vibrance,
+    NodeType::Hue,
+    NodeType::Hsl,
+    NodeType::Gamma,
+    NodeType::Levels,
+    NodeType::Curves,
+    NodeType::ColorBalance,
+    NodeType::ColorGrading,
+    NodeType::WhiteBalance,
+    NodeType::Lut3D,
+    NodeType::ToneMap,
+    NodeType::Denoise,
+    NodeType::Blur,
+    NodeType::Sharpen,
+    NodeType::UnsharpMask,
+    NodeType::Clarity,
+    NodeType::Noise,
+    NodeType::Vignette,
+    NodeType::Grain,
+    NodeType::Dehaze,
+    NodeType::Resize,
+    NodeType::Crop,
+    NodeType::Rotate,
+    NodeType::Flip,
+    NodeType::Straighten,
+    NodeType::Perspective,
+    NodeType::Mix,
+    NodeType::Mask,
+    NodeType::Invert,
+    NodeType::LuminanceMask,
+    NodeType::ColorRangeMask,
+    NodeType::PaintedMask,
+    NodeType::LinearGradient,
+    NodeType::RadialGradient,
+    NodeType::Custom,
+  ];
+
+  /// Whether the node works on linear light or on values encoded with the sRGB curve.
+  ///
+  /// Adjustments defined around mid grey, and those clamping to 0..1, see encoded
+  /// values. Gains, inversion, filters that model light and compositing work on
+  /// linear light.
+  pub fn encoding(&self) -> ColorEncoding {
+    match self {
+      NodeType::Contrast
+      | NodeType::Saturation
+      | NodeType::Vibrance
+      | NodeType::Hue
+      | NodeType::Hsl
+      | NodeType::Gamma
+      | NodeType::Levels
+      | NodeType::Curves
+      | NodeType::ColorBalance
+      | NodeType::ColorGrading
+      | NodeType::Lut3D
+      // Noise is about equally visible across encoded values
+      | NodeType::Denoise
+      | NodeType::Sharpen
+      | NodeType::Noise
+      | NodeType::Grain => ColorEncoding::Encoded,
+      NodeType::ImageInput
+      | NodeType::ImageOutput
+      | NodeType::Exposure
+      | NodeType::Brightness
+      | NodeType::WhiteBalance
+      | NodeType::ToneMap
+      | NodeType::Blur
+      // Their base layer is blurred in linear light, details are added to encoded values
+      | NodeType::UnsharpMask
+      | NodeType::Clarity
+      | NodeType::Vignette
+      | NodeType::Dehaze
+      | NodeType::Resize
+      | NodeType::Crop
+      | NodeType::Rotate
+      | NodeType::Flip
+      | NodeType::Straighten
+      | NodeType::Perspective
+      | NodeType::Mix
+      | NodeType::Mask
+      | NodeType::Invert
+      | NodeType::LuminanceMask
+      | NodeType::ColorRangeMask
+      | NodeType::PaintedMask
+      | NodeType::LinearGradient
+      | NodeType::RadialGradient
+      | NodeType::Custom => ColorEncoding::Linear,
+    }
+  }
+
+  /// Neighbourhood a node reads around every output pixel of an image of
+  /// `dimensions`.
+  ///
+  /// Tiled processing pads each tile by a `Local` footprint and crops the padding
+  /// from the result, so filters show no seams between tiles. Nodes placing their
+  /// effect in the image get the position of the tile, and statistics of the whole
+  /// image are measured before the tiles are dispatched. `Global` nodes are always
+  /// processed as a whole.
+  pub fn footprint(&self, params: &NodeParams, dimensions: (u32, u32)) -> Footprint {
+    match (self, params) {
+      (NodeType::Blur, NodeParams::Blur { radius }) => {
+        Footprint::Local(blur_extent(*radius))
+      }
+      (NodeType::Sharpen, _) => Footprint::Local(1),
+      (NodeType::Denoise, NodeParams::Denoise { radius, .. }) => {
+        Footprint::Local(denoise_extent(*radius))
+      }
+      (NodeType::UnsharpMask, NodeParams::UnsharpMask { radius, .. })
+      | (NodeType::Clarity, NodeParams::Clarity { radius, .. }) => {
+        Footprint::Local(blur_extent(*radius))
+      }
+      (NodeType::Dehaze, _) => {
+        let (width, height) = dimensions;
+        Footprint::Local(2 * dehaze::dark_channel_step(width, height))
+      }
+      // These read input pixels anywhere in the image
+      (
+        NodeType::Resize
+        | NodeType::Crop
+        | NodeType::Rotate
+        | NodeType::Flip
+        | NodeType::Straighten
+        | NodeType::Perspective,
+        _,
+      ) => Footprint::Global,
+      // A custom shader may read any pixel of its input
+      (NodeType::Custom, _) => Footprint::Global,
+      _ => Footprint::Local(0),
+    }
+  }
+}
+
+/// How far a node looks beyond the pixel it writes, see `NodeType::footprint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Footprint {
+  /// Reads pixels up to this many pixels away, 0 for per-pixel operations
+  Local(u32),
+  /// Depends on the whole image
+  Global,
+}
+
+/// Curves a `ToneMap` node maps scene-linear light to display light with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapOperator {
+  Reinhard,
+  /// AgX-style log encoding with a filmic contrast curve
+  #[default]
+  Filmic,
+  /// Fit of the ACES reference rendering and sRGB output transforms
+  Aces,
+}
+
+impl std::str::FromStr for ToneMapOperator {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "reinhard" => Ok(ToneMapOperator::Reinhard),
+      "filmic" => Ok(ToneMapOperator::Filmic),
+      "aces" => Ok(ToneMapOperator::Aces),
+      _ => Err(format!(
+        "Unknown tone map operator '{}', expected reinhard, filmic or aces",
+        value
+      )),
+    }
+  }
 }
 
 /// Parameters for different node types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NodeParams {
+  /// Exposure change in stops on scene-linear data
+  Exposure {
+    stops: f32,
+  },
   Brightness {
     value: f32,
   },
@@ -62,9 +367,18 @@ pub enum NodeParams {
   Saturation {
     value: f32,
   },
+  /// Saturation boost (-1.0 to 1.0) that favours muted colours and spares skin tones
+  Vibrance {
+    amount: f32,
+  },
   Hue {
     value: f32,
   },
+  /// Hue shift, saturation and luminance changes of eight hue bands
+  Hsl {
+    #[serde(default)]
+    bands: HslBands,
+  },
   Gamma {
     value: f32,
   },
@@ -74,44 +388,229 @@ pub enum NodeParams {
     output_black: f32,
     output_white: f32,
   },
+  /// Control points of the master curve, applied first, and the per-channel curves.
+  /// A curve with fewer than two points leaves its channel unchanged.
+  Curves {
+    #[serde(default)]
+    master: CurvePoints,
+    #[serde(default)]
+    red: CurvePoints,
+    #[serde(default)]
+    green: CurvePoints,
+    #[serde(default)]
+    blue: CurvePoints,
+  },
+  /// Per-channel gains added to 1.0 in the shadows, midtones and highlights, so zero
+  /// leaves the image unchanged
   ColorBalance {
     shadows: [f32; 3],    // RGB
     midtones: [f32; 3],   // RGB
     highlights: [f32; 3], // RGB
   },
+  /// Hue, saturation and luminance wheels for the shadows, midtones and highlights.
+  /// `balance` (-1.0 to 1.0) moves the split between the ranges, `blending` (0.0 to
+  /// 1.0) sets how much they overlap.
+  ColorGrading {
+    #[serde(default)]
+    shadows: GradingWheel,
+    #[serde(default)]
+    midtones: GradingWheel,
+    #[serde(default)]
+    highlights: GradingWheel,
+    #[serde(default)]
+    balance: f32,
+    #[serde(default = "default_blending")]
+    blending: f32,
+  },
+  /// Balance for the colour of the light. A `neutral_point` (fractions of width and
+  /// height) is made grey, otherwise `auto_adjust` estimates the light with `method`.
+  /// Manual balance takes the temperature of the light in `kelvin`, or the relative
+  /// `temperature`, and shifts green by `tint`.
   WhiteBalance {
     auto_adjust: bool,
     temperature: f32, // Color temperature adjustment (-1.0 to 1.0)
     tint: f32,        // Tint adjustment (-1.0 to 1.0)
+    #[serde(default)]
+    method: WhiteBalanceMethod,
+    #[serde(default)]
+    neutral_point: Option<[f32; 2]>,
+    #[serde(default)]
+    kelvin: Option<f32>,
+    /// Temperature in Kelvin the input is balanced for, the pipeline's as-shot
+    /// temperature when unset
+    #[serde(default)]
+    as_shot_kelvin: Option<f32>,
+  },
+  /// A `.cube` or `.3dl` file, blended with the input by `intensity` (0.0 to 1.0)
+  #[serde(rename = "lut3d")]
+  Lut3D {
+    path: PathBuf,
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+    #[serde(default)]
+    interpolation: LutInterpolation,
+  },
+  /// Maps scene-linear input to linear display values in 0..1. `white_point` is the
+  /// scene value that becomes display white, `contrast` scales around middle grey.
+  ToneMap {
+    operator: ToneMapOperator,
+    white_point: f32,
+    contrast: f32,
   },
+  /// Edge-preserving noise reduction within `radius` pixels. `luminance` and `chroma`
+  /// (0.0 to 1.0) set how large differences in brightness and colour are smoothed.
+  Denoise {
+    luminance: f32,
+    chroma: f32,
+    radius: f32,
+  },
+  /// Gaussian blur whose `radius` in pixels spans two standard deviations. A radius of
+  /// zero leaves the image unchanged.
   Blur {
     radius: f32,
   },
   Sharpen {
     amount: f32,
   },
+  /// Adds `amount` times the difference to a blur of `radius` pixels. Differences in
+  /// luminance below `threshold` (0.0 to 1.0, on encoded values) are left alone.
+  UnsharpMask {
+    amount: f32,
+    radius: f32,
+    threshold: f32,
+  },
+  /// Midtone local contrast against a blur of `radius` pixels, softening for negative
+  /// `amount`. Large radii act as clarity, small ones bring out texture.
+  Clarity {
+    amount: f32,
+    radius: f32,
+  },
   Noise {
     amount: f32,
     seed: u32,
   },
+  /// Changes exposure towards the corners by up to two stops times `amount`, darkening
+  /// for negative values. `midpoint` and `feather` are fractions of the distance from
+  /// the centre to the corners, so the vignette looks the same at any resolution.
+  /// `roundness` runs from a rounded rectangle (-1.0) over an ellipse following the
+  /// frame (0.0) to a circle (1.0).
+  Vignette {
+    amount: f32,
+    midpoint: f32,
+    roundness: f32,
+    feather: f32,
+  },
+  /// Monochrome film grain, strongest in the midtones. `size` is in thousandths of the
+  /// shorter image side, so previews and full resolution exports show the same grain.
+  Grain {
+    amount: f32,
+    size: f32,
+    seed: u32,
+  },
+  /// Removes haze for positive `amount` (up to 1.0) and adds an even haze for negative
+  /// values.
+  Dehaze {
+    amount: f32,
+  },
   Resize {
     width: Option<u32>,
     height: Option<u32>,
   },
+  /// Cuts out the region of `width` by `height` pixels whose top left corner is at `x`,
+  /// `y`. The region is clamped to the input image.
   Crop {
     x: u32,
     y: u32,
     width: u32,
     height: u32,
   },
+  /// Turns the image clockwise by `quarter_turns` times 90 degrees
+  Rotate {
+    quarter_turns: u32,
+  },
+  /// Mirrors the image left to right and top to bottom
+  Flip {
+    horizontal: bool,
+    vertical: bool,
+  },
+  /// Rotates the image clockwise by `angle` degrees. With `crop` the result is the
+  /// largest rectangle of the original aspect ratio inside the rotated image, otherwise
+  /// the image grows to hold all of it and the corners are transparent.
+  Straighten {
+    angle: f32,
+    #[serde(default = "default_crop")]
+    crop: bool,
+    #[serde(default)]
+    resampling: Resampling,
+  },
+  /// Maps the quadrilateral `corners`, given as fractions of width and height in the
+  /// order top left, top right, bottom right, bottom left, onto the whole output image
+  Perspective {
+    corners: [[f32; 2]; 4],
+    #[serde(default)]
+    resampling: Resampling,
+  },
+  /// Blends `image2` over `image1`. With `use_alpha` the blend is also weighted by the
+  /// alpha of `image2`, so an image limited by a `Mask` node only lands inside its mask.
   Mix {
     factor: f32,
+    #[serde(default)]
+    use_alpha: bool,
+  },
+  /// Selects pixels whose perceptual lightness lies between `low` and `high` (0..1),
+  /// fading out over `feather`
+  LuminanceMask {
+    low: f32,
+    high: f32,
+    feather: f32,
+  },
+  /// Selects pixels within `hue_range` degrees of `hue` and inside the saturation and
+  /// lightness ranges. Edges fade over `hue_feather` degrees and `feather` respectively.
+  ColorRangeMask {
+    hue: f32,
+    hue_range: f32,
+    hue_feather: f32,
+    saturation: [f32; 2],
+    luminance: [f32; 2],
+    feather: f32,
+  },
+  /// A bitmap painted by a client, stretched over the input image
+  PaintedMask {
+    bitmap: MaskBitmap,
+  },
+  /// Rises from 0.0 to 1.0 across a line through `center` (fractions of width and
+  /// height), towards `angle` degrees counterclockwise from the right. `feather` is
+  /// the width of the transition relative to the shorter image side.
+  LinearGradient {
+    center: [f32; 2],
+    angle: f32,
+    feather: f32,
+    #[serde(default)]
+    invert: bool,
+  },
+  /// 1.0 inside an ellipse around `center` with `radius` relative to the shorter image
+  /// side, rotated by `angle` degrees. The edge fades over `feather`, a fraction of the
+  /// radius.
+  RadialGradient {
+    center: [f32; 2],
+    radius: [f32; 2],
+    angle: f32,
+    feather: f32,
+    #[serde(default)]
+    invert: bool,
+  },
+  /// A user-defined shader added with `ImagePipeline::add_custom_shader`. `params` sets
+  /// members of its parameter block by name, members left out are 0.0.
+  Custom {
+    shader: String,
+    #[serde(default)]
+    params: BTreeMap<String, f32>,
   },
   None,
 }
 
 /// Represents a connection between two nodes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Connection {
   pub from_node: usize,
   pub to_node: usize,
@@ -120,28 +619,67 @@ pub struct Connection {
 }
 
 /// A single processing node in the pipeline
-#[derive(Debug, Clone)]
+///
+/// Inputs and outputs follow from the node type and are not serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessingNode {
   pub id: usize,
   pub name: String,
+  #[serde(rename = "type")]
   pub node_type: NodeType,
   pub params: NodeParams,
+  #[serde(default = "default_enabled")]
   pub enabled: bool,
+  #[serde(skip)]
   pub inputs: Vec<String>,
+  #[serde(skip)]
   pub outputs: Vec<String>,
 }
 
+fn default_enabled() -> bool {
+  true
+}
+
+fn default_intensity() -> f32 {
+  1.0
+}
+
+fn default_crop() -> bool {
+  true
+}
+
+fn default_blending() -> f32 {
+  0.5
+}
+
+/// Half width in pixels of the kernel of a blur with `radius`, covering three standard
+/// deviations
+pub fn blur_extent(radius: f32) -> u32 {
+  (radius.max(0.0) * 1.5).ceil() as u32
+}
+
+/// Half width in pixels of the window of a denoise node with `radius`
+pub fn denoise_extent(radius: f32) -> u32 {
+  radius.max(0.0).ceil() as u32
+}
+
+impl NodeParams {
+  /// The mask generator node these parameters belong to, if any
+  pub fn mask_generator(&self) -> Option<NodeType> {
+    match self {
+      NodeParams::LuminanceMask { .. } => Some(NodeType::LuminanceMask),
+      NodeParams::ColorRangeMask { .. } => Some(NodeType::ColorRangeMask),
+      NodeParams::PaintedMask { .. } => Some(NodeType::PaintedMask),
+      NodeParams::LinearGradient { .. } => Some(NodeType::LinearGradient),
+      NodeParams::RadialGradient { .. } => Some(NodeType::RadialGradient),
+      _ => None,
+    }
+  }
+}
+
 impl ProcessingNode {
   pub fn new(id: usize, name: String, node_type: NodeType) -> Self {
-    let (inputs, outputs) = match node_type {
-      NodeType::ImageInput => (vec![], vec!["image".to_string()]),
-      NodeType::ImageOutput => (vec!["image".to_string()], vec![]),
-      NodeType::Mix => (
-        vec!["image1".to_string(), "image2".to_string()],
-        vec!["image".to_string()],
-      ),
-      _ => (vec!["image".to_string()], vec!["image".to_string()]),
-    };
+    let (inputs, outputs) = Self::ports(&node_type);
 
     Self {
       id,
@@ -154,12 +692,39 @@ impl ProcessingNode {
     }
   }
 
+  /// Names of the inputs and outputs of a node type
+  fn ports(node_type: &NodeType) -> (Vec<String>, Vec<String>) {
+    match node_type {
+      NodeType::ImageInput => (vec![], vec!["image".to_string()]),
+      NodeType::ImageOutput => (vec!["image".to_string()], vec![]),
+      NodeType::Mix => (
+        vec!["image1".to_string(), "image2".to_string()],
+        vec!["image".to_string()],
+      ),
+      NodeType::Mask => (
+        vec!["image".to_string(), "mask".to_string()],
+        vec!["image".to_string()],
+      ),
+      NodeType::LuminanceMask
+      | NodeType::ColorRangeMask
+      | NodeType::PaintedMask
+      | NodeType::LinearGradient
+      | NodeType::RadialGradient => (vec!["image".to_string()], vec!["mask".to_string()]),
+      _ => (vec!["image".to_string()], vec!["image".to_string()]),
+    }
+  }
+
   fn default_params(node_type: &NodeType) -> NodeParams {
     match node_type {
+      NodeType::Exposure => NodeParams::Exposure { stops: 0.0 },
       NodeType::Brightness => NodeParams::Brightness { value: 0.0 },
       NodeType::Contrast => NodeParams::Contrast { value: 1.0 },
       NodeType::Saturation => NodeParams::Saturation { value: 1.0 },
+      NodeType::Vibrance => NodeParams::Vibrance { amount: 0.0 },
       NodeType::Hue => NodeParams::Hue { value: 0.0 },
+      NodeType::Hsl => NodeParams::Hsl {
+        bands: HslBands::default(),
+      },
       NodeType::Gamma => NodeParams::Gamma { value: 1.0 },
       NodeType::Levels => NodeParams::Levels {
         input_black: 0.0,
@@ -167,22 +732,75 @@ impl ProcessingNode {
         output_black: 0.0,
         output_white: 1.0,
       },
+      NodeType::Curves => NodeParams::Curves {
+        master: Vec::new(),
+        red: Vec::new(),
+        green: Vec::new(),
+        blue: Vec::new(),
+      },
       NodeType::ColorBalance => NodeParams::ColorBalance {
-        shadows: [1.0, 1.0, 1.0],
-        midtones: [1.0, 1.0, 1.0],
-        highlights: [1.0, 1.0, 1.0],
+        shadows: [0.0, 0.0, 0.0],
+        midtones: [0.0, 0.0, 0.0],
+        highlights: [0.0, 0.0, 0.0],
+      },
+      NodeType::ColorGrading => NodeParams::ColorGrading {
+        shadows: GradingWheel::default(),
+        midtones: GradingWheel::default(),
+        highlights: GradingWheel::default(),
+        balance: 0.0,
+        blending: default_blending(),
       },
       NodeType::WhiteBalance => NodeParams::WhiteBalance {
         auto_adjust: false,
         temperature: 0.0,
         tint: 0.0,
+        method: WhiteBalanceMethod::default(),
+        neutral_point: None,
+        kelvin: None,
+        as_shot_kelvin: None,
+      },
+      NodeType::Lut3D => NodeParams::Lut3D {
+        path: PathBuf::new(),
+        intensity: 1.0,
+        interpolation: LutInterpolation::Trilinear,
+      },
+      NodeType::ToneMap => NodeParams::ToneMap {
+        operator: ToneMapOperator::Filmic,
+        white_point: 16.0,
+        contrast: 1.0,
+      },
+      NodeType::Denoise => NodeParams::Denoise {
+        luminance: 0.5,
+        chroma: 0.5,
+        radius: 2.0,
       },
       NodeType::Blur => NodeParams::Blur { radius: 1.0 },
       NodeType::Sharpen => NodeParams::Sharpen { amount: 1.0 },
+      NodeType::UnsharpMask => NodeParams::UnsharpMask {
+        amount: 1.0,
+        radius: 1.0,
+        threshold: 0.0,
+      },
+      NodeType::Clarity => NodeParams::Clarity {
+        amount: 0.3,
+        radius: 40.0,
+      },
       NodeType::Noise => NodeParams::Noise {
         amount: 0.1,
         seed: 42,
       },
+      NodeType::Vignette => NodeParams::Vignette {
+        amount: -0.5,
+        midpoint: 0.5,
+        roundness: 0.0,
+        feather: 0.5,
+      },
+      NodeType::Grain => NodeParams::Grain {
+        amount: 0.3,
+        size: 1.0,
+        seed: 42,
+      },
+      NodeType::Dehaze => NodeParams::Dehaze { amount: 0.3 },
       NodeType::Resize => NodeParams::Resize {
         width: None,
         height: None,
@@ -193,7 +811,57 @@ impl ProcessingNode {
         width: 512,
         height: 512,
       },
-      NodeType::Mix => NodeParams::Mix { factor: 0.5 },
+      NodeType::Rotate => NodeParams::Rotate { quarter_turns: 0 },
+      NodeType::Flip => NodeParams::Flip {
+        horizontal: true,
+        vertical: false,
+      },
+      NodeType::Straighten => NodeParams::Straighten {
+        angle: 0.0,
+        crop: true,
+        resampling: Resampling::Bilinear,
+      },
+      NodeType::Perspective => NodeParams::Perspective {
+        corners: geometry::FULL_FRAME,
+        resampling: Resampling::Bilinear,
+      },
+      NodeType::Mix => NodeParams::Mix {
+        factor: 0.5,
+        use_alpha: false,
+      },
+      NodeType::LuminanceMask => NodeParams::LuminanceMask {
+        low: 0.5,
+        high: 1.0,
+        feather: 0.1,
+      },
+      NodeType::ColorRangeMask => NodeParams::ColorRangeMask {
+        hue: 0.0,
+        hue_range: 30.0,
+        hue_feather: 20.0,
+        saturation: [0.1, 1.0],
+        luminance: [0.0, 1.0],
+        feather: 0.1,
+      },
+      NodeType::PaintedMask => NodeParams::PaintedMask {
+        bitmap: MaskBitmap::default(),
+      },
+      NodeType::LinearGradient => NodeParams::LinearGradient {
+        center: [0.5, 0.5],
+        angle: 90.0,
+        feather: 0.5,
+        invert: false,
+      },
+      NodeType::RadialGradient => NodeParams::RadialGradient {
+        center: [0.5, 0.5],
+        radius: [0.5, 0.4],
+        angle: 0.0,
+        feather: 0.5,
+        invert: false,
+      },
+      NodeType::Custom => NodeParams::Custom {
+        shader: String::new(),
+        params: BTreeMap::new(),
+      },
       _ => NodeParams::None,
     }
   }
@@ -209,27 +877,83 @@ impl ProcessingNode {
   }
 }
 
+/// Lookup tables bound for a single dispatch: a 2D table at binding 4, holding baked
+/// curves or a painted mask, and a 3D table at binding 5. Nodes without a table bind
+/// single texel placeholders.
+#[derive(Clone)]
+struct LookupTables {
+  table: TextureView,
+  cube: TextureView,
+}
+
+/// What a node dispatch knows of the whole input image. Statistics are measured once
+/// per node, so every invocation and every tile uses the same values.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct NodeContext {
+  /// Colour measured from the input: the gains of a white balance or the haze colour
+  measured: Option<[f32; 3]>,
+  /// Pixel of the whole image the dispatched region starts at
+  origin: (u32, u32),
+  /// Size of the whole image
+  size: (u32, u32),
+}
+
+/// Part of an image processed on its own, as x, y, width and height
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Tile {
+  /// Region of the result the tile writes
+  region: [u32; 4],
+  /// Region of the input the tile reads: `region` grown by the node's footprint
+  padded: [u32; 4],
+}
+
+/// How `process_buffered` runs a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dispatch {
+  /// The whole image in a single texture
+  Whole,
+  /// The image split into tiles, dispatched one after another
+  Tiled,
+}
+
 /// The main image processing pipeline
 pub struct ImagePipeline {
   pub nodes: HashMap<usize, ProcessingNode>,
   pub connections: Vec<Connection>,
   pub input_node_id: Option<usize>,
   pub output_node_id: Option<usize>,
+  /// Node returned from `process` in place of the output, e.g. to inspect its scopes
+  target_node_id: Option<usize>,
   next_node_id: usize,
+  backend: Backend,
+  result_cache: Option<ResultCache>,
+  input_key: Option<u64>,
+  input_space: ColorSpace,
+  working_space: ColorSpace,
+  output_space: ColorSpace,
+  /// Temperature in Kelvin the input image is balanced for
+  as_shot_kelvin: f32,
 
   // GPU resources (optional, set when initialized)
   device: Option<Device>,
   queue: Option<Queue>,
   pipelines: HashMap<NodeType, ComputePipeline>,
+  /// Shaders of `Custom` nodes and their pipelines, by shader name
+  custom_shaders: HashMap<String, CustomShader>,
+  custom_pipelines: HashMap<String, ComputePipeline>,
+  bind_group_layout: Option<wgpu::BindGroupLayout>,
+  lookup_table_placeholders: Option<LookupTables>,
   textures: HashMap<usize, Texture>,
   texture_views: HashMap<usize, TextureView>,
+  /// Staging buffer size above which nodes are processed in tiles
+  tile_threshold: u64,
 }
 
 impl ImagePipeline {
   /// Calculate the maximum square image dimension that can be processed without tiling.
   ///
   /// Uses binary search to find the largest dimension where the required staging buffer
-  /// would not exceed MAX_BUFFER_SIZE (256MB).
+  /// would not exceed the tiling threshold, MAX_BUFFER_SIZE (256MB) by default.
   fn calculate_max_processable_dimension(&self) -> u32 {
     let bytes_per_row = |width: u32| self.aligned_bytes_per_row(width);
     let mut max_width = 1;
@@ -239,7 +963,7 @@ impl ImagePipeline {
     while high - max_width > 1 {
       let mid = (max_width + high) / 2;
       let buffer_size = bytes_per_row(mid) as u64 * mid as u64;
-      if buffer_size <= MAX_BUFFER_SIZE {
+      if buffer_size <= self.tile_threshold {
         max_width = mid;
       } else {
         high = mid;
@@ -250,12 +974,12 @@ impl ImagePipeline {
 
   /// Determine if an image of given dimensions requires tiled processing.
   ///
-  /// Returns true if the staging buffer for this image would exceed the
-  /// WebGPU buffer size limit of 256MB.
+  /// Returns true if the staging buffer for this image would exceed the tiling
+  /// threshold, by default the WebGPU buffer size limit of 256MB.
   fn needs_tiling(&self, width: u32, height: u32) -> bool {
     let aligned_bytes_per_row = self.aligned_bytes_per_row(width) as u64;
     let buffer_size = aligned_bytes_per_row * height as u64;
-    buffer_size > MAX_BUFFER_SIZE
+    buffer_size > self.tile_threshold
   }
   /// Calculate aligned bytes per row for texture operations
   fn aligned_bytes_per_row(&self, width: u32) -> u32 {
@@ -270,13 +994,97 @@ impl ImagePipeline {
       connections: Vec::new(),
       input_node_id: None,
       output_node_id: None,
+      target_node_id: None,
       next_node_id: 0,
+      backend: Backend::default(),
+      result_cache: None,
+      input_key: None,
+      input_space: ColorSpace::LinearSrgb,
+      working_space: ColorSpace::LinearSrgb,
+      output_space: ColorSpace::LinearSrgb,
+      as_shot_kelvin: white_balance::D65_KELVIN,
       device: None,
       queue: None,
       pipelines: HashMap::new(),
+      custom_shaders: HashMap::new(),
+      custom_pipelines: HashMap::new(),
+      bind_group_layout: None,
+      lookup_table_placeholders: None,
       textures: HashMap::new(),
       texture_views: HashMap::new(),
+      tile_threshold: MAX_BUFFER_SIZE,
+    }
+  }
+
+  /// Select where nodes are executed
+  pub fn set_backend(&mut self, backend: Backend) {
+    self.backend = backend;
+  }
+
+  pub fn backend(&self) -> Backend {
+    self.backend
+  }
+
+  /// Attach a cache so unchanged nodes reuse their results from earlier runs
+  pub fn set_result_cache(&mut self, cache: ResultCache) {
+    self.result_cache = Some(cache);
+  }
+
+  /// Detach the result cache, e.g. to hand it to the next pipeline
+  pub fn take_result_cache(&mut self) -> Option<ResultCache> {
+    self.result_cache.take()
+  }
+
+  /// Identify the input image by a caller-provided key, such as a hash of the source
+  /// file, instead of hashing the pixel data on every run
+  pub fn set_input_key(&mut self, key: u64) {
+    self.input_key = Some(key);
+  }
+
+  /// Colour space of the input image, converted into the working space before processing
+  pub fn set_input_space(&mut self, space: ColorSpace) {
+    self.input_space = space;
+  }
+
+  /// Temperature in Kelvin the input image is balanced for, such as the as-shot white
+  /// balance of a raw file, which white balance nodes in Kelvin start from
+  pub fn set_as_shot_kelvin(&mut self, kelvin: f32) {
+    self.as_shot_kelvin = kelvin;
+  }
+
+  /// Linear colour space the nodes work in, linear sRGB by default
+  pub fn set_working_space(&mut self, space: ColorSpace) -> Result<(), String> {
+    if !space.is_linear() {
+      return Err(format!("Working space {} is not linear", space));
+    }
+    self.working_space = space;
+    Ok(())
+  }
+
+  /// Colour space the processed image is converted to
+  pub fn set_output_space(&mut self, space: ColorSpace) {
+    self.output_space = space;
+  }
+
+  /// Return the result of `node_id` from `process` instead of the pipeline output.
+  /// Nodes that only feed later nodes are then left out of the run.
+  pub fn set_target_node(&mut self, node_id: usize) -> Result<(), String> {
+    if !self.nodes.contains_key(&node_id) {
+      return Err(format!("Node {} does not exist", node_id));
+    }
+    self.target_node_id = Some(node_id);
+    Ok(())
+  }
+
+  /// Make a shader available to `Custom` nodes, replacing one of the same name
+  pub fn add_custom_shader(&mut self, shader: CustomShader) {
+    if let (Some(device), Some(bind_group_layout)) =
+      (&self.device, &self.bind_group_layout)
+    {
+      let pipeline = Self::create_custom_pipeline(device, bind_group_layout, &shader);
+      self.custom_pipelines.insert(shader.name.clone(), pipeline);
     }
+    self.custom_shaders.insert(shader.name.clone(), shader);
   }
 
   /// Initialize GPU resources
@@ -285,85 +1093,43 @@ impl ImagePipeline {
     self.queue = Some(queue);
 
     // Store device reference to avoid borrow checker issues
-    if let Some(device) = &self.device {
-      self.pipelines = self.create_compute_pipelines(device);
+    if let (Some(device), Some(queue)) = (&self.device, &self.queue) {
+      // The layout is shared by every shader and all dispatches of this pipeline
+      let bind_group_layout =
+        Self::create_bind_group_layout(device, "Image Processing Bind Group Layout");
+      self.pipelines = self.create_compute_pipelines(device, &bind_group_layout);
+      self.custom_pipelines = self
+        .custom_shaders
+        .values()
+        .map(|shader| {
+          let pipeline = Self::create_custom_pipeline(device, &bind_group_layout, shader);
+          (shader.name.clone(), pipeline)
+        })
+        .collect();
+      self.bind_group_layout = Some(bind_group_layout);
+      self.lookup_table_placeholders = Some(LookupTables {
+        table: Self::upload_lookup_table(device, queue, &[[0.0; 4]], 1),
+        cube: Self::upload_lut_3d(device, queue, 1, &[[0.0; 3]]),
+      });
     }
   }
 
   fn create_compute_pipelines(
     &self,
     device: &Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
   ) -> HashMap<NodeType, ComputePipeline> {
     let mut pipelines = HashMap::new();
-    // Create bind group layout for image processing shaders
-    let bind_group_layout =
-      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("Image Processing Bind Group Layout"),
-        entries: &[
-          // Input texture
-          wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::Texture {
-              sample_type: wgpu::TextureSampleType::Float { filterable: false },
-              view_dimension: wgpu::TextureViewDimension::D2,
-              multisampled: false,
-            },
-            count: None,
-          },
-          // Output texture
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              access: wgpu::StorageTextureAccess::WriteOnly,
-              format: TEXTURE_FORMAT,
-              view_dimension: wgpu::TextureViewDimension::D2,
-            },
-            count: None,
-          },
-          // Parameters uniform buffer
-          wgpu::BindGroupLayoutEntry {
-            binding: 2,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::Buffer {
-              ty: wgpu::BufferBindingType::Uniform,
-              has_dynamic_offset: false,
-              min_binding_size: None,
-            },
-            count: None,
-          },
-        ],
-      });
 
     let pipeline_layout =
       device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Image Processing Pipeline Layout"),
-        bind_group_layouts: &[&bind_group_layout],
+        bind_group_layouts: &[bind_group_layout],
         push_constant_ranges: &[],
       });
 
     // Initialize pipelines for each node type that needs GPU processing
-    let node_types_to_initialize = [
-      NodeType::Brightness,
-      NodeType::Contrast,
-      NodeType::Saturation,
-      NodeType::Hue,
-      NodeType::Gamma,
-      NodeType::Levels,
-      NodeType::ColorBalance,
-      NodeType::WhiteBalance,
-      NodeType::Blur,
-      NodeType::Sharpen,
-      NodeType::Noise,
-      NodeType::Resize,
-      NodeType::Crop,
-      NodeType::Mix,
-      NodeType::Mask,
-      NodeType::Invert,
-    ];
-
-    for node_type in &node_types_to_initialize {
+    for node_type in NodeType::ALL {
       if let Some(shader_source) = self.get_shader_source_for_node_type(node_type) {
         let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
           label: Some(&format!("{:?} Shader", node_type)),
@@ -386,29 +1152,190 @@ impl ImagePipeline {
     pipelines
   }
 
+  /// Compile the pipeline of a custom shader, which was validated when it was loaded
+  fn create_custom_pipeline(
+    device: &Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    shader: &CustomShader,
+  ) -> ComputePipeline {
+    let pipeline_layout =
+      device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Custom Pipeline Layout"),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some(&format!("Custom {} Shader", shader.name)),
+      source: wgpu::ShaderSource::Wgsl(shader.source.as_str().into()),
+    });
+
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some(&format!("Custom {} Pipeline", shader.name)),
+      layout: Some(&pipeline_layout),
+      module: &shader_module,
+      entry_point: Some("main"),
+      compilation_options: Default::default(),
+      cache: None,
+    })
+  }
+
+  /// Pipeline running a node, the pipeline of its shader for `Custom` nodes
+  fn pipeline_for(
+    &self,
+    node_type: &NodeType,
+    params: &NodeParams,
+  ) -> Option<&ComputePipeline> {
+    match params {
+      NodeParams::Custom { shader, .. } => self.custom_pipelines.get(shader),
+      _ => self.pipelines.get(node_type),
+    }
+  }
+
+  /// Bind group layout shared by all image processing shaders.
+  ///
+  /// Binding 3 holds a secondary input texture for two-input nodes such as `Mix`.
+  /// Single-input nodes bind their primary input there as well. Binding 4 holds the 2D
+  /// lookup table of `Curves` and `PaintedMask` nodes, binding 5 the 3D table of `Lut3D`
+  /// nodes.
+  fn create_bind_group_layout(device: &Device, label: &str) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some(label),
+      entries: &[
+        // Input texture
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        // Output texture
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: TEXTURE_FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D2,
+          },
+          count: None,
+        },
+        // Parameters uniform buffer
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        // Secondary input texture
+        wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        // Lookup table texture
+        wgpu::BindGroupLayoutEntry {
+          binding: 4,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        // 3D lookup table texture
+        wgpu::BindGroupLayoutEntry {
+          binding: 5,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D3,
+            multisampled: false,
+          },
+          count: None,
+        },
+      ],
+    })
+  }
+
   fn get_shader_source_for_node_type(&self, node_type: &NodeType) -> Option<String> {
     let base_shader = match node_type {
+      NodeType::Exposure => Some(include_str!("shaders/exposure.wgsl")),
       NodeType::Brightness => Some(include_str!("shaders/brightness.wgsl")),
       NodeType::Contrast => Some(include_str!("shaders/contrast.wgsl")),
       NodeType::Saturation => Some(include_str!("shaders/saturation.wgsl")),
+      NodeType::Vibrance => Some(include_str!("shaders/vibrance.wgsl")),
       NodeType::Hue => Some(include_str!("shaders/hue.wgsl")),
+      NodeType::Hsl => Some(include_str!("shaders/hsl.wgsl")),
       NodeType::Gamma => Some(include_str!("shaders/gamma.wgsl")),
       NodeType::Levels => Some(include_str!("shaders/levels.wgsl")),
+      NodeType::Curves => Some(include_str!("shaders/curves.wgsl")),
       NodeType::ColorBalance => Some(include_str!("shaders/color_balance.wgsl")),
+      NodeType::ColorGrading => Some(include_str!("shaders/color_grading.wgsl")),
       NodeType::WhiteBalance => Some(include_str!("shaders/white_balance.wgsl")),
+      NodeType::Lut3D => Some(include_str!("shaders/lut3d.wgsl")),
+      NodeType::ToneMap => Some(include_str!("shaders/tone_map.wgsl")),
+      NodeType::Denoise => Some(include_str!("shaders/denoise.wgsl")),
       NodeType::Blur => Some(include_str!("shaders/blur.wgsl")),
       NodeType::Sharpen => Some(include_str!("shaders/sharpen.wgsl")),
+      NodeType::UnsharpMask => Some(include_str!("shaders/unsharp_mask.wgsl")),
+      NodeType::Clarity => Some(include_str!("shaders/clarity.wgsl")),
       NodeType::Noise => Some(include_str!("shaders/noise.wgsl")),
+      NodeType::Vignette => Some(include_str!("shaders/vignette.wgsl")),
+      NodeType::Grain => Some(include_str!("shaders/grain.wgsl")),
+      NodeType::Dehaze => Some(include_str!("shaders/dehaze.wgsl")),
       NodeType::Resize => Some(include_str!("shaders/resize.wgsl")),
       NodeType::Crop => Some(include_str!("shaders/crop.wgsl")),
+      NodeType::Rotate => Some(include_str!("shaders/rotate.wgsl")),
+      NodeType::Flip => Some(include_str!("shaders/flip.wgsl")),
+      NodeType::Straighten => Some(include_str!("shaders/straighten.wgsl")),
+      NodeType::Perspective => Some(include_str!("shaders/perspective.wgsl")),
       NodeType::Mix => Some(include_str!("shaders/mix.wgsl")),
       NodeType::Mask => Some(include_str!("shaders/mask.wgsl")),
       NodeType::Invert => Some(include_str!("shaders/invert.wgsl")),
+      NodeType::LuminanceMask => Some(include_str!("shaders/luminance_mask.wgsl")),
+      NodeType::ColorRangeMask => Some(include_str!("shaders/color_range_mask.wgsl")),
+      NodeType::PaintedMask => Some(include_str!("shaders/painted_mask.wgsl")),
+      NodeType::LinearGradient => Some(include_str!("shaders/linear_gradient.wgsl")),
+      NodeType::RadialGradient => Some(include_str!("shaders/radial_gradient.wgsl")),
       _ => None,
     }?;
 
+    // Transforms read their input through the shared resampling filters
+    let base_shader = match node_type {
+      NodeType::Straighten | NodeType::Perspective => {
+        format!("{}\n{}", base_shader, include_str!("shaders/resample.wgsl"))
+      }
+      _ => base_shader.to_string(),
+    };
+
+    // Encoded nodes read their inputs through the sRGB curve and store linear results
+    let shader = match node_type.encoding() {
+      ColorEncoding::Linear => base_shader,
+      ColorEncoding::Encoded => {
+        let wrapped = base_shader
+          .replace("textureLoad(input_texture,", "load_encoded(input_texture,")
+          .replace("textureLoad(secondary_texture,", "load_encoded(secondary_texture,")
+          .replace("textureStore(output_texture,", "store_decoded(");
+        format!("{}\n{}", wrapped, include_str!("shaders/encoded.wgsl"))
+      }
+    };
+
     // Replace the hardcoded texture format with the dynamic one
-    let shader_with_format = base_shader.replace("rgba32float", SHADER_FORMAT);
+    let shader_with_format = shader.replace("rgba32float", SHADER_FORMAT);
     Some(shader_with_format)
   }
 
@@ -430,8 +1357,44 @@ impl ImagePipeline {
     id
   }
 
-  /// Remove a node from the pipeline
-  pub fn remove_node(&mut self, node_id: usize) -> Result<(), String> {
+  /// Insert a fully specified node, keeping its id.
+  ///
+  /// Used when restoring a saved graph. Inputs and outputs are reset from the node
+  /// type and the parameters must belong to that type.
+  pub fn insert_node(&mut self, mut node: ProcessingNode) -> Result<usize, String> {
+    let id = node.id;
+    if self.nodes.contains_key(&id) {
+      return Err(format!("Node {} already exists", id));
+    }
+    if std::mem::discriminant(&node.params)
+      != std::mem::discriminant(&ProcessingNode::default_params(&node.node_type))
+    {
+      return Err(format!(
+        "Parameters of node {} do not match its type {:?}",
+        id, node.node_type
+      ));
+    }
+
+    // New nodes get ids after this one, so the largest id is left unused
+    let next_id = id
+      .checked_add(1)
+      .ok_or_else(|| format!("Node id {} is too large", id))?;
+    (node.inputs, node.outputs) = ProcessingNode::ports(&node.node_type);
+    self.next_node_id = self.next_node_id.max(next_id);
+
+    // Set input/output node references
+    match node.node_type {
+      NodeType::ImageInput => self.input_node_id = Some(id),
+      NodeType::ImageOutput => self.output_node_id = Some(id),
+      _ => {}
+    }
+
+    self.nodes.insert(id, node);
+    Ok(id)
+  }
+
+  /// Remove a node from the pipeline
+  pub fn remove_node(&mut self, node_id: usize) -> Result<(), String> {
     if !self.nodes.contains_key(&node_id) {
       return Err(format!("Node {} does not exist", node_id));
     }
@@ -448,6 +1411,9 @@ impl ImagePipeline {
     if self.output_node_id == Some(node_id) {
       self.output_node_id = None;
     }
+    if self.target_node_id == Some(node_id) {
+      self.target_node_id = None;
+    }
 
     self.nodes.remove(&node_id);
     Ok(())
@@ -576,231 +1542,76 @@ impl ImagePipeline {
   }
 
   /// Process the entire pipeline
+  ///
+  /// Nodes run in topological order and every named input is routed from the output
+  /// of the node connected to it. Inputs without a connection fall back to the
+  /// pipeline input image.
+  ///
+  /// Intermediate results stay in GPU textures and only the final node is read back.
+  /// Graphs containing images larger than the device texture size limit are instead
+  /// processed node by node, tiling each node through CPU memory.
+  ///
+  /// The CPU backend is used when selected, or when no GPU device was initialized.
+  ///
+  /// With a result cache attached, nodes whose settings and inputs are unchanged
+  /// since an earlier run reuse their cached output instead of being processed.
+  ///
+  /// The input image is converted from the input colour space into the working space
+  /// before the graph runs, and the result into the output space.
   pub async fn process(
     &mut self,
-    input_data: Vec<u8>,
+    mut input_data: Vec<u8>,
     dimensions: (u32, u32),
   ) -> Result<(Vec<u8>, (u32, u32)), String> {
-    let execution_order = self.get_execution_order()?;
-
-    if let (Some(device), Some(queue)) = (self.device.as_ref(), self.queue.as_ref()) {
-      // Create input texture and upload data
-      let mut current_data = input_data.clone();
-      let mut current_dimensions = dimensions;
-
-      log::info!("Processing pipeline with {} nodes", execution_order.len());
-
-      for &node_id in &execution_order {
-        if let Some(node) = self.nodes.get(&node_id) {
-          if node.enabled {
-            log::info!("Processing node: {} ({})", node.name, node.id);
-
-            // Skip ImageInput and ImageOutput nodes as they don't need GPU processing
-            if matches!(node.node_type, NodeType::ImageInput | NodeType::ImageOutput) {
-              continue;
-            }
-
-            // Process the node if we have a pipeline for it
-            if let Some(pipeline) = self.pipelines.get(&node.node_type) {
-              let (width, height) = current_dimensions;
-              if self.needs_tiling(width, height)
-                && !matches!(node.node_type, NodeType::Resize)
-              {
-                log::info!(
-                  "Using tiled processing for large image: {}x{}",
-                  width,
-                  height
-                );
-                current_data = self
-                  .process_node_tiled(
-                    device,
-                    queue,
-                    pipeline,
-                    &node.node_type,
-                    &node.params,
-                    current_data,
-                    current_dimensions,
-                  )
-                  .await?;
-              } else {
-                let (processed_data, new_dimensions) = self
-                  .process_node_with_dimensions(
-                    device,
-                    queue,
-                    pipeline,
-                    &node.node_type,
-                    &node.params,
-                    current_data,
-                    current_dimensions,
-                  )
-                  .await?;
-                current_data = processed_data;
-                current_dimensions = new_dimensions;
-              }
-            } else {
-              log::warn!("No pipeline found for node type: {:?}", node.node_type);
-            }
-          }
-        }
-      }
-
-      Ok((current_data, current_dimensions))
-    } else {
-      Err("GPU resources not initialized".to_string())
-    }
+    color::convert_pixels(&mut input_data, self.input_space, self.working_space);
+    let (mut output_data, output_dimensions) =
+      self.process_graph(input_data, dimensions).await?;
+    color::convert_pixels(&mut output_data, self.working_space, self.output_space);
+    Ok((output_data, output_dimensions))
   }
 
-  async fn process_node_with_dimensions(
+  /// Count an image, usually a result of `process`, into its histogram, waveform and
+  /// vectorscope.
+  ///
+  /// Runs `shaders/scopes.wgsl` when a GPU is initialized and the image fits into a
+  /// texture, and counts on the CPU otherwise.
+  pub async fn analyze(
     &self,
-    device: &Device,
-    queue: &Queue,
-    pipeline: &ComputePipeline,
-    node_type: &NodeType,
-    params: &NodeParams,
-    input_data: Vec<u8>,
+    data: &[u8],
     dimensions: (u32, u32),
-  ) -> Result<(Vec<u8>, (u32, u32)), String> {
-    // Handle resize specially
-    if let NodeType::Resize = node_type {
-      return self
-        .process_resize_node(device, queue, pipeline, params, input_data, dimensions)
-        .await;
+    waveform_columns: u32,
+  ) -> Result<Scopes, String> {
+    let (Backend::Gpu, Some(device), Some(queue)) =
+      (self.backend, &self.device, &self.queue)
+    else {
+      return Ok(Scopes::from_pixels(data, dimensions, waveform_columns));
+    };
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    if dimensions.0 > max_dimension || dimensions.1 > max_dimension {
+      return Ok(Scopes::from_pixels(data, dimensions, waveform_columns));
     }
 
-    // For other nodes, call the original process_node method and return same dimensions
-    let processed_data = self
-      .process_node(
-        device, queue, pipeline, node_type, params, input_data, dimensions,
-      )
-      .await?;
-    Ok((processed_data, dimensions))
-  }
-
-  async fn process_resize_node(
-    &self,
-    device: &Device,
-    queue: &Queue,
-    pipeline: &ComputePipeline,
-    params: &NodeParams,
-    input_data: Vec<u8>,
-    dimensions: (u32, u32),
-  ) -> Result<(Vec<u8>, (u32, u32)), String> {
-    let (current_width, current_height) = dimensions;
-
-    // Extract resize parameters
-    let (target_width, target_height) =
-      if let NodeParams::Resize { width, height } = params {
-        match (width, height) {
-          (Some(w), Some(h)) => (*w, *h),
-          (Some(w), None) => {
-            // Maintain aspect ratio, set width
-            let aspect_ratio = current_height as f32 / current_width as f32;
-            let h = (*w as f32 * aspect_ratio) as u32;
-            (*w, h)
-          }
-          (None, Some(h)) => {
-            // Maintain aspect ratio, set height
-            let aspect_ratio = current_width as f32 / current_height as f32;
-            let w = (*h as f32 * aspect_ratio) as u32;
-            (w, *h)
-          }
-          (None, None) => return Ok((input_data, dimensions)), // No resize needed
-        }
-      } else {
-        return Err("Invalid parameters for resize node".to_string());
-      };
-
-    log::info!(
-      "Resizing image from {}x{} to {}x{}",
-      current_width,
-      current_height,
-      target_width,
-      target_height
-    );
-
-    // Create input texture
-    let input_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Resize Input Texture"),
-      size: wgpu::Extent3d {
-        width: current_width,
-        height: current_height,
-        depth_or_array_layers: 1,
-      },
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: TEXTURE_FORMAT,
-      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-      view_formats: &[],
-    });
-
-    // Create output texture with new dimensions
-    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Resize Output Texture"),
-      size: wgpu::Extent3d {
-        width: target_width,
-        height: target_height,
-        depth_or_array_layers: 1,
-      },
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: TEXTURE_FORMAT,
-      usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
-      view_formats: &[],
-    });
-
-    // Upload input data to texture
-    queue.write_texture(
-      wgpu::TexelCopyTextureInfo {
-        texture: &input_texture,
-        mip_level: 0,
-        origin: wgpu::Origin3d::ZERO,
-        aspect: wgpu::TextureAspect::All,
-      },
-      &input_data,
-      wgpu::TexelCopyBufferLayout {
-        offset: 0,
-        bytes_per_row: Some(BYTES_PER_PIXEL * current_width),
-        rows_per_image: Some(current_height),
-      },
-      wgpu::Extent3d {
-        width: current_width,
-        height: current_height,
-        depth_or_array_layers: 1,
-      },
-    );
-
-    // Create parameter buffer with target dimensions
-    let param_data = self.serialize_params(params)?;
-    let param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-      label: Some("Resize Parameter Buffer"),
-      contents: &param_data,
-      usage: wgpu::BufferUsages::UNIFORM,
-    });
-
-    // Create bind group layout
     let bind_group_layout =
       device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("Resize Bind Group Layout"),
+        label: Some("Scopes Bind Group Layout"),
         entries: &[
           wgpu::BindGroupLayoutEntry {
             binding: 0,
             visibility: wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::Texture {
-              multisampled: false,
-              view_dimension: wgpu::TextureViewDimension::D2,
               sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
             },
             count: None,
           },
           wgpu::BindGroupLayoutEntry {
             binding: 1,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              access: wgpu::StorageTextureAccess::WriteOnly,
-              format: TEXTURE_FORMAT,
-              view_dimension: wgpu::TextureViewDimension::D2,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: false },
+              has_dynamic_offset: false,
+              min_binding_size: None,
             },
             count: None,
           },
@@ -816,91 +1627,83 @@ impl ImagePipeline {
           },
         ],
       });
+    let pipeline_layout =
+      device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Scopes Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Scopes Shader"),
+      source: wgpu::ShaderSource::Wgsl(include_str!("shaders/scopes.wgsl").into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Scopes Pipeline"),
+      layout: Some(&pipeline_layout),
+      module: &shader_module,
+      entry_point: Some("main"),
+      compilation_options: Default::default(),
+      cache: None,
+    });
 
-    // Create bind group
+    let texture =
+      Self::upload_texture(device, queue, "Scopes Input Texture", data, dimensions);
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let size = (scopes::counts_len(waveform_columns) * 4) as u64;
+    let counts_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Scopes Counts Buffer"),
+      size,
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    });
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Scopes Params Buffer"),
+      contents: &[waveform_columns.to_le_bytes(), [0; 4], [0; 4], [0; 4]].concat(),
+      usage: wgpu::BufferUsages::UNIFORM,
+    });
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      label: Some("Resize Bind Group"),
+      label: Some("Scopes Bind Group"),
       layout: &bind_group_layout,
       entries: &[
         wgpu::BindGroupEntry {
           binding: 0,
-          resource: wgpu::BindingResource::TextureView(
-            &input_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-          ),
+          resource: wgpu::BindingResource::TextureView(&view),
         },
         wgpu::BindGroupEntry {
           binding: 1,
-          resource: wgpu::BindingResource::TextureView(
-            &output_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-          ),
+          resource: counts_buffer.as_entire_binding(),
         },
         wgpu::BindGroupEntry {
           binding: 2,
-          resource: param_buffer.as_entire_binding(),
+          resource: params_buffer.as_entire_binding(),
         },
       ],
     });
-
-    // Create command encoder and compute pass
-    let mut command_encoder =
-      device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("Resize Command Encoder"),
-      });
-
-    {
-      let mut compute_pass =
-        command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-          label: Some("Resize Compute Pass"),
-          timestamp_writes: None,
-        });
-      compute_pass.set_bind_group(0, &bind_group, &[]);
-      compute_pass.set_pipeline(pipeline);
-      compute_pass.dispatch_workgroups(
-        (target_width + 7) / 8,
-        (target_height + 7) / 8,
-        1,
-      );
-    }
-
-    // Read back the result
-    let unpadded_bytes_per_row = target_width * BYTES_PER_PIXEL;
-    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u32;
-    let padded_bytes_per_row = (unpadded_bytes_per_row + align - 1) / align * align;
-    let buffer_size = padded_bytes_per_row * target_height;
-
-    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-      label: Some("Resize Output Buffer"),
-      size: buffer_size as u64,
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Scopes Staging Buffer"),
+      size,
       usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
       mapped_at_creation: false,
     });
 
-    command_encoder.copy_texture_to_buffer(
-      wgpu::TexelCopyTextureInfo {
-        texture: &output_texture,
-        mip_level: 0,
-        origin: wgpu::Origin3d::ZERO,
-        aspect: wgpu::TextureAspect::All,
-      },
-      wgpu::TexelCopyBufferInfo {
-        buffer: &output_buffer,
-        layout: wgpu::TexelCopyBufferLayout {
-          offset: 0,
-          bytes_per_row: Some(padded_bytes_per_row),
-          rows_per_image: Some(target_height),
-        },
-      },
-      wgpu::Extent3d {
-        width: target_width,
-        height: target_height,
-        depth_or_array_layers: 1,
-      },
-    );
-
-    queue.submit(Some(command_encoder.finish()));
+    // Storage buffers start out zeroed, so the counts need no clearing
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Scopes Command Encoder"),
+    });
+    {
+      let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Scopes Pass"),
+        timestamp_writes: None,
+      });
+      compute_pass.set_pipeline(&pipeline);
+      compute_pass.set_bind_group(0, &bind_group, &[]);
+      let (width, height) = dimensions;
+      compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+    }
+    encoder.copy_buffer_to_buffer(&counts_buffer, 0, &staging_buffer, 0, size);
+    queue.submit(Some(encoder.finish()));
 
-    // Map and read the buffer
-    let buffer_slice = output_buffer.slice(..);
+    let buffer_slice = staging_buffer.slice(..);
     let (sender, receiver) = flume::bounded(1);
     buffer_slice.map_async(wgpu::MapMode::Read, move |r| sender.send(r).unwrap());
     device.poll(wgpu::PollType::Wait).unwrap();
@@ -908,224 +1711,1332 @@ impl ImagePipeline {
       .recv_async()
       .await
       .map_err(|e| e.to_string())?
-      .map_err(|e| e.to_string())?;
+      .map_err(|e| format!("Buffer mapping failed: {:?}", e))?;
 
-    // Copy data accounting for row padding
-    let mut result_data =
-      vec![0u8; (target_width * target_height * BYTES_PER_PIXEL) as usize];
-    {
-      let view = buffer_slice.get_mapped_range();
-      for row in 0..target_height {
-        let src_start = (row * padded_bytes_per_row) as usize;
-        let src_end = src_start + (unpadded_bytes_per_row as usize);
-        let dst_start = (row * unpadded_bytes_per_row) as usize;
-        let dst_end = dst_start + (unpadded_bytes_per_row as usize);
-        result_data[dst_start..dst_end].copy_from_slice(&view[src_start..src_end]);
+    let counts = buffer_slice
+      .get_mapped_range()
+      .chunks_exact(4)
+      .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+      .collect();
+    staging_buffer.unmap();
+    Ok(Scopes::from_counts(counts, waveform_columns))
+  }
+
+  /// Run the graph on an image in the working space
+  async fn process_graph(
+    &mut self,
+    input_data: Vec<u8>,
+    dimensions: (u32, u32),
+  ) -> Result<NodeOutput, String> {
+    let execution_order = self.get_execution_order()?;
+    let final_node = self.final_node(&execution_order);
+    self.check_custom_nodes(&execution_order)?;
+
+    let hashes = self.result_cache.is_some().then(|| {
+      let input_hash = self.input_hash(&input_data, dimensions);
+      self.result_hashes(&execution_order, input_hash)
+    });
+
+    let (Backend::Gpu, Some(device), Some(queue)) =
+      (self.backend, self.device.clone(), self.queue.clone())
+    else {
+      if self.backend == Backend::Gpu {
+        log::warn!("GPU resources not initialized, processing on the CPU");
       }
-    }
-    output_buffer.unmap();
+      log::info!(
+        "Processing pipeline with {} nodes on the CPU",
+        execution_order.len()
+      );
+      return self.process_cpu(
+        &execution_order,
+        final_node,
+        input_data,
+        dimensions,
+        hashes.as_ref(),
+      );
+    };
+
+    log::info!("Processing pipeline with {} nodes", execution_order.len());
 
-    Ok((result_data, (target_width, target_height)))
+    let planned = self.plan_dimensions(&execution_order, dimensions)?;
+    let max_dimension = device.limits().max_texture_dimension_2d;
+
+    if planned
+      .values()
+      .all(|&(width, height)| width <= max_dimension && height <= max_dimension)
+    {
+      self
+        .process_resident(
+          &device,
+          &queue,
+          &execution_order,
+          final_node,
+          input_data,
+          dimensions,
+          &planned,
+          hashes.as_ref(),
+        )
+        .await
+    } else {
+      log::info!(
+        "Image exceeds the GPU texture size limit of {}, processing node by node",
+        max_dimension
+      );
+      self
+        .process_buffered(
+          &device,
+          &queue,
+          &execution_order,
+          final_node,
+          input_data,
+          dimensions,
+        )
+        .await
+    }
   }
 
-  async fn process_node(
-    &self,
+  /// Run the graph with every intermediate image kept in a GPU texture.
+  ///
+  /// Node results live in `textures`/`texture_views` until their last consumer has
+  /// been recorded. All passes go into a single command encoder and only the texture
+  /// of the final node is copied back to CPU memory.
+  async fn process_resident(
+    &mut self,
     device: &Device,
     queue: &Queue,
-    pipeline: &ComputePipeline,
-    _node_type: &NodeType,
-    params: &NodeParams,
+    execution_order: &[usize],
+    final_node: Option<usize>,
     input_data: Vec<u8>,
     dimensions: (u32, u32),
-  ) -> Result<Vec<u8>, String> {
-    let (width, height) = dimensions;
-
-    log::info!("Texture dimensions: {:?}", dimensions);
+    planned: &HashMap<Option<usize>, (u32, u32)>,
+    hashes: Option<&HashMap<Option<usize>, u64>>,
+  ) -> Result<NodeOutput, String> {
+    let Some(final_node) = final_node else {
+      return Ok((input_data, dimensions));
+    };
 
-    // Create input texture
-    let input_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Input Texture"),
-      size: wgpu::Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-      },
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: TEXTURE_FORMAT,
-      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-      view_formats: &[],
+    let (computed, reused) = self.plan_run(execution_order, Some(final_node), |node_id| {
+      self.cached_texture(hashes, node_id).is_some()
     });
-
-    // Create output texture
-    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Output Texture"),
-      size: wgpu::Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-      },
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: TEXTURE_FORMAT,
-      usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
-      view_formats: &[],
+    let mut pending = self.pending_consumers(&computed);
+
+    // The input image is only uploaded when a computed node reads it
+    let input = pending.contains_key(&None).then(|| {
+      let texture =
+        Self::upload_texture(device, queue, "Pipeline Input Texture", &input_data, dimensions);
+      let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+      (texture, view)
     });
+    drop(input_data);
 
-    // Upload input data to texture
-    queue.write_texture(
-      wgpu::TexelCopyTextureInfo {
-        texture: &input_texture,
-        mip_level: 0,
-        origin: wgpu::Origin3d::ZERO,
-        aspect: wgpu::TextureAspect::All,
-      },
-      &input_data,
-      wgpu::TexelCopyBufferLayout {
-        offset: 0,
-        bytes_per_row: Some(BYTES_PER_PIXEL * width),
-        rows_per_image: Some(height),
-      },
-      wgpu::Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-      },
-    );
+    self.textures.clear();
+    self.texture_views.clear();
 
-    // Create and upload parameter buffer
-    let param_data = self.serialize_params(params)?;
-    let param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-      label: Some("Parameter Buffer"),
-      contents: &param_data,
-      usage: wgpu::BufferUsages::UNIFORM,
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Pipeline Command Encoder"),
     });
 
-    // Create bind group layout (we need to recreate this for each call)
-    let bind_group_layout =
-      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("Processing Bind Group Layout"),
-        entries: &[
-          wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::Texture {
-              sample_type: wgpu::TextureSampleType::Float { filterable: false },
-              view_dimension: wgpu::TextureViewDimension::D2,
-              multisampled: false,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              access: wgpu::StorageTextureAccess::WriteOnly,
-              format: TEXTURE_FORMAT,
-              view_dimension: wgpu::TextureViewDimension::D2,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 2,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::Buffer {
-              ty: wgpu::BufferBindingType::Uniform,
-              has_dynamic_offset: false,
-              min_binding_size: None,
-            },
-            count: None,
+    for &node_id in execution_order {
+      let Some(node) = self.nodes.get(&node_id) else {
+        continue;
+      };
+
+      if reused.contains(&node_id) {
+        log::info!("Reusing cached result for node: {} ({})", node.name, node.id);
+        let (texture, view, _) = self
+          .cached_texture(hashes, node_id)
+          .ok_or_else(|| format!("Result of node {} is not available", node_id))?;
+        self.textures.insert(node_id, texture);
+        self.texture_views.insert(node_id, view);
+        continue;
+      }
+      if !computed.contains(&node_id) {
+        continue;
+      }
+
+      let sources = self.input_sources(node_id);
+      let primary_source = *sources
+        .first()
+        .ok_or_else(|| format!("Node {} has no input image", node_id))?;
+
+      let pipeline = if matches!(node.node_type, NodeType::ImageInput | NodeType::ImageOutput)
+        || !node.enabled
+      {
+        None
+      } else {
+        let pipeline = self.pipeline_for(&node.node_type, &node.params);
+        if pipeline.is_none() {
+          log::warn!("No pipeline found for node type: {:?}", node.node_type);
+        }
+        pipeline
+      };
+
+      let output = match pipeline {
+        // Pass-through nodes share the texture of their primary input
+        None => self.resident_texture(primary_source, input.as_ref())?,
+        Some(_) => {
+          log::info!("Processing node: {} ({})", node.name, node.id);
+
+          let (width, height) = planned[&Some(node_id)];
+          let (input_texture, input_view) =
+            self.resident_texture(primary_source, input.as_ref())?;
+          let context = self
+            .resident_context(
+              device,
+              queue,
+              &mut encoder,
+              &node.params,
+              &input_texture,
+              planned[&primary_source],
+            )
+            .await?;
+          let secondary_view = match sources.get(1) {
+            Some(&source_id) => {
+              self
+                .resident_texture(source_id, input.as_ref())?
+                .1
+            }
+            None => input_view.clone(),
+          };
+
+          let output_texture =
+            Self::create_output_texture(device, "Node Output Texture", (width, height));
+          let output_view =
+            output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+          self.encode_node_passes(
+            device,
+            queue,
+            &mut encoder,
+            &node.node_type,
+            &node.params,
+            &input_view,
+            &secondary_view,
+            &output_view,
+            (width, height),
+            context,
+          )?;
+
+          (output_texture, output_view)
+        }
+      };
+
+      // Inputs are released once their last consumer has been recorded
+      for source in sources {
+        let remaining = pending.entry(source).or_default();
+        *remaining = remaining.saturating_sub(1);
+        if let (0, Some(source_id)) = (*remaining, source) {
+          self.textures.remove(&source_id);
+          self.texture_views.remove(&source_id);
+        }
+      }
+
+      if let (Some(cache), Some(hash)) = (
+        self.result_cache.as_mut(),
+        hashes.and_then(|hashes| hashes.get(&Some(node_id))),
+      ) {
+        cache.insert(
+          *hash,
+          CachedResult::Texture(
+            output.0.clone(),
+            output.1.clone(),
+            planned[&Some(node_id)],
+          ),
+        );
+      }
+
+      if pending.get(&Some(node_id)).copied().unwrap_or(0) > 0 || node_id == final_node {
+        self.textures.insert(node_id, output.0);
+        self.texture_views.insert(node_id, output.1);
+      }
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    if let (Some(cache), Some(hashes)) = (self.result_cache.as_mut(), hashes) {
+      cache.retain_hashes(hashes);
+    }
+
+    let output_texture = self
+      .textures
+      .remove(&final_node)
+      .ok_or_else(|| format!("Output node {} produced no image", final_node))?;
+    self.textures.clear();
+    self.texture_views.clear();
+
+    let output_dimensions = planned[&Some(final_node)];
+    let output_data = self
+      .read_texture(device, queue, &output_texture, output_dimensions)
+      .await?;
+    Ok((output_data, output_dimensions))
+  }
+
+  /// Run the graph one node at a time, reading every result back to CPU memory.
+  ///
+  /// Used for images too large to be held in a single texture; such nodes are split
+  /// into tiles by `process_node_tiled`. Intermediate results are kept alive only
+  /// until their last downstream consumer has run.
+  async fn process_buffered(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    execution_order: &[usize],
+    final_node: Option<usize>,
+    input_data: Vec<u8>,
+    dimensions: (u32, u32),
+  ) -> Result<NodeOutput, String> {
+    let mut pending = self.pending_consumers(execution_order);
+
+    // `None` is the pipeline input image, `Some(id)` the output of a node
+    let mut results: HashMap<Option<usize>, NodeOutput> = HashMap::new();
+    if pending.get(&None).copied().unwrap_or(0) > 0 || final_node.is_none() {
+      results.insert(None, (input_data, dimensions));
+    }
+
+    for &node_id in execution_order {
+      let Some(node) = self.nodes.get(&node_id) else {
+        continue;
+      };
+
+      let mut inputs = Vec::new();
+      for source in self.input_sources(node_id) {
+        inputs.push(Self::take_result(&mut results, &mut pending, source)?);
+      }
+
+      let output = if matches!(node.node_type, NodeType::ImageInput | NodeType::ImageOutput)
+        || !node.enabled
+      {
+        // Pass-through nodes forward their primary input unchanged
+        inputs.into_iter().next()
+      } else if self.pipeline_for(&node.node_type, &node.params).is_some() {
+        log::info!("Processing node: {} ({})", node.name, node.id);
+
+        let mut inputs = inputs.into_iter();
+        let primary = inputs
+          .next()
+          .ok_or_else(|| format!("Node {} has no input image", node.id))?;
+        let secondary = inputs.next();
+
+        let (width, height) = primary.1;
+        let dispatch = self.plan_dispatch(
+          node,
+          primary.1,
+          secondary.as_ref().map(|(_, dimensions)| *dimensions),
+          device.limits().max_texture_dimension_2d,
+        )?;
+        let context = self.buffered_context(&node.params, &primary);
+        if dispatch == Dispatch::Tiled {
+          log::info!(
+            "Using tiled processing for large image: {}x{}",
+            width,
+            height
+          );
+          let processed_data = self
+            .process_node_tiled(
+              device,
+              queue,
+              &node.node_type,
+              &node.params,
+              primary.0,
+              primary.1,
+              secondary.as_ref(),
+              context,
+            )
+            .await?;
+          Some((processed_data, primary.1))
+        } else {
+          Some(
+            self
+              .process_node_with_dimensions(
+                device,
+                queue,
+                &node.node_type,
+                &node.params,
+                primary.0,
+                primary.1,
+                secondary.as_ref(),
+                context,
+              )
+              .await?,
+          )
+        }
+      } else {
+        log::warn!("No pipeline found for node type: {:?}", node.node_type);
+        inputs.into_iter().next()
+      };
+
+      // Only keep results that are consumed downstream or form the final output
+      if let Some(output) = output {
+        if pending.get(&Some(node_id)).copied().unwrap_or(0) > 0
+          || final_node == Some(node_id)
+        {
+          results.insert(Some(node_id), output);
+        }
+      }
+    }
+
+    match final_node {
+      Some(node_id) => results
+        .remove(&Some(node_id))
+        .ok_or_else(|| format!("Output node {} produced no image", node_id)),
+      None => results
+        .remove(&None)
+        .ok_or_else(|| "Pipeline produced no image".to_string()),
+    }
+  }
+
+  /// Decide how `process_buffered` runs a node on an input of `dimensions`.
+  ///
+  /// Images too large for a staging buffer or for a texture of `max_dimension` pixels
+  /// per side are split into tiles. `Global` nodes can not be, so they fail before
+  /// dispatch when their input or output exceeds the texture limit.
+  fn plan_dispatch(
+    &self,
+    node: &ProcessingNode,
+    dimensions: (u32, u32),
+    secondary: Option<(u32, u32)>,
+    max_dimension: u32,
+  ) -> Result<Dispatch, String> {
+    let fits =
+      |(width, height): (u32, u32)| width <= max_dimension && height <= max_dimension;
+    let large = self.needs_tiling(dimensions.0, dimensions.1) || !fits(dimensions);
+    if large && node.node_type.footprint(&node.params, dimensions) != Footprint::Global {
+      return Ok(Dispatch::Tiled);
+    }
+
+    let output = Self::output_dimensions(&node.node_type, &node.params, dimensions)?;
+    match [Some(dimensions), secondary, Some(output)]
+      .into_iter()
+      .flatten()
+      .find(|&size| !fits(size))
+    {
+      Some((width, height)) => Err(format!(
+        "{:?} node needs the {}x{} image as a whole, which exceeds the GPU limit of {} pixels per side",
+        node.node_type, width, height, max_dimension
+      )),
+      None => Ok(Dispatch::Whole),
+    }
+  }
+
+  /// Run the graph with the CPU reference implementation of every node
+  fn process_cpu(
+    &mut self,
+    execution_order: &[usize],
+    final_node: Option<usize>,
+    input_data: Vec<u8>,
+    dimensions: (u32, u32),
+    hashes: Option<&HashMap<Option<usize>, u64>>,
+  ) -> Result<NodeOutput, String> {
+    let (computed, reused) = self.plan_run(execution_order, final_node, |node_id| {
+      self.cached_image(hashes, node_id).is_some()
+    });
+    let mut pending = self.pending_consumers(&computed);
+
+    // `None` is the pipeline input image, `Some(id)` the output of a node
+    let mut results: HashMap<Option<usize>, CpuImage> = HashMap::new();
+    if pending.get(&None).copied().unwrap_or(0) > 0 || final_node.is_none() {
+      results.insert(None, CpuImage::from_bytes(&input_data, dimensions));
+    }
+    drop(input_data);
+
+    for &node_id in execution_order {
+      let Some(node) = self.nodes.get(&node_id) else {
+        continue;
+      };
+
+      let output = if reused.contains(&node_id) {
+        log::info!("Reusing cached result for node: {} ({})", node.name, node.id);
+        self
+          .cached_image(hashes, node_id)
+          .ok_or_else(|| format!("Result of node {} is not available", node_id))?
+      } else if computed.contains(&node_id) {
+        let mut inputs = Vec::new();
+        for source in self.input_sources(node_id) {
+          inputs.push(Self::take_result(&mut results, &mut pending, source)?);
+        }
+        let mut inputs = inputs.into_iter();
+        let primary = inputs
+          .next()
+          .ok_or_else(|| format!("Node {} has no input image", node.id))?;
+
+        let output = if matches!(
+          node.node_type,
+          NodeType::ImageInput | NodeType::ImageOutput
+        ) || !node.enabled
+        {
+          // Pass-through nodes forward their primary input unchanged
+          primary
+        } else {
+          log::info!("Processing node on the CPU: {} ({})", node.name, node.id);
+
+          let output_dimensions =
+            Self::output_dimensions(&node.node_type, &node.params, primary.dimensions())?;
+          let secondary = inputs.next();
+          cpu::process_node(
+            &node.node_type,
+            &self.with_as_shot_kelvin(&node.params),
+            &primary,
+            secondary.as_ref(),
+            output_dimensions,
+          )?
+        };
+
+        if let (Some(cache), Some(hash)) = (
+          self.result_cache.as_mut(),
+          hashes.and_then(|hashes| hashes.get(&Some(node_id))),
+        ) {
+          cache.insert(*hash, CachedResult::Image(output.clone()));
+        }
+        output
+      } else {
+        continue;
+      };
+
+      // Only keep results that are consumed downstream or form the final output
+      if pending.get(&Some(node_id)).copied().unwrap_or(0) > 0
+        || final_node == Some(node_id)
+      {
+        results.insert(Some(node_id), output);
+      }
+    }
+
+    if let (Some(cache), Some(hashes)) = (self.result_cache.as_mut(), hashes) {
+      cache.retain_hashes(hashes);
+    }
+
+    let output = results
+      .remove(&final_node)
+      .ok_or_else(|| "Pipeline produced no image".to_string())?;
+    Ok((output.to_bytes(), output.dimensions()))
+  }
+
+  /// Split a run into the nodes to compute and the nodes whose result is reused.
+  ///
+  /// Walks upstream from the final node and stops at reusable results, so nodes that
+  /// only feed reused results, or nothing at all, are skipped entirely. The computed
+  /// nodes are returned in execution order.
+  fn plan_run(
+    &self,
+    execution_order: &[usize],
+    final_node: Option<usize>,
+    reusable: impl Fn(usize) -> bool,
+  ) -> (Vec<usize>, HashSet<usize>) {
+    let mut required = HashSet::new();
+    let mut reused = HashSet::new();
+    let mut stack: Vec<usize> = final_node.into_iter().collect();
+
+    while let Some(node_id) = stack.pop() {
+      if !required.insert(node_id) {
+        continue;
+      }
+      if reusable(node_id) {
+        reused.insert(node_id);
+        continue;
+      }
+      stack.extend(self.input_sources(node_id).into_iter().flatten());
+    }
+
+    let computed = execution_order
+      .iter()
+      .copied()
+      .filter(|node_id| required.contains(node_id) && !reused.contains(node_id))
+      .collect();
+    (computed, reused)
+  }
+
+  /// Hash every result of a run from its node settings and the hashes of its inputs.
+  ///
+  /// Pass-through and disabled nodes share the hash of their primary input, so
+  /// toggling a node off reuses the result upstream of it.
+  fn result_hashes(
+    &self,
+    execution_order: &[usize],
+    input_hash: u64,
+  ) -> HashMap<Option<usize>, u64> {
+    let mut hashes = HashMap::from([(None, input_hash)]);
+
+    for &node_id in execution_order {
+      let Some(node) = self.nodes.get(&node_id) else {
+        continue;
+      };
+
+      let input_hashes: Vec<u64> = self
+        .input_sources(node_id)
+        .iter()
+        .map(|source| hashes.get(source).copied().unwrap_or(input_hash))
+        .collect();
+
+      let hash = if matches!(node.node_type, NodeType::ImageInput | NodeType::ImageOutput)
+        || !node.enabled
+      {
+        input_hashes.first().copied().unwrap_or(input_hash)
+      } else {
+        let mut hasher = DefaultHasher::new();
+        node.node_type.hash(&mut hasher);
+        // Parameters contain floats, their debug form identifies them exactly
+        format!("{:?}", node.params).hash(&mut hasher);
+        // Files referenced by a node may change between runs
+        if let NodeParams::Lut3D { path, .. } = &node.params {
+          let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+          modified.ok().hash(&mut hasher);
+        }
+        // Custom shaders may be edited between runs
+        if let NodeParams::Custom { shader, .. } = &node.params {
+          let source = self.custom_shaders.get(shader).map(|custom| &custom.source);
+          source.hash(&mut hasher);
+        }
+        input_hashes.hash(&mut hasher);
+        hasher.finish()
+      };
+      hashes.insert(Some(node_id), hash);
+    }
+
+    hashes
+  }
+
+  /// Check that enabled `Custom` nodes use an added shader and only its parameters
+  fn check_custom_nodes(&self, execution_order: &[usize]) -> Result<(), String> {
+    for node in execution_order.iter().filter_map(|id| self.nodes.get(id)) {
+      let NodeParams::Custom { shader, params } = &node.params else {
+        continue;
+      };
+      if !node.enabled {
+        continue;
+      }
+      let custom = self.custom_shaders.get(shader).ok_or_else(|| {
+        format!("Node {} uses unknown custom shader '{}'", node.id, shader)
+      })?;
+      if let Some(name) = params.keys().find(|name| !custom.params.contains(name)) {
+        return Err(format!(
+          "Custom shader '{}' has no parameter '{}'",
+          shader, name
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  /// Identify the pipeline input image, preferring a key provided by the caller
+  fn input_hash(&self, input_data: &[u8], dimensions: (u32, u32)) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match self.input_key {
+      Some(key) => key.hash(&mut hasher),
+      None => input_data.hash(&mut hasher),
+    }
+    dimensions.hash(&mut hasher);
+    (self.input_space, self.working_space).hash(&mut hasher);
+    self.as_shot_kelvin.to_bits().hash(&mut hasher);
+    hasher.finish()
+  }
+
+  /// Cached GPU result of a node, if the cache holds its current hash
+  fn cached_texture(
+    &self,
+    hashes: Option<&HashMap<Option<usize>, u64>>,
+    node_id: usize,
+  ) -> Option<(Texture, TextureView, (u32, u32))> {
+    let hash = hashes?.get(&Some(node_id))?;
+    match self.result_cache.as_ref()?.entries.get(hash)? {
+      CachedResult::Texture(texture, view, dimensions) => {
+        Some((texture.clone(), view.clone(), *dimensions))
+      }
+      CachedResult::Image(_) => None,
+    }
+  }
+
+  /// Cached CPU result of a node, if the cache holds its current hash
+  fn cached_image(
+    &self,
+    hashes: Option<&HashMap<Option<usize>, u64>>,
+    node_id: usize,
+  ) -> Option<CpuImage> {
+    let hash = hashes?.get(&Some(node_id))?;
+    match self.result_cache.as_ref()?.entries.get(hash)? {
+      CachedResult::Image(image) => Some(image.clone()),
+      CachedResult::Texture(..) => None,
+    }
+  }
+  /// Work out the output dimensions of every node before anything runs.
+  ///
+  /// Keys follow the same convention as `input_sources`: `None` is the pipeline
+  /// input image.
+  fn plan_dimensions(
+    &self,
+    execution_order: &[usize],
+    dimensions: (u32, u32),
+  ) -> Result<HashMap<Option<usize>, (u32, u32)>, String> {
+    let mut planned = HashMap::from([(None, dimensions)]);
+
+    for &node_id in execution_order {
+      let Some(node) = self.nodes.get(&node_id) else {
+        continue;
+      };
+
+      let primary = self
+        .input_sources(node_id)
+        .first()
+        .and_then(|source| planned.get(source))
+        .copied()
+        .unwrap_or(dimensions);
+
+      let output = if node.enabled {
+        Self::output_dimensions(&node.node_type, &node.params, primary)?
+      } else {
+        primary
+      };
+      planned.insert(Some(node_id), output);
+    }
+
+    Ok(planned)
+  }
+
+  /// Look up the texture holding a result during resident processing
+  fn resident_texture(
+    &self,
+    source: Option<usize>,
+    input: Option<&(Texture, TextureView)>,
+  ) -> Result<(Texture, TextureView), String> {
+    match source {
+      None => input
+        .cloned()
+        .ok_or_else(|| "Pipeline input image is not available".to_string()),
+      Some(node_id) => match (self.textures.get(&node_id), self.texture_views.get(&node_id))
+      {
+        (Some(texture), Some(view)) => Ok((texture.clone(), view.clone())),
+        _ => Err(format!("Result of node {} is not available", node_id)),
+      },
+    }
+  }
+
+  /// Resolve the node feeding each named input of a node, in input order.
+  ///
+  /// `None` refers to the pipeline input image, which feeds the `ImageInput` node and
+  /// any input that has no connection.
+  fn input_sources(&self, node_id: usize) -> Vec<Option<usize>> {
+    let Some(node) = self.nodes.get(&node_id) else {
+      return Vec::new();
+    };
+
+    if node.node_type == NodeType::ImageInput {
+      return vec![None];
+    }
+
+    node
+      .inputs
+      .iter()
+      .map(|input| {
+        self
+          .connections
+          .iter()
+          .find(|conn| conn.to_node == node_id && &conn.to_input == input)
+          .map(|conn| conn.from_node)
+      })
+      .collect()
+  }
+
+  /// Count how many node inputs read each result during one pipeline run
+  fn pending_consumers(&self, execution_order: &[usize]) -> HashMap<Option<usize>, usize> {
+    let mut pending = HashMap::new();
+    for &node_id in execution_order {
+      for source in self.input_sources(node_id) {
+        *pending.entry(source).or_default() += 1;
+      }
+    }
+    pending
+  }
+
+  /// The node whose result is returned from `process`.
+  ///
+  /// This is the target node if one is set, then the output node if there is one,
+  /// otherwise the last node in execution order that has no outgoing connections.
+  fn final_node(&self, execution_order: &[usize]) -> Option<usize> {
+    self.target_node_id.or(self.output_node_id).or_else(|| {
+      execution_order
+        .iter()
+        .rev()
+        .find(|&&node_id| !self.connections.iter().any(|conn| conn.from_node == node_id))
+        .copied()
+    })
+  }
+
+  /// Hand out a result to one of its consumers, releasing it after the last one.
+  fn take_result<T: Clone>(
+    results: &mut HashMap<Option<usize>, T>,
+    pending: &mut HashMap<Option<usize>, usize>,
+    source: Option<usize>,
+  ) -> Result<T, String> {
+    let remaining = pending.entry(source).or_default();
+    *remaining = remaining.saturating_sub(1);
+
+    let result = if *remaining == 0 {
+      results.remove(&source)
+    } else {
+      results.get(&source).cloned()
+    };
+
+    result.ok_or_else(|| match source {
+      Some(node_id) => format!("Result of node {} is not available", node_id),
+      None => "Pipeline input image is not available".to_string(),
+    })
+  }
+
+  async fn process_node_with_dimensions(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    node_type: &NodeType,
+    params: &NodeParams,
+    input_data: Vec<u8>,
+    dimensions: (u32, u32),
+    secondary: Option<&NodeOutput>,
+    context: NodeContext,
+  ) -> Result<(Vec<u8>, (u32, u32)), String> {
+    // Nodes changing the image size render into an output texture of the new size
+    let output_dimensions = Self::output_dimensions(node_type, params, dimensions)?;
+    if output_dimensions != dimensions {
+      let processed_data = self
+        .process_resized_node(
+          device,
+          queue,
+          node_type,
+          params,
+          input_data,
+          dimensions,
+          output_dimensions,
+          context,
+        )
+        .await?;
+      return Ok((processed_data, output_dimensions));
+    }
+
+    // For other nodes, call the original process_node method and return same dimensions
+    let processed_data = self
+      .process_node(
+        device, queue, node_type, params, input_data, dimensions, secondary, context,
+      )
+      .await?;
+    Ok((processed_data, dimensions))
+  }
+
+  /// Size of the image a node produces from an input of `dimensions`.
+  ///
+  /// Only resizing and geometric transforms change the size. A resize with a missing
+  /// side keeps the aspect ratio.
+  fn output_dimensions(
+    node_type: &NodeType,
+    params: &NodeParams,
+    dimensions: (u32, u32),
+  ) -> Result<(u32, u32), String> {
+    let (current_width, current_height) = dimensions;
+
+    match (node_type, params) {
+      (NodeType::Resize, NodeParams::Resize { width, height }) => {
+        Ok(match (width, height) {
+          (Some(w), Some(h)) => (*w, *h),
+          (Some(w), None) => {
+            // Maintain aspect ratio, set width
+            let aspect_ratio = current_height as f32 / current_width as f32;
+            let h = (*w as f32 * aspect_ratio) as u32;
+            (*w, h)
+          }
+          (None, Some(h)) => {
+            // Maintain aspect ratio, set height
+            let aspect_ratio = current_width as f32 / current_height as f32;
+            let w = (*h as f32 * aspect_ratio) as u32;
+            (w, *h)
+          }
+          (None, None) => dimensions,
+        })
+      }
+      (
+        NodeType::Crop,
+        NodeParams::Crop {
+          x,
+          y,
+          width,
+          height,
+        },
+      ) => geometry::cropped_dimensions(dimensions, [*x, *y, *width, *height]),
+      (NodeType::Rotate, NodeParams::Rotate { quarter_turns }) => {
+        Ok(geometry::rotated_dimensions(dimensions, *quarter_turns))
+      }
+      (NodeType::Straighten, NodeParams::Straighten { angle, crop, .. }) => {
+        Ok(geometry::straightened_dimensions(dimensions, *angle, *crop))
+      }
+      (NodeType::Perspective, NodeParams::Perspective { corners, .. }) => {
+        geometry::perspective_matrix(corners)?;
+        Ok(geometry::perspective_dimensions(dimensions, corners))
+      }
+      (
+        NodeType::Resize
+        | NodeType::Crop
+        | NodeType::Rotate
+        | NodeType::Straighten
+        | NodeType::Perspective,
+        _,
+      ) => Err(format!("Invalid parameters for {:?} node", node_type)),
+      _ => Ok(dimensions),
+    }
+  }
+
+  /// Run a node whose output differs in size from its input, such as `Resize`
+  async fn process_resized_node(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    node_type: &NodeType,
+    params: &NodeParams,
+    input_data: Vec<u8>,
+    dimensions: (u32, u32),
+    output_dimensions: (u32, u32),
+    context: NodeContext,
+  ) -> Result<Vec<u8>, String> {
+    log::info!(
+      "Resizing image from {}x{} to {}x{}",
+      dimensions.0,
+      dimensions.1,
+      output_dimensions.0,
+      output_dimensions.1
+    );
+
+    // Create input texture and output texture with new dimensions
+    let input_texture =
+      Self::upload_texture(device, queue, "Resize Input Texture", &input_data, dimensions);
+    let output_texture =
+      Self::create_output_texture(device, "Resize Output Texture", output_dimensions);
+    let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Resize Command Encoder"),
+    });
+    self.encode_node_passes(
+      device,
+      queue,
+      &mut encoder,
+      node_type,
+      params,
+      &input_view,
+      &input_view,
+      &output_view,
+      output_dimensions,
+      context,
+    )?;
+    queue.submit(Some(encoder.finish()));
+
+    // Read back the result
+    self
+      .read_texture(device, queue, &output_texture, output_dimensions)
+      .await
+  }
+
+  /// Create a sampled texture and upload RGBA32F pixel data into it.
+  ///
+  /// The upload is split into row strips so no single write exceeds MAX_BUFFER_SIZE.
+  fn upload_texture(
+    device: &Device,
+    queue: &Queue,
+    label: &str,
+    data: &[u8],
+    dimensions: (u32, u32),
+  ) -> Texture {
+    let (width, height) = dimensions;
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: TEXTURE_FORMAT,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING
+        | wgpu::TextureUsages::COPY_DST
+        | wgpu::TextureUsages::COPY_SRC,
+      view_formats: &[],
+    });
+
+    let bytes_per_row = width * BYTES_PER_PIXEL;
+    let rows_per_strip = (MAX_BUFFER_SIZE / bytes_per_row.max(1) as u64).max(1) as u32;
+
+    let mut start_row = 0;
+    while start_row < height {
+      let rows = rows_per_strip.min(height - start_row);
+      let offset = start_row as usize * bytes_per_row as usize;
+      let length = rows as usize * bytes_per_row as usize;
+
+      queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+          texture: &texture,
+          mip_level: 0,
+          origin: wgpu::Origin3d {
+            x: 0,
+            y: start_row,
+            z: 0,
           },
-        ],
-      });
+          aspect: wgpu::TextureAspect::All,
+        },
+        &data[offset..offset + length],
+        wgpu::TexelCopyBufferLayout {
+          offset: 0,
+          bytes_per_row: Some(bytes_per_row),
+          rows_per_image: Some(rows),
+        },
+        wgpu::Extent3d {
+          width,
+          height: rows,
+          depth_or_array_layers: 1,
+        },
+      );
 
-    // Create bind group
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      start_row += rows;
+    }
+
+    texture
+  }
+
+  /// Create a texture a shader can write into and later nodes can read from
+  fn create_output_texture(device: &Device, label: &str, dimensions: (u32, u32)) -> Texture {
+    let (width, height) = dimensions;
+    device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: TEXTURE_FORMAT,
+      usage: wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING
+        | wgpu::TextureUsages::COPY_SRC,
+      view_formats: &[],
+    })
+  }
+
+  /// Lookup tables bound for a node: the baked curves of `Curves` nodes, the bitmap of
+  /// `PaintedMask` nodes and the table of `Lut3D` nodes, with placeholders in the unused
+  /// slots
+  fn lookup_tables(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    params: &NodeParams,
+  ) -> Result<LookupTables, String> {
+    let placeholders = self
+      .lookup_table_placeholders
+      .clone()
+      .ok_or("GPU resources not initialized")?;
+
+    Ok(match params {
+      NodeParams::Curves {
+        master,
+        red,
+        green,
+        blue,
+      } => {
+        let lut = curves::bake_lut(master, red, green, blue);
+        LookupTables {
+          table: Self::upload_lookup_table(device, queue, &lut, lut.len() as u32),
+          ..placeholders
+        }
+      }
+      NodeParams::PaintedMask { bitmap } => {
+        let limit = device.limits().max_texture_dimension_2d;
+        if bitmap.width > limit || bitmap.height > limit {
+          return Err(format!(
+            "Painted mask of {}x{} exceeds the GPU limit of {} pixels per side",
+            bitmap.width, bitmap.height, limit
+          ));
+        }
+        let pixels = bitmap.to_pixels()?;
+        LookupTables {
+          table: Self::upload_lookup_table(device, queue, &pixels, bitmap.width),
+          ..placeholders
+        }
+      }
+      NodeParams::Lut3D { path, .. } => {
+        let lut = lut::load(path)?;
+        LookupTables {
+          cube: Self::upload_lut_3d(device, queue, lut.size, &lut.table),
+          ..placeholders
+        }
+      }
+      _ => placeholders,
+    })
+  }
+
+  /// Create a 3D RGBA32F texture holding a `size³` table with the red index fastest
+  fn upload_lut_3d(
+    device: &Device,
+    queue: &Queue,
+    size: u32,
+    table: &[[f32; 3]],
+  ) -> TextureView {
+    let extent = wgpu::Extent3d {
+      width: size,
+      height: size,
+      depth_or_array_layers: size,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("3D Lookup Table Texture"),
+      size: extent,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D3,
+      format: TEXTURE_FORMAT,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    });
+
+    let data: Vec<u8> = table
+      .iter()
+      .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+      .flat_map(|value| value.to_le_bytes())
+      .collect();
+    queue.write_texture(
+      wgpu::TexelCopyTextureInfo {
+        texture: &texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+        aspect: wgpu::TextureAspect::All,
+      },
+      &data,
+      wgpu::TexelCopyBufferLayout {
+        offset: 0,
+        bytes_per_row: Some(size * BYTES_PER_PIXEL),
+        rows_per_image: Some(size),
+      },
+      extent,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+  }
+
+  /// Create an RGBA32F texture holding a lookup table of rows with `width` entries.
+  ///
+  /// One dimensional tables are a single row, since not every backend supports sampling
+  /// 1D textures.
+  fn upload_lookup_table(
+    device: &Device,
+    queue: &Queue,
+    lut: &[[f32; 4]],
+    width: u32,
+  ) -> TextureView {
+    let size = wgpu::Extent3d {
+      width,
+      height: lut.len() as u32 / width,
+      depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Lookup Table Texture"),
+      size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: TEXTURE_FORMAT,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    });
+
+    let data: Vec<u8> = lut
+      .iter()
+      .flat_map(|entry| entry.iter().flat_map(|value| value.to_le_bytes()))
+      .collect();
+    queue.write_texture(
+      wgpu::TexelCopyTextureInfo {
+        texture: &texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+        aspect: wgpu::TextureAspect::All,
+      },
+      &data,
+      wgpu::TexelCopyBufferLayout {
+        offset: 0,
+        bytes_per_row: Some(width * BYTES_PER_PIXEL),
+        rows_per_image: None,
+      },
+      size,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+  }
+
+  /// Bind the resources of a single node dispatch to the shared layout
+  fn create_bind_group(
+    device: &Device,
+    layout: &wgpu::BindGroupLayout,
+    input_view: &TextureView,
+    output_view: &TextureView,
+    param_buffer: &wgpu::Buffer,
+    secondary_view: &TextureView,
+    lookup_tables: &LookupTables,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
       label: Some("Processing Bind Group"),
-      layout: &bind_group_layout,
+      layout,
       entries: &[
         wgpu::BindGroupEntry {
           binding: 0,
-          resource: wgpu::BindingResource::TextureView(
-            &input_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-          ),
+          resource: wgpu::BindingResource::TextureView(input_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(output_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: param_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(secondary_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::TextureView(&lookup_tables.table),
+        },
+        wgpu::BindGroupEntry {
+          binding: 5,
+          resource: wgpu::BindingResource::TextureView(&lookup_tables.cube),
+        },
+      ],
+    })
+  }
+
+  /// Copy a texture back into tightly packed RGBA32F bytes.
+  ///
+  /// Rows are read in strips so no staging buffer exceeds MAX_BUFFER_SIZE.
+  async fn read_texture(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    texture: &Texture,
+    dimensions: (u32, u32),
+  ) -> Result<Vec<u8>, String> {
+    let (width, height) = dimensions;
+    let unpadded_bytes_per_row = width * BYTES_PER_PIXEL;
+    let aligned_bytes_per_row = self.aligned_bytes_per_row(width);
+    let rows_per_strip = (MAX_BUFFER_SIZE / aligned_bytes_per_row.max(1) as u64).max(1) as u32;
+
+    let mut result =
+      Vec::with_capacity(unpadded_bytes_per_row as usize * height as usize);
+
+    let mut start_row = 0;
+    while start_row < height {
+      let rows = rows_per_strip.min(height - start_row);
+
+      // Create staging buffer for reading back result with aligned row size
+      let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Staging Buffer"),
+        size: aligned_bytes_per_row as u64 * rows as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+      });
+
+      let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Command Encoder"),
+      });
+      encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+          texture,
+          mip_level: 0,
+          origin: wgpu::Origin3d {
+            x: 0,
+            y: start_row,
+            z: 0,
+          },
+          aspect: wgpu::TextureAspect::All,
         },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::TextureView(
-            &output_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-          ),
+        wgpu::TexelCopyBufferInfo {
+          buffer: &staging_buffer,
+          layout: wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(aligned_bytes_per_row),
+            rows_per_image: Some(rows),
+          },
         },
-        wgpu::BindGroupEntry {
-          binding: 2,
-          resource: param_buffer.as_entire_binding(),
+        wgpu::Extent3d {
+          width,
+          height: rows,
+          depth_or_array_layers: 1,
         },
-      ],
-    });
+      );
+      queue.submit(Some(encoder.finish()));
+
+      let buffer_slice = staging_buffer.slice(..);
+      let (sender, receiver) = flume::bounded(1);
+      buffer_slice.map_async(wgpu::MapMode::Read, move |r| sender.send(r).unwrap());
+      device.poll(wgpu::PollType::Wait).unwrap();
+      receiver
+        .recv_async()
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Buffer mapping failed: {:?}", e))?;
+
+      // Handle row padding when copying data back
+      {
+        let data = buffer_slice.get_mapped_range();
+        for row in 0..rows {
+          let src_start = (row * aligned_bytes_per_row) as usize;
+          let src_end = src_start + unpadded_bytes_per_row as usize;
+          result.extend_from_slice(&data[src_start..src_end]);
+        }
+      }
+      staging_buffer.unmap();
 
-    // Create staging buffer for reading back result with aligned row size
-    let aligned_bytes_per_row = self.aligned_bytes_per_row(width);
+      start_row += rows;
+    }
+
+    Ok(result)
+  }
+
+  /// Read single pixels of a texture, one 1x1 copy each
+  async fn read_texels(
+    device: &Device,
+    queue: &Queue,
+    texture: &Texture,
+    positions: &[(u32, u32)],
+  ) -> Result<Vec<[f32; 4]>, String> {
     let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-      label: Some("Staging Buffer"),
-      size: (aligned_bytes_per_row * height) as u64,
+      label: Some("Texel Staging Buffer"),
+      size: positions.len() as u64 * BYTES_PER_PIXEL as u64,
       usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
       mapped_at_creation: false,
     });
 
-    log::info!("Execute compute shader");
-
-    // Execute compute shader
     let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-      label: Some("Processing Command Encoder"),
+      label: Some("Texel Readback Command Encoder"),
     });
-
-    {
-      let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-        label: Some("Processing Compute Pass"),
-        timestamp_writes: None,
-      });
-      compute_pass.set_pipeline(pipeline);
-      compute_pass.set_bind_group(0, &bind_group, &[]);
-
-      // Dispatch with 8x8 workgroup size
-      let workgroup_size = 8;
-      let dispatch_x = (width + workgroup_size - 1) / workgroup_size;
-      let dispatch_y = (height + workgroup_size - 1) / workgroup_size;
-      compute_pass.dispatch_workgroups(dispatch_x, dispatch_y, 1);
-    }
-
-    log::info!("Copy to staging buffer");
-
-    // Copy result to staging buffer
-    encoder.copy_texture_to_buffer(
-      wgpu::TexelCopyTextureInfo {
-        texture: &output_texture,
-        mip_level: 0,
-        origin: wgpu::Origin3d::ZERO,
-        aspect: wgpu::TextureAspect::All,
-      },
-      wgpu::TexelCopyBufferInfo {
-        buffer: &staging_buffer,
-        layout: wgpu::TexelCopyBufferLayout {
-          offset: 0,
-          bytes_per_row: Some(self.aligned_bytes_per_row(width)),
-          rows_per_image: Some(height),
+    for (index, &(x, y)) in positions.iter().enumerate() {
+      encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+          texture,
+          mip_level: 0,
+          origin: wgpu::Origin3d { x, y, z: 0 },
+          aspect: wgpu::TextureAspect::All,
         },
-      },
-      wgpu::Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-      },
-    );
-
+        wgpu::TexelCopyBufferInfo {
+          buffer: &staging_buffer,
+          layout: wgpu::TexelCopyBufferLayout {
+            offset: index as u64 * BYTES_PER_PIXEL as u64,
+            bytes_per_row: None,
+            rows_per_image: None,
+          },
+        },
+        wgpu::Extent3d {
+          width: 1,
+          height: 1,
+          depth_or_array_layers: 1,
+        },
+      );
+    }
     queue.submit(Some(encoder.finish()));
 
-    log::info!("Copying data back to memory");
-
-    // Read back result
     let buffer_slice = staging_buffer.slice(..);
     let (sender, receiver) = flume::bounded(1);
     buffer_slice.map_async(wgpu::MapMode::Read, move |r| sender.send(r).unwrap());
@@ -1133,33 +3044,155 @@ impl ImagePipeline {
     receiver
       .recv_async()
       .await
-      .unwrap()
+      .map_err(|e| e.to_string())?
       .map_err(|e| format!("Buffer mapping failed: {:?}", e))?;
 
-    let data = buffer_slice.get_mapped_range();
+    let texels = buffer_slice
+      .get_mapped_range()
+      .chunks_exact(BYTES_PER_PIXEL as usize)
+      .map(|texel| {
+        [0, 1, 2, 3]
+          .map(|i| f32::from_le_bytes(texel[i * 4..i * 4 + 4].try_into().unwrap()))
+      })
+      .collect();
+    staging_buffer.unmap();
+    Ok(texels)
+  }
+
+  /// Record the compute passes of a node into `encoder`.
+  ///
+  /// Separable filters such as `Blur` run one pass per axis, going through an
+  /// intermediate texture; the last pass writes `output_view`. Every pass binds the
+  /// same secondary input and lookup tables.
+  fn encode_node_passes(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    encoder: &mut wgpu::CommandEncoder,
+    node_type: &NodeType,
+    params: &NodeParams,
+    input_view: &TextureView,
+    secondary_view: &TextureView,
+    output_view: &TextureView,
+    dimensions: (u32, u32),
+    context: NodeContext,
+  ) -> Result<(), String> {
+    let (width, height) = dimensions;
+    let bind_group_layout = self
+      .bind_group_layout
+      .as_ref()
+      .ok_or("GPU resources not initialized")?;
+    let lookup_tables = self.lookup_tables(device, queue, params)?;
+
+    let passes = self.passes(node_type, params, context)?;
+    let mut source = input_view.clone();
+    for (index, (pass_type, param_data)) in passes.iter().enumerate() {
+      let pipeline = self
+        .pipeline_for(pass_type, params)
+        .ok_or_else(|| format!("No pipeline found for node type: {:?}", pass_type))?;
+      let intermediate = (index + 1 < passes.len()).then(|| {
+        Self::create_output_texture(device, "Intermediate Pass Texture", dimensions)
+          .create_view(&wgpu::TextureViewDescriptor::default())
+      });
+      let target = intermediate.as_ref().unwrap_or(output_view);
 
-    // Handle row padding when copying data back
-    let unpadded_bytes_per_row = width * BYTES_PER_PIXEL;
-    let aligned_bytes_per_row = self.aligned_bytes_per_row(width);
-    let mut result = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+      let param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Parameter Buffer"),
+        contents: param_data,
+        usage: wgpu::BufferUsages::UNIFORM,
+      });
+      let bind_group = Self::create_bind_group(
+        device,
+        bind_group_layout,
+        &source,
+        target,
+        &param_buffer,
+        secondary_view,
+        &lookup_tables,
+      );
 
-    for row in 0..height {
-      let src_start = (row * aligned_bytes_per_row) as usize;
-      let src_end = src_start + unpadded_bytes_per_row as usize;
-      result.extend_from_slice(&data[src_start..src_end]);
+      {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+          label: Some("Node Compute Pass"),
+          timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups((width + 7) / 8, (height + 7) / 8, 1);
+      }
+
+      if let Some(view) = intermediate {
+        source = view;
+      }
     }
 
-    drop(data);
-    staging_buffer.unmap();
+    Ok(())
+  }
 
-    Ok(result)
+  async fn process_node(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    node_type: &NodeType,
+    params: &NodeParams,
+    input_data: Vec<u8>,
+    dimensions: (u32, u32),
+    secondary: Option<&NodeOutput>,
+    context: NodeContext,
+  ) -> Result<Vec<u8>, String> {
+    log::info!("Texture dimensions: {:?}", dimensions);
+
+    // Create and upload input texture
+    let input_texture =
+      Self::upload_texture(device, queue, "Input Texture", &input_data, dimensions);
+
+    // Two-input nodes get their second image uploaded separately
+    let secondary_texture = secondary.map(|(data, dims)| {
+      Self::upload_texture(device, queue, "Secondary Input Texture", data, *dims)
+    });
+
+    // Create output texture
+    let output_texture = Self::create_output_texture(device, "Output Texture", dimensions);
+
+    let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let secondary_view = secondary_texture
+      .as_ref()
+      .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+    log::info!("Execute compute shader");
+
+    // Execute compute shader
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Processing Command Encoder"),
+    });
+    self.encode_node_passes(
+      device,
+      queue,
+      &mut encoder,
+      node_type,
+      params,
+      &input_view,
+      secondary_view.as_ref().unwrap_or(&input_view),
+      &output_view,
+      dimensions,
+      context,
+    )?;
+    queue.submit(Some(encoder.finish()));
+
+    log::info!("Copying data back to memory");
+
+    self
+      .read_texture(device, queue, &output_texture, dimensions)
+      .await
   }
 
   /// Process a large image using tiled approach to avoid buffer size limits.
   ///
   /// This method splits large images into smaller tiles, processes each tile
   /// individually using the regular `process_node` method, and then reassembles
-  /// the results into the final image.
+  /// the results into the final image. Tiles are read with an overlap of the node's
+  /// footprint and cropped back, so no seams appear between them.
   ///
   /// This approach is automatically used when an image would require a staging
   /// buffer larger than the WebGPU maximum of 256MB.
@@ -1167,90 +3200,343 @@ impl ImagePipeline {
     &self,
     device: &Device,
     queue: &Queue,
-    pipeline: &ComputePipeline,
     node_type: &NodeType,
     params: &NodeParams,
     input_data: Vec<u8>,
     dimensions: (u32, u32),
+    secondary: Option<&NodeOutput>,
+    context: NodeContext,
   ) -> Result<Vec<u8>, String> {
     let (width, height) = dimensions;
     let max_tile_size = self.calculate_max_processable_dimension();
 
+    // Tiles of the secondary input are cut at the same offsets as the primary input
+    if let Some((_, secondary_dimensions)) = secondary {
+      if *secondary_dimensions != dimensions {
+        return Err(format!(
+          "Secondary input is {}x{} but tiled processing requires {}x{}",
+          secondary_dimensions.0, secondary_dimensions.1, width, height
+        ));
+      }
+    }
+
+    let Footprint::Local(overlap) = node_type.footprint(params, dimensions) else {
+      return Err(format!(
+        "{:?} nodes can not be processed in tiles",
+        node_type
+      ));
+    };
+    let tiles = self.plan_tiles(dimensions, overlap)?;
+
     log::info!(
-      "Processing {}x{} image in tiles of max size {}",
+      "Processing {}x{} image in {} tiles of max size {}",
       width,
       height,
+      tiles.len(),
       max_tile_size
     );
 
-    // Calculate tile dimensions
-    let tiles_x = (width + max_tile_size - 1) / max_tile_size;
-    let tiles_y = (height + max_tile_size - 1) / max_tile_size;
-
     let mut result_data = vec![0u8; input_data.len()];
 
-    for tile_y in 0..tiles_y {
-      for tile_x in 0..tiles_x {
-        let start_x = tile_x * max_tile_size;
-        let start_y = tile_y * max_tile_size;
-        let tile_width = (max_tile_size).min(width - start_x);
-        let tile_height = (max_tile_size).min(height - start_y);
-
-        log::debug!(
-          "Processing tile ({}, {}): {}x{}",
-          tile_x,
-          tile_y,
-          tile_width,
-          tile_height
-        );
+    for tile in &tiles {
+      let [padded_x, padded_y, padded_width, padded_height] = tile.padded;
+      log::debug!("Processing tile {:?}", tile.region);
 
-        // Extract tile data from input
-        let mut tile_data =
-          Vec::with_capacity((tile_width * tile_height * BYTES_PER_PIXEL) as usize);
-        for y in start_y..(start_y + tile_height) {
-          let input_row_start = (y * width * BYTES_PER_PIXEL) as usize;
-          let input_tile_start = input_row_start + (start_x * BYTES_PER_PIXEL) as usize;
-          let input_tile_end = input_tile_start + (tile_width * BYTES_PER_PIXEL) as usize;
-          tile_data.extend_from_slice(&input_data[input_tile_start..input_tile_end]);
-        }
+      // Extract tile data from input
+      let tile_data = Self::extract_tile(
+        &input_data,
+        width,
+        padded_x,
+        padded_y,
+        padded_width,
+        padded_height,
+      );
+      let secondary_tile = secondary.map(|(data, _)| {
+        (
+          Self::extract_tile(
+            data,
+            width,
+            padded_x,
+            padded_y,
+            padded_width,
+            padded_height,
+          ),
+          (padded_width, padded_height),
+        )
+      });
 
-        // Process tile
-        let processed_tile = self
-          .process_node(
-            device,
-            queue,
-            pipeline,
-            node_type,
-            params,
-            tile_data,
-            (tile_width, tile_height),
-          )
-          .await?;
+      // Process tile
+      let processed_tile = self
+        .process_node(
+          device,
+          queue,
+          node_type,
+          params,
+          tile_data,
+          (padded_width, padded_height),
+          secondary_tile.as_ref(),
+          NodeContext {
+            origin: (padded_x, padded_y),
+            ..context
+          },
+        )
+        .await?;
 
-        // Copy processed tile back to result
-        for y in 0..tile_height {
-          let result_y = start_y + y;
-          let result_row_start = (result_y * width * BYTES_PER_PIXEL) as usize;
-          let result_tile_start = result_row_start + (start_x * BYTES_PER_PIXEL) as usize;
-          let result_tile_end =
-            result_tile_start + (tile_width * BYTES_PER_PIXEL) as usize;
+      Self::place_tile(&mut result_data, width, tile, &processed_tile);
+    }
 
-          let tile_row_start = (y * tile_width * BYTES_PER_PIXEL) as usize;
-          let tile_row_end = tile_row_start + (tile_width * BYTES_PER_PIXEL) as usize;
+    Ok(result_data)
+  }
 
-          result_data[result_tile_start..result_tile_end]
-            .copy_from_slice(&processed_tile[tile_row_start..tile_row_end]);
-        }
+  /// Split an image into tiles of at most the processable size. Every tile reads
+  /// `overlap` pixels around the region it writes, where the image has them.
+  fn plan_tiles(
+    &self,
+    dimensions: (u32, u32),
+    overlap: u32,
+  ) -> Result<Vec<Tile>, String> {
+    let (width, height) = dimensions;
+    let max_tile_size = self.calculate_max_processable_dimension();
+    if overlap * 2 >= max_tile_size {
+      return Err(format!(
+        "A kernel reaching {} pixels does not fit into tiles of {} pixels",
+        overlap, max_tile_size
+      ));
+    }
+    let step = max_tile_size - overlap * 2;
+
+    let mut tiles = Vec::new();
+    for start_y in (0..height).step_by(step as usize) {
+      for start_x in (0..width).step_by(step as usize) {
+        let tile_width = step.min(width - start_x);
+        let tile_height = step.min(height - start_y);
+
+        // The padded tile includes the pixels the kernel reads around the tile
+        let padded_x = start_x.saturating_sub(overlap);
+        let padded_y = start_y.saturating_sub(overlap);
+        let padded_width = (start_x + tile_width + overlap).min(width) - padded_x;
+        let padded_height = (start_y + tile_height + overlap).min(height) - padded_y;
+
+        tiles.push(Tile {
+          region: [start_x, start_y, tile_width, tile_height],
+          padded: [padded_x, padded_y, padded_width, padded_height],
+        });
       }
     }
+    Ok(tiles)
+  }
 
-    Ok(result_data)
+  /// Copy a processed padded tile into the result of `width`, without the overlap
+  fn place_tile(result: &mut [u8], width: u32, tile: &Tile, processed: &[u8]) {
+    let [start_x, start_y, tile_width, tile_height] = tile.region;
+    let [padded_x, padded_y, padded_width, _] = tile.padded;
+    let (offset_x, offset_y) = (start_x - padded_x, start_y - padded_y);
+    let bytes = |pixels: u32| pixels as usize * BYTES_PER_PIXEL as usize;
+
+    for y in 0..tile_height {
+      let result_start = bytes(start_y + y) * width as usize + bytes(start_x);
+      let tile_start = bytes(offset_y + y) * padded_width as usize + bytes(offset_x);
+      result[result_start..result_start + bytes(tile_width)]
+        .copy_from_slice(&processed[tile_start..tile_start + bytes(tile_width)]);
+    }
+  }
+
+  /// Copy a rectangular region out of an RGBA32F image
+  fn extract_tile(
+    data: &[u8],
+    width: u32,
+    start_x: u32,
+    start_y: u32,
+    tile_width: u32,
+    tile_height: u32,
+  ) -> Vec<u8> {
+    let mut tile_data =
+      Vec::with_capacity((tile_width * tile_height * BYTES_PER_PIXEL) as usize);
+    for y in start_y..(start_y + tile_height) {
+      let input_row_start = (y * width * BYTES_PER_PIXEL) as usize;
+      let input_tile_start = input_row_start + (start_x * BYTES_PER_PIXEL) as usize;
+      let input_tile_end = input_tile_start + (tile_width * BYTES_PER_PIXEL) as usize;
+      tile_data.extend_from_slice(&data[input_tile_start..input_tile_end]);
+    }
+    tile_data
+  }
+
+  /// Pipeline and uniform data of every pass a node is dispatched in.
+  ///
+  /// Filters working against a blurred base run the two blur passes first. Their own
+  /// pass then reads the base at binding 0 and the node input at binding 3.
+  fn passes(
+    &self,
+    node_type: &NodeType,
+    params: &NodeParams,
+    context: NodeContext,
+  ) -> Result<Vec<(NodeType, Vec<u8>)>, String> {
+    let blur = |radius: f32| {
+      vec![
+        (NodeType::Blur, Self::blur_pass_params(radius, [1.0, 0.0])),
+        (NodeType::Blur, Self::blur_pass_params(radius, [0.0, 1.0])),
+      ]
+    };
+
+    Ok(match params {
+      NodeParams::Blur { radius } => blur(*radius),
+      NodeParams::UnsharpMask { radius, .. } | NodeParams::Clarity { radius, .. } => {
+        let mut passes = blur(*radius);
+        passes.push((*node_type, self.serialize_params(params, context)?));
+        passes
+      }
+      _ => vec![(*node_type, self.serialize_params(params, context)?)],
+    })
+  }
+
+  /// Parameters of one pass of the separable blur along `direction`
+  fn blur_pass_params(radius: f32, direction: [f32; 2]) -> Vec<u8> {
+    let extent = blur_extent(radius) as f32;
+    [radius, direction[0], direction[1], extent]
+      .iter()
+      .flat_map(|value| value.to_le_bytes())
+      .collect()
+  }
+
+  /// Pixels of a node input `node_context` measures, so a texture input only needs
+  /// these read back
+  fn measured_positions(params: &NodeParams, dimensions: (u32, u32)) -> Vec<(u32, u32)> {
+    let (width, height) = dimensions;
+    match params {
+      NodeParams::WhiteBalance {
+        auto_adjust,
+        method,
+        neutral_point,
+        ..
+      } => white_balance::measured_positions(
+        width,
+        height,
+        auto_adjust.then_some(*method),
+        *neutral_point,
+      ),
+      NodeParams::Dehaze { .. } => dehaze::sample_positions(width, height).collect(),
+      _ => Vec::new(),
+    }
+  }
+
+  /// Measure what a node needs of its whole input of `dimensions`, whose pixels
+  /// `load` returns
+  fn node_context(
+    &self,
+    params: &NodeParams,
+    dimensions: (u32, u32),
+    load: impl Fn(u32, u32) -> [f32; 4],
+  ) -> NodeContext {
+    let (width, height) = dimensions;
+    let rgb = |x, y| {
+      let [r, g, b, _] = load(x, y);
+      [r, g, b]
+    };
+    let measured = match params {
+      NodeParams::WhiteBalance {
+        auto_adjust,
+        temperature,
+        tint,
+        method,
+        neutral_point,
+        kelvin,
+        as_shot_kelvin,
+      } => Some(white_balance::image_gains(
+        width,
+        height,
+        auto_adjust.then_some(*method),
+        *neutral_point,
+        white_balance::manual_gains(
+          *temperature,
+          *tint,
+          *kelvin,
+          as_shot_kelvin.unwrap_or(self.as_shot_kelvin),
+        ),
+        rgb,
+      )),
+      NodeParams::Dehaze { .. } => Some(dehaze::atmospheric_light(
+        dehaze::sample_positions(width, height).map(|(x, y)| rgb(x, y)),
+      )),
+      _ => None,
+    };
+    NodeContext {
+      measured,
+      origin: (0, 0),
+      size: dimensions,
+    }
+  }
+
+  /// Context of a node whose input is held in CPU memory
+  fn buffered_context(&self, params: &NodeParams, input: &NodeOutput) -> NodeContext {
+    let (data, dimensions) = input;
+    self.node_context(params, *dimensions, |x, y| {
+      let pixel =
+        (y as usize * dimensions.0 as usize + x as usize) * BYTES_PER_PIXEL as usize;
+      [0, 1, 2, 3].map(|i| {
+        let start = pixel + i * 4;
+        f32::from_le_bytes(data[start..start + 4].try_into().unwrap())
+      })
+    })
+  }
+
+  /// Context of a node whose input is a texture. The commands recorded so far produce
+  /// the input, so they are submitted before the measured pixels are read back.
+  async fn resident_context(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    encoder: &mut wgpu::CommandEncoder,
+    params: &NodeParams,
+    texture: &Texture,
+    dimensions: (u32, u32),
+  ) -> Result<NodeContext, String> {
+    let positions = Self::measured_positions(params, dimensions);
+    let mut texels = HashMap::new();
+    if !positions.is_empty() {
+      let recorded = std::mem::replace(
+        encoder,
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+          label: Some("Pipeline Command Encoder"),
+        }),
+      );
+      queue.submit(Some(recorded.finish()));
+      let values = Self::read_texels(device, queue, texture, &positions).await?;
+      texels.extend(positions.into_iter().zip(values));
+    }
+    Ok(self.node_context(params, dimensions, |x, y| {
+      texels.get(&(x, y)).copied().unwrap_or_default()
+    }))
+  }
+
+  /// Node parameters with the pipeline's as-shot temperature filled in where unset
+  fn with_as_shot_kelvin<'a>(&self, params: &'a NodeParams) -> Cow<'a, NodeParams> {
+    match params {
+      NodeParams::WhiteBalance {
+        as_shot_kelvin: None,
+        ..
+      } => {
+        let mut params = params.clone();
+        if let NodeParams::WhiteBalance { as_shot_kelvin, .. } = &mut params {
+          *as_shot_kelvin = Some(self.as_shot_kelvin);
+        }
+        Cow::Owned(params)
+      }
+      _ => Cow::Borrowed(params),
+    }
   }
 
-  fn serialize_params(&self, params: &NodeParams) -> Result<Vec<u8>, String> {
+  fn serialize_params(
+    &self,
+    params: &NodeParams,
+    context: NodeContext,
+  ) -> Result<Vec<u8>, String> {
     let mut buffer = Vec::new();
 
     match params {
+      NodeParams::Exposure { stops } => {
+        buffer.extend_from_slice(&stops.to_le_bytes());
+        buffer.resize(16, 0);
+      }
       NodeParams::Brightness { value } => {
         buffer.extend_from_slice(&value.to_le_bytes());
         // Pad to 16 bytes (uniform buffer alignment)
@@ -1264,10 +3550,23 @@ impl ImagePipeline {
         buffer.extend_from_slice(&value.to_le_bytes());
         buffer.resize(16, 0);
       }
+      NodeParams::Vibrance { amount } => {
+        buffer.extend_from_slice(&amount.to_le_bytes());
+        buffer.resize(16, 0);
+      }
       NodeParams::Hue { value } => {
         buffer.extend_from_slice(&value.to_le_bytes());
         buffer.resize(16, 0);
       }
+      NodeParams::Hsl { bands } => {
+        // One vec4 per band: hue, saturation, luminance and padding
+        for band in bands.to_array() {
+          buffer.extend_from_slice(&band.hue.to_le_bytes());
+          buffer.extend_from_slice(&band.saturation.to_le_bytes());
+          buffer.extend_from_slice(&band.luminance.to_le_bytes());
+          buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
+        }
+      }
       NodeParams::Gamma { value } => {
         buffer.extend_from_slice(&value.to_le_bytes());
         buffer.resize(16, 0);
@@ -1283,6 +3582,10 @@ impl ImagePipeline {
         buffer.extend_from_slice(&output_black.to_le_bytes());
         buffer.extend_from_slice(&output_white.to_le_bytes());
       }
+      NodeParams::Curves { .. } => {
+        // The curves are baked into the lookup table at binding 4
+        buffer.resize(16, 0);
+      }
       NodeParams::ColorBalance {
         shadows,
         midtones,
@@ -1302,22 +3605,90 @@ impl ImagePipeline {
         }
         buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
       }
-      NodeParams::WhiteBalance {
-        auto_adjust,
-        temperature,
-        tint,
+      NodeParams::ColorGrading {
+        shadows,
+        midtones,
+        highlights,
+        balance,
+        blending,
       } => {
-        buffer.extend_from_slice(&(*auto_adjust as u32 as f32).to_le_bytes());
-        buffer.extend_from_slice(&temperature.to_le_bytes());
-        buffer.extend_from_slice(&tint.to_le_bytes());
+        // One vec4 per wheel: hue, saturation, luminance and padding
+        for wheel in [shadows, midtones, highlights] {
+          buffer.extend_from_slice(&wheel.hue.to_le_bytes());
+          buffer.extend_from_slice(&wheel.saturation.to_le_bytes());
+          buffer.extend_from_slice(&wheel.luminance.to_le_bytes());
+          buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
+        }
+        buffer.extend_from_slice(&balance.to_le_bytes());
+        buffer.extend_from_slice(&blending.to_le_bytes());
+        buffer.resize(64, 0);
+      }
+      NodeParams::WhiteBalance { .. } => {
+        let gains = context
+          .measured
+          .ok_or("White balance gains were not measured")?;
+        for value in gains {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
         buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
       }
-      NodeParams::Blur { radius } => {
+      NodeParams::Lut3D {
+        path,
+        intensity,
+        interpolation,
+      } => {
+        let lut = lut::load(path)?;
+        for &val in &lut.domain_min {
+          buffer.extend_from_slice(&val.to_le_bytes());
+        }
+        buffer.extend_from_slice(&intensity.to_le_bytes());
+        for &val in &lut.domain_max {
+          buffer.extend_from_slice(&val.to_le_bytes());
+        }
+        let tetrahedral = *interpolation == LutInterpolation::Tetrahedral;
+        buffer.extend_from_slice(&(tetrahedral as u32 as f32).to_le_bytes());
+      }
+      NodeParams::ToneMap {
+        operator,
+        white_point,
+        contrast,
+      } => {
+        buffer.extend_from_slice(&(*operator as u32 as f32).to_le_bytes());
+        buffer.extend_from_slice(&white_point.to_le_bytes());
+        buffer.extend_from_slice(&contrast.to_le_bytes());
+        buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
+      }
+      NodeParams::Denoise {
+        luminance,
+        chroma,
+        radius,
+      } => {
+        buffer.extend_from_slice(&luminance.to_le_bytes());
+        buffer.extend_from_slice(&chroma.to_le_bytes());
         buffer.extend_from_slice(&radius.to_le_bytes());
         buffer.resize(16, 0);
       }
-      NodeParams::Sharpen { amount } => {
+      NodeParams::Blur { radius } => {
+        // The horizontal pass, `passes` adds the vertical one
+        buffer = Self::blur_pass_params(*radius, [1.0, 0.0]);
+      }
+      NodeParams::Sharpen { amount } => {
+        buffer.extend_from_slice(&amount.to_le_bytes());
+        buffer.resize(16, 0);
+      }
+      NodeParams::UnsharpMask {
+        amount,
+        radius,
+        threshold,
+      } => {
+        buffer.extend_from_slice(&amount.to_le_bytes());
+        buffer.extend_from_slice(&radius.to_le_bytes());
+        buffer.extend_from_slice(&threshold.to_le_bytes());
+        buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
+      }
+      NodeParams::Clarity { amount, radius } => {
         buffer.extend_from_slice(&amount.to_le_bytes());
+        buffer.extend_from_slice(&radius.to_le_bytes());
         buffer.resize(16, 0);
       }
       NodeParams::Noise { amount, seed } => {
@@ -1325,6 +3696,30 @@ impl ImagePipeline {
         buffer.extend_from_slice(&(*seed as f32).to_le_bytes());
         buffer.resize(16, 0);
       }
+      NodeParams::Vignette {
+        amount,
+        midpoint,
+        roundness,
+        feather,
+      } => {
+        buffer.extend_from_slice(&amount.to_le_bytes());
+        buffer.extend_from_slice(&midpoint.to_le_bytes());
+        buffer.extend_from_slice(&roundness.to_le_bytes());
+        buffer.extend_from_slice(&feather.to_le_bytes());
+      }
+      NodeParams::Grain { amount, size, seed } => {
+        buffer.extend_from_slice(&amount.to_le_bytes());
+        buffer.extend_from_slice(&size.to_le_bytes());
+        buffer.extend_from_slice(&seed.to_le_bytes());
+        buffer.resize(16, 0);
+      }
+      NodeParams::Dehaze { amount } => {
+        let light = context.measured.ok_or("Haze colour was not measured")?;
+        for value in light {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer.extend_from_slice(&amount.to_le_bytes());
+      }
       NodeParams::Resize { width, height } => {
         buffer.extend_from_slice(&width.unwrap_or(0).to_le_bytes());
         buffer.extend_from_slice(&height.unwrap_or(0).to_le_bytes());
@@ -1341,10 +3736,112 @@ impl ImagePipeline {
         buffer.extend_from_slice(&(*width as f32).to_le_bytes());
         buffer.extend_from_slice(&(*height as f32).to_le_bytes());
       }
-      NodeParams::Mix { factor } => {
+      NodeParams::Rotate { quarter_turns } => {
+        buffer.extend_from_slice(&((quarter_turns % 4) as f32).to_le_bytes());
+        buffer.resize(16, 0);
+      }
+      NodeParams::Flip {
+        horizontal,
+        vertical,
+      } => {
+        buffer.extend_from_slice(&(*horizontal as u32 as f32).to_le_bytes());
+        buffer.extend_from_slice(&(*vertical as u32 as f32).to_le_bytes());
+        buffer.resize(16, 0);
+      }
+      NodeParams::Straighten {
+        angle, resampling, ..
+      } => {
+        // The shader rotates back with the sine and cosine computed here, like the CPU
+        let (sin, cos) = angle.to_radians().sin_cos();
+        buffer.extend_from_slice(&cos.to_le_bytes());
+        buffer.extend_from_slice(&sin.to_le_bytes());
+        buffer.extend_from_slice(&(*resampling as u32 as f32).to_le_bytes());
+        buffer.resize(16, 0);
+      }
+      NodeParams::Perspective {
+        corners,
+        resampling,
+      } => {
+        for row in geometry::perspective_matrix(corners)? {
+          for value in row {
+            buffer.extend_from_slice(&value.to_le_bytes());
+          }
+          buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
+        }
+        buffer.extend_from_slice(&(*resampling as u32 as f32).to_le_bytes());
+        buffer.resize(64, 0);
+      }
+      NodeParams::Mix { factor, use_alpha } => {
         buffer.extend_from_slice(&factor.to_le_bytes());
+        buffer.extend_from_slice(&(*use_alpha as u32 as f32).to_le_bytes());
+        buffer.resize(16, 0);
+      }
+      NodeParams::LuminanceMask { low, high, feather } => {
+        buffer.extend_from_slice(&low.to_le_bytes());
+        buffer.extend_from_slice(&high.to_le_bytes());
+        buffer.extend_from_slice(&feather.to_le_bytes());
+        buffer.extend_from_slice(&0.0f32.to_le_bytes()); // padding
+      }
+      NodeParams::ColorRangeMask {
+        hue,
+        hue_range,
+        hue_feather,
+        saturation,
+        luminance,
+        feather,
+      } => {
+        for value in [*hue, *hue_range, *hue_feather, *feather] {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in saturation.iter().chain(luminance) {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+      }
+      NodeParams::PaintedMask { .. } => {
+        // The bitmap is uploaded as the lookup table at binding 4
         buffer.resize(16, 0);
       }
+      NodeParams::LinearGradient {
+        center,
+        angle,
+        feather,
+        invert,
+      } => {
+        for value in center {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [*angle, *feather, *invert as u32 as f32] {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer.resize(32, 0);
+      }
+      NodeParams::RadialGradient {
+        center,
+        radius,
+        angle,
+        feather,
+        invert,
+      } => {
+        for value in center.iter().chain(radius) {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [*angle, *feather, *invert as u32 as f32] {
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer.resize(32, 0);
+      }
+      NodeParams::Custom { shader, params } => {
+        let custom = self
+          .custom_shaders
+          .get(shader)
+          .ok_or_else(|| format!("Unknown custom shader '{}'", shader))?;
+        for name in &custom.params {
+          let value = params.get(name).copied().unwrap_or(0.0);
+          buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        // Uniform buffers hold whole multiples of 16 bytes
+        buffer.resize(buffer.len().div_ceil(16).max(1) * 16, 0);
+      }
       NodeParams::None => {
         // Just add a dummy float for shaders that don't need parameters
         buffer.extend_from_slice(&0.0f32.to_le_bytes());
@@ -1352,6 +3849,25 @@ impl ImagePipeline {
       }
     }
 
+    // Nodes placing their effect in the image follow with the region they are
+    // dispatched on, so tiles line up
+    if matches!(
+      params,
+      NodeParams::Noise { .. }
+        | NodeParams::Vignette { .. }
+        | NodeParams::Grain { .. }
+        | NodeParams::Dehaze { .. }
+        | NodeParams::PaintedMask { .. }
+        | NodeParams::LinearGradient { .. }
+        | NodeParams::RadialGradient { .. }
+    ) {
+      let (x, y) = context.origin;
+      let (width, height) = context.size;
+      for value in [x, y, width, height] {
+        buffer.extend_from_slice(&(value as f32).to_le_bytes());
+      }
+    }
+
     Ok(buffer)
   }
 
@@ -1376,6 +3892,7 @@ impl ImagePipeline {
     self.connections.clear();
     self.input_node_id = None;
     self.output_node_id = None;
+    self.target_node_id = None;
     self.next_node_id = 0;
   }
 
@@ -1512,6 +4029,12 @@ mod tests {
     assert!(pipeline.output_node_id.is_none());
   }
 
+  /// Device for the tests that need a GPU adapter. They are ignored by default, run
+  /// them with `cargo test -- --ignored` on a machine with a GPU.
+  fn gpu() -> (Device, Queue) {
+    pollster::block_on(request_device()).expect("No GPU adapter available")
+  }
+
   #[test]
   fn test_tiling_calculation() {
     let pipeline = ImagePipeline::new();
@@ -1534,4 +4057,731 @@ mod tests {
     let buffer_size = aligned_bytes as u64 * max_dim as u64;
     assert!(buffer_size <= MAX_BUFFER_SIZE);
   }
+
+  #[test]
+  fn test_tiles_match_whole_image() {
+    // Tiles are cut and placed as on the GPU, but processed by the CPU backend
+    let mut pipeline = ImagePipeline::new();
+    pipeline.tile_threshold = pipeline.aligned_bytes_per_row(32) as u64 * 32;
+    assert_eq!(pipeline.calculate_max_processable_dimension(), 32);
+
+    let dimensions = (66, 60);
+    let input: Vec<u8> = (0..dimensions.0 * dimensions.1)
+      .flat_map(|i| {
+        let (x, y) = (i % 66, i / 66);
+        let stripes = ((x * 7 + y * 3) % 11) as f32 / 11.0;
+        [stripes, (y % 5) as f32 / 5.0, 0.5, 1.0]
+      })
+      .flat_map(f32::to_le_bytes)
+      .collect();
+    let image = CpuImage::from_bytes(&input, dimensions);
+
+    // Radii small enough for the kernels to fit into the tiles
+    for (node_type, params) in [
+      (NodeType::Blur, NodeParams::Blur { radius: 4.0 }),
+      (NodeType::Sharpen, NodeParams::Sharpen { amount: 0.8 }),
+      (
+        NodeType::Denoise,
+        NodeParams::Denoise {
+          luminance: 0.5,
+          chroma: 0.5,
+          radius: 3.0,
+        },
+      ),
+      (
+        NodeType::UnsharpMask,
+        NodeParams::UnsharpMask {
+          amount: 1.0,
+          radius: 5.0,
+          threshold: 0.0,
+        },
+      ),
+      (
+        NodeType::Clarity,
+        NodeParams::Clarity {
+          amount: 0.6,
+          radius: 6.0,
+        },
+      ),
+      (NodeType::Invert, NodeParams::None),
+    ] {
+      let Footprint::Local(overlap) = node_type.footprint(&params, dimensions) else {
+        panic!("{:?} can not be tiled", node_type);
+      };
+      let tiles = pipeline.plan_tiles(dimensions, overlap).unwrap();
+      let area = |tile: &Tile| tile.region[2] * tile.region[3];
+      let covered: u32 = tiles.iter().map(area).sum();
+      assert_eq!(covered, dimensions.0 * dimensions.1);
+
+      let mut tiled = vec![0u8; input.len()];
+      for tile in &tiles {
+        let [x, y, width, height] = tile.padded;
+        let data = ImagePipeline::extract_tile(&input, dimensions.0, x, y, width, height);
+        let tile_image = CpuImage::from_bytes(&data, (width, height));
+        let processed =
+          cpu::process_node(&node_type, &params, &tile_image, None, (width, height))
+            .unwrap();
+        ImagePipeline::place_tile(&mut tiled, dimensions.0, tile, &processed.to_bytes());
+      }
+
+      let whole = cpu::process_node(&node_type, &params, &image, None, dimensions);
+      assert!(tiled == whole.unwrap().to_bytes(), "{:?}", node_type);
+    }
+  }
+
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_tiled_matches_untiled() {
+    let (device, queue) = gpu();
+
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let balance_id = pipeline.add_node("Balance".to_string(), NodeType::WhiteBalance);
+    let dehaze_id = pipeline.add_node("Dehaze".to_string(), NodeType::Dehaze);
+    let vignette_id = pipeline.add_node("Vignette".to_string(), NodeType::Vignette);
+    let grain_id = pipeline.add_node("Grain".to_string(), NodeType::Grain);
+    let noise_id = pipeline.add_node("Noise".to_string(), NodeType::Noise);
+    let denoise_id = pipeline.add_node("Denoise".to_string(), NodeType::Denoise);
+    let sharpen_id = pipeline.add_node("Sharpen".to_string(), NodeType::Sharpen);
+    let blur_id = pipeline.add_node("Blur".to_string(), NodeType::Blur);
+    let mix_id = pipeline.add_node("Mix".to_string(), NodeType::Mix);
+    let gradient_id = pipeline.add_node("Gradient".to_string(), NodeType::RadialGradient);
+    let mask_id = pipeline.add_node("Mask".to_string(), NodeType::Mask);
+
+    if let NodeParams::WhiteBalance { auto_adjust, .. } =
+      &mut pipeline.get_node_mut(balance_id).unwrap().params
+    {
+      *auto_adjust = true;
+    }
+    pipeline.get_node_mut(sharpen_id).unwrap().params =
+      NodeParams::Sharpen { amount: 0.8 };
+    pipeline.get_node_mut(blur_id).unwrap().params = NodeParams::Blur { radius: 3.0 };
+    assert_eq!(
+      NodeType::Blur.footprint(&NodeParams::Blur { radius: 3.0 }, (66, 60)),
+      Footprint::Local(5)
+    );
+    for (from, from_output, to, to_input) in [
+      (input_id, "image", balance_id, "image"),
+      (balance_id, "image", dehaze_id, "image"),
+      (dehaze_id, "image", vignette_id, "image"),
+      (vignette_id, "image", grain_id, "image"),
+      (grain_id, "image", noise_id, "image"),
+      (noise_id, "image", denoise_id, "image"),
+      (denoise_id, "image", sharpen_id, "image"),
+      (sharpen_id, "image", blur_id, "image"),
+      (input_id, "image", mix_id, "image1"),
+      (blur_id, "image", mix_id, "image2"),
+      (input_id, "image", gradient_id, "image"),
+      (mix_id, "image", mask_id, "image"),
+      (gradient_id, "mask", mask_id, "mask"),
+    ] {
+      pipeline
+        .connect_nodes(from, from_output.to_string(), to, to_input.to_string())
+        .unwrap();
+    }
+    pipeline.init_gpu(device.clone(), queue.clone());
+
+    let dimensions = (66, 60);
+    let input: Vec<u8> = (0..dimensions.0 * dimensions.1)
+      .flat_map(|i| {
+        let (x, y) = (i % 66, i / 66);
+        let stripes = ((x * 7 + y * 3) % 11) as f32 / 11.0;
+        [stripes, (y % 5) as f32 / 5.0, 0.5, 1.0]
+      })
+      .flat_map(f32::to_le_bytes)
+      .collect();
+
+    let order = pipeline.get_execution_order().unwrap();
+    let final_node = pipeline.final_node(&order);
+    let mut run = |pipeline: &ImagePipeline| {
+      pollster::block_on(pipeline.process_buffered(
+        &device,
+        &queue,
+        &order,
+        final_node,
+        input.clone(),
+        dimensions,
+      ))
+      .unwrap()
+    };
+    let untiled = run(&pipeline);
+
+    // Lower the threshold just below the image, so it is split into 64 pixel tiles
+    pipeline.tile_threshold = pipeline.aligned_bytes_per_row(64) as u64 * 64;
+    assert!(pipeline.needs_tiling(dimensions.0, dimensions.1));
+    assert_eq!(pipeline.calculate_max_processable_dimension(), 64);
+    let tiled = run(&pipeline);
+
+    assert_eq!(tiled, untiled);
+  }
+
+  #[test]
+  fn test_plan_dispatch() {
+    let pipeline = ImagePipeline::new();
+    let node = |node_type: NodeType| ProcessingNode::new(0, String::new(), node_type);
+    let mut resize = node(NodeType::Resize);
+    resize.params = NodeParams::Resize {
+      width: Some(100),
+      height: Some(50),
+    };
+    let plan = |node: &ProcessingNode, dimensions: (u32, u32)| {
+      pipeline.plan_dispatch(node, dimensions, None, 64)
+    };
+
+    assert_eq!(
+      plan(&resize, (32, 16)).unwrap_err(),
+      "Resize node needs the 100x50 image as a whole, which exceeds the GPU limit of 64 \
+       pixels per side"
+    );
+    assert!(plan(&node(NodeType::Custom), (65, 10)).is_err());
+    assert!(plan(&node(NodeType::Rotate), (10, 65)).is_err());
+    assert_eq!(plan(&node(NodeType::Rotate), (64, 64)), Ok(Dispatch::Whole));
+    let crop = node(NodeType::Crop);
+    let error = pipeline
+      .plan_dispatch(&crop, (60, 60), Some((65, 1)), 64)
+      .unwrap_err();
+    assert!(error.starts_with("Crop node needs the 65x1 image"));
+
+    // Nodes placing their effect in the image are split like filters
+    for node_type in [
+      NodeType::Noise,
+      NodeType::Vignette,
+      NodeType::Grain,
+      NodeType::Dehaze,
+      NodeType::WhiteBalance,
+      NodeType::PaintedMask,
+      NodeType::LinearGradient,
+      NodeType::RadialGradient,
+      NodeType::Blur,
+    ] {
+      let node = node(node_type);
+      assert_eq!(plan(&node, (100, 10)), Ok(Dispatch::Tiled));
+      assert_eq!(plan(&node, (10, 10)), Ok(Dispatch::Whole));
+    }
+  }
+
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_buffered_global_node_over_texture_limit() {
+    let (device, queue) = gpu();
+
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let noise_id = pipeline.add_node("Noise".to_string(), NodeType::Noise);
+    pipeline
+      .connect_nodes(input_id, "image".to_string(), noise_id, "image".to_string())
+      .unwrap();
+    pipeline.init_gpu(device.clone(), queue.clone());
+
+    // One pixel wider than the largest texture
+    let dimensions = (device.limits().max_texture_dimension_2d + 1, 1);
+    let input = vec![0u8; (dimensions.0 * BYTES_PER_PIXEL) as usize];
+    let run = |pipeline: &ImagePipeline| {
+      let order = pipeline.get_execution_order().unwrap();
+      let final_node = pipeline.final_node(&order);
+      pollster::block_on(pipeline.process_buffered(
+        &device,
+        &queue,
+        &order,
+        final_node,
+        input.clone(),
+        dimensions,
+      ))
+    };
+
+    // Noise is split into tiles, a flip needs the whole image
+    assert_eq!(run(&pipeline).unwrap().1, dimensions);
+    let flip_id = pipeline.add_node("Flip".to_string(), NodeType::Flip);
+    pipeline
+      .connect_nodes(noise_id, "image".to_string(), flip_id, "image".to_string())
+      .unwrap();
+    let error = run(&pipeline).unwrap_err();
+    assert!(error.starts_with("Flip node needs the"), "{}", error);
+  }
+
+  #[test]
+  fn test_branching_graph_routing() {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let blur_id = pipeline.add_node("Blur".to_string(), NodeType::Blur);
+    let invert_id = pipeline.add_node("Invert".to_string(), NodeType::Invert);
+    let mix_id = pipeline.add_node("Mix".to_string(), NodeType::Mix);
+
+    for (from, to, to_input) in [
+      (input_id, blur_id, "image"),
+      (input_id, invert_id, "image"),
+      (blur_id, mix_id, "image1"),
+      (invert_id, mix_id, "image2"),
+    ] {
+      pipeline
+        .connect_nodes(from, "image".to_string(), to, to_input.to_string())
+        .unwrap();
+    }
+
+    let order = pipeline.get_execution_order().unwrap();
+    assert_eq!(order.last(), Some(&mix_id));
+
+    // Each named input is fed by the node connected to it
+    assert_eq!(pipeline.input_sources(input_id), vec![None]);
+    assert_eq!(
+      pipeline.input_sources(mix_id),
+      vec![Some(blur_id), Some(invert_id)]
+    );
+
+    // The input node result is shared by both branches
+    let pending = pipeline.pending_consumers(&order);
+    assert_eq!(pending.get(&Some(input_id)), Some(&2));
+    assert_eq!(pending.get(&Some(blur_id)), Some(&1));
+    assert_eq!(pending.get(&Some(invert_id)), Some(&1));
+
+    // Without an output node the sink of the graph is returned
+    assert_eq!(pipeline.final_node(&order), Some(mix_id));
+  }
+
+  #[test]
+  fn test_plan_dimensions() {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let resize_id = pipeline.add_node("Resize".to_string(), NodeType::Resize);
+    let invert_id = pipeline.add_node("Invert".to_string(), NodeType::Invert);
+
+    pipeline.get_node_mut(resize_id).unwrap().params = NodeParams::Resize {
+      width: Some(100),
+      height: None,
+    };
+    pipeline
+      .connect_nodes(input_id, "image".to_string(), resize_id, "image".to_string())
+      .unwrap();
+    pipeline
+      .connect_nodes(resize_id, "image".to_string(), invert_id, "image".to_string())
+      .unwrap();
+
+    let order = pipeline.get_execution_order().unwrap();
+    let planned = pipeline.plan_dimensions(&order, (400, 200)).unwrap();
+    assert_eq!(planned[&Some(input_id)], (400, 200));
+    assert_eq!(planned[&Some(resize_id)], (100, 50));
+    assert_eq!(planned[&Some(invert_id)], (100, 50));
+  }
+
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_resident_matches_buffered() {
+    let (device, queue) = gpu();
+
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let brightness_id = pipeline.add_node("Brightness".to_string(), NodeType::Brightness);
+    let invert_id = pipeline.add_node("Invert".to_string(), NodeType::Invert);
+    let balance_id = pipeline.add_node("Balance".to_string(), NodeType::WhiteBalance);
+    let blur_id = pipeline.add_node("Blur".to_string(), NodeType::Blur);
+    let mix_id = pipeline.add_node("Mix".to_string(), NodeType::Mix);
+    let resize_id = pipeline.add_node("Resize".to_string(), NodeType::Resize);
+
+    // The gains are measured from the inverted image in the middle of the graph
+    if let NodeParams::WhiteBalance { auto_adjust, .. } =
+      &mut pipeline.get_node_mut(balance_id).unwrap().params
+    {
+      *auto_adjust = true;
+    }
+    pipeline.get_node_mut(blur_id).unwrap().params = NodeParams::Blur { radius: 3.0 };
+    pipeline.get_node_mut(resize_id).unwrap().params = NodeParams::Resize {
+      width: Some(20),
+      height: Some(10),
+    };
+    for (from, to, to_input) in [
+      (input_id, brightness_id, "image"),
+      (brightness_id, invert_id, "image"),
+      (invert_id, balance_id, "image"),
+      (balance_id, blur_id, "image"),
+      (brightness_id, mix_id, "image1"),
+      (blur_id, mix_id, "image2"),
+      (mix_id, resize_id, "image"),
+    ] {
+      pipeline
+        .connect_nodes(from, "image".to_string(), to, to_input.to_string())
+        .unwrap();
+    }
+    pipeline.init_gpu(device.clone(), queue.clone());
+
+    let dimensions = (37, 23);
+    let input: Vec<u8> = (0..dimensions.0 * dimensions.1)
+      .flat_map(|i| [(i % 37) as f32 / 37.0, (i % 23) as f32 / 23.0, 0.25, 1.0])
+      .flat_map(f32::to_le_bytes)
+      .collect();
+
+    let resident = pollster::block_on(pipeline.process(input.clone(), dimensions)).unwrap();
+
+    let order = pipeline.get_execution_order().unwrap();
+    let final_node = pipeline.final_node(&order);
+    let buffered = pollster::block_on(pipeline.process_buffered(
+      &device, &queue, &order, final_node, input, dimensions,
+    ))
+    .unwrap();
+
+    assert_eq!(resident.1, (20, 10));
+    assert_eq!(resident, buffered);
+  }
+
+  /// Rotate, straighten and perspective nodes in a chain, with their ids
+  fn transform_chain() -> (ImagePipeline, [usize; 3]) {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let rotate_id = pipeline.add_node("Rotate".to_string(), NodeType::Rotate);
+    let straighten_id = pipeline.add_node("Straighten".to_string(), NodeType::Straighten);
+    let perspective_id =
+      pipeline.add_node("Perspective".to_string(), NodeType::Perspective);
+
+    pipeline.get_node_mut(rotate_id).unwrap().params =
+      NodeParams::Rotate { quarter_turns: 1 };
+    pipeline.get_node_mut(straighten_id).unwrap().params = NodeParams::Straighten {
+      angle: 4.0,
+      crop: true,
+      resampling: Resampling::Bicubic,
+    };
+    pipeline.get_node_mut(perspective_id).unwrap().params = NodeParams::Perspective {
+      corners: [[0.1, 0.0], [0.9, 0.0], [1.0, 1.0], [0.0, 1.0]],
+      resampling: Resampling::Bilinear,
+    };
+    for (from, to) in [
+      (input_id, rotate_id),
+      (rotate_id, straighten_id),
+      (straighten_id, perspective_id),
+    ] {
+      pipeline
+        .connect_nodes(from, "image".to_string(), to, "image".to_string())
+        .unwrap();
+    }
+    (pipeline, [rotate_id, straighten_id, perspective_id])
+  }
+
+  #[test]
+  fn test_transforms_change_dimensions() {
+    let (pipeline, [rotate_id, straighten_id, perspective_id]) = transform_chain();
+    let dimensions = (41, 29);
+    let order = pipeline.get_execution_order().unwrap();
+    let planned = pipeline.plan_dimensions(&order, dimensions).unwrap();
+    assert_eq!(planned[&Some(rotate_id)], (29, 41));
+    let straightened = geometry::straightened_dimensions((29, 41), 4.0, true);
+    assert_eq!(planned[&Some(straighten_id)], straightened);
+    assert!(straightened.0 < 29 && straightened.1 < 41);
+    assert_eq!(
+      planned[&Some(perspective_id)],
+      geometry::perspective_dimensions(
+        straightened,
+        &[[0.1, 0.0], [0.9, 0.0], [1.0, 1.0], [0.0, 1.0]]
+      )
+    );
+  }
+
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_transforms_resident_matches_buffered() {
+    let (device, queue) = gpu();
+    let (mut pipeline, [.., perspective_id]) = transform_chain();
+    pipeline.init_gpu(device.clone(), queue.clone());
+
+    let dimensions = (41, 29);
+    let order = pipeline.get_execution_order().unwrap();
+    let planned = pipeline.plan_dimensions(&order, dimensions).unwrap();
+    let input: Vec<u8> = (0..dimensions.0 * dimensions.1)
+      .flat_map(|i| [(i % 41) as f32 / 41.0, (i % 29) as f32 / 29.0, 0.5, 1.0])
+      .flat_map(f32::to_le_bytes)
+      .collect();
+    let resident =
+      pollster::block_on(pipeline.process(input.clone(), dimensions)).unwrap();
+    let final_node = pipeline.final_node(&order);
+    let buffered = pollster::block_on(
+      pipeline.process_buffered(&device, &queue, &order, final_node, input, dimensions),
+    )
+    .unwrap();
+
+    assert_eq!(resident.1, planned[&Some(perspective_id)]);
+    assert_eq!(resident, buffered);
+  }
+
+  /// A custom shader scaling and offsetting colours
+  fn gain_shader() -> CustomShader {
+    let source = "
+struct Params {
+    gain: f32,
+    offset: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let coords = vec2<i32>(global_id.xy);
+    let color = textureLoad(input_texture, coords, 0);
+    textureStore(output_texture, coords, vec4<f32>(color.rgb * params.gain + params.offset, color.a));
+}
+";
+    CustomShader::from_source("gain", source, std::path::Path::new("gain.wgsl")).unwrap()
+  }
+
+  /// A pipeline running the custom `gain` shader with `values`, and its input
+  fn gain_pipeline(values: &[(&str, f32)]) -> (ImagePipeline, Vec<u8>, (u32, u32)) {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let custom_id = pipeline.add_node("Gain".to_string(), NodeType::Custom);
+    pipeline
+      .connect_nodes(
+        input_id,
+        "image".to_string(),
+        custom_id,
+        "image".to_string(),
+      )
+      .unwrap();
+    pipeline.get_node_mut(custom_id).unwrap().params = NodeParams::Custom {
+      shader: "gain".to_string(),
+      params: values
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value))
+        .collect(),
+    };
+
+    let dimensions = (4, 2);
+    let input: Vec<u8> = [0.25f32, 0.5, 0.125, 1.0]
+      .repeat(8)
+      .into_iter()
+      .flat_map(f32::to_le_bytes)
+      .collect();
+    (pipeline, input, dimensions)
+  }
+
+  #[test]
+  fn test_custom_shader_node() {
+    let (mut pipeline, input, dimensions) = gain_pipeline(&[("gain", 2.0)]);
+    let error = pollster::block_on(pipeline.process(input, dimensions)).unwrap_err();
+    assert!(error.contains("unknown custom shader 'gain'"), "{}", error);
+
+    let (mut pipeline, input, dimensions) = gain_pipeline(&[("gian", 2.0)]);
+    pipeline.add_custom_shader(gain_shader());
+    let error = pollster::block_on(pipeline.process(input, dimensions)).unwrap_err();
+    assert!(error.contains("no parameter 'gian'"), "{}", error);
+  }
+
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_custom_shader_node_gpu() {
+    let (device, queue) = gpu();
+    let (mut pipeline, input, dimensions) = gain_pipeline(&[("gain", 2.0)]);
+    pipeline.add_custom_shader(gain_shader());
+    pipeline.init_gpu(device, queue);
+
+    // The offset left out of the parameters is 0.0
+    let (output, output_dimensions) =
+      pollster::block_on(pipeline.process(input, dimensions)).unwrap();
+    assert_eq!(output_dimensions, dimensions);
+    let pixel: Vec<f32> = output[..16]
+      .chunks_exact(4)
+      .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+      .collect();
+    assert_eq!(pixel, [0.5, 1.0, 0.25, 1.0]);
+  }
+
+  /// A pipeline inverting its input, and a gradient with values below 0 and above 1
+  /// in every channel
+  fn scopes_pipeline() -> (ImagePipeline, usize, Vec<u8>, (u32, u32)) {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let invert_id = pipeline.add_node("Invert".to_string(), NodeType::Invert);
+    pipeline
+      .connect_nodes(input_id, "image".to_string(), invert_id, "image".to_string())
+      .unwrap();
+
+    let dimensions = (37, 23);
+    let input: Vec<u8> = (0..dimensions.0 * dimensions.1)
+      .flat_map(|index| {
+        let (x, y) = (index % dimensions.0, index / dimensions.0);
+        let value = |offset: u32| ((x * 7 + y * 13 + offset) % 300) as f32 / 256.0 - 0.1;
+        [value(0), value(90), value(180), 1.0]
+      })
+      .flat_map(f32::to_le_bytes)
+      .collect();
+    (pipeline, input_id, input, dimensions)
+  }
+
+  #[test]
+  fn test_analyze_target_node() {
+    let (mut pipeline, input_id, input, dimensions) = scopes_pipeline();
+    assert!(pipeline.set_target_node(7).is_err());
+
+    // The target node's result is returned in place of the output
+    pipeline.set_target_node(input_id).unwrap();
+    let (output, _) =
+      pollster::block_on(pipeline.process(input.clone(), dimensions)).unwrap();
+    assert_eq!(output, input);
+
+    let expected = Scopes::from_pixels(&input, dimensions, 16);
+    let cpu = pollster::block_on(pipeline.analyze(&input, dimensions, 16)).unwrap();
+    assert_eq!(cpu, expected);
+  }
+
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_analyze_gpu_matches_cpu() {
+    let (device, queue) = gpu();
+    let (mut pipeline, _, input, dimensions) = scopes_pipeline();
+    pipeline.init_gpu(device, queue);
+    let expected = Scopes::from_pixels(&input, dimensions, 16);
+    let gpu = pollster::block_on(pipeline.analyze(&input, dimensions, 16)).unwrap();
+
+    // Rounding may move values on a bin edge into the neighbouring bin
+    let pixels = dimensions.0 * dimensions.1;
+    for (name, gpu, cpu) in [
+      ("histogram", &gpu.histogram, &expected.histogram),
+      ("waveform", &gpu.waveform, &expected.waveform),
+      ("vectorscope", &gpu.vectorscope, &expected.vectorscope),
+    ] {
+      assert_eq!(gpu.len(), cpu.len(), "{}", name);
+      assert_eq!(gpu.iter().sum::<u32>(), cpu.iter().sum::<u32>(), "{}", name);
+      let moved: u32 = gpu.iter().zip(cpu).map(|(a, b)| a.abs_diff(*b)).sum();
+      assert!(
+        moved <= pixels / 100,
+        "{} differs in {} counts",
+        name,
+        moved
+      );
+    }
+  }
+
+  #[test]
+  fn test_cpu_backend_without_gpu() {
+    let mut pipeline = ImagePipeline::new();
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let invert_id = pipeline.add_node("Invert".to_string(), NodeType::Invert);
+    let resize_id = pipeline.add_node("Resize".to_string(), NodeType::Resize);
+    pipeline.get_node_mut(resize_id).unwrap().params = NodeParams::Resize {
+      width: Some(2),
+      height: None,
+    };
+    pipeline
+      .connect_nodes(input_id, "image".to_string(), invert_id, "image".to_string())
+      .unwrap();
+    pipeline
+      .connect_nodes(invert_id, "image".to_string(), resize_id, "image".to_string())
+      .unwrap();
+
+    let input: Vec<u8> = [0.25f32, 0.5, 0.75, 1.0]
+      .repeat(16)
+      .into_iter()
+      .flat_map(f32::to_le_bytes)
+      .collect();
+
+    // No device was initialized, so the CPU backend runs the graph
+    let (output, dimensions) = pollster::block_on(pipeline.process(input, (4, 4))).unwrap();
+    assert_eq!(dimensions, (2, 2));
+
+    let first_pixel: Vec<f32> = output[..16]
+      .chunks(4)
+      .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+      .collect();
+    assert_eq!(first_pixel, vec![0.75, 0.5, 0.25, 1.0]);
+  }
+
+  /// Input -> Invert -> Brightness chain and a 2x1 test image
+  fn cached_chain(backend: Backend) -> (ImagePipeline, usize, usize, Vec<u8>) {
+    let mut pipeline = ImagePipeline::new();
+    pipeline.set_backend(backend);
+    let input_id = pipeline.add_node("Input".to_string(), NodeType::ImageInput);
+    let invert_id = pipeline.add_node("Invert".to_string(), NodeType::Invert);
+    let brightness_id = pipeline.add_node("Brightness".to_string(), NodeType::Brightness);
+    pipeline
+      .connect_nodes(input_id, "image".to_string(), invert_id, "image".to_string())
+      .unwrap();
+    pipeline
+      .connect_nodes(invert_id, "image".to_string(), brightness_id, "image".to_string())
+      .unwrap();
+    pipeline.set_result_cache(ResultCache::new());
+
+    let input = [0.25f32, 0.5, 0.75, 1.0]
+      .repeat(2)
+      .into_iter()
+      .flat_map(f32::to_le_bytes)
+      .collect();
+    (pipeline, invert_id, brightness_id, input)
+  }
+
+  #[test]
+  fn test_result_cache_reuses_unchanged_nodes() {
+    let (mut pipeline, invert_id, brightness_id, input) = cached_chain(Backend::Cpu);
+    pollster::block_on(pipeline.process(input.clone(), (2, 1))).unwrap();
+    assert_eq!(pipeline.result_cache.as_ref().unwrap().len(), 3);
+
+    // Replace the cached invert output with a marker image
+    let order = pipeline.get_execution_order().unwrap();
+    let hashes = pipeline.result_hashes(&order, pipeline.input_hash(&input, (2, 1)));
+    let marker = CpuImage {
+      width: 2,
+      height: 1,
+      pixels: vec![[0.2, 0.2, 0.2, 1.0]; 2],
+    };
+    pipeline
+      .result_cache
+      .as_mut()
+      .unwrap()
+      .insert(hashes[&Some(invert_id)], CachedResult::Image(marker));
+
+    // Changing the last node only reprocesses that node, on top of the marker
+    pipeline
+      .get_node_mut(brightness_id)
+      .unwrap()
+      .set_params(NodeParams::Brightness { value: 1.0 });
+    let (output, _) = pollster::block_on(pipeline.process(input.clone(), (2, 1))).unwrap();
+    assert_eq!(f32::from_le_bytes(output[..4].try_into().unwrap()), 0.4);
+
+    // Changing an upstream node invalidates everything after it
+    pipeline.get_node_mut(invert_id).unwrap().set_enabled(false);
+    let (output, _) = pollster::block_on(pipeline.process(input, (2, 1))).unwrap();
+    assert_eq!(f32::from_le_bytes(output[..4].try_into().unwrap()), 0.5);
+  }
+
+  #[test]
+  #[ignore = "needs a GPU adapter"]
+  fn test_result_cache_matches_uncached_gpu() {
+    let (device, queue) = gpu();
+
+    let (mut cached, invert_id, brightness_id, input) = cached_chain(Backend::Gpu);
+    cached.init_gpu(device.clone(), queue.clone());
+    pollster::block_on(cached.process(input.clone(), (2, 1))).unwrap();
+
+    let (mut uncached, ..) = cached_chain(Backend::Gpu);
+    uncached.take_result_cache();
+    uncached.init_gpu(device, queue);
+
+    for pipeline in [&mut cached, &mut uncached] {
+      pipeline
+        .get_node_mut(brightness_id)
+        .unwrap()
+        .set_params(NodeParams::Brightness { value: 0.5 });
+      pipeline.get_node_mut(invert_id).unwrap().set_enabled(false);
+    }
+
+    let expected = pollster::block_on(uncached.process(input.clone(), (2, 1))).unwrap();
+    assert_eq!(
+      pollster::block_on(cached.process(input.clone(), (2, 1))).unwrap(),
+      expected
+    );
+    // A fully cached run reads the previous result back
+    assert_eq!(
+      pollster::block_on(cached.process(input, (2, 1))).unwrap(),
+      expected
+    );
+  }
+
+  #[test]
+  fn test_unconnected_input_reads_pipeline_image() {
+    let mut pipeline = ImagePipeline::new();
+    let mix_id = pipeline.add_node("Mix".to_string(), NodeType::Mix);
+
+    assert_eq!(pipeline.input_sources(mix_id), vec![None, None]);
+  }
 }
@@ -1,5 +1,10 @@
 struct Params {
     radius: f32,
+    // Axis of this pass, (1, 0) for rows and (0, 1) for columns
+    direction_x: f32,
+    direction_y: f32,
+    // Kernel half width in pixels, see `blur_extent`
+    extent: f32,
 }
 
 @group(0) @binding(0)
@@ -11,6 +16,8 @@ var output_texture: texture_storage_2d<rgba32float, write>;
 @group(0) @binding(2)
 var<uniform> params: Params;
 
+// One pass of a separable Gaussian blur. The node runs a horizontal and a vertical
+// pass, so the cost grows linearly with the radius.
 @compute @workgroup_size(8, 8)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let dimensions = textureDimensions(input_texture);
@@ -20,35 +27,28 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         return;
     }
 
-    let blur_radius = max(1.0, params.radius);
-    let kernel_size = i32(blur_radius * 2.0) + 1;
-    let half_kernel = kernel_size / 2;
+    let extent = i32(params.extent);
+    if (extent == 0) {
+        textureStore(output_texture, coords, textureLoad(input_texture, coords, 0));
+        return;
+    }
+
+    // The radius spans two standard deviations
+    let sigma = params.radius * 0.5;
+    let direction = vec2<i32>(i32(params.direction_x), i32(params.direction_y));
+    let max_coords = vec2<i32>(dimensions) - vec2<i32>(1);
 
     var color_sum = vec4<f32>(0.0);
     var weight_sum = 0.0;
-
-    // Simple box blur
-    for (var y = -half_kernel; y <= half_kernel; y++) {
-        for (var x = -half_kernel; x <= half_kernel; x++) {
-            let sample_coords = coords + vec2<i32>(x, y);
-
-            // Clamp coordinates to texture bounds
-            let clamped_coords = vec2<i32>(
-                clamp(sample_coords.x, 0, i32(dimensions.x) - 1),
-                clamp(sample_coords.y, 0, i32(dimensions.y) - 1)
-            );
-
-            let sample_color = textureLoad(input_texture, clamped_coords, 0);
-
-            // Calculate weight based on distance from center
-            let distance = length(vec2<f32>(f32(x), f32(y)));
-            let weight = exp(-distance * distance / (2.0 * blur_radius * blur_radius));
-
-            color_sum += sample_color * weight;
-            weight_sum += weight;
-        }
+    for (var i = -extent; i <= extent; i++) {
+        // Clamp coordinates to texture bounds
+        let sample_coords = clamp(coords + direction * i, vec2<i32>(0), max_coords);
+        let offset = f32(i);
+        let weight = exp(-offset * offset / (2.0 * sigma * sigma));
+
+        color_sum += textureLoad(input_texture, sample_coords, 0) * weight;
+        weight_sum += weight;
     }
 
-    let final_color = color_sum / weight_sum;
-    textureStore(output_texture, coords, final_color);
+    textureStore(output_texture, coords, color_sum / weight_sum);
 }
@@ -0,0 +1,56 @@
+// Colour grading wheels, see grading.rs for the tonal ranges and the tints
+
+struct Params {
+    // Hue in degrees, saturation and luminance of each wheel
+    shadows: vec4<f32>,
+    midtones: vec4<f32>,
+    highlights: vec4<f32>,
+    balance: f32,
+    blending: f32,
+    _padding1: f32,
+    _padding2: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+const STRENGTH: f32 = 0.2;
+
+// Offset a wheel adds at full weight: a tint without luma plus a brightness change
+fn wheel_offset(wheel: vec4<f32>) -> vec3<f32> {
+    let t = wheel.x / 360.0 + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0);
+    let color = clamp(abs(fract(t) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+    return ((color - dot(color, LUMA)) * wheel.y + wheel.z) * STRENGTH;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let color = textureLoad(input_texture, coords, 0);
+
+    // pow is undefined for zero on some GPUs
+    let x = pow(clamp(dot(color.rgb, LUMA), 1e-6, 1.0), exp2(-params.balance));
+    let overlap = clamp(params.blending, 0.01, 1.0) / 3.0;
+    let shadows = 1.0 - smoothstep(1.0 / 3.0 - overlap, 1.0 / 3.0 + overlap, x);
+    let highlights = smoothstep(2.0 / 3.0 - overlap, 2.0 / 3.0 + overlap, x);
+    let midtones = 1.0 - shadows - highlights;
+
+    let graded = color.rgb
+        + wheel_offset(params.shadows) * shadows
+        + wheel_offset(params.midtones) * midtones
+        + wheel_offset(params.highlights) * highlights;
+    textureStore(output_texture, coords, vec4<f32>(max(graded, vec3<f32>(0.0)), color.a));
+}
@@ -0,0 +1,89 @@
+struct Params {
+    hue: f32,
+    hue_range: f32,
+    hue_feather: f32,
+    feather: f32,
+    saturation_min: f32,
+    saturation_max: f32,
+    luminance_min: f32,
+    luminance_max: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+fn srgb_encode(c: f32) -> f32 {
+    if (c <= 0.0031308) {
+        return c * 12.92;
+    }
+    return 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+}
+
+// 1.0 inside low..high, fading to 0.0 over `feather` outside of it
+fn range_weight(value: f32, low: f32, high: f32, feather: f32) -> f32 {
+    let softness = max(feather, 1e-5);
+    return smoothstep(low - softness, low, value) * (1.0 - smoothstep(high, high + softness, value));
+}
+
+fn rgb_to_hsv(rgb: vec3<f32>) -> vec3<f32> {
+    let max_val = max(max(rgb.r, rgb.g), rgb.b);
+    let min_val = min(min(rgb.r, rgb.g), rgb.b);
+    let delta = max_val - min_val;
+
+    var hue = 0.0;
+    let saturation = select(0.0, delta / max_val, max_val > 0.0);
+
+    if (delta > 0.0) {
+        if (max_val == rgb.r) {
+            hue = (rgb.g - rgb.b) / delta;
+            if (rgb.g < rgb.b) {
+                hue += 6.0;
+            }
+        } else if (max_val == rgb.g) {
+            hue = (rgb.b - rgb.r) / delta + 2.0;
+        } else {
+            hue = (rgb.r - rgb.g) / delta + 4.0;
+        }
+        hue /= 6.0;
+    }
+
+    return vec3<f32>(hue, saturation, max_val);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let input_color = textureLoad(input_texture, coords, 0);
+
+    // Hue and saturation of the encoded colour, perceptual lightness
+    let linear = max(input_color.rgb, vec3<f32>(0.0));
+    let encoded = vec3<f32>(srgb_encode(linear.r), srgb_encode(linear.g), srgb_encode(linear.b));
+    let hsv = rgb_to_hsv(encoded);
+    let lightness = srgb_encode(dot(linear, vec3<f32>(0.2126, 0.7152, 0.0722)));
+
+    // Distance to the selected hue around the colour wheel, in degrees
+    let hue_distance = abs(fract(hsv.x - params.hue / 360.0 + 0.5) - 0.5) * 360.0;
+    let hue_weight = 1.0 - smoothstep(
+        params.hue_range,
+        params.hue_range + max(params.hue_feather, 1e-5),
+        hue_distance
+    );
+
+    let mask_value = hue_weight
+        * range_weight(hsv.y, params.saturation_min, params.saturation_max, params.feather)
+        * range_weight(lightness, params.luminance_min, params.luminance_max, params.feather);
+
+    textureStore(output_texture, coords, vec4<f32>(vec3<f32>(mask_value), 1.0));
+}
@@ -0,0 +1,54 @@
+struct Params {
+    unused: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// Baked master and per-channel curves: a single row with one RGB entry per input level
+@group(0) @binding(4)
+var lookup_table: texture_2d<f32>;
+
+fn apply_curve(value: f32, channel: i32) -> f32 {
+    let size = i32(textureDimensions(lookup_table).x);
+    let clamped = clamp(value, 0.0, 1.0);
+    let position = clamped * f32(size - 1);
+    let index = i32(floor(position));
+    let next = min(index + 1, size - 1);
+    let t = position - f32(index);
+    let curved = mix(
+        textureLoad(lookup_table, vec2<i32>(index, 0), 0)[channel],
+        textureLoad(lookup_table, vec2<i32>(next, 0), 0)[channel],
+        t
+    );
+
+    // Keep HDR values beyond the curve range at their distance from it
+    return curved + (value - clamped);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let input_color = textureLoad(input_texture, coords, 0);
+
+    let curved_color = vec4<f32>(
+        apply_curve(input_color.r, 0),
+        apply_curve(input_color.g, 1),
+        apply_curve(input_color.b, 2),
+        input_color.a
+    );
+
+    textureStore(output_texture, coords, curved_color);
+}
@@ -0,0 +1,64 @@
+// Removes or adds atmospheric haze with the dark channel prior: in haze-free areas
+// some colour channel is close to black, so how far the darkest channel is raised
+// towards the haze colour tells how much haze lies over a pixel.
+
+struct Params {
+    // The haze colour, measured once per image in dehaze.rs
+    light: vec3<f32>,
+    // Positive values remove haze, negative values add an even haze
+    amount: f32,
+    // Pixel the dispatched region starts at and size of the whole image, as tiles of
+    // a large image are dispatched one by one
+    origin: vec2<f32>,
+    image_size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+fn min_channel(color: vec3<f32>) -> f32 {
+    return min(color.r, min(color.g, color.b));
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let color = textureLoad(input_texture, coords, 0);
+    let light = params.light;
+
+    // The dark channel is the minimum over a 5x5 grid spanning 1% of the shorter side
+    // on either side, so it covers the same part of the scene at every resolution.
+    // Tiles are padded by the grid, so clamping to the tile stays within the image.
+    let step = max(i32(min(params.image_size.x, params.image_size.y) * 0.005), 1);
+    let last = vec2<i32>(dimensions) - 1;
+    var dark = 1e9;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let sample = clamp(coords + vec2<i32>(x, y) * step, vec2<i32>(0), last);
+            dark = min(dark, min_channel(textureLoad(input_texture, sample, 0).rgb / light));
+        }
+    }
+
+    var result: vec3<f32>;
+    if (params.amount >= 0.0) {
+        let transmission = max(1.0 - 0.95 * params.amount * clamp(dark, 0.0, 1.0), 0.1);
+        result = (color.rgb - light) / transmission + light;
+    } else {
+        let transmission = 1.0 + 0.5 * params.amount;
+        result = color.rgb * transmission + light * (1.0 - transmission);
+    }
+
+    textureStore(output_texture, coords, vec4<f32>(max(result, vec3<f32>(0.0)), color.a));
+}
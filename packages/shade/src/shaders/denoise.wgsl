@@ -0,0 +1,70 @@
+// Bilateral filter that smooths brightness and colour separately. Neighbours are
+// weighted by their distance and by how much they differ from the centre pixel, so
+// noise below the strengths is averaged away while edges above them are kept.
+
+struct Params {
+    luminance: f32,
+    chroma: f32,
+    radius: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let center = textureLoad(input_texture, coords, 0);
+    let center_luma = dot(center.rgb, LUMA);
+    let center_chroma = center.rgb - center_luma;
+
+    // Colour noise is blotchier than luminance noise, so chroma averages a wider area
+    let extent = i32(ceil(max(params.radius, 0.0)));
+    let luma_spatial = 2.0 * pow(max(params.radius * 0.5, 0.5), 2.0);
+    let chroma_spatial = 2.0 * pow(max(params.radius, 0.5), 2.0);
+    let luma_range = 2.0 * pow(max(params.luminance * 0.15, 1e-4), 2.0);
+    let chroma_range = 2.0 * pow(max(params.chroma * 0.15, 1e-4), 2.0);
+
+    let last = vec2<i32>(dimensions) - 1;
+    var luma_sum = 0.0;
+    var luma_weight = 0.0;
+    var chroma_sum = vec3<f32>(0.0);
+    var chroma_weight = 0.0;
+    for (var y = -extent; y <= extent; y++) {
+        for (var x = -extent; x <= extent; x++) {
+            let sample = textureLoad(input_texture, clamp(coords + vec2<i32>(x, y), vec2<i32>(0), last), 0);
+            let luma = dot(sample.rgb, LUMA);
+            let chroma = sample.rgb - luma;
+            let distance = f32(x * x + y * y);
+
+            let luma_difference = luma - center_luma;
+            let weight = exp(-distance / luma_spatial - luma_difference * luma_difference / luma_range);
+            luma_sum += luma * weight;
+            luma_weight += weight;
+
+            let chroma_difference = chroma - center_chroma;
+            let chroma_factor = exp(-distance / chroma_spatial - dot(chroma_difference, chroma_difference) / chroma_range);
+            chroma_sum += chroma * chroma_factor;
+            chroma_weight += chroma_factor;
+        }
+    }
+
+    // The centre always has a weight of one, so neither sum is empty
+    let color = luma_sum / luma_weight + chroma_sum / chroma_weight;
+    textureStore(output_texture, coords, vec4<f32>(color, center.a));
+}
@@ -0,0 +1,26 @@
+// Appended to the shaders of nodes that work on sRGB encoded values. Input loads
+// and the output store are rewritten to go through these functions, so the node
+// sees its inputs through the sRGB curve while the pipeline stays linear.
+
+fn srgb_encode(color: vec3<f32>) -> vec3<f32> {
+    let magnitude = abs(color);
+    let low = magnitude * 12.92;
+    let high = 1.055 * pow(magnitude, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return sign(color) * select(high, low, magnitude <= vec3<f32>(0.0031308));
+}
+
+fn srgb_decode(color: vec3<f32>) -> vec3<f32> {
+    let magnitude = abs(color);
+    let low = magnitude / 12.92;
+    let high = pow((magnitude + 0.055) / 1.055, vec3<f32>(2.4));
+    return sign(color) * select(high, low, magnitude <= vec3<f32>(0.04045));
+}
+
+fn load_encoded(source: texture_2d<f32>, coords: vec2<i32>, level: i32) -> vec4<f32> {
+    let color = textureLoad(source, coords, level);
+    return vec4<f32>(srgb_encode(color.rgb), color.a);
+}
+
+fn store_decoded(coords: vec2<i32>, color: vec4<f32>) {
+    textureStore(output_texture, coords, vec4<f32>(srgb_decode(color.rgb), color.a));
+}
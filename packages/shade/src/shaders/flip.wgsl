@@ -0,0 +1,34 @@
+struct Params {
+    horizontal: f32,
+    vertical: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let last = vec2<i32>(dimensions) - 1;
+    var source = coords;
+    if (params.horizontal > 0.5) {
+        source.x = last.x - coords.x;
+    }
+    if (params.vertical > 0.5) {
+        source.y = last.y - coords.y;
+    }
+
+    textureStore(output_texture, coords, textureLoad(input_texture, source, 0));
+}
@@ -0,0 +1,75 @@
+// Film grain: smooth value noise laid over the image at a size relative to the
+// shorter image side, so a proxy shows the same grain as the full resolution export.
+// Unlike the Noise node it is monochrome and strongest in the midtones.
+
+struct Params {
+    amount: f32,
+    // Grain size in thousandths of the shorter image side
+    size: f32,
+    seed: u32,
+    _padding: f32,
+    // Pixel the dispatched region starts at and size of the whole image, as tiles of
+    // a large image are dispatched one by one
+    origin: vec2<f32>,
+    image_size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+// Integer hash of a lattice point to -1.0..1.0. Integer arithmetic gives the same
+// values on every GPU and on the CPU.
+fn hash(x: u32, y: u32, seed: u32) -> f32 {
+    var h = (x * 0x8da6b343u) ^ (y * 0xd8163841u) ^ (seed * 0xcb1ab31fu);
+    h = (h ^ (h >> 16u)) * 0x7feb352du;
+    h = (h ^ (h >> 15u)) * 0x846ca68bu;
+    h = h ^ (h >> 16u);
+    return f32(h >> 8u) / 8388607.5 - 1.0;
+}
+
+fn value_noise(position: vec2<f32>, seed: u32) -> f32 {
+    let cell = floor(position);
+    let fraction = position - cell;
+    let t = fraction * fraction * (3.0 - 2.0 * fraction);
+    // Through i32, so cells left of or above the origin hash like on the CPU
+    let x = bitcast<u32>(i32(cell.x));
+    let y = bitcast<u32>(i32(cell.y));
+
+    let top = mix(hash(x, y, seed), hash(x + 1u, y, seed), t.x);
+    let bottom = mix(hash(x, y + 1u, seed), hash(x + 1u, y + 1u, seed), t.x);
+    return mix(top, bottom, t.y);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let short_side = min(params.image_size.x, params.image_size.y);
+    let grain_size = max(params.size, 0.01) * short_side / 1000.0;
+    let position = (vec2<f32>(global_id.xy) + params.origin + 0.5) / grain_size;
+    // A finer octave on a rotated lattice breaks up the square cells of a single one
+    let rotated = vec2<f32>(0.8 * position.x - 0.6 * position.y, 0.6 * position.x + 0.8 * position.y);
+    let grain = 0.75 * value_noise(position, params.seed)
+        + 0.25 * value_noise(rotated * 2.0, params.seed + 1u);
+
+    let color = textureLoad(input_texture, coords, 0);
+    let luma = clamp(dot(color.rgb, LUMA), 0.0, 1.0);
+    let centered = 2.0 * luma - 1.0;
+    let visibility = 0.2 + 0.8 * (1.0 - centered * centered);
+
+    let grained = color.rgb + vec3<f32>(grain * params.amount * 0.2 * visibility);
+    textureStore(output_texture, coords, vec4<f32>(grained, color.a));
+}
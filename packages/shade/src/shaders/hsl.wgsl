@@ -0,0 +1,111 @@
+struct Params {
+    // Hue shift in degrees, saturation and luminance change of each band
+    bands: array<vec4<f32>, 8>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+fn rgb_to_hsv(rgb: vec3<f32>) -> vec3<f32> {
+    let max_val = max(max(rgb.r, rgb.g), rgb.b);
+    let min_val = min(min(rgb.r, rgb.g), rgb.b);
+    let delta = max_val - min_val;
+
+    var hue = 0.0;
+    let saturation = select(0.0, delta / max_val, max_val > 0.0);
+    let value = max_val;
+
+    if (delta > 0.0) {
+        if (max_val == rgb.r) {
+            hue = (rgb.g - rgb.b) / delta;
+            if (rgb.g < rgb.b) {
+                hue += 6.0;
+            }
+        } else if (max_val == rgb.g) {
+            hue = (rgb.b - rgb.r) / delta + 2.0;
+        } else {
+            hue = (rgb.r - rgb.g) / delta + 4.0;
+        }
+        hue /= 6.0;
+    }
+
+    return vec3<f32>(hue, saturation, value);
+}
+
+fn hsv_to_rgb(hsv: vec3<f32>) -> vec3<f32> {
+    let h = hsv.x * 6.0;
+    let s = hsv.y;
+    let v = hsv.z;
+
+    let c = v * s;
+    let x = c * (1.0 - abs((h % 2.0) - 1.0));
+    let m = v - c;
+
+    var rgb = vec3<f32>(0.0);
+
+    if (h < 1.0) {
+        rgb = vec3<f32>(c, x, 0.0);
+    } else if (h < 2.0) {
+        rgb = vec3<f32>(x, c, 0.0);
+    } else if (h < 3.0) {
+        rgb = vec3<f32>(0.0, c, x);
+    } else if (h < 4.0) {
+        rgb = vec3<f32>(0.0, x, c);
+    } else if (h < 5.0) {
+        rgb = vec3<f32>(x, 0.0, c);
+    } else {
+        rgb = vec3<f32>(c, 0.0, x);
+    }
+
+    return rgb + vec3<f32>(m);
+}
+
+// Adjustment for a hue in degrees, blended between the two nearest band centres
+fn band_adjustment(hue: f32) -> vec3<f32> {
+    var centers = array<f32, 9>(0.0, 30.0, 60.0, 120.0, 180.0, 240.0, 270.0, 300.0, 360.0);
+
+    var segment = 0;
+    for (var i = 7; i > 0; i--) {
+        if (hue >= centers[i]) {
+            segment = i;
+            break;
+        }
+    }
+
+    let start = centers[segment];
+    let end = centers[segment + 1];
+    let weight = smoothstep(0.0, 1.0, clamp((hue - start) / (end - start), 0.0, 1.0));
+    return mix(
+        params.bands[segment].xyz,
+        params.bands[(segment + 1) % 8].xyz,
+        weight
+    );
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let input_color = textureLoad(input_texture, coords, 0);
+    let hsv = rgb_to_hsv(input_color.rgb);
+    let adjustment = band_adjustment(hsv.x * 360.0);
+
+    let hue = fract(hsv.x + adjustment.x / 360.0);
+    let saturation = clamp(hsv.y * (1.0 + adjustment.y), 0.0, 1.0);
+    // Luminance changes fade out towards greys
+    let value = hsv.z * max(1.0 + adjustment.z * hsv.y, 0.0);
+
+    let adjusted_rgb = hsv_to_rgb(vec3<f32>(hue, saturation, value));
+    textureStore(output_texture, coords, vec4<f32>(adjusted_rgb, input_color.a));
+}
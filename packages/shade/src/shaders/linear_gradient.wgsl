@@ -0,0 +1,51 @@
+struct Params {
+    // Point on the middle of the transition, as a fraction of width and height
+    center: vec2<f32>,
+    // Direction the mask rises towards, in degrees counterclockwise from the right
+    angle: f32,
+    // Width of the transition, relative to the shorter image side
+    feather: f32,
+    invert: f32,
+    _padding1: f32,
+    _padding2: vec2<f32>,
+    // Pixel the dispatched region starts at and size of the whole image, as tiles of
+    // a large image are dispatched one by one
+    origin: vec2<f32>,
+    image_size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    // Normalised coordinates keep the mask identical on proxies and full resolution
+    let size = params.image_size;
+    let position = (vec2<f32>(global_id.xy) + params.origin) / size;
+    let offset = (position - params.center) * size / min(size.x, size.y);
+
+    // Image rows grow downwards, so the angle is measured with y flipped
+    let theta = params.angle * 0.017453292;
+    let edge_distance = dot(offset, vec2<f32>(cos(theta), -sin(theta)));
+    let half_feather = max(params.feather, 1e-5) * 0.5;
+
+    var mask_value = smoothstep(-half_feather, half_feather, edge_distance);
+    if (params.invert > 0.5) {
+        mask_value = 1.0 - mask_value;
+    }
+
+    textureStore(output_texture, coords, vec4<f32>(vec3<f32>(mask_value), 1.0));
+}
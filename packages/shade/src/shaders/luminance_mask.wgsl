@@ -0,0 +1,47 @@
+struct Params {
+    low: f32,
+    high: f32,
+    feather: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+fn srgb_encode(c: f32) -> f32 {
+    if (c <= 0.0031308) {
+        return c * 12.92;
+    }
+    return 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+}
+
+// 1.0 inside low..high, fading to 0.0 over `feather` outside of it
+fn range_weight(value: f32, low: f32, high: f32, feather: f32) -> f32 {
+    let softness = max(feather, 1e-5);
+    return smoothstep(low - softness, low, value) * (1.0 - smoothstep(high, high + softness, value));
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let input_color = textureLoad(input_texture, coords, 0);
+
+    // Ranges are given in perceptual lightness, so 0.5 is a mid tone
+    let luminance = dot(input_color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let lightness = srgb_encode(max(luminance, 0.0));
+    let mask_value = range_weight(lightness, params.low, params.high, params.feather);
+
+    textureStore(output_texture, coords, vec4<f32>(vec3<f32>(mask_value), 1.0));
+}
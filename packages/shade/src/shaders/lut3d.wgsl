@@ -0,0 +1,105 @@
+struct Params {
+    domain_min: vec3<f32>,
+    intensity: f32,
+    domain_max: vec3<f32>,
+    // 0.0 = trilinear, 1.0 = tetrahedral
+    tetrahedral: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// The 3D lookup table, indexed by the red, green and blue input levels
+@group(0) @binding(5)
+var lut_texture: texture_3d<f32>;
+
+fn entry(coords: vec3<i32>) -> vec3<f32> {
+    return textureLoad(lut_texture, coords, 0).rgb;
+}
+
+fn apply_lut(color: vec3<f32>) -> vec3<f32> {
+    let last = f32(textureDimensions(lut_texture).x - 1u);
+    let normalized = clamp(
+        (color - params.domain_min) / (params.domain_max - params.domain_min),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0)
+    );
+    let position = normalized * last;
+    let index = min(floor(position), vec3<f32>(last - 1.0));
+    let f = position - index;
+    let base = vec3<i32>(index);
+
+    let c000 = entry(base);
+    let c111 = entry(base + vec3<i32>(1, 1, 1));
+
+    if (params.tetrahedral < 0.5) {
+        let c100 = entry(base + vec3<i32>(1, 0, 0));
+        let c010 = entry(base + vec3<i32>(0, 1, 0));
+        let c110 = entry(base + vec3<i32>(1, 1, 0));
+        let c001 = entry(base + vec3<i32>(0, 0, 1));
+        let c101 = entry(base + vec3<i32>(1, 0, 1));
+        let c011 = entry(base + vec3<i32>(0, 1, 1));
+        let c00 = mix(c000, c100, f.x);
+        let c10 = mix(c010, c110, f.x);
+        let c01 = mix(c001, c101, f.x);
+        let c11 = mix(c011, c111, f.x);
+        return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+    }
+
+    // Blend the four corners of the tetrahedron holding the color
+    var c1: vec3<f32>;
+    var c2: vec3<f32>;
+    var weights: vec4<f32>;
+    if (f.x > f.y) {
+        if (f.y > f.z) {
+            c1 = entry(base + vec3<i32>(1, 0, 0));
+            c2 = entry(base + vec3<i32>(1, 1, 0));
+            weights = vec4<f32>(1.0 - f.x, f.x - f.y, f.y - f.z, f.z);
+        } else if (f.x > f.z) {
+            c1 = entry(base + vec3<i32>(1, 0, 0));
+            c2 = entry(base + vec3<i32>(1, 0, 1));
+            weights = vec4<f32>(1.0 - f.x, f.x - f.z, f.z - f.y, f.y);
+        } else {
+            c1 = entry(base + vec3<i32>(0, 0, 1));
+            c2 = entry(base + vec3<i32>(1, 0, 1));
+            weights = vec4<f32>(1.0 - f.z, f.z - f.x, f.x - f.y, f.y);
+        }
+    } else if (f.z > f.y) {
+        c1 = entry(base + vec3<i32>(0, 0, 1));
+        c2 = entry(base + vec3<i32>(0, 1, 1));
+        weights = vec4<f32>(1.0 - f.z, f.z - f.y, f.y - f.x, f.x);
+    } else if (f.z > f.x) {
+        c1 = entry(base + vec3<i32>(0, 1, 0));
+        c2 = entry(base + vec3<i32>(0, 1, 1));
+        weights = vec4<f32>(1.0 - f.y, f.y - f.z, f.z - f.x, f.x);
+    } else {
+        c1 = entry(base + vec3<i32>(0, 1, 0));
+        c2 = entry(base + vec3<i32>(1, 1, 0));
+        weights = vec4<f32>(1.0 - f.y, f.y - f.x, f.x - f.z, f.z);
+    }
+    return weights.x * c000 + weights.y * c1 + weights.z * c2 + weights.w * c111;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let input_color = textureLoad(input_texture, coords, 0);
+
+    // Blend the graded color with the original by the intensity
+    let graded = apply_lut(input_color.rgb);
+    let output_color = vec4<f32>(mix(input_color.rgb, graded, params.intensity), input_color.a);
+
+    textureStore(output_texture, coords, output_color);
+}
@@ -1,5 +1,5 @@
 struct Params {
-    // No specific parameters needed for basic masking
+    // No specific parameters needed for masking
     _dummy: f32,
 }
 
@@ -12,9 +12,9 @@ var output_texture: texture_storage_2d<rgba32float, write>;
 @group(0) @binding(2)
 var<uniform> params: Params;
 
-// Note: For a proper mask operation, we would need a second input texture (the mask)
-// This shader assumes the mask is stored elsewhere or passed differently
-// For now, we'll use the alpha channel as a simple mask
+// The `mask` input. Nearest-neighbour sampled when its size differs from `image`
+@group(0) @binding(3)
+var secondary_texture: texture_2d<f32>;
 
 @compute @workgroup_size(8, 8)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
@@ -27,17 +27,17 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 
     let input_color = textureLoad(input_texture, coords, 0);
 
-    // Simple mask operation using alpha channel
-    // In a real implementation, this would use a separate mask texture
-    let mask_value = input_color.a;
-
-    // Apply mask: multiply RGB by mask value, keep original alpha
-    let masked_color = vec4<f32>(
-        input_color.r * mask_value,
-        input_color.g * mask_value,
-        input_color.b * mask_value,
-        input_color.a
+    let mask_dimensions = textureDimensions(secondary_texture);
+    let mask_coords = vec2<i32>(
+        min(global_id.xy * mask_dimensions / dimensions, mask_dimensions - vec2<u32>(1u))
     );
+    let mask_color = textureLoad(secondary_texture, mask_coords, 0);
+
+    // The luminance of the mask image selects, keeping grey masks exact
+    let mask_value = clamp(dot(mask_color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+
+    // Colour is kept, the mask limits where a following `mix` applies the image
+    let masked_color = vec4<f32>(input_color.rgb, input_color.a * mask_value);
 
     textureStore(output_texture, coords, masked_color);
 }
@@ -1,5 +1,7 @@
 struct Params {
     factor: f32,
+    // Non-zero to blend `image2` in proportion to its alpha, keeping `image1` alpha
+    use_alpha: f32,
 }
 
 @group(0) @binding(0)
@@ -11,9 +13,9 @@ var output_texture: texture_storage_2d<rgba32float, write>;
 @group(0) @binding(2)
 var<uniform> params: Params;
 
-// Note: For a proper mix operation, we would need a second input texture
-// This shader assumes the second input is stored elsewhere or passed differently
-// For now, we'll mix with a default color or the input itself
+// The `image2` input. Nearest-neighbour sampled when its size differs from `image1`
+@group(0) @binding(3)
+var secondary_texture: texture_2d<f32>;
 
 @compute @workgroup_size(8, 8)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
@@ -26,17 +28,19 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 
     let input_color = textureLoad(input_texture, coords, 0);
 
-    // For demonstration, mix with a neutral gray color
-    // In a real implementation, this would be a second input texture
-    let mix_color = vec4<f32>(0.5, 0.5, 0.5, 1.0);
+    // Map output coordinates onto the secondary image
+    let secondary_dimensions = textureDimensions(secondary_texture);
+    let secondary_coords = vec2<i32>(
+        min(global_id.xy * secondary_dimensions / dimensions, secondary_dimensions - vec2<u32>(1u))
+    );
+    let mix_color = textureLoad(secondary_texture, secondary_coords, 0);
 
     // Apply mix operation: mix(a, b, factor) = a * (1 - factor) + b * factor
-    let mixed_color = vec4<f32>(
-        mix(input_color.r, mix_color.r, params.factor),
-        mix(input_color.g, mix_color.g, params.factor),
-        mix(input_color.b, mix_color.b, params.factor),
-        mix(input_color.a, mix_color.a, params.factor)
-    );
+    var mixed_color = mix(input_color, mix_color, vec4<f32>(params.factor));
+    if (params.use_alpha > 0.5) {
+        let weight = params.factor * mix_color.a;
+        mixed_color = vec4<f32>(mix(input_color.rgb, mix_color.rgb, vec3<f32>(weight)), input_color.a);
+    }
 
     textureStore(output_texture, coords, mixed_color);
 }
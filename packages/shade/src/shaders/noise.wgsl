@@ -1,6 +1,11 @@
 struct Params {
     amount: f32,
     seed: f32,
+    _padding: vec2<f32>,
+    // Pixel the dispatched region starts at and size of the whole image, as tiles of
+    // a large image are dispatched one by one
+    origin: vec2<f32>,
+    image_size: vec2<f32>,
 }
 
 @group(0) @binding(0)
@@ -35,7 +40,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let input_color = textureLoad(input_texture, coords, 0);
 
     // Generate noise value
-    let noise_coord = vec2<f32>(f32(global_id.x), f32(global_id.y)) / vec2<f32>(f32(dimensions.x), f32(dimensions.y));
+    let noise_coord = (vec2<f32>(global_id.xy) + params.origin) / params.image_size;
     let noise_value = noise(noise_coord, params.seed) * params.amount;
 
     // Add noise to the input color
@@ -0,0 +1,51 @@
+struct Params {
+    _padding: vec4<f32>,
+    // Pixel the dispatched region starts at and size of the whole image, as tiles of
+    // a large image are dispatched one by one
+    origin: vec2<f32>,
+    image_size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// The painted bitmap, stretched over the input image
+@group(0) @binding(4)
+var lookup_table: texture_2d<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let bitmap_dimensions = textureDimensions(lookup_table);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    // Map the pixel onto the bitmap like `resize.wgsl` does
+    let position = vec2<f32>(global_id.xy) + params.origin;
+    let source_x = position.x * f32(bitmap_dimensions.x) / params.image_size.x;
+    let source_y = position.y * f32(bitmap_dimensions.y) / params.image_size.y;
+    let source_coords = vec2<i32>(i32(source_x), i32(source_y));
+
+    var mask_value = textureLoad(lookup_table, source_coords, 0).r;
+
+    // Bilinear interpolation away from the right and bottom edges
+    if (source_coords.x + 1 < i32(bitmap_dimensions.x) &&
+        source_coords.y + 1 < i32(bitmap_dimensions.y)) {
+        let tl = textureLoad(lookup_table, source_coords, 0).r;
+        let tr = textureLoad(lookup_table, source_coords + vec2<i32>(1, 0), 0).r;
+        let bl = textureLoad(lookup_table, source_coords + vec2<i32>(0, 1), 0).r;
+        let br = textureLoad(lookup_table, source_coords + vec2<i32>(1, 1), 0).r;
+        mask_value = mix(mix(tl, tr, fract(source_x)), mix(bl, br, fract(source_x)), fract(source_y));
+    }
+
+    textureStore(output_texture, coords, vec4<f32>(vec3<f32>(mask_value), 1.0));
+}
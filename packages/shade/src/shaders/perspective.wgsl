@@ -0,0 +1,35 @@
+struct Params {
+    // Rows of the projective transform from output to input positions, both given as
+    // fractions of the image size
+    row_x: vec4<f32>,
+    row_y: vec4<f32>,
+    row_w: vec4<f32>,
+    // 0 for bilinear, 1 for bicubic resampling
+    resampling: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let output_dimensions = textureDimensions(output_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= output_dimensions.x || global_id.y >= output_dimensions.y) {
+        return;
+    }
+
+    let uv = vec3<f32>((vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(output_dimensions), 1.0);
+    let mapped = vec2<f32>(dot(params.row_x.xyz, uv), dot(params.row_y.xyz, uv))
+        / dot(params.row_w.xyz, uv);
+    let position = mapped * vec2<f32>(textureDimensions(input_texture));
+
+    textureStore(output_texture, coords, resample(position, params.resampling));
+}
@@ -0,0 +1,55 @@
+struct Params {
+    // Centre of the ellipse, as a fraction of width and height
+    center: vec2<f32>,
+    // Radii of the ellipse, relative to the shorter image side
+    radius: vec2<f32>,
+    // Rotation of the ellipse in degrees, counterclockwise
+    angle: f32,
+    // Fraction of the radius over which the mask fades out
+    feather: f32,
+    invert: f32,
+    _padding: f32,
+    // Pixel the dispatched region starts at and size of the whole image, as tiles of
+    // a large image are dispatched one by one
+    origin: vec2<f32>,
+    image_size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    // Normalised coordinates keep the mask identical on proxies and full resolution
+    let size = params.image_size;
+    let position = (vec2<f32>(global_id.xy) + params.origin) / size;
+    let offset = (position - params.center) * size / min(size.x, size.y);
+
+    // Rotate into the frame of the ellipse, with image rows growing downwards
+    let theta = params.angle * 0.017453292;
+    let c = cos(theta);
+    let s = sin(theta);
+    let local = vec2<f32>(c * offset.x - s * offset.y, s * offset.x + c * offset.y);
+    let edge_distance = length(local / max(params.radius, vec2<f32>(1e-5)));
+
+    let feather = clamp(params.feather, 1e-5, 1.0);
+    var mask_value = 1.0 - smoothstep(1.0 - feather, 1.0, edge_distance);
+    if (params.invert > 0.5) {
+        mask_value = 1.0 - mask_value;
+    }
+
+    textureStore(output_texture, coords, vec4<f32>(vec3<f32>(mask_value), 1.0));
+}
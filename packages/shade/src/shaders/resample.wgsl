@@ -0,0 +1,55 @@
+// Appended to the shaders of geometric transforms. Positions are measured in pixels
+// of the input texture with pixel centres at half-integers. Positions outside the
+// image read as transparent black, edge pixels are repeated for the filter taps.
+
+fn load_clamped(position: vec2<i32>) -> vec4<f32> {
+    let last = vec2<i32>(textureDimensions(input_texture)) - 1;
+    return textureLoad(input_texture, clamp(position, vec2<i32>(0), last), 0);
+}
+
+// Catmull-Rom weights of the four pixels around a position `t` past the second one
+fn cubic_weights(t: f32) -> vec4<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    return vec4<f32>(
+        -0.5 * t3 + t2 - 0.5 * t,
+        1.5 * t3 - 2.5 * t2 + 1.0,
+        -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+        0.5 * t3 - 0.5 * t2,
+    );
+}
+
+// Read the input at a position, bilinear for `resampling` 0 and bicubic for 1
+fn resample(position: vec2<f32>, resampling: f32) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(input_texture));
+    if (any(position < vec2<f32>(0.0)) || any(position > size)) {
+        return vec4<f32>(0.0);
+    }
+
+    let texel = position - 0.5;
+    let base = floor(texel);
+    let t = texel - base;
+    let origin = vec2<i32>(base);
+
+    if (resampling < 0.5) {
+        let top = mix(load_clamped(origin), load_clamped(origin + vec2<i32>(1, 0)), t.x);
+        let bottom = mix(
+            load_clamped(origin + vec2<i32>(0, 1)),
+            load_clamped(origin + vec2<i32>(1, 1)),
+            t.x,
+        );
+        return mix(top, bottom, t.y);
+    }
+
+    let weights_x = cubic_weights(t.x);
+    let weights_y = cubic_weights(t.y);
+    var color = vec4<f32>(0.0);
+    for (var j = 0; j < 4; j++) {
+        var row = vec4<f32>(0.0);
+        for (var i = 0; i < 4; i++) {
+            row += load_clamped(origin + vec2<i32>(i - 1, j - 1)) * weights_x[i];
+        }
+        color += row * weights_y[j];
+    }
+    return color;
+}
@@ -0,0 +1,37 @@
+struct Params {
+    // Clockwise quarter turns, 0 to 3
+    quarter_turns: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let output_dimensions = textureDimensions(output_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= output_dimensions.x || global_id.y >= output_dimensions.y) {
+        return;
+    }
+
+    // Pixels are moved without resampling, the output texture has the turned size
+    let last = vec2<i32>(textureDimensions(input_texture)) - 1;
+    let turns = i32(params.quarter_turns + 0.5);
+    var source = coords;
+    if (turns == 1) {
+        source = vec2<i32>(coords.y, last.y - coords.x);
+    } else if (turns == 2) {
+        source = last - coords;
+    } else if (turns == 3) {
+        source = vec2<i32>(last.x - coords.y, coords.x);
+    }
+
+    textureStore(output_texture, coords, textureLoad(input_texture, source, 0));
+}
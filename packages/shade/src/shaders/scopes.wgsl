@@ -0,0 +1,65 @@
+// Counts the pixels of an image into a histogram, a waveform and a vectorscope,
+// stored one after another in `counts` with the layout of `Scopes` in scopes.rs.
+
+struct Params {
+    waveform_columns: u32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> counts: array<atomic<u32>>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+const LEVELS: u32 = 256u;
+const HISTOGRAM_SIZE: u32 = 1024u;
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+// Every workgroup counts its histogram locally, so the few global histogram bins
+// are not hit by every single pixel
+var<workgroup> histogram: array<atomic<u32>, 1024>;
+
+fn level(value: f32) -> u32 {
+    return u32(clamp(value * f32(LEVELS), 0.0, f32(LEVELS - 1u)));
+}
+
+@compute @workgroup_size(8, 8)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    for (var i = local_index; i < HISTOGRAM_SIZE; i += 64u) {
+        atomicStore(&histogram[i], 0u);
+    }
+    workgroupBarrier();
+
+    let dimensions = textureDimensions(input_texture);
+    if (global_id.x < dimensions.x && global_id.y < dimensions.y) {
+        let color = textureLoad(input_texture, vec2<i32>(global_id.xy), 0).rgb;
+        let luma = dot(color, LUMA);
+        let values = vec4<f32>(color, luma);
+
+        let column = global_id.x * params.waveform_columns / dimensions.x;
+        for (var channel = 0u; channel < 4u; channel++) {
+            let bin = channel * LEVELS + level(values[channel]);
+            atomicAdd(&histogram[bin], 1u);
+            atomicAdd(&counts[HISTOGRAM_SIZE + bin * params.waveform_columns + column], 1u);
+        }
+
+        let cb = (color.b - luma) / (2.0 * (1.0 - LUMA.b));
+        let cr = (color.r - luma) / (2.0 * (1.0 - LUMA.r));
+        let vectorscope = HISTOGRAM_SIZE * (1u + params.waveform_columns);
+        atomicAdd(&counts[vectorscope + level(cr + 0.5) * LEVELS + level(cb + 0.5)], 1u);
+    }
+
+    workgroupBarrier();
+    for (var i = local_index; i < HISTOGRAM_SIZE; i += 64u) {
+        let count = atomicLoad(&histogram[i]);
+        if (count > 0u) {
+            atomicAdd(&counts[i], count);
+        }
+    }
+}
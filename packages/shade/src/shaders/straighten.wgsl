@@ -0,0 +1,36 @@
+struct Params {
+    // Cosine and sine of the clockwise rotation
+    cos_angle: f32,
+    sin_angle: f32,
+    // 0 for bilinear, 1 for bicubic resampling
+    resampling: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let output_dimensions = textureDimensions(output_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= output_dimensions.x || global_id.y >= output_dimensions.y) {
+        return;
+    }
+
+    // Rotate the offset from the output centre back around the input centre
+    let offset = vec2<f32>(global_id.xy) + 0.5 - vec2<f32>(output_dimensions) * 0.5;
+    let rotated = vec2<f32>(
+        params.cos_angle * offset.x + params.sin_angle * offset.y,
+        params.cos_angle * offset.y - params.sin_angle * offset.x,
+    );
+    let position = rotated + vec2<f32>(textureDimensions(input_texture)) * 0.5;
+
+    textureStore(output_texture, coords, resample(position, params.resampling));
+}
@@ -0,0 +1,102 @@
+struct Params {
+    // 0.0 = Reinhard, 1.0 = filmic (AgX-style), 2.0 = ACES
+    tone_operator: f32,
+    white_point: f32,
+    contrast: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+const MIDDLE_GREY: f32 = 0.18;
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Minimal AgX: log encoding between inset and outset matrices with a polynomial
+// fit of the default contrast curve
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104)
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116)
+    );
+
+    var x = inset * color;
+    x = clamp(log2(max(x, vec3<f32>(1e-10))), vec3<f32>(AGX_MIN_EV), vec3<f32>(AGX_MAX_EV));
+    x = (x - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    x = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+        + 0.4298 * x2 + 0.1191 * x - 0.00232;
+
+    return pow(max(outset * x, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+// Stephen Hill's fit of the ACES RRT and sRGB ODT
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input_matrix = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777)
+    );
+    let output_matrix = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602)
+    );
+
+    let v = input_matrix * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return output_matrix * (a / b);
+}
+
+fn tone_curve(color: vec3<f32>) -> vec3<f32> {
+    if (params.tone_operator < 0.5) {
+        return reinhard(color);
+    } else if (params.tone_operator < 1.5) {
+        return agx(color);
+    }
+    return aces(color);
+}
+
+// Scale contrast around middle grey in log space
+fn apply_contrast(color: vec3<f32>) -> vec3<f32> {
+    return MIDDLE_GREY * pow(max(color, vec3<f32>(0.0)) / MIDDLE_GREY, vec3<f32>(params.contrast));
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let input_color = textureLoad(input_texture, coords, 0);
+
+    // The curve is normalized so the white point lands exactly on display white
+    let white = tone_curve(apply_contrast(vec3<f32>(max(params.white_point, 1e-4))));
+    let mapped = tone_curve(apply_contrast(input_color.rgb)) / white;
+    let display = clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+
+    textureStore(output_texture, coords, vec4<f32>(display, input_color.a));
+}
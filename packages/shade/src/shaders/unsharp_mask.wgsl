@@ -0,0 +1,67 @@
+struct Params {
+    amount: f32,
+    radius: f32,
+    // Smallest luminance difference to the base that is sharpened
+    threshold: f32,
+    _padding: f32,
+}
+
+// The blurred base, rendered by the blur passes of the node
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// The original image
+@group(0) @binding(3)
+var secondary_texture: texture_2d<f32>;
+
+fn srgb_encode(c: vec3<f32>) -> vec3<f32> {
+    let magnitude = abs(c);
+    let encoded = select(
+        1.055 * pow(magnitude, vec3<f32>(1.0 / 2.4)) - 0.055,
+        magnitude * 12.92,
+        magnitude <= vec3<f32>(0.0031308)
+    );
+    return sign(c) * encoded;
+}
+
+fn srgb_decode(c: vec3<f32>) -> vec3<f32> {
+    let magnitude = abs(c);
+    let decoded = select(
+        pow((magnitude + 0.055) / 1.055, vec3<f32>(2.4)),
+        magnitude / 12.92,
+        magnitude <= vec3<f32>(0.04045)
+    );
+    return sign(c) * decoded;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let original = textureLoad(secondary_texture, coords, 0);
+    let base = textureLoad(input_texture, coords, 0);
+
+    // Detail is measured on encoded values, so shadows and highlights sharpen evenly
+    let encoded = srgb_encode(original.rgb);
+    let detail = encoded - srgb_encode(base.rgb);
+
+    var weight = 1.0;
+    if (params.threshold > 0.0) {
+        let difference = abs(dot(detail, vec3<f32>(0.2126, 0.7152, 0.0722)));
+        weight = smoothstep(params.threshold * 0.5, params.threshold, difference);
+    }
+
+    let sharpened = encoded + detail * params.amount * weight;
+    textureStore(output_texture, coords, vec4<f32>(srgb_decode(sharpened), original.a));
+}
@@ -0,0 +1,67 @@
+// Saturation boost that favours muted colours. Saturated colours change little, so
+// they do not clip, and skin tones around an orange hue are largely protected.
+
+struct Params {
+    amount: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// Hue of skin tones in degrees and how far around it they are protected
+const SKIN_HUE: f32 = 25.0;
+const SKIN_RANGE: f32 = 35.0;
+
+fn rgb_to_hsv(rgb: vec3<f32>) -> vec3<f32> {
+    let max_val = max(max(rgb.r, rgb.g), rgb.b);
+    let min_val = min(min(rgb.r, rgb.g), rgb.b);
+    let delta = max_val - min_val;
+
+    var hue = 0.0;
+    let saturation = select(0.0, delta / max_val, max_val > 0.0);
+    let value = max_val;
+
+    if (delta > 0.0) {
+        if (max_val == rgb.r) {
+            hue = (rgb.g - rgb.b) / delta;
+            if (rgb.g < rgb.b) {
+                hue += 6.0;
+            }
+        } else if (max_val == rgb.g) {
+            hue = (rgb.b - rgb.r) / delta + 2.0;
+        } else {
+            hue = (rgb.r - rgb.g) / delta + 4.0;
+        }
+        hue /= 6.0;
+    }
+
+    return vec3<f32>(hue, saturation, value);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    let color = textureLoad(input_texture, coords, 0);
+    let hsv = rgb_to_hsv(color.rgb);
+
+    // Distance to the skin hue around the colour wheel, in degrees
+    let skin_offset = fract(hsv.x - SKIN_HUE / 360.0 + 0.5) - 0.5;
+    let skin = 1.0 - smoothstep(0.0, SKIN_RANGE, abs(skin_offset) * 360.0);
+    let weight = (1.0 - clamp(hsv.y, 0.0, 1.0)) * (1.0 - 0.75 * skin);
+
+    let luminance = 0.299 * color.r + 0.587 * color.g + 0.114 * color.b;
+    let vibrant = mix(vec3<f32>(luminance), color.rgb, 1.0 + params.amount * weight);
+    textureStore(output_texture, coords, vec4<f32>(max(vibrant, vec3<f32>(0.0)), color.a));
+}
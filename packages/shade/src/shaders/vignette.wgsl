@@ -0,0 +1,61 @@
+// Darkens or brightens the image towards its corners. Distances are measured in
+// fractions of the distance from the centre to the corners, so the vignette has the
+// same shape on proxies and full resolution images, and follows any earlier crop.
+
+struct Params {
+    // Exposure change in the corners in units of two stops, negative darkens
+    amount: f32,
+    // Distance at which the vignette reaches half its strength
+    midpoint: f32,
+    // -1.0 for a rounded rectangle, 0.0 for an ellipse following the frame, 1.0 for a circle
+    roundness: f32,
+    // Width of the transition
+    feather: f32,
+    // Pixel the dispatched region starts at and size of the whole image, as tiles of
+    // a large image are dispatched one by one
+    origin: vec2<f32>,
+    image_size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// Length of `v` in the superellipse norm with `exponent`, 2.0 being the Euclidean one
+fn shape_length(v: vec2<f32>, exponent: f32) -> f32 {
+    let magnitude = max(abs(v), vec2<f32>(1e-6));
+    return pow(pow(magnitude.x, exponent) + pow(magnitude.y, exponent), 1.0 / exponent);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
+
+    // -1.0 to 1.0 across the image, measured from pixel centres
+    let size = params.image_size;
+    let offset = ((vec2<f32>(global_id.xy) + params.origin + 0.5) / size - 0.5) * 2.0;
+
+    // Positive roundness squeezes the long side towards a circle, negative roundness
+    // raises the exponent towards a rectangle
+    let roundness = clamp(params.roundness, -1.0, 1.0);
+    let scale = mix(vec2<f32>(1.0), size / max(size.x, size.y), max(roundness, 0.0));
+    let exponent = 2.0 + 6.0 * max(-roundness, 0.0);
+    let distance = shape_length(offset * scale, exponent) / shape_length(scale, exponent);
+
+    let feather = max(params.feather, 1e-3);
+    let weight = smoothstep(params.midpoint - feather * 0.5, params.midpoint + feather * 0.5, distance);
+
+    let color = textureLoad(input_texture, coords, 0);
+    let gain = exp2(params.amount * 2.0 * weight);
+    textureStore(output_texture, coords, vec4<f32>(color.rgb * gain, color.a));
+}
@@ -269,10 +269,10 @@ pub fn output_image_wasm(image_data: Vec<u8>, texture_dims: (usize, usize)) {
       let b = f32::from_le_bytes([chunk[8], chunk[9], chunk[10], chunk[11]]);
       let a = f32::from_le_bytes([chunk[12], chunk[13], chunk[14], chunk[15]]);
 
-      // Use better gamma correction for higher quality conversion
-      u8_data.push((r.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8);
-      u8_data.push((g.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8);
-      u8_data.push((b.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8);
+      // The pipeline already encoded the output for display
+      u8_data.push((r.clamp(0.0, 1.0) * 255.0) as u8);
+      u8_data.push((g.clamp(0.0, 1.0) * 255.0) as u8);
+      u8_data.push((b.clamp(0.0, 1.0) * 255.0) as u8);
       u8_data.push((a.clamp(0.0, 1.0) * 255.0) as u8);
     }
     u8_data
@@ -285,10 +285,10 @@ pub fn output_image_wasm(image_data: Vec<u8>, texture_dims: (usize, usize)) {
       let b = half::f16::from_le_bytes([chunk[4], chunk[5]]).to_f32();
       let a = half::f16::from_le_bytes([chunk[6], chunk[7]]).to_f32();
 
-      // Use better gamma correction for higher quality conversion
-      u8_data.push((r.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8);
-      u8_data.push((g.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8);
-      u8_data.push((b.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8);
+      // The pipeline already encoded the output for display
+      u8_data.push((r.clamp(0.0, 1.0) * 255.0) as u8);
+      u8_data.push((g.clamp(0.0, 1.0) * 255.0) as u8);
+      u8_data.push((b.clamp(0.0, 1.0) * 255.0) as u8);
       u8_data.push((a.clamp(0.0, 1.0) * 255.0) as u8);
     }
     u8_data
@@ -0,0 +1,379 @@
+//! White balance from the colour of the light
+//!
+//! The light is estimated from a grid of samples of the image, picked at a point that
+//! should be neutral or given as a colour temperature in Kelvin. Gains multiply linear
+//! RGB with green fixed at 1.0, so balancing changes colours but hardly the exposure.
+//! The gains are measured once on the CPU, from the few pixels `measured_positions`
+//! lists, and `shaders/white_balance.wgsl` only applies them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::{self, ColorSpace, Mat3};
+
+/// Temperature of the D65 white of sRGB, which images without an as-shot white balance
+/// are taken to be balanced for
+pub const D65_KELVIN: f32 = 6500.0;
+
+/// Temperatures covered by the approximation of the Planckian locus
+pub const MIN_KELVIN: f32 = 1667.0;
+pub const MAX_KELVIN: f32 = 25000.0;
+
+/// Samples per side of the grid the automatic estimate reads
+pub const SAMPLE_GRID: u32 = 16;
+
+/// Largest gain, so an image of a single colour is not forced to grey at any cost
+pub const MAX_GAIN: f32 = 4.0;
+
+/// Rec. 709 luma weights
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Statistic an automatic white balance estimates the light with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WhiteBalanceMethod {
+  /// The average colour of the image is grey
+  #[default]
+  GreyWorld,
+  /// The brightest colours are white
+  WhitePatch,
+}
+
+impl FromStr for WhiteBalanceMethod {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "grey_world" => Ok(WhiteBalanceMethod::GreyWorld),
+      "white_patch" => Ok(WhiteBalanceMethod::WhitePatch),
+      _ => Err(format!(
+        "Unknown white balance method '{}', expected grey_world or white_patch",
+        value
+      )),
+    }
+  }
+}
+
+impl fmt::Display for WhiteBalanceMethod {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      WhiteBalanceMethod::GreyWorld => write!(f, "grey_world"),
+      WhiteBalanceMethod::WhitePatch => write!(f, "white_patch"),
+    }
+  }
+}
+
+fn luma(rgb: [f32; 3]) -> f32 {
+  (0..3).map(|i| rgb[i] * LUMA[i]).sum()
+}
+
+/// Pixel positions of the samples of the automatic estimate, at the centres of the
+/// cells of a `SAMPLE_GRID` square grid
+pub fn sample_positions(width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+  let position = |i: u32, size: u32| {
+    let position = (i as f32 + 0.5) / SAMPLE_GRID as f32 * size as f32;
+    (position as u32).min(size.saturating_sub(1))
+  };
+  (0..SAMPLE_GRID).flat_map(move |y| {
+    (0..SAMPLE_GRID).map(move |x| (position(x, width), position(y, height)))
+  })
+}
+
+/// Pixel positions of the 5x5 patch around a neutral point given in fractions of
+/// width and height, cropped to the image
+pub fn patch_positions(
+  width: u32,
+  height: u32,
+  [x, y]: [f32; 2],
+) -> impl Iterator<Item = (u32, u32)> {
+  let center = |position: f32, size: u32| {
+    ((position * size as f32).floor() as i64).clamp(0, size as i64 - 1)
+  };
+  let (cx, cy) = (center(x, width), center(y, height));
+  (cy - 2..=cy + 2).flat_map(move |y| {
+    (cx - 2..=cx + 2)
+      .filter(move |&x| x >= 0 && x < width as i64 && y >= 0 && y < height as i64)
+      .map(move |x| (x as u32, y as u32))
+  })
+}
+
+/// Colour of the light, estimated from samples in linear light. Clipped and nearly
+/// black samples carry no colour and are skipped; `None` when no sample is left.
+pub fn estimate_light(
+  samples: impl IntoIterator<Item = [f32; 3]>,
+  method: WhiteBalanceMethod,
+) -> Option<[f32; 3]> {
+  let samples = samples
+    .into_iter()
+    .filter(|&rgb| rgb.iter().all(|&c| c < 1.0) && luma(rgb) > 0.01)
+    .collect::<Vec<_>>();
+  let threshold = match method {
+    WhiteBalanceMethod::GreyWorld => 0.0,
+    // The brightest samples, so one specular highlight does not decide alone
+    WhiteBalanceMethod::WhitePatch => {
+      0.9 * samples.iter().map(|&rgb| luma(rgb)).fold(0.0, f32::max)
+    }
+  };
+
+  let mut sum = [0.0f32; 3];
+  let mut count = 0.0f32;
+  for rgb in samples.into_iter().filter(|&rgb| luma(rgb) >= threshold) {
+    for i in 0..3 {
+      sum[i] += rgb[i];
+    }
+    count += 1.0;
+  }
+  (count > 0.0).then(|| sum.map(|c| c / count))
+}
+
+/// Gains that turn `color` grey
+pub fn neutral_gains([r, g, b]: [f32; 3]) -> [f32; 3] {
+  let gain = |c: f32| (g / c.max(1e-6)).clamp(1.0 / MAX_GAIN, MAX_GAIN);
+  [gain(r), 1.0, gain(b)]
+}
+
+/// Pixels the gains of a white balance are measured from: the patch around the
+/// neutral point or the grid of the automatic `method`. Empty for manual gains.
+pub fn measured_positions(
+  width: u32,
+  height: u32,
+  method: Option<WhiteBalanceMethod>,
+  neutral_point: Option<[f32; 2]>,
+) -> Vec<(u32, u32)> {
+  match (neutral_point, method) {
+    (Some(point), _) => patch_positions(width, height, point).collect(),
+    (None, Some(_)) => sample_positions(width, height).collect(),
+    (None, None) => Vec::new(),
+  }
+}
+
+/// Gains of a white balance, made grey at the neutral point or estimated with the
+/// automatic `method` from the pixels `load` returns. Falls back to the `manual`
+/// gains.
+pub fn image_gains(
+  width: u32,
+  height: u32,
+  method: Option<WhiteBalanceMethod>,
+  neutral_point: Option<[f32; 2]>,
+  manual: [f32; 3],
+  load: impl Fn(u32, u32) -> [f32; 3],
+) -> [f32; 3] {
+  let samples = measured_positions(width, height, method, neutral_point)
+    .into_iter()
+    .map(|(x, y)| load(x, y))
+    .collect::<Vec<_>>();
+  if neutral_point.is_some() {
+    let mut color = [0.0f32; 3];
+    for sample in &samples {
+      for i in 0..3 {
+        color[i] += sample[i] / samples.len() as f32;
+      }
+    }
+    return neutral_gains(color);
+  }
+  match method {
+    // Without a usable sample the image is left unchanged
+    Some(method) => estimate_light(samples, method).map_or([1.0; 3], neutral_gains),
+    None => manual,
+  }
+}
+
+/// Gains that balance for light of `kelvin` an image balanced for `as_shot_kelvin`.
+/// Lower temperatures than the as-shot one cool the image down.
+pub fn kelvin_gains(kelvin: f32, as_shot_kelvin: f32) -> [f32; 3] {
+  // Balancing for the as-shot light first, then for the new light
+  let light = color::xy_to_rgb(ColorSpace::LinearSrgb, kelvin_to_xy(kelvin));
+  let as_shot = color::xy_to_rgb(ColorSpace::LinearSrgb, kelvin_to_xy(as_shot_kelvin));
+  let [r, _, b] = neutral_gains(light);
+  let [as_shot_r, _, as_shot_b] = neutral_gains(as_shot);
+  [r / as_shot_r, 1.0, b / as_shot_b]
+}
+
+/// Gains of a manual white balance: for light of `kelvin` when set, otherwise from
+/// the relative `temperature` (-1.0 to 1.0) trading red for blue. `tint` (-1.0 to 1.0)
+/// scales green.
+pub fn manual_gains(
+  temperature: f32,
+  tint: f32,
+  kelvin: Option<f32>,
+  as_shot_kelvin: f32,
+) -> [f32; 3] {
+  let [r, _, b] = match kelvin {
+    Some(kelvin) => kelvin_gains(kelvin, as_shot_kelvin),
+    None => [1.0 + temperature * 0.5, 1.0, 1.0 - temperature * 0.5],
+  };
+  [r, 1.0 + tint * 0.3, b]
+}
+
+/// Chromaticity of a black body at `kelvin`, after the cubic spline approximation of
+/// the Planckian locus by Kim et al.
+pub fn kelvin_to_xy(kelvin: f32) -> [f32; 2] {
+  let t = kelvin.clamp(MIN_KELVIN, MAX_KELVIN);
+  let x = if t <= 4000.0 {
+    -0.2661239e9 / t.powi(3) - 0.2343589e6 / t.powi(2) + 0.8776956e3 / t + 0.179910
+  } else {
+    -3.025847e9 / t.powi(3) + 2.107038e6 / t.powi(2) + 0.2226347e3 / t + 0.240390
+  };
+  let y = if t <= 2222.0 {
+    -1.106381 * x.powi(3) - 1.34811 * x.powi(2) + 2.185558 * x - 0.2021968
+  } else if t <= 4000.0 {
+    -0.9549476 * x.powi(3) - 1.374186 * x.powi(2) + 2.09137 * x - 0.1674887
+  } else {
+    3.081758 * x.powi(3) - 5.873387 * x.powi(2) + 3.75113 * x - 0.3700148
+  };
+  [x, y]
+}
+
+/// Correlated colour temperature of a chromaticity near the Planckian locus, after
+/// McCamy's cubic approximation
+pub fn xy_to_kelvin([x, y]: [f32; 2]) -> f32 {
+  let n = (x - 0.3320) / (0.1858 - y);
+  let kelvin = 449.0 * n.powi(3) + 3525.0 * n.powi(2) + 6823.3 * n + 5520.33;
+  kelvin.clamp(MIN_KELVIN, MAX_KELVIN)
+}
+
+/// Temperature of the light a camera's white balance was set for, from the
+/// multipliers of its red, green and blue channels and its XYZ to camera matrix, row
+/// by row. The scale of either does not matter.
+pub fn as_shot_kelvin(multipliers: [f32; 4], xyz_to_camera: &[f32]) -> Option<f32> {
+  if xyz_to_camera.len() < 9 || !multipliers[..3].iter().all(|&m| m > 0.0) {
+    return None;
+  }
+  let matrix: Mat3 =
+    [0, 1, 2].map(|row| [0, 1, 2].map(|col| xyz_to_camera[row * 3 + col]));
+  // Neutral surfaces are recorded in inverse proportion to the multipliers
+  let neutral = [0, 1, 2].map(|i| 1.0 / multipliers[i]);
+  let xyz = color::mul_vec(color::invert(matrix), neutral);
+  let xy = color::xyz_to_xy(xyz);
+  xy.iter().all(|c| c.is_finite()).then(|| xy_to_kelvin(xy))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_kelvin_round_trip() {
+    for kelvin in [2500.0, 3200.0, 4000.0, 5000.0, 6500.0, 8000.0] {
+      let estimate = xy_to_kelvin(kelvin_to_xy(kelvin));
+      assert!(
+        (estimate - kelvin).abs() < kelvin * 0.01,
+        "{} {}",
+        kelvin,
+        estimate
+      );
+    }
+
+    // Balancing for the light the image was taken in changes nothing, balancing for
+    // warmer light cools the image down
+    let unchanged = kelvin_gains(5000.0, 5000.0);
+    assert!(unchanged.iter().all(|&gain| (gain - 1.0).abs() < 1e-5));
+    let [r, g, b] = kelvin_gains(3200.0, D65_KELVIN);
+    assert!(r < 0.8 && g == 1.0 && b > 1.3, "{:?}", [r, g, b]);
+  }
+
+  #[test]
+  fn test_estimate_light() {
+    let samples = [
+      [0.2, 0.3, 0.4],
+      [0.4, 0.5, 0.6],
+      [0.8, 0.9, 0.95],
+      [1.2, 1.0, 1.0], // clipped
+      [0.001, 0.002, 0.0],
+    ];
+    let grey_world = estimate_light(samples, WhiteBalanceMethod::GreyWorld).unwrap();
+    assert!((grey_world[0] - 1.4 / 3.0).abs() < 1e-6, "{:?}", grey_world);
+    let white_patch = estimate_light(samples, WhiteBalanceMethod::WhitePatch).unwrap();
+    assert_eq!(white_patch, [0.8, 0.9, 0.95]);
+    assert_eq!(
+      estimate_light([[1.0; 3]], WhiteBalanceMethod::GreyWorld),
+      None
+    );
+
+    let gains = neutral_gains([0.5, 0.4, 0.2]);
+    assert_eq!(gains, [0.8, 1.0, 2.0]);
+    assert_eq!(neutral_gains([0.0, 0.5, 0.5])[0], MAX_GAIN);
+  }
+
+  #[test]
+  fn test_sample_positions() {
+    let positions = sample_positions(32, 3).collect::<Vec<_>>();
+    assert_eq!(positions.len(), (SAMPLE_GRID * SAMPLE_GRID) as usize);
+    assert_eq!(positions[0], (1, 0));
+    assert_eq!(positions[SAMPLE_GRID as usize - 1], (31, 0));
+    assert_eq!(positions.last(), Some(&(31, 2)));
+
+    let patch = patch_positions(32, 3, [0.0, 0.5]).collect::<Vec<_>>();
+    assert_eq!(patch.len(), 9);
+    assert_eq!(patch[0], (0, 0));
+    assert_eq!(patch.last(), Some(&(2, 2)));
+  }
+
+  #[test]
+  fn test_image_gains() {
+    // Red on the left half, grey on the right
+    let load = |x: u32, _: u32| if x < 16 { [0.4, 0.2, 0.2] } else { [0.5; 3] };
+    let manual = [1.2, 1.0, 0.8];
+
+    assert_eq!(image_gains(32, 8, None, None, manual, load), manual);
+    assert!(measured_positions(32, 8, None, None).is_empty());
+
+    let [r, _, b] = image_gains(32, 8, None, Some([0.1, 0.5]), manual, load);
+    assert!(
+      (r - 0.5).abs() < 1e-5 && (b - 1.0).abs() < 1e-5,
+      "{} {}",
+      r,
+      b
+    );
+    let grey = image_gains(
+      32,
+      8,
+      Some(WhiteBalanceMethod::GreyWorld),
+      Some([0.9, 0.5]),
+      manual,
+      load,
+    );
+    assert_eq!(grey, [1.0; 3]);
+
+    // Half of the grid samples are red
+    let [r, _, b] = image_gains(
+      32,
+      8,
+      Some(WhiteBalanceMethod::GreyWorld),
+      None,
+      manual,
+      load,
+    );
+    assert!(
+      (r - 0.35 / 0.45).abs() < 1e-5 && (b - 1.0).abs() < 1e-5,
+      "{} {}",
+      r,
+      b
+    );
+    let clipped = image_gains(
+      32,
+      8,
+      Some(WhiteBalanceMethod::WhitePatch),
+      None,
+      manual,
+      |_, _| [1.0; 3],
+    );
+    assert_eq!(clipped, [1.0; 3]);
+  }
+
+  #[test]
+  fn test_as_shot_kelvin() {
+    // A camera recording XYZ directly, white balanced for 4000 K light
+    let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+    let [x, y] = kelvin_to_xy(4000.0);
+    let white = [x / y, 1.0, (1.0 - x - y) / y];
+    let multipliers = [1.0 / white[0], 1.0 / white[1], 1.0 / white[2], f32::NAN];
+    let kelvin = as_shot_kelvin(multipliers, &identity).unwrap();
+    assert!((kelvin - 4000.0).abs() < 40.0, "{}", kelvin);
+
+    assert_eq!(as_shot_kelvin([0.0, 1.0, 1.0, 1.0], &identity), None);
+    assert_eq!(as_shot_kelvin([2.0, 1.0, 1.5, 1.0], &identity[..6]), None);
+  }
+}